@@ -21,7 +21,7 @@ macro_rules! create_benchmarks {
                     PlotConfiguration::default()
                     .summary_scale(AxisScale::Logarithmic);
                 let bench = ParameterizedBenchmark::new(
-                    $first_name, $first_func, input.into_iter().cloned())
+                    $first_name, $first_func, input.iter().cloned())
                     $( .with_function($rest_name, $rest_func) )*
                     .plot_config(plot_config);
                 c.bench(stringify!($group_id), bench);
@@ -70,5 +70,30 @@ create_benchmarks! {
     }
 }
 
-criterion_group!(benches, weight, distance);
+macro_rules! fixed_benchmarks {
+    ($($name: ident, $len: expr, $kernel: path;)*) => {
+        $(
+            fn $name(c: &mut Criterion) {
+                let x = [0x5A; $len];
+                let y = [0xA5; $len];
+                c.bench_function(concat!(stringify!($name), "/distance"), move |b| {
+                    b.iter(|| hamming::distance(criterion::black_box(&x), criterion::black_box(&y)))
+                });
+                c.bench_function(concat!(stringify!($name), "/distance_array"), move |b| {
+                    b.iter(|| hamming::distance_array(criterion::black_box(&x), criterion::black_box(&y)))
+                });
+                c.bench_function(concat!(stringify!($name), "/", stringify!($kernel)), move |b| {
+                    b.iter(|| $kernel(criterion::black_box(&x), criterion::black_box(&y)))
+                });
+            }
+        )*
+    }
+}
+
+fixed_benchmarks! {
+    fixed32, 32, hamming::distance32;
+    fixed64, 64, hamming::distance64;
+}
+
+criterion_group!(benches, weight, distance, fixed32, fixed64);
 criterion_main!(benches);
@@ -15,19 +15,25 @@ if [ ! -z "$TARGET" ]; then
 fi
 
 $cargo build -v $target_param --features "$features"
-if [ "$TRAVIS_RUST_VERSION" = "1.13.0" ]; then
+$cargo build -v $target_param --features "$features alloc"
+$cargo build -v $target_param --features "$features std"
+if [ "$TRAVIS_RUST_VERSION" = "1.89.0" ]; then
     # unfortunately, testing requires building dev-deps, which
     # requires a newer rustc than this.
     exit 0
 fi
 
 $cargo test -v $target_param --features "$features"
+$cargo test -v $target_param --features "$features alloc"
+$cargo test -v $target_param --features "$features std"
+CARGO_PROFILE_RELEASE_CODEGEN_UNITS=1 $cargo test -v $target_param --release --features "$features std no-panic" --lib checked
+$cargo test -v $target_param --features "$features std serde memmap space acap libm ndarray bitvec fixedbitset roaring bytes bytemuck digest capi wasm-bindgen python futures gpu cli rand"
 
 # for now, `cross bench` is broken https://github.com/rust-embedded/cross/issues/239
 if [ "$cargo" != "cross" ]; then
     $cargo bench -v $target_param --features "$features" -- --test # don't actually record numbers
 fi
-$cargo doc -v $target_param --features "$features"
+$cargo doc -v $target_param --features "$features std"
 
 
 if [ ! -z "$COVERAGE" ]; then
@@ -37,6 +43,6 @@ if [ ! -z "$COVERAGE" ]; then
     fi
 
     cargo install -v cargo-travis || echo "cargo-travis already installed"
-    cargo coverage -v --features "$features" -m coverage-reports
+    cargo coverage -v --features "$features std" -m coverage-reports
     bash <(curl -s https://codecov.io/bash) -c -X gcov -X coveragepy -s coverage-reports
 fi
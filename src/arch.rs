@@ -0,0 +1,341 @@
+//! Kernels compiled for specific CPU features, for callers that do
+//! their own feature detection.
+//!
+//! `weight` and `distance` are compiled for the features of the build
+//! target (such as those enabled by `-C target-cpu=native`) and don't
+//! check the CPU at run time. The functions here are each compiled
+//! with a particular set of instructions enabled, whatever the build
+//! target, and are called directly, with no dispatch: this is for
+//! programs that pick a kernel once, with `is_x86_feature_detected!`
+//! or their own framework, and then call it in a hot loop, such as a
+//! scan over codes only 32 bytes long.
+//!
+//! # Safety
+//!
+//! Each function is `unsafe` because calling it on a CPU without the
+//! features listed in its documentation is undefined behaviour (in
+//! practice, usually a crash with an illegal instruction). Given those
+//! features, they are safe to call with any inputs, and return the
+//! same value as `weight` or `distance` on the same bytes.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[cfg(target_arch = "x86_64")] {
+//! let codes = vec![[0xF0u8; 32]; 100];
+//! let query = [0x0Fu8; 32];
+//!
+//! let kernel: unsafe fn(&[u8], &[u8]) -> u64 = if is_x86_feature_detected!("avx2") &&
+//!                                                   is_x86_feature_detected!("popcnt") {
+//!     hamming::arch::distance_avx2
+//! } else {
+//!     |x, y| hamming::distance(x, y)
+//! };
+//! // safe: the kernel is only used if the CPU supports it
+//! let total = codes.iter().map(|c| unsafe { kernel(c, &query) }).sum::<u64>();
+//! assert_eq!(total, 100 * 256);
+//! # }
+//! ```
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+#[cfg(target_arch = "aarch64")]
+use core::arch::aarch64::*;
+
+/// The weight of `x`, a word at a time, for the bytes left over by a
+/// vector loop.
+///
+/// This is inlined into each kernel, so uses `popcnt` where the kernel
+/// enables it.
+#[inline(always)]
+fn weight_tail(x: &[u8]) -> u64 {
+    let chunks = x.chunks_exact(8);
+    let tail = chunks.remainder().iter().fold(0, |a, b| a + b.count_ones() as u64);
+    chunks.fold(tail, |a, c| {
+        let mut word = [0; 8];
+        word.copy_from_slice(c);
+        a + u64::from_ne_bytes(word).count_ones() as u64
+    })
+}
+
+/// `weight_tail` for the xor of `x` and `y`, which have the same
+/// length.
+#[inline(always)]
+fn distance_tail(x: &[u8], y: &[u8]) -> u64 {
+    let (x_chunks, y_chunks) = (x.chunks_exact(8), y.chunks_exact(8));
+    let tail = x_chunks.remainder().iter().zip(y_chunks.remainder())
+        .fold(0, |a, (b, c)| a + (b ^ c).count_ones() as u64);
+    x_chunks.zip(y_chunks).fold(tail, |a, (b, c)| {
+        let (mut u, mut v) = ([0; 8], [0; 8]);
+        u.copy_from_slice(b);
+        v.copy_from_slice(c);
+        a + (u64::from_ne_bytes(u) ^ u64::from_ne_bytes(v)).count_ones() as u64
+    })
+}
+
+/// Computes the Hamming weight of `x` with the `popcnt` instruction,
+/// a 64-bit word at a time.
+///
+/// # Safety
+///
+/// The CPU must support `popcnt`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "popcnt")]
+pub unsafe fn weight_popcnt(x: &[u8]) -> u64 {
+    weight_tail(x)
+}
+
+/// Computes the Hamming distance between `x` and `y` with the `popcnt`
+/// instruction, a 64-bit word at a time.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length.
+///
+/// # Safety
+///
+/// The CPU must support `popcnt`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "popcnt")]
+pub unsafe fn distance_popcnt(x: &[u8], y: &[u8]) -> u64 {
+    assert_eq!(x.len(), y.len());
+    distance_tail(x, y)
+}
+
+/// The number of ones in each 64-bit lane of `v`, by looking up each
+/// nibble in a table with `vpshufb` (Wojciech Muła's method).
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn popcount256(v: __m256i) -> __m256i {
+    let table = _mm256_setr_epi8(0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+                                 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4);
+    let nibbles = _mm256_set1_epi8(0x0F);
+    let low = _mm256_and_si256(v, nibbles);
+    let high = _mm256_and_si256(_mm256_srli_epi16(v, 4), nibbles);
+    let counts = _mm256_add_epi8(_mm256_shuffle_epi8(table, low), _mm256_shuffle_epi8(table, high));
+    _mm256_sad_epu8(counts, _mm256_setzero_si256())
+}
+
+/// The sum of the 64-bit lanes of `v`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn sum256(v: __m256i) -> u64 {
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, v);
+    lanes.iter().sum()
+}
+
+/// Computes the Hamming weight of `x` with AVX2, 32 bytes at a time.
+///
+/// The bytes left over are counted with `popcnt`.
+///
+/// # Safety
+///
+/// The CPU must support `avx2` and `popcnt`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2,popcnt")]
+pub unsafe fn weight_avx2(x: &[u8]) -> u64 {
+    let chunks = x.chunks_exact(32);
+    let tail = weight_tail(chunks.remainder());
+    let mut acc = _mm256_setzero_si256();
+    for c in chunks {
+        let v = _mm256_loadu_si256(c.as_ptr() as *const __m256i);
+        acc = _mm256_add_epi64(acc, popcount256(v));
+    }
+    sum256(acc) + tail
+}
+
+/// Computes the Hamming distance between `x` and `y` with AVX2, 32
+/// bytes at a time.
+///
+/// The bytes left over are counted with `popcnt`.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length.
+///
+/// # Safety
+///
+/// The CPU must support `avx2` and `popcnt`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2,popcnt")]
+pub unsafe fn distance_avx2(x: &[u8], y: &[u8]) -> u64 {
+    assert_eq!(x.len(), y.len());
+    let (x_chunks, y_chunks) = (x.chunks_exact(32), y.chunks_exact(32));
+    let tail = distance_tail(x_chunks.remainder(), y_chunks.remainder());
+    let mut acc = _mm256_setzero_si256();
+    for (a, b) in x_chunks.zip(y_chunks) {
+        let u = _mm256_loadu_si256(a.as_ptr() as *const __m256i);
+        let v = _mm256_loadu_si256(b.as_ptr() as *const __m256i);
+        acc = _mm256_add_epi64(acc, popcount256(_mm256_xor_si256(u, v)));
+    }
+    sum256(acc) + tail
+}
+
+/// Computes the Hamming weight of `x` with the AVX-512 `vpopcntq`
+/// instruction, 64 bytes at a time.
+///
+/// The bytes left over are counted with `popcnt`.
+///
+/// # Safety
+///
+/// The CPU must support `avx512f`, `avx512vpopcntdq` and `popcnt`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512f,avx512vpopcntdq,popcnt")]
+pub unsafe fn weight_avx512(x: &[u8]) -> u64 {
+    let chunks = x.chunks_exact(64);
+    let tail = weight_tail(chunks.remainder());
+    let mut acc = _mm512_setzero_si512();
+    for c in chunks {
+        let v = _mm512_loadu_si512(c.as_ptr() as *const __m512i);
+        acc = _mm512_add_epi64(acc, _mm512_popcnt_epi64(v));
+    }
+    _mm512_reduce_add_epi64(acc) as u64 + tail
+}
+
+/// Computes the Hamming distance between `x` and `y` with the AVX-512
+/// `vpopcntq` instruction, 64 bytes at a time.
+///
+/// The bytes left over are counted with `popcnt`.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length.
+///
+/// # Safety
+///
+/// The CPU must support `avx512f`, `avx512vpopcntdq` and `popcnt`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512f,avx512vpopcntdq,popcnt")]
+pub unsafe fn distance_avx512(x: &[u8], y: &[u8]) -> u64 {
+    assert_eq!(x.len(), y.len());
+    let (x_chunks, y_chunks) = (x.chunks_exact(64), y.chunks_exact(64));
+    let tail = distance_tail(x_chunks.remainder(), y_chunks.remainder());
+    let mut acc = _mm512_setzero_si512();
+    for (a, b) in x_chunks.zip(y_chunks) {
+        let u = _mm512_loadu_si512(a.as_ptr() as *const __m512i);
+        let v = _mm512_loadu_si512(b.as_ptr() as *const __m512i);
+        acc = _mm512_add_epi64(acc, _mm512_popcnt_epi64(_mm512_xor_si512(u, v)));
+    }
+    _mm512_reduce_add_epi64(acc) as u64 + tail
+}
+
+/// Computes the Hamming weight of `x` with NEON, 16 bytes at a time.
+///
+/// # Safety
+///
+/// The CPU must support `neon`. (Every AArch64 target Rust supports
+/// enables it by default, but the function is still `unsafe` for
+/// targets that don't.)
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn weight_neon(x: &[u8]) -> u64 {
+    let chunks = x.chunks_exact(16);
+    let tail = weight_tail(chunks.remainder());
+    let mut acc = vdupq_n_u64(0);
+    for c in chunks {
+        let counts = vcntq_u8(vld1q_u8(c.as_ptr()));
+        acc = vaddq_u64(acc, vpaddlq_u32(vpaddlq_u16(vpaddlq_u8(counts))));
+    }
+    vaddvq_u64(acc) + tail
+}
+
+/// Computes the Hamming distance between `x` and `y` with NEON, 16
+/// bytes at a time.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length.
+///
+/// # Safety
+///
+/// The CPU must support `neon`. (Every AArch64 target Rust supports
+/// enables it by default, but the function is still `unsafe` for
+/// targets that don't.)
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+pub unsafe fn distance_neon(x: &[u8], y: &[u8]) -> u64 {
+    assert_eq!(x.len(), y.len());
+    let (x_chunks, y_chunks) = (x.chunks_exact(16), y.chunks_exact(16));
+    let tail = distance_tail(x_chunks.remainder(), y_chunks.remainder());
+    let mut acc = vdupq_n_u64(0);
+    for (a, b) in x_chunks.zip(y_chunks) {
+        let counts = vcntq_u8(veorq_u8(vld1q_u8(a.as_ptr()), vld1q_u8(b.as_ptr())));
+        acc = vaddq_u64(acc, vpaddlq_u32(vpaddlq_u16(vpaddlq_u8(counts))));
+    }
+    vaddvq_u64(acc) + tail
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck as qc;
+    use rand;
+
+    /// The kernels this CPU supports, with their names.
+    #[allow(clippy::type_complexity)]
+    fn kernels() -> Vec<(&'static str, unsafe fn(&[u8]) -> u64, unsafe fn(&[u8], &[u8]) -> u64)> {
+        #[allow(unused_mut)]
+        let mut kernels = vec![];
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("popcnt") {
+                kernels.push(("popcnt", super::weight_popcnt as unsafe fn(&[u8]) -> u64,
+                              super::distance_popcnt as unsafe fn(&[u8], &[u8]) -> u64));
+                if is_x86_feature_detected!("avx2") {
+                    kernels.push(("avx2", super::weight_avx2, super::distance_avx2));
+                }
+                if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512vpopcntdq") {
+                    kernels.push(("avx512", super::weight_avx512, super::distance_avx512));
+                }
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if ::std::arch::is_aarch64_feature_detected!("neon") {
+                kernels.push(("neon", super::weight_neon as unsafe fn(&[u8]) -> u64,
+                              super::distance_neon as unsafe fn(&[u8], &[u8]) -> u64));
+            }
+        }
+        kernels
+    }
+
+    #[test]
+    fn kernels_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, misalign: u8) -> qc::TestResult {
+            let l = ::std::cmp::min(v.len(), w.len());
+            if misalign as usize > l {
+                return qc::TestResult::discard();
+            }
+            let (x, y) = (&v[misalign as usize..l], &w[..l - misalign as usize]);
+            for (name, weight, distance) in kernels() {
+                unsafe {
+                    assert_eq!(weight(x), ::weight(x), "{}", name);
+                    assert_eq!(distance(x, y), ::distance(x, y), "{}", name);
+                }
+            }
+            qc::TestResult::from_bool(true)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, u8) -> qc::TestResult)
+    }
+
+    #[test]
+    fn lengths() {
+        let x = (0..300).map(|i| (i * 37 + 11) as u8).collect::<Vec<u8>>();
+        let y = (0..300).map(|i| (i * 101) as u8).collect::<Vec<u8>>();
+        for len in 0..x.len() {
+            for (name, weight, distance) in kernels() {
+                unsafe {
+                    assert_eq!(weight(&x[..len]), ::weight(&x[..len]), "{} {}", name, len);
+                    assert_eq!(distance(&x[..len], &y[..len]), ::distance(&x[..len], &y[..len]),
+                               "{} {}", name, len);
+                }
+            }
+        }
+    }
+}
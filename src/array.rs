@@ -0,0 +1,180 @@
+//! Binary codes stored as the rows of `ndarray` matrices.
+//!
+//! Each row of an `ArrayView2<u8>` is one code, with bit `i` of a row
+//! being bit `i % 8` of byte `i / 8`, as everywhere else in this
+//! crate. Rows that are contiguous in memory, such as those of a
+//! standard-layout array, are passed straight to `weight` and
+//! `distance`, and other layouts are handled without copying out each
+//! row first.
+//!
+//! This requires the `ndarray` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[cfg(feature = "ndarray")] extern crate ndarray;
+//! # extern crate hamming;
+//! # #[cfg(feature = "ndarray")] fn main() {
+//! use hamming::array;
+//! use ndarray::{array, Axis};
+//!
+//! let codes = array![[0xFFu8, 0x00],
+//!                    [0x0F, 0x01],
+//!                    [0x00, 0x01]];
+//! assert_eq!(array::weight_axis(codes.view(), Axis(1)), array![8, 5, 1]);
+//! assert_eq!(array::weight_axis(codes.view(), Axis(0)), array![12, 2]);
+//!
+//! let distances = array::cross_distances(codes.view(), codes.slice(ndarray::s![..2, ..]));
+//! assert_eq!(distances, array![[0, 5], [5, 0], [9, 4]]);
+//! # }
+//! # #[cfg(not(feature = "ndarray"))] fn main() {}
+//! ```
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Axis, Zip};
+
+use matrix::check_element;
+use DistanceElement;
+
+fn weight_lane(lane: ArrayView1<u8>) -> u64 {
+    match lane.to_slice() {
+        Some(x) => ::weight(x),
+        None => ::weight_iter(lane.iter().cloned()),
+    }
+}
+
+fn distance_lanes(x: ArrayView1<u8>, y: ArrayView1<u8>) -> u64 {
+    match (x.to_slice(), y.to_slice()) {
+        (Some(x), Some(y)) => ::distance(x, y),
+        _ => ::distance_iter(x.iter().cloned().zip(y.iter().cloned())),
+    }
+}
+
+/// Computes the Hamming weights of `a` along `axis`: the weight of
+/// each row for `Axis(1)`, or of each column of bytes for `Axis(0)`.
+///
+/// Lanes that are contiguous are counted with `weight`. Otherwise,
+/// such as for the columns of a standard-layout array, the array is
+/// walked in memory order, adding the weight of every byte to the
+/// total for its lane.
+///
+/// # Panics
+///
+/// `axis` must be `Axis(0)` or `Axis(1)`.
+pub fn weight_axis(a: ArrayView2<u8>, axis: Axis) -> Array1<u64> {
+    if a.stride_of(axis) == 1 {
+        return a.map_axis(axis, weight_lane)
+    }
+    let mut out = Array1::zeros(a.len_of(Axis(1 - axis.index())));
+    for sub in a.axis_iter(axis) {
+        Zip::from(&mut out).and(&sub).for_each(|w, &b| *w += b.count_ones() as u64);
+    }
+    out
+}
+
+/// Computes the Hamming distance between each row of `a` and the
+/// corresponding row of `b`.
+///
+/// # Panics
+///
+/// `a` and `b` must have the same shape.
+pub fn row_distances(a: ArrayView2<u8>, b: ArrayView2<u8>) -> Array1<u64> {
+    assert_eq!(a.shape(), b.shape(), "arrays must have the same shape");
+    Zip::from(a.rows()).and(b.rows()).map_collect(distance_lanes)
+}
+
+/// Computes the Hamming distance between every row of `a` and every
+/// row of `b`, with element `[i, j]` of the result being the distance
+/// between row `i` of `a` and row `j` of `b`.
+///
+/// Inputs that are not in standard layout are copied into it first,
+/// so every one of the `a.nrows() * b.nrows()` distances is computed
+/// on contiguous rows.
+///
+/// # Panics
+///
+/// `a` and `b` must have the same number of columns.
+pub fn cross_distances(a: ArrayView2<u8>, b: ArrayView2<u8>) -> Array2<u64> {
+    cross_distances_as(a, b)
+}
+
+/// Computes the Hamming distance between every row of `a` and every
+/// row of `b`, like `cross_distances`, storing them as `T`s.
+///
+/// # Panics
+///
+/// `a` and `b` must have the same number of columns, and the largest
+/// possible distance between rows, `8 * a.ncols()`, must fit in a `T`,
+/// unless it is one of the quantised types.
+pub fn cross_distances_as<T: DistanceElement>(a: ArrayView2<u8>, b: ArrayView2<u8>) -> Array2<T> {
+    assert_eq!(a.ncols(), b.ncols(), "rows must have the same length");
+    let max_distance = check_element::<T>(a.ncols());
+    let (a, b) = (a.as_standard_layout(), b.as_standard_layout());
+    let mut out = Array2::from_elem((a.nrows(), b.nrows()), T::default());
+    for (mut out, x) in out.rows_mut().into_iter().zip(a.rows()) {
+        let x = x.to_slice().unwrap();
+        for (d, y) in out.iter_mut().zip(b.rows()) {
+            *d = T::from_distance(::distance(x, y.to_slice().unwrap()), max_distance);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cross_distances, cross_distances_as, row_distances, weight_axis};
+    use ndarray::{s, Array2, ArrayView2, Axis, ShapeBuilder};
+    use quickcheck as qc;
+    use rand;
+
+    // the same matrix in several layouts
+    fn layouts(v: &[u8], cols: usize, f: &mut dyn FnMut(ArrayView2<u8>) -> bool) -> bool {
+        let rows = v.len() / cols;
+        let a = Array2::from_shape_vec((rows, cols), v[..rows * cols].to_vec()).unwrap();
+        let mut fortran = Array2::zeros(a.raw_dim().f());
+        fortran.assign(&a);
+        f(a.view()) && f(fortran.view()) && f(a.slice(s![..;-1, ..;-1])) && f(a.slice(s![.., ..;2]))
+    }
+
+    #[test]
+    fn weight_axis_qc() {
+        fn prop(v: Vec<u8>, cols: usize) -> bool {
+            layouts(&v, cols % 5 + 1, &mut |a| {
+                let rows = a.rows().into_iter().map(|r| ::weight(&r.to_vec())).collect::<Vec<_>>();
+                let cols = a.columns().into_iter().map(|c| ::weight(&c.to_vec())).collect::<Vec<_>>();
+                weight_axis(a, Axis(1)).to_vec() == rows && weight_axis(a, Axis(0)).to_vec() == cols
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, usize) -> bool);
+    }
+
+    #[test]
+    fn distances_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, cols: usize) -> bool {
+            layouts(&v, cols % 5 + 1, &mut |a| {
+                let cols = a.ncols();
+                let b = Array2::from_shape_vec((w.len() / cols, cols), w[..w.len() / cols * cols].to_vec())
+                    .unwrap();
+                let cross = cross_distances(a, b.view());
+                let narrow = cross_distances_as::<u16>(a, b.view());
+                let paired = row_distances(a, a.slice(s![..;-1, ..]));
+                narrow.mapv(|d| d as u64) == cross && a.rows().into_iter().enumerate().all(|(i, x)| {
+                    let x = x.to_vec();
+                    b.rows().into_iter().enumerate()
+                        .all(|(j, y)| cross[[i, j]] == ::distance(&x, &y.to_vec())) &&
+                        paired[i] == ::distance(&x, &a.row(a.nrows() - 1 - i).to_vec())
+                })
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, usize) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn row_distances_mismatch() {
+        row_distances(Array2::zeros((2, 3)).view(), Array2::zeros((3, 3)).view());
+    }
+}
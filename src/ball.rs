@@ -0,0 +1,160 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Call `f` with every code within distance `radius` of `code`, in
+/// order of increasing distance.
+///
+/// Codes are produced by flipping bits of `code` in place, and `code`
+/// is restored to its original value before this returns. Nothing is
+/// allocated, and the recursion depth is at most `radius`.
+///
+/// The number of codes visited is `sum(binomial(8 * code.len(), d)
+/// for d in 0..=radius)`, which grows very quickly with `radius`.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut code = [0b0000_0011];
+/// let mut seen = vec![];
+/// hamming::visit_ball(&mut code, 1, |c| seen.push(c[0]));
+/// assert_eq!(seen, [0b0000_0011,
+///                   0b0000_0010, 0b0000_0001, 0b0000_0111, 0b0000_1011,
+///                   0b0001_0011, 0b0010_0011, 0b0100_0011, 0b1000_0011]);
+/// assert_eq!(code, [0b0000_0011]);
+/// ```
+pub fn visit_ball<F: FnMut(&[u8])>(code: &mut [u8], radius: u64, mut f: F) {
+    let bits = code.len() as u64 * 8;
+    for d in 0..radius.min(bits) + 1 {
+        visit_sphere(code, 0, d as usize, &mut f);
+    }
+}
+
+/// Call `f` with every code that differs from `code` in exactly
+/// `dist` bits, all at positions at least `from`.
+fn visit_sphere<F: FnMut(&[u8])>(code: &mut [u8], from: usize, dist: usize, f: &mut F) {
+    if dist == 0 {
+        f(code);
+        return
+    }
+    let bits = code.len() * 8;
+    for p in from..bits + 1 - dist {
+        code[p / 8] ^= 1 << (p % 8);
+        visit_sphere(code, p + 1, dist - 1, f);
+        code[p / 8] ^= 1 << (p % 8);
+    }
+}
+
+/// Iterate over every code within distance `radius` of `code`, in
+/// order of increasing distance.
+///
+/// This yields the same codes in the same order as `visit_ball`, but
+/// allocates each one. The iterator's own state is proportional to
+/// `code.len() + radius`.
+///
+/// # Examples
+///
+/// ```rust
+/// let ball = hamming::ball(&[0xFF, 0x00], 2).collect::<Vec<_>>();
+/// assert_eq!(ball.len(), 1 + 16 + 16 * 15 / 2);
+/// assert_eq!(ball[0], [0xFF, 0x00]);
+/// assert!(ball.iter().all(|c| hamming::distance(c, &[0xFF, 0x00]) <= 2));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn ball(code: &[u8], radius: u64) -> Ball {
+    let bits = code.len() * 8;
+    Ball {
+        code: code.to_vec(),
+        max_dist: radius.min(bits as u64) as usize,
+        positions: Some(Vec::new()),
+    }
+}
+
+/// An iterator over the codes within a given distance of a code,
+/// created by `ball`.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct Ball {
+    code: Vec<u8>,
+    max_dist: usize,
+    // the increasing bit positions flipped to create the next code,
+    // or `None` once finished.
+    positions: Option<Vec<usize>>,
+}
+
+#[cfg(feature = "alloc")]
+impl Iterator for Ball {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let bits = self.code.len() * 8;
+        let positions = self.positions.as_mut()?;
+
+        let mut result = self.code.clone();
+        for &p in positions.iter() {
+            result[p / 8] ^= 1 << (p % 8);
+        }
+
+        // step to the next combination of the same size, or else the
+        // first combination of the next size.
+        let len = positions.len();
+        match (0..len).rev().find(|&j| positions[j] < bits - (len - j)) {
+            Some(j) => {
+                positions[j] += 1;
+                for k in j + 1..len {
+                    positions[k] = positions[k - 1] + 1;
+                }
+            }
+            None if len < self.max_dist => {
+                positions.clear();
+                positions.extend(0..len + 1);
+            }
+            None => self.positions = None,
+        }
+        Some(result)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{ball, visit_ball};
+
+    fn binomial(n: u64, k: u64) -> u64 {
+        (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+    }
+
+    #[test]
+    fn sizes_and_order() {
+        let code = [0x5A, 0x3C];
+        for radius in 0..5 {
+            let mut visited = vec![];
+            let mut copy = code;
+            visit_ball(&mut copy, radius, |c| visited.push(c.to_vec()));
+            assert_eq!(copy, code);
+
+            let iterated = ball(&code, radius).collect::<Vec<_>>();
+            assert_eq!(visited, iterated);
+
+            let expected = (0..radius + 1).map(|d| binomial(16, d)).sum::<u64>();
+            assert_eq!(iterated.len() as u64, expected);
+
+            let distances = iterated.iter().map(|c| ::distance(c, &code)).collect::<Vec<_>>();
+            assert!(distances.windows(2).all(|w| w[0] <= w[1]));
+            assert_eq!(distances.last(), Some(&radius));
+
+            let mut unique = iterated.clone();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), iterated.len());
+        }
+    }
+
+    #[test]
+    fn radius_beyond_length() {
+        assert_eq!(ball(&[0x00], 100).count(), 256);
+        assert_eq!(ball(&[], 3).collect::<Vec<_>>(), vec![Vec::<u8>::new()]);
+
+        let mut count = 0;
+        visit_ball(&mut [0x00], 100, |_| count += 1);
+        assert_eq!(count, 256);
+    }
+}
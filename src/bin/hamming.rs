@@ -0,0 +1,320 @@
+//! A command-line tool for counting the set bits of files and
+//! comparing them bit by bit, built with the `cli` feature.
+//!
+//! ```text
+//! hamming weight FILE
+//! hamming distance FILE_A FILE_B
+//! hamming scan --threshold T [--threads N] QUERY CORPUS_DIR
+//! ```
+//!
+//! `weight` and `distance` stream their files, so they work on files
+//! of any size. `scan` compares `QUERY` with every file directly
+//! inside `CORPUS_DIR` on several threads, and prints the distance and
+//! path of each one of the same length within `T` bits of it, closest
+//! first.
+
+#![deny(warnings)]
+
+extern crate hamming;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+
+const USAGE: &str = "\
+usage: hamming weight FILE
+       hamming distance FILE_A FILE_B
+       hamming scan --threshold T [--threads N] QUERY CORPUS_DIR";
+
+/// The number of bytes read from each file at a time.
+const CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Help,
+    Weight(PathBuf),
+    Distance(PathBuf, PathBuf),
+    Scan { threshold: u64, threads: usize, query: PathBuf, corpus: PathBuf },
+}
+
+fn parse(args: &[String]) -> Result<Command, String> {
+    let number = |flag: &str, value: Option<&String>| {
+        let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+        value.parse::<u64>().map_err(|_| format!("invalid value for {}: {}", flag, value))
+    };
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Err("no command given".to_string()),
+    };
+    match (command, rest) {
+        ("-h", _) | ("--help", _) | ("help", _) => Ok(Command::Help),
+        ("weight", [file]) => Ok(Command::Weight(file.into())),
+        ("distance", [a, b]) => Ok(Command::Distance(a.into(), b.into())),
+        ("scan", _) => {
+            let mut threshold = None;
+            let mut threads = None;
+            let mut paths = vec![];
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--threshold" => threshold = Some(number(arg, rest.next())?),
+                    "--threads" => threads = Some(number(arg, rest.next())? as usize),
+                    _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                    _ => paths.push(PathBuf::from(arg)),
+                }
+            }
+            let threshold = threshold.ok_or("scan needs --threshold")?;
+            let threads = match threads {
+                Some(0) => return Err("--threads must be at least 1".to_string()),
+                Some(threads) => threads,
+                None => thread::available_parallelism().map_or(1, |n| n.get()),
+            };
+            match &paths[..] {
+                [query, corpus] => Ok(Command::Scan {
+                    threshold, threads, query: query.clone(), corpus: corpus.clone(),
+                }),
+                _ => Err("scan needs a query file and a corpus directory".to_string()),
+            }
+        }
+        ("weight", _) | ("distance", _) => Err(format!("wrong number of arguments to {}", command)),
+        _ => Err(format!("unknown command {}", command)),
+    }
+}
+
+/// Prefix the message of `e` with `path`.
+fn context(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// Read into `buf` until it is full or the reader is exhausted,
+/// returning the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn weight(path: &Path) -> io::Result<u64> {
+    File::open(path).and_then(hamming::weight_reader).map_err(|e| context(path, e))
+}
+
+fn distance(a: &Path, b: &Path) -> io::Result<u64> {
+    let len = |path: &Path| fs::metadata(path).map(|m| m.len()).map_err(|e| context(path, e));
+    let (a_len, b_len) = (len(a)?, len(b)?);
+    if a_len != b_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("files have different lengths ({} and {} bytes)",
+                                          a_len, b_len)))
+    }
+    let mut a_file = File::open(a).map_err(|e| context(a, e))?;
+    let mut b_file = File::open(b).map_err(|e| context(b, e))?;
+    let mut acc = hamming::DistanceAccumulator::new();
+    let (mut x, mut y) = (vec![0; CHUNK], vec![0; CHUNK]);
+    loop {
+        let n = read_full(&mut a_file, &mut x).map_err(|e| context(a, e))?;
+        let m = read_full(&mut b_file, &mut y).map_err(|e| context(b, e))?;
+        acc.update(&x[..n], &y[..m]);
+        if n < x.len() && m < y.len() {
+            break
+        }
+    }
+    acc.finish().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// The distance between `query` and the file at `path`, if it has the
+/// same length and is within `threshold` bits of it.
+///
+/// This stops reading as soon as the distance exceeds `threshold`.
+fn compare(query: &[u8], path: &Path, threshold: u64) -> io::Result<Option<u64>> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() != query.len() as u64 {
+        return Ok(None)
+    }
+    let mut buf = vec![0; CHUNK];
+    let mut count = 0;
+    let mut offset = 0;
+    loop {
+        let n = read_full(&mut file, &mut buf)?;
+        if n > query.len() - offset {
+            // the file grew since it was measured
+            return Ok(None)
+        }
+        count += hamming::distance(&buf[..n], &query[offset..offset + n]);
+        offset += n;
+        if count > threshold {
+            return Ok(None)
+        }
+        if n < buf.len() {
+            return Ok(if offset == query.len() { Some(count) } else { None })
+        }
+    }
+}
+
+/// A file found by `scan`, and its distance from the query.
+type Match = (u64, PathBuf);
+
+/// The distance and path of each file directly inside `corpus` within
+/// `threshold` bits of the file at `query`, sorted by distance then
+/// path, along with the errors from files that couldn't be read.
+fn scan(threshold: u64, threads: usize, query: &Path, corpus: &Path)
+        -> io::Result<(Vec<Match>, Vec<io::Error>)> {
+    assert!(threads > 0, "cannot scan with no threads");
+    let query = fs::read(query).map_err(|e| context(query, e))?;
+    let mut paths = vec![];
+    for entry in fs::read_dir(corpus).map_err(|e| context(corpus, e))? {
+        let path = entry.map_err(|e| context(corpus, e))?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+
+    let chunk = paths.len().div_ceil(threads).max(1);
+    let query = &query;
+    let results = thread::scope(|s| {
+        let handles = paths.chunks(chunk).map(|paths| s.spawn(move || {
+            paths.iter()
+                .filter_map(|path| match compare(query, path, threshold) {
+                    Ok(Some(d)) => Some(Ok((d, path.clone()))),
+                    Ok(None) => None,
+                    Err(e) => Some(Err(context(path, e))),
+                })
+                .collect::<Vec<_>>()
+        })).collect::<Vec<_>>();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    let mut matches = vec![];
+    let mut errors = vec![];
+    for result in results {
+        match result {
+            Ok(m) => matches.push(m),
+            Err(e) => errors.push(e),
+        }
+    }
+    matches.sort();
+    Ok((matches, errors))
+}
+
+fn run(command: Command) -> io::Result<bool> {
+    match command {
+        Command::Help => println!("{}", USAGE),
+        Command::Weight(path) => println!("{}", weight(&path)?),
+        Command::Distance(a, b) => println!("{}", distance(&a, &b)?),
+        Command::Scan { threshold, threads, query, corpus } => {
+            let (matches, errors) = scan(threshold, threads, &query, &corpus)?;
+            for (d, path) in &matches {
+                println!("{}\t{}", d, path.display());
+            }
+            for e in &errors {
+                eprintln!("hamming: {}", e);
+            }
+            return Ok(errors.is_empty())
+        }
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let command = match parse(&args) {
+        Ok(command) => command,
+        Err(msg) => {
+            eprintln!("hamming: {}\n{}", msg, USAGE);
+            process::exit(2)
+        }
+    };
+    match run(command) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("hamming: {}", e);
+            process::exit(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance, parse, scan, weight, Command};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("hamming-cli-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(parse(&args("weight a")), Ok(Command::Weight("a".into())));
+        assert_eq!(parse(&args("distance a b")), Ok(Command::Distance("a".into(), "b".into())));
+        assert_eq!(parse(&args("scan q --threads 3 dir --threshold 10")),
+                   Ok(Command::Scan { threshold: 10, threads: 3, query: "q".into(), corpus: "dir".into() }));
+        assert_eq!(parse(&args("--help")), Ok(Command::Help));
+
+        for bad in ["", "weight", "distance a", "scan q dir", "scan --threshold x q dir",
+                    "scan --threshold 1 --threads 0 q dir", "scan --threshold 1 q", "scan --threshold",
+                    "scan --bogus 1 q dir", "frobnicate"] {
+            assert!(parse(&args(bad)).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn files() {
+        let dir = temp_dir("files");
+        let big = (0..200_000).map(|i| (i * 37) as u8).collect::<Vec<u8>>();
+        let mut flipped = big.clone();
+        flipped[123_456] ^= 0b101;
+        fs::write(dir.join("a"), &big).unwrap();
+        fs::write(dir.join("b"), &flipped).unwrap();
+        fs::write(dir.join("short"), &big[1..]).unwrap();
+
+        assert_eq!(weight(&dir.join("a")).unwrap(), hamming::weight(&big));
+        assert_eq!(distance(&dir.join("a"), &dir.join("b")).unwrap(), 2);
+        assert!(distance(&dir.join("a"), &dir.join("short")).is_err());
+        assert!(weight(&dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scan_corpus() {
+        let dir = temp_dir("scan");
+        let corpus = dir.join("corpus");
+        fs::create_dir(&corpus).unwrap();
+        let query = vec![0x5A; 100_000];
+        fs::write(dir.join("query"), &query).unwrap();
+        for (name, flips) in [("same", 0), ("near", 3), ("far", 100)] {
+            let mut code = query.clone();
+            for i in 0..flips {
+                code[i * 997] ^= 1;
+            }
+            fs::write(corpus.join(name), &code).unwrap();
+        }
+        fs::write(corpus.join("short"), &query[1..]).unwrap();
+        fs::create_dir(corpus.join("subdir")).unwrap();
+
+        for threads in 1..5 {
+            let (matches, errors) = scan(10, threads, &dir.join("query"), &corpus).unwrap();
+            assert!(errors.is_empty());
+            assert_eq!(matches, [(0, corpus.join("same")), (3, corpus.join("near"))]);
+        }
+        assert!(scan(10, 1, &dir.join("missing"), &corpus).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
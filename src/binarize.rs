@@ -0,0 +1,473 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use util::SplitMix64;
+#[cfg(feature = "rand")]
+use rand_core::RngCore;
+
+/// Pack the signs of `values` into `out`, setting bit `i` (bit `i % 8`
+/// of byte `i / 8`) exactly when `values[i] > 0`.
+///
+/// Unused bits of the last byte are cleared, and NaNs become `0`
+/// bits. This is the usual way to turn a real-valued embedding into a
+/// binary code, where the Hamming distance approximates the angle
+/// between the original vectors.
+///
+/// # Panics
+///
+/// `out` must be exactly `values.len().div_ceil(8)` bytes long.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut code = [0; 2];
+/// hamming::binarize(&[0.5, -1.0, 2.0, 0.0, 1e-9, -3.0, 7.0, 1.0, -0.1], &mut code);
+/// assert_eq!(code, [0b1101_0101, 0b0]);
+/// ```
+pub fn binarize(values: &[f32], out: &mut [u8]) {
+    assert_eq!(out.len(), values.len().div_ceil(8));
+    for (byte, chunk) in out.iter_mut().zip(values.chunks(8)) {
+        *byte = chunk.iter().enumerate()
+            .fold(0, |byte, (j, &x)| byte | ((x > 0.0) as u8) << j);
+    }
+}
+
+/// Pack `values` into `out` by comparing each against its own
+/// threshold, setting bit `i` exactly when `values[i] >
+/// thresholds[i]`.
+///
+/// With the per-dimension medians of a training set (see `medians`)
+/// as thresholds, every bit is set for about half of that set, which
+/// makes the bits more informative than plain signs for embeddings
+/// that are not centred on zero.
+///
+/// # Panics
+///
+/// `thresholds` must be the same length as `values`, and `out` must
+/// be exactly `values.len().div_ceil(8)` bytes long.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut code = [0];
+/// hamming::binarize_thresholds(&[0.5, 0.5, 3.0], &[0.0, 1.0, 2.5], &mut code);
+/// assert_eq!(code, [0b101]);
+/// ```
+pub fn binarize_thresholds(values: &[f32], thresholds: &[f32], out: &mut [u8]) {
+    assert_eq!(values.len(), thresholds.len());
+    assert_eq!(out.len(), values.len().div_ceil(8));
+    for ((byte, chunk), ts) in out.iter_mut().zip(values.chunks(8)).zip(thresholds.chunks(8)) {
+        *byte = chunk.iter().zip(ts).enumerate()
+            .fold(0, |byte, (j, (&x, &t))| byte | ((x > t) as u8) << j);
+    }
+}
+
+/// Compute the median of each of the `dim` dimensions of `vectors`,
+/// stored one after another, for use with `binarize_thresholds`.
+///
+/// For an even number of vectors, this is the midpoint of the two
+/// middle values.
+///
+/// # Panics
+///
+/// `dim` must be non-zero, and `vectors` must be a non-empty whole
+/// number of vectors.
+///
+/// # Examples
+///
+/// ```rust
+/// let medians = hamming::medians(&[1.0, 10.0,
+///                                  2.0, 30.0,
+///                                  9.0, 20.0,
+///                                  3.0, 40.0], 2);
+/// assert_eq!(medians, [2.5, 25.0]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn medians(vectors: &[f32], dim: usize) -> Vec<f32> {
+    assert!(dim > 0 && !vectors.is_empty() && vectors.len().is_multiple_of(dim),
+            "{} values are not a whole number of {}-dimensional vectors", vectors.len(), dim);
+    let n = vectors.len() / dim;
+    let mut column = Vec::with_capacity(n);
+    (0..dim).map(|d| {
+        column.clear();
+        column.extend(vectors[d..].iter().step_by(dim));
+        column.sort_by(f32::total_cmp);
+        if n % 2 == 1 {
+            column[n / 2]
+        } else {
+            (column[n / 2 - 1] + column[n / 2]) / 2.0
+        }
+    }).collect()
+}
+
+/// A random rotation of `dim`-dimensional vectors, applied before
+/// `binarize` to spread variance evenly across the bits.
+///
+/// Sign binarization of a randomly rotated vector is the classic
+/// random hyperplane hash (SimHash) with orthogonal hyperplanes. This
+/// is the first step of iterative quantization without the
+/// iterations, see Gong and Lazebnik, [Iterative Quantization: A
+/// Procrustean Approach to Learning Binary
+/// Codes](https://doi.org/10.1109/CVPR.2011.5995432).
+///
+/// # Examples
+///
+/// ```rust
+/// let rotation = hamming::RandomRotation::new(4, 0x5EED);
+/// let mut rotated = [0.0; 4];
+/// rotation.rotate(&[1.0, 2.0, 3.0, 4.0], &mut rotated);
+///
+/// let mut code = [0];
+/// hamming::binarize(&rotated, &mut code);
+///
+/// // rotations preserve lengths.
+/// let norm = rotated.iter().map(|x| x * x).sum::<f32>();
+/// assert!((norm - 30.0).abs() < 1e-4);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomRotation {
+    dim: usize,
+    // row-major
+    matrix: Vec<f32>,
+}
+
+#[cfg(feature = "std")]
+impl RandomRotation {
+    /// Create a uniformly random rotation of `dim`-dimensional
+    /// vectors, chosen pseudo-randomly from `seed`.
+    ///
+    /// This orthonormalises a matrix of Gaussian samples, taking
+    /// `O(dim^3)` time.
+    pub fn new(dim: usize, seed: u64) -> RandomRotation {
+        let mut rng = SplitMix64::new(seed);
+        let mut rows = vec![0f64; dim * dim];
+        for i in 0..dim {
+            // modified Gram-Schmidt, redrawing the (vanishingly
+            // unlikely) rows that are dependent on earlier ones.
+            loop {
+                for x in &mut rows[i * dim..(i + 1) * dim] {
+                    *x = gaussian(|| rng.next_u64());
+                }
+                let (done, row) = rows.split_at_mut(i * dim);
+                let row = &mut row[..dim];
+                for prev in done.chunks(dim) {
+                    let dot = dot(prev, row);
+                    for (x, &p) in row.iter_mut().zip(prev) {
+                        *x -= dot * p;
+                    }
+                }
+                let norm = dot(row, row).sqrt();
+                if norm > 1e-6 {
+                    for x in row.iter_mut() {
+                        *x /= norm;
+                    }
+                    break
+                }
+            }
+        }
+        RandomRotation { dim, matrix: rows.into_iter().map(|x| x as f32).collect() }
+    }
+
+    /// The number of dimensions rotated.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Store the rotation of `input` into `output`.
+    ///
+    /// # Panics
+    ///
+    /// `input` and `output` must both be `self.dim()` long.
+    pub fn rotate(&self, input: &[f32], output: &mut [f32]) {
+        assert_eq!(input.len(), self.dim);
+        assert_eq!(output.len(), self.dim);
+        for (out, row) in output.iter_mut().zip(self.matrix.chunks(self.dim)) {
+            *out = row.iter().zip(input).map(|(a, b)| a * b).sum();
+        }
+    }
+}
+
+/// A seeded random-hyperplane (sign random projection) encoder, which
+/// turns `dim`-dimensional vectors into `bits`-bit codes whose Hamming
+/// distances estimate the angles between the vectors.
+///
+/// Bit `i` of a code is set when the vector is on the positive side of
+/// the `i`th hyperplane through the origin, with normals drawn from a
+/// standard Gaussian. Two vectors at angle `theta` land on different
+/// sides of each hyperplane with probability `theta / pi`, so the
+/// distance between their codes is about `bits * theta / pi`, and
+/// `cosine` inverts this: cosine similarity search becomes Hamming
+/// search, with the accuracy improving with `bits`. See Charikar,
+/// [Similarity Estimation Techniques from Rounding
+/// Algorithms](https://doi.org/10.1145/509907.509965).
+///
+/// Unlike `RandomRotation`, the number of bits is independent of the
+/// dimension, and there is no orthogonalisation, so creating an
+/// encoder takes just `O(dim * bits)` time. The hyperplanes are
+/// determined by the seed, so codes made by encoders with the same
+/// parameters are comparable, even across processes. With the `rand`
+/// feature, `from_rng` draws them from any random number generator
+/// instead.
+///
+/// # Examples
+///
+/// ```rust
+/// let encoder = hamming::RandomHyperplanes::new(3, 256, 0x5EED);
+/// let vectors = [1.0, 0.0, 0.0,
+///                1.0, 0.5, 0.0,
+///                -1.0, 0.0, 0.5];
+/// let db = encoder.encode_all(&vectors);
+///
+/// let mut query = [0; 32];
+/// encoder.encode(&[1.0, 0.05, 0.0], &mut query);
+/// let nearest = db.top_k(&query, 3);
+/// assert_eq!(nearest.iter().map(|&(i, _)| i).collect::<Vec<_>>(), [0, 1, 2]);
+/// assert!(encoder.cosine(nearest[2].1) < -0.5);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomHyperplanes {
+    dim: usize,
+    bits: usize,
+    // the normal of hyperplane `i` is row `i`
+    normals: Vec<f32>,
+}
+
+#[cfg(feature = "std")]
+impl RandomHyperplanes {
+    /// Create an encoder of `dim`-dimensional vectors into `bits`-bit
+    /// codes, with hyperplanes chosen pseudo-randomly from `seed`.
+    pub fn new(dim: usize, bits: usize, seed: u64) -> RandomHyperplanes {
+        let mut rng = SplitMix64::new(seed);
+        RandomHyperplanes::from_fn(dim, bits, || rng.next_u64())
+    }
+
+    /// Create an encoder of `dim`-dimensional vectors into `bits`-bit
+    /// codes, with hyperplanes drawn from `rng`.
+    ///
+    /// Codes are only comparable between encoders whose generators
+    /// produced the same sequence, so to share an encoder between
+    /// processes, seed `rng` deterministically (or use `new`).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate rand_core;
+    /// # extern crate rand_pcg;
+    /// # extern crate hamming;
+    /// # fn main() {
+    /// use rand_core::SeedableRng;
+    ///
+    /// let mut rng = rand_pcg::Pcg64::seed_from_u64(0x5EED);
+    /// let encoder = hamming::RandomHyperplanes::from_rng(3, 256, &mut rng);
+    /// assert_eq!(encoder.code_len(), 32);
+    /// # }
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn from_rng<R: RngCore + ?Sized>(dim: usize, bits: usize, rng: &mut R) -> RandomHyperplanes {
+        RandomHyperplanes::from_fn(dim, bits, || rng.next_u64())
+    }
+
+    fn from_fn<F: FnMut() -> u64>(dim: usize, bits: usize, mut next_u64: F) -> RandomHyperplanes {
+        let normals = (0..dim * bits).map(|_| gaussian(&mut next_u64) as f32).collect();
+        RandomHyperplanes { dim, bits, normals }
+    }
+
+    /// The number of dimensions of the vectors encoded.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// The number of bits in each code.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The length in bytes of each code, `bits().div_ceil(8)`.
+    pub fn code_len(&self) -> usize {
+        self.bits.div_ceil(8)
+    }
+
+    /// Store the code of `vector` in `out`.
+    ///
+    /// Any unused bits of the last byte are cleared, and a vector on a
+    /// hyperplane (such as the zero vector) gets a `0` bit for it.
+    ///
+    /// # Panics
+    ///
+    /// `vector` must be `self.dim()` long, and `out` must be
+    /// `self.code_len()` bytes long.
+    pub fn encode(&self, vector: &[f32], out: &mut [u8]) {
+        assert_eq!(vector.len(), self.dim, "vector has the wrong number of dimensions");
+        assert_eq!(out.len(), self.code_len(), "output length doesn't match the code length");
+        // a zero-dimensional encoder has no normals to chunk
+        if self.dim == 0 {
+            return out.iter_mut().for_each(|o| *o = 0)
+        }
+        for (byte, normals) in out.iter_mut().zip(self.normals.chunks(8 * self.dim)) {
+            *byte = normals.chunks(self.dim).enumerate().fold(0, |byte, (j, normal)| {
+                let dot = normal.iter().zip(vector).map(|(a, b)| a * b).sum::<f32>();
+                byte | ((dot > 0.0) as u8) << j
+            });
+        }
+    }
+
+    /// Encode each of the `dim`-dimensional vectors stored one after
+    /// another in `vectors`, into a packed `Database` ready for
+    /// searching.
+    ///
+    /// # Panics
+    ///
+    /// `vectors` must be a whole number of vectors.
+    pub fn encode_all(&self, vectors: &[f32]) -> ::Database {
+        let mut db = ::Database::new(self.code_len());
+        let mut code = vec![0; self.code_len()];
+        if self.dim == 0 {
+            assert!(vectors.is_empty(), "{} values are not zero-dimensional vectors", vectors.len());
+            return db
+        }
+        assert!(vectors.len().is_multiple_of(self.dim),
+                "{} values are not a whole number of {}-dimensional vectors", vectors.len(), self.dim);
+        for vector in vectors.chunks(self.dim) {
+            self.encode(vector, &mut code);
+            db.push(&code);
+        }
+        db
+    }
+
+    /// The cosine similarity estimated from the `distance` between two
+    /// codes, `cos(pi * distance / bits)`.
+    pub fn cosine(&self, distance: u64) -> f64 {
+        (::std::f64::consts::PI * distance as f64 / self.bits as f64).cos()
+    }
+}
+
+#[cfg(feature = "std")]
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// A standard normal sample from uniform random `u64`s, via the
+/// Box-Muller transform.
+#[cfg(feature = "std")]
+fn gaussian<F: FnMut() -> u64>(mut next_u64: F) -> f64 {
+    // uniform in (0, 1] and [0, 1) respectively
+    let u = ((next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+    let v = (next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    (-2.0 * u.ln()).sqrt() * (2.0 * ::std::f64::consts::PI * v).cos()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::{binarize, binarize_thresholds, medians, RandomHyperplanes, RandomRotation};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn binarize_qc() {
+        fn prop(v: Vec<i8>, t: Vec<i8>) -> bool {
+            let values = v.iter().map(|&x| x as f32 / 4.0).collect::<Vec<_>>();
+            let thresholds = (0..v.len())
+                .map(|i| t.get(i).map_or(0.0, |&x| x as f32 / 4.0))
+                .collect::<Vec<_>>();
+            let mut signs = vec![0xFF; v.len().div_ceil(8)];
+            let mut thresholded = signs.clone();
+            binarize(&values, &mut signs);
+            binarize_thresholds(&values, &thresholds, &mut thresholded);
+
+            let bit = |code: &[u8], i: usize| code[i / 8] >> (i % 8) & 1 == 1;
+            (0..v.len()).all(|i| bit(&signs, i) == (values[i] > 0.0) &&
+                                 bit(&thresholded, i) == (values[i] > thresholds[i])) &&
+                ::weight(&signs) == values.iter().filter(|&&x| x > 0.0).count() as u64
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<i8>, Vec<i8>) -> bool)
+    }
+
+    #[test]
+    fn random_hyperplanes() {
+        let encoder = RandomHyperplanes::new(4, 4096, 1);
+        assert_eq!(encoder, RandomHyperplanes::new(4, 4096, 1));
+        let vectors = [1.0, 0.0, 0.0, 0.0,
+                       1.0, 1.0, 0.0, 0.0,
+                       0.0, 0.0, 1.0, 0.0,
+                       -1.0, 0.0, 0.0, 0.0,
+                       2.0, 0.0, 0.0, 0.0];
+        let db = encoder.encode_all(&vectors);
+        assert_eq!(db.len(), 5);
+        let mut code = vec![0; encoder.code_len()];
+        encoder.encode(&vectors[4..8], &mut code);
+        assert_eq!(db.get(1), &code[..]);
+
+        let estimate = |i, j| encoder.cosine(::distance(db.get(i), db.get(j)));
+        assert!((estimate(0, 1) - 0.5f64.sqrt()).abs() < 0.05);
+        assert!(estimate(0, 2).abs() < 0.05);
+        assert_eq!(estimate(0, 3), -1.0);
+        // scaling doesn't change the code
+        assert_eq!(db.get(0), db.get(4));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn random_hyperplanes_from_rng() {
+        use rand_core::SeedableRng;
+        use rand_pcg::Pcg64;
+
+        let encoder = RandomHyperplanes::from_rng(2, 4096, &mut Pcg64::seed_from_u64(1));
+        assert_eq!(encoder, RandomHyperplanes::from_rng(2, 4096, &mut Pcg64::seed_from_u64(1)));
+        assert!(encoder != RandomHyperplanes::from_rng(2, 4096, &mut Pcg64::seed_from_u64(2)));
+
+        let db = encoder.encode_all(&[1.0, 0.0, 1.0, 1.0, 0.0, 1.0]);
+        let estimate = |i, j| encoder.cosine(::distance(db.get(i), db.get(j)));
+        assert!((estimate(0, 1) - 0.5f64.sqrt()).abs() < 0.05);
+        assert!(estimate(0, 2).abs() < 0.05);
+    }
+
+    #[test]
+    fn nan() {
+        let mut code = [0xFF];
+        binarize(&[f32::NAN, 1.0], &mut code);
+        assert_eq!(code, [0b10]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn binarize_wrong_length() {
+        binarize(&[1.0; 9], &mut [0; 1]);
+    }
+
+    #[test]
+    fn medians_balance() {
+        let vectors = (0..99).flat_map(|i| vec![i as f32, -(i as f32) * 3.0, 5.0])
+            .collect::<Vec<_>>();
+        let medians = medians(&vectors, 3);
+        assert_eq!(medians, [49.0, -147.0, 5.0]);
+
+        let mut set = [0; 3];
+        for v in vectors.chunks(3) {
+            let mut code = [0];
+            binarize_thresholds(v, &medians, &mut code);
+            for (j, s) in set.iter_mut().enumerate() {
+                *s += code[0] as usize >> j & 1;
+            }
+        }
+        assert_eq!(set, [49, 49, 0]);
+    }
+
+    #[test]
+    fn rotation_orthonormal() {
+        let dim = 7;
+        let rotation = RandomRotation::new(dim, 42);
+        assert_eq!(rotation, RandomRotation::new(dim, 42));
+        assert!(rotation != RandomRotation::new(dim, 43));
+        for (i, a) in rotation.matrix.chunks(dim).enumerate() {
+            for (j, b) in rotation.matrix.chunks(dim).enumerate() {
+                let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((dot - expected).abs() < 1e-5, "{} {} {}", i, j, dot);
+            }
+        }
+    }
+}
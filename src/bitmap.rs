@@ -0,0 +1,926 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::iter;
+
+use checked::{unwrap, ArgumentError};
+use weight_::weight_bytes;
+
+fn check_range(x: &[u8], offset: usize, len: usize) -> Result<(), ArgumentError> {
+    let bits = x.len().saturating_mul(8);
+    if offset.checked_add(len).is_some_and(|end| end <= bits) {
+        Ok(())
+    } else {
+        Err(ArgumentError::OutOfRange { offset, len, bits })
+    }
+}
+
+/// The numbering of the bits within each byte of a bitmap or code.
+///
+/// Bit `i` is always in byte `i / 8`; the order says which bit of
+/// that byte it is. This crate uses `Lsb0` throughout unless told
+/// otherwise, as Apache Arrow and most in-memory bitsets do, while
+/// network protocols and many codec formats number bits from the most
+/// significant end, with `Msb0`. Mixing the two silently reverses the
+/// bits of each byte, so the functions that take or report bit
+/// positions have a `BitOrder` parameter or an `_msb0` variant.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::BitOrder;
+///
+/// let x = [0b0000_0110u8];
+/// assert!(BitOrder::Lsb0.get(&x, 1) && !BitOrder::Lsb0.get(&x, 6));
+/// assert!(BitOrder::Msb0.get(&x, 6) && !BitOrder::Msb0.get(&x, 1));
+/// assert_eq!(BitOrder::Msb0.lsb0_position(6), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BitOrder {
+    /// Bit `i` is bit `i % 8` of its byte, counting from the least
+    /// significant bit, `1 << (i % 8)`.
+    #[default]
+    Lsb0,
+    /// Bit `i` is bit `i % 8` of its byte, counting from the most
+    /// significant bit, `0x80 >> (i % 8)`.
+    Msb0,
+}
+
+impl BitOrder {
+    /// The mask of bit `i` within its byte `i / 8`.
+    pub fn mask(self, i: usize) -> u8 {
+        match self {
+            BitOrder::Lsb0 => 1 << (i % 8),
+            BitOrder::Msb0 => 0x80 >> (i % 8),
+        }
+    }
+
+    /// Whether bit `i` of `x` is set.
+    ///
+    /// # Panics
+    ///
+    /// `i` must be less than `8 * x.len()`.
+    pub fn get(self, x: &[u8], i: usize) -> bool {
+        x[i / 8] & self.mask(i) != 0
+    }
+
+    /// The position in `Lsb0` order of bit `i` in this order, for
+    /// translating the positions used by the functions without a
+    /// `BitOrder` parameter. This is its own inverse, so it also
+    /// translates `Lsb0` positions to this order.
+    pub fn lsb0_position(self, i: usize) -> usize {
+        match self {
+            BitOrder::Lsb0 => i,
+            BitOrder::Msb0 => i ^ 7,
+        }
+    }
+
+    /// `byte` with its bits rearranged into `Lsb0` order.
+    fn to_lsb0(self, byte: u8) -> u8 {
+        match self {
+            BitOrder::Lsb0 => byte,
+            BitOrder::Msb0 => byte.reverse_bits(),
+        }
+    }
+}
+
+/// The 64 bits of `x` starting at bit `start`, in `order`, with any
+/// bits past the end of `x` zero.
+#[inline]
+fn bits_at(x: &[u8], start: usize, order: BitOrder) -> u64 {
+    let (byte, shift) = (start / 8, start % 8);
+    let x = x.get(byte..).unwrap_or(&[]);
+    let mut buf = [0u8; 9];
+    let n = x.len().min(9);
+    for (b, &x) in buf.iter_mut().zip(&x[..n]) {
+        *b = order.to_lsb0(x);
+    }
+    let low = u64::from_le_bytes(buf[..8].try_into().unwrap());
+    if shift == 0 {
+        low
+    } else {
+        low >> shift | (buf[8] as u64) << (64 - shift)
+    }
+}
+
+/// Computes the Hamming weight of the `len` bits of `x` starting at
+/// bit `offset`, where bit `i` is bit `i % 8` of byte `i / 8`.
+///
+/// This is the bit order of Apache Arrow validity bitmaps, so the
+/// null count of an array slice is `len - weight_bits(validity,
+/// offset, len)`. The whole bytes in the range are counted like
+/// `weight`, and only the partial bytes at the ends are masked. For
+/// bitmaps numbered from the most significant bit of each byte, use
+/// `weight_bits_msb0`.
+///
+/// # Panics
+///
+/// The bits must lie within `x`, that is, `offset + len <= 8 *
+/// x.len()`.
+///
+/// # Examples
+///
+/// ```rust
+/// let validity = [0b1111_0000u8, 0b0000_0011];
+/// assert_eq!(hamming::weight_bits(&validity, 0, 16), 6);
+/// assert_eq!(hamming::weight_bits(&validity, 2, 4), 2);
+/// assert_eq!(hamming::weight_bits(&validity, 6, 4), 4);
+/// ```
+pub fn weight_bits(x: &[u8], offset: usize, len: usize) -> u64 {
+    unwrap(try_weight_bits(x, offset, len))
+}
+
+/// As `weight_bits`, but returns an error if the range is out of
+/// bounds, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::try_weight_bits(&[0xFF, 0x01], 4, 6), Ok(4 + 1));
+/// assert!(hamming::try_weight_bits(&[0xFF, 0x01], 4, 13).is_err());
+/// ```
+pub fn try_weight_bits(x: &[u8], offset: usize, len: usize) -> Result<u64, ArgumentError> {
+    panic_free!("try_weight_bits", { weight_bits_in(x, offset, len, BitOrder::Lsb0) })
+}
+
+/// Computes the Hamming weight of the `len` bits of `x` starting at
+/// bit `offset`, like `weight_bits`, but with bits numbered in
+/// `BitOrder::Msb0` order, from the most significant bit of each
+/// byte.
+///
+/// # Panics
+///
+/// The bits must lie within `x`, that is, `offset + len <= 8 *
+/// x.len()`.
+///
+/// # Examples
+///
+/// ```rust
+/// let flags = [0b1111_0000u8, 0b0000_0011];
+/// assert_eq!(hamming::weight_bits_msb0(&flags, 2, 4), 2);
+/// assert_eq!(hamming::weight_bits_msb0(&flags, 6, 4), 0);
+/// assert_eq!(hamming::weight_bits_msb0(&flags, 12, 4), 2);
+/// ```
+pub fn weight_bits_msb0(x: &[u8], offset: usize, len: usize) -> u64 {
+    unwrap(try_weight_bits_msb0(x, offset, len))
+}
+
+/// As `weight_bits_msb0`, but returns an error if the range is out of
+/// bounds, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::try_weight_bits_msb0(&[0x0F, 0x80], 4, 5), Ok(4 + 1));
+/// assert!(hamming::try_weight_bits_msb0(&[0x0F, 0x80], 4, 13).is_err());
+/// ```
+pub fn try_weight_bits_msb0(x: &[u8], offset: usize, len: usize) -> Result<u64, ArgumentError> {
+    panic_free!("try_weight_bits_msb0", { weight_bits_in(x, offset, len, BitOrder::Msb0) })
+}
+
+fn weight_bits_in(x: &[u8], offset: usize, len: usize, order: BitOrder) -> Result<u64, ArgumentError> {
+    check_range(x, offset, len)?;
+    if len == 0 {
+        return Ok(0)
+    }
+    let end = offset + len;
+    let (first, last) = (offset / 8, (end - 1) / 8);
+    let head = 0xFFu8 << (offset % 8);
+    let tail = 0xFFu8 >> (7 - (end - 1) % 8);
+    // (in bounds after `check_range`)
+    let byte = |i: usize| order.to_lsb0(x.get(i).cloned().unwrap_or(0));
+    let (x_first, x_last) = (byte(first), byte(last));
+    Ok(if first == last {
+        (x_first & head & tail).count_ones() as u64
+    } else {
+        (x_first & head).count_ones() as u64 +
+            weight_bytes(x.get(first + 1..last).unwrap_or(&[])) +
+            (x_last & tail).count_ones() as u64
+    })
+}
+
+/// Computes the number of positions set in both the `len` bits of `x`
+/// starting at bit `x_offset` and the `len` bits of `y` starting at
+/// bit `y_offset`.
+///
+/// That is, this is `weight_bits` of the bitwise and of the two
+/// ranges, without materialising it. For Arrow-style bitmaps, this
+/// counts the rows of a selection that are also valid, even when the
+/// two bitmaps are sliced at different offsets. The ranges are
+/// realigned and combined 64 bits at a time. For bitmaps numbered
+/// from the most significant bit of each byte, use
+/// `weight_bits_and_msb0`.
+///
+/// # Panics
+///
+/// The bits must lie within `x` and `y` respectively.
+///
+/// # Examples
+///
+/// ```rust
+/// let selection = [0b0110_1101u8];
+/// // the same bits, shifted up by one
+/// let validity = [0b1101_1010u8, 0b0];
+/// assert_eq!(hamming::weight_bits_and(&selection, 0, &validity, 1, 8), 5);
+/// assert_eq!(hamming::weight_bits_and(&selection, 0, &validity, 0, 8), 2);
+/// ```
+pub fn weight_bits_and(x: &[u8], x_offset: usize, y: &[u8], y_offset: usize, len: usize) -> u64 {
+    unwrap(try_weight_bits_and(x, x_offset, y, y_offset, len))
+}
+
+/// As `weight_bits_and`, but returns an error if either range is out
+/// of bounds, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::try_weight_bits_and(&[0xFF], 2, &[0x0F], 0, 6), Ok(4));
+/// assert!(hamming::try_weight_bits_and(&[0xFF], 2, &[0x0F], 4, 6).is_err());
+/// ```
+pub fn try_weight_bits_and(x: &[u8], x_offset: usize, y: &[u8], y_offset: usize, len: usize)
+                           -> Result<u64, ArgumentError> {
+    panic_free!("try_weight_bits_and", {
+        weight_bits_and_in(x, x_offset, y, y_offset, len, BitOrder::Lsb0)
+    })
+}
+
+/// Computes the number of positions set in both the `len` bits of `x`
+/// starting at bit `x_offset` and the `len` bits of `y` starting at
+/// bit `y_offset`, like `weight_bits_and`, but with bits numbered in
+/// `BitOrder::Msb0` order.
+///
+/// # Panics
+///
+/// The bits must lie within `x` and `y` respectively.
+///
+/// # Examples
+///
+/// ```rust
+/// let selection = [0b1011_0110u8];
+/// // the same bits, shifted along by one
+/// let validity = [0b0101_1011u8, 0b0];
+/// assert_eq!(hamming::weight_bits_and_msb0(&selection, 0, &validity, 1, 8), 5);
+/// ```
+pub fn weight_bits_and_msb0(x: &[u8], x_offset: usize, y: &[u8], y_offset: usize, len: usize) -> u64 {
+    unwrap(try_weight_bits_and_msb0(x, x_offset, y, y_offset, len))
+}
+
+/// As `weight_bits_and_msb0`, but returns an error if either range is
+/// out of bounds, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::try_weight_bits_and_msb0(&[0xFF], 2, &[0xF0], 0, 6), Ok(4));
+/// assert!(hamming::try_weight_bits_and_msb0(&[0xFF], 2, &[0xF0], 4, 6).is_err());
+/// ```
+pub fn try_weight_bits_and_msb0(x: &[u8], x_offset: usize, y: &[u8], y_offset: usize, len: usize)
+                                -> Result<u64, ArgumentError> {
+    panic_free!("try_weight_bits_and_msb0", {
+        weight_bits_and_in(x, x_offset, y, y_offset, len, BitOrder::Msb0)
+    })
+}
+
+fn weight_bits_and_in(x: &[u8], x_offset: usize, y: &[u8], y_offset: usize, len: usize,
+                      order: BitOrder) -> Result<u64, ArgumentError> {
+    check_range(x, x_offset, len)?;
+    check_range(y, y_offset, len)?;
+    Ok(::weight_iter_u64((0..len).step_by(64).map(|k| {
+        let both = bits_at(x, x_offset + k, order) & bits_at(y, y_offset + k, order);
+        if len - k < 64 {
+            both & ((1 << (len - k)) - 1)
+        } else {
+            both
+        }
+    })))
+}
+
+/// The positions of the set bits of `byte`, in `Lsb0` order.
+fn set_bits(mut byte: u8) -> impl Iterator<Item = usize> {
+    iter::from_fn(move || {
+        if byte == 0 {
+            return None
+        }
+        let i = byte.trailing_zeros() as usize;
+        byte &= byte - 1;
+        Some(i)
+    })
+}
+
+/// The number of set bits among the first `i` bits of `x`, numbered in
+/// `order`.
+///
+/// # Panics
+///
+/// `i` must be at most `8 * x.len()`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::BitOrder;
+///
+/// let x = [0b0000_0111u8, 0xFF];
+/// assert_eq!(hamming::rank(&x, 4, BitOrder::Lsb0), 3);
+/// assert_eq!(hamming::rank(&x, 4, BitOrder::Msb0), 0);
+/// assert_eq!(hamming::rank(&x, 16, BitOrder::Msb0), 11);
+/// ```
+pub fn rank(x: &[u8], i: usize, order: BitOrder) -> u64 {
+    unwrap(try_rank(x, i, order))
+}
+
+/// As `rank`, but returns an error if `i` is out of bounds, rather
+/// than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::BitOrder;
+///
+/// assert_eq!(hamming::try_rank(&[0b0000_0111], 4, BitOrder::Lsb0), Ok(3));
+/// assert!(hamming::try_rank(&[0b0000_0111], 9, BitOrder::Lsb0).is_err());
+/// ```
+pub fn try_rank(x: &[u8], i: usize, order: BitOrder) -> Result<u64, ArgumentError> {
+    panic_free!("try_rank", { weight_bits_in(x, 0, i, order) })
+}
+
+/// The position of the set bit of `x` with `rank` set bits before
+/// it, numbered in `order`, or `None` if `x` has no more than `rank`
+/// set bits.
+///
+/// This is the inverse of `rank`: `select(x, rank(x, i, order),
+/// order) == Some(i)` for every set bit `i`. Whole bytes are skipped
+/// by their weight, so this takes time linear in the position found.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::BitOrder;
+///
+/// let x = [0b0000_0110u8, 0b1000_0000];
+/// assert_eq!(hamming::select(&x, 0, BitOrder::Lsb0), Some(1));
+/// assert_eq!(hamming::select(&x, 2, BitOrder::Lsb0), Some(15));
+/// assert_eq!(hamming::select(&x, 2, BitOrder::Msb0), Some(8));
+/// assert_eq!(hamming::select(&x, 3, BitOrder::Msb0), None);
+/// ```
+pub fn select(x: &[u8], rank: u64, order: BitOrder) -> Option<usize> {
+    let mut remaining = rank;
+    for (i, &byte) in x.iter().enumerate() {
+        let ones = byte.count_ones() as u64;
+        if remaining < ones {
+            return set_bits(order.to_lsb0(byte)).nth(remaining as usize).map(|j| 8 * i + j)
+        }
+        remaining -= ones;
+    }
+    None
+}
+
+/// The positions of the set bits of `x`, numbered in `order`, in
+/// increasing order.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::BitOrder;
+///
+/// let x = [0b0000_0101u8, 0b1000_0000];
+/// assert!(hamming::iter_ones(&x, BitOrder::Lsb0).eq(vec![0, 2, 15]));
+/// assert!(hamming::iter_ones(&x, BitOrder::Msb0).eq(vec![5, 7, 8]));
+/// ```
+pub fn iter_ones(x: &[u8], order: BitOrder) -> impl Iterator<Item = usize> + '_ {
+    x.iter().enumerate()
+        .flat_map(move |(i, &byte)| set_bits(order.to_lsb0(byte)).map(move |j| 8 * i + j))
+}
+
+/// The positions at which `x` and `y` differ, numbered in `order`, in
+/// increasing order.
+///
+/// There are `distance(x, y)` of these. This shows which bits account
+/// for the distance, such as which flags of two packets differ.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::BitOrder;
+///
+/// let (x, y) = ([0xF0u8, 0x01], [0xE0u8, 0x03]);
+/// assert!(hamming::diff_positions(&x, &y, BitOrder::Lsb0).eq(vec![4, 9]));
+/// assert!(hamming::diff_positions(&x, &y, BitOrder::Msb0).eq(vec![3, 14]));
+/// ```
+pub fn diff_positions<'a>(x: &'a [u8], y: &'a [u8], order: BitOrder)
+                          -> impl Iterator<Item = usize> + 'a
+{
+    assert_eq!(x.len(), y.len(), "cannot compare codes of {} and {} bytes", x.len(), y.len());
+    x.iter().zip(y).enumerate()
+        .flat_map(move |(i, (&a, &b))| set_bits(order.to_lsb0(a ^ b)).map(move |j| 8 * i + j))
+}
+
+/// A rectangle of a 2D bitmap: `width` columns starting at column `x`
+/// of `height` rows starting at row `y`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The number of rows of `width_bits` bits, starting every
+/// `row_stride_bytes` bytes, in `bitmap`, where the last row may be
+/// missing its padding.
+fn bitmap_rows(bitmap: &[u8], width_bits: usize, row_stride_bytes: usize) -> Result<usize, ArgumentError> {
+    let row_bytes = width_bits.div_ceil(8);
+    if row_stride_bytes == 0 || row_bytes > row_stride_bytes {
+        return Err(ArgumentError::InvalidSize { name: "row stride", value: row_stride_bytes })
+    }
+    Ok(if bitmap.len() < row_bytes {
+        0
+    } else {
+        (bitmap.len() - row_bytes) / row_stride_bytes + 1
+    })
+}
+
+/// Check that `rect` lies within a `width` by `height` bitmap.
+fn check_rect(rect: Rect, width: usize, height: usize) -> Result<(), ArgumentError> {
+    if rect.x.checked_add(rect.width).is_some_and(|end| end <= width) &&
+        rect.y.checked_add(rect.height).is_some_and(|end| end <= height) {
+        Ok(())
+    } else {
+        Err(ArgumentError::RectOutOfRange { rect, width, height })
+    }
+}
+
+/// Computes the Hamming weight of the bits inside `rect` in a packed
+/// 2D bitmap.
+///
+/// The bitmap consists of rows of `width_bits` bits, starting every
+/// `row_stride_bytes` bytes, with column `i` of a row at bit `i % 8`
+/// of its byte `i / 8`, as in `weight_bits`. The padding after the
+/// last row may be omitted. The partial bytes at the left and right
+/// edges of the rectangle are masked, and the whole bytes between
+/// them counted like `weight`, so this is suitable for the area of a
+/// mask or the occupancy of a region of a grid.
+///
+/// # Panics
+///
+/// `row_stride_bytes` must be non-zero and hold `width_bits` bits, and
+/// `rect` must lie within the columns and rows of the bitmap.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::Rect;
+///
+/// // a 10 by 3 bitmap, with rows padded to 4 bytes
+/// let bitmap = [0b1111_1111, 0b01, 0, 0,
+///               0b0000_1100, 0b10, 0, 0,
+///               0b0000_0000, 0b11];
+/// let all = Rect { x: 0, y: 0, width: 10, height: 3 };
+/// assert_eq!(hamming::weight_rect(&bitmap, 10, 4, all), 14);
+/// let rect = Rect { x: 3, y: 1, width: 7, height: 2 };
+/// assert_eq!(hamming::weight_rect(&bitmap, 10, 4, rect), 4);
+/// ```
+pub fn weight_rect(bitmap: &[u8], width_bits: usize, row_stride_bytes: usize, rect: Rect) -> u64 {
+    unwrap(try_weight_rect(bitmap, width_bits, row_stride_bytes, rect))
+}
+
+/// As `weight_rect`, but returns an error if the layout is invalid or
+/// `rect` is out of bounds, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::Rect;
+///
+/// let bitmap = [0xFF, 0x00, 0x0F];
+/// let rect = Rect { x: 2, y: 0, width: 4, height: 2 };
+/// assert_eq!(hamming::try_weight_rect(&bitmap, 8, 2, rect), Ok(4 + 2));
+/// assert!(hamming::try_weight_rect(&bitmap, 8, 2, Rect { height: 3, ..rect }).is_err());
+/// assert!(hamming::try_weight_rect(&bitmap, 9, 1, rect).is_err());
+/// ```
+pub fn try_weight_rect(bitmap: &[u8], width_bits: usize, row_stride_bytes: usize, rect: Rect)
+                       -> Result<u64, ArgumentError> {
+    panic_free!("try_weight_rect", {
+        check_rect(rect, width_bits, bitmap_rows(bitmap, width_bits, row_stride_bytes)?)?;
+        (rect.y..rect.y + rect.height)
+            .map(|row| {
+                let row = bitmap.get(row * row_stride_bytes..).unwrap_or(&[]);
+                weight_bits_in(row, rect.x, rect.width, BitOrder::Lsb0)
+            })
+            .sum()
+    })
+}
+
+/// A summed-area table of a packed 2D bitmap, for computing the
+/// Hamming weight of any rectangle of it in constant time.
+///
+/// This stores, for every row and column, the weight of the rectangle
+/// above and to the left of it, so the weight of any rectangle is the
+/// sum and difference of four entries, however large it is. Building
+/// it takes a single pass over the bitmap, so it pays for itself when
+/// many regions of the same mask or occupancy grid are queried,
+/// compared to `weight_rect` for each. It needs the `alloc` feature,
+/// and uses 8 bytes for every bit of the bitmap.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::{PopcountIntegral, Rect};
+///
+/// // a 10 by 3 bitmap, with rows padded to 4 bytes
+/// let bitmap = [0b1111_1111, 0b01, 0, 0,
+///               0b0000_1100, 0b10, 0, 0,
+///               0b0000_0000, 0b11];
+/// let integral = PopcountIntegral::new(&bitmap, 10, 4);
+/// assert_eq!((integral.width(), integral.height()), (10, 3));
+/// assert_eq!(integral.weight(Rect { x: 0, y: 0, width: 10, height: 3 }), 14);
+/// assert_eq!(integral.weight(Rect { x: 3, y: 1, width: 7, height: 2 }), 4);
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct PopcountIntegral {
+    width: usize,
+    height: usize,
+    // `sums[r * (width + 1) + c]` is the weight of the first `c`
+    // columns of the first `r` rows
+    sums: Vec<u64>,
+}
+
+#[cfg(feature = "alloc")]
+impl PopcountIntegral {
+    /// Build the table of the bitmap consisting of rows of
+    /// `width_bits` bits starting every `row_stride_bytes` bytes in
+    /// `bitmap`, laid out as for `weight_rect`.
+    ///
+    /// # Panics
+    ///
+    /// `row_stride_bytes` must be non-zero and hold `width_bits` bits.
+    pub fn new(bitmap: &[u8], width_bits: usize, row_stride_bytes: usize) -> PopcountIntegral {
+        unwrap(PopcountIntegral::try_new(bitmap, width_bits, row_stride_bytes))
+    }
+
+    /// As `new`, but returns an error if `row_stride_bytes` is zero or
+    /// can't hold `width_bits` bits, rather than panicking.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use hamming::PopcountIntegral;
+    ///
+    /// assert!(PopcountIntegral::try_new(&[0xFF, 0x01], 9, 2).is_ok());
+    /// assert!(PopcountIntegral::try_new(&[0xFF, 0x01], 9, 1).is_err());
+    /// ```
+    pub fn try_new(bitmap: &[u8], width_bits: usize, row_stride_bytes: usize)
+                   -> Result<PopcountIntegral, ArgumentError> {
+        let height = bitmap_rows(bitmap, width_bits, row_stride_bytes)?;
+        let columns = width_bits + 1;
+        let mut sums = vec![0; (height + 1) * columns];
+        for r in 0..height {
+            let row = &bitmap[r * row_stride_bytes..];
+            let (above, current) = sums[r * columns..(r + 2) * columns].split_at_mut(columns);
+            let mut row_weight = 0;
+            for c in 0..width_bits {
+                row_weight += (row[c / 8] >> (c % 8) & 1) as u64;
+                current[c + 1] = above[c + 1] + row_weight;
+            }
+        }
+        Ok(PopcountIntegral { width: width_bits, height, sums })
+    }
+
+    /// The number of columns of the bitmap.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows of the bitmap.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The Hamming weight of the bits inside `rect`.
+    ///
+    /// # Panics
+    ///
+    /// `rect` must lie within the bitmap.
+    pub fn weight(&self, rect: Rect) -> u64 {
+        unwrap(self.try_weight(rect))
+    }
+
+    /// As `weight`, but returns an error if `rect` doesn't lie within
+    /// the bitmap, rather than panicking.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use hamming::{PopcountIntegral, Rect};
+    ///
+    /// let integral = PopcountIntegral::new(&[0xFF, 0x0F], 8, 1);
+    /// assert_eq!(integral.try_weight(Rect { x: 0, y: 0, width: 6, height: 2 }), Ok(6 + 4));
+    /// assert!(integral.try_weight(Rect { x: 4, y: 0, width: 6, height: 2 }).is_err());
+    /// ```
+    pub fn try_weight(&self, rect: Rect) -> Result<u64, ArgumentError> {
+        check_rect(rect, self.width, self.height)?;
+        let columns = self.width + 1;
+        let at = |r: usize, c: usize| self.sums[r * columns + c];
+        let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+        Ok(at(bottom, right) + at(rect.y, rect.x) - at(rect.y, right) - at(bottom, rect.x))
+    }
+}
+
+/// The bitwise Hamming distance between the `len` bits of `x` starting
+/// at bit `x_offset` and the first `len` bits of `y`, or `None` if it
+/// is more than `limit`.
+fn distance_bits_within(x: &[u8], x_offset: usize, y: &[u8], len: usize, limit: u64) -> Option<u64> {
+    let mut count = 0;
+    for k in (0..len).step_by(64) {
+        let mut diff = bits_at(x, x_offset + k, BitOrder::Lsb0) ^ bits_at(y, k, BitOrder::Lsb0);
+        if len - k < 64 {
+            diff &= (1 << (len - k)) - 1;
+        }
+        count += diff.count_ones() as u64;
+        if count > limit {
+            return None
+        }
+    }
+    Some(count)
+}
+
+/// Finds the translation of a binary template that best matches an
+/// image, returning the offset `(dx, dy)` of the template's top-left
+/// corner in the image and the bitwise Hamming distance there.
+///
+/// The image and template are packed bitmaps of the given `(width,
+/// height)` dimensions, in bits and rows, with each row starting on a
+/// new byte, and column `i` of a row at bit `i % 8` of its byte `i /
+/// 8`, as for `weight_bits`. Every offset with `dx <=
+/// max_offset.0` and `dy <= max_offset.1` at which the template lies
+/// inside the image is tried, and the first (in order of `dy` then
+/// `dx`) with the smallest distance is returned. The comparison at
+/// each offset stops as soon as it is known to be worse than the best
+/// so far, so a good match found early makes the rest of the search
+/// cheap. This returns `None` if the template doesn't fit in the
+/// image.
+///
+/// # Panics
+///
+/// `image` and `template` must hold their dimensions' worth of rows.
+///
+/// # Examples
+///
+/// ```rust
+/// // a 12 by 3 image, with an L shape...
+/// let image = [0b0000_0000, 0b0000,
+///              0b0010_0000, 0b0000,
+///              0b1110_0000, 0b0000];
+/// // ...that this 3 by 2 template matches when moved by (5, 1)
+/// let template = [0b001,
+///                 0b111];
+/// assert_eq!(hamming::match_template(&image, (12, 3), &template, (3, 2), (10, 10)),
+///            Some((5, 1, 0)));
+/// // the best match with limited movement
+/// assert_eq!(hamming::match_template(&image, (12, 3), &template, (3, 2), (4, 0)),
+///            Some((3, 0, 3)));
+/// ```
+pub fn match_template(image: &[u8], image_dims: (usize, usize),
+                      template: &[u8], template_dims: (usize, usize),
+                      max_offset: (usize, usize)) -> Option<(usize, usize, u64)> {
+    unwrap(try_match_template(image, image_dims, template, template_dims, max_offset))
+}
+
+/// As `match_template`, but returns an error if `image` or `template`
+/// is too short for its dimensions, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let image = [0b0000, 0b0110];
+/// assert_eq!(hamming::try_match_template(&image, (4, 2), &[0b11], (2, 1), (4, 4)),
+///            Ok(Some((1, 1, 0))));
+/// assert!(hamming::try_match_template(&image, (4, 3), &[0b11], (2, 1), (4, 4)).is_err());
+/// ```
+pub fn try_match_template(image: &[u8], image_dims: (usize, usize),
+                          template: &[u8], template_dims: (usize, usize),
+                          max_offset: (usize, usize)) -> Result<Option<(usize, usize, u64)>, ArgumentError> {
+    let ((image_width, image_height), (width, height)) = (image_dims, template_dims);
+    let (image_stride, stride) = (image_width.div_ceil(8), width.div_ceil(8));
+    let image_len = image_stride.checked_mul(image_height).ok_or(ArgumentError::Overflow)?;
+    if image.len() < image_len {
+        return Err(ArgumentError::InvalidSize { name: "image length", value: image.len() })
+    }
+    let template_len = stride.checked_mul(height).ok_or(ArgumentError::Overflow)?;
+    if template.len() < template_len {
+        return Err(ArgumentError::InvalidSize { name: "template length", value: template.len() })
+    }
+    if width > image_width || height > image_height {
+        return Ok(None)
+    }
+
+    let mut best: Option<(usize, usize, u64)> = None;
+    for dy in 0..=max_offset.1.min(image_height - height) {
+        for dx in 0..=max_offset.0.min(image_width - width) {
+            // only strictly better matches are interesting
+            let limit = match best {
+                Some((_, _, 0)) => return Ok(best),
+                Some((_, _, d)) => d - 1,
+                None => !0,
+            };
+            let mut total = Some(0);
+            for r in 0..height {
+                total = total.and_then(|t| {
+                    let row = &image[(dy + r) * image_stride..(dy + r + 1) * image_stride];
+                    distance_bits_within(row, dx, &template[r * stride..(r + 1) * stride],
+                                         width, limit - t)
+                        .map(|d| t + d)
+                });
+                if total.is_none() {
+                    break
+                }
+            }
+            if let Some(d) = total {
+                best = Some((dx, dy, d));
+            }
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_positions, iter_ones, match_template, rank, select, weight_bits, weight_bits_and,
+                weight_bits_and_msb0, weight_bits_msb0, weight_rect, BitOrder, Rect};
+    #[cfg(feature = "alloc")]
+    use super::PopcountIntegral;
+    use quickcheck as qc;
+    use rand;
+
+    fn bit(x: &[u8], i: usize) -> bool {
+        x[i / 8] >> (i % 8) & 1 == 1
+    }
+
+    #[test]
+    fn weight_bits_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, offset: usize, len: usize) -> bool {
+            let bits = v.len().min(w.len()) * 8;
+            let offset = offset % (bits + 1);
+            let len = len % (bits - offset + 1);
+            let y_offset = bits - offset - len;
+            let naive = (0..len).filter(|&i| bit(&v, offset + i)).count() as u64;
+            let naive_and = (0..len).filter(|&i| bit(&v, offset + i) && bit(&w, y_offset + i))
+                .count() as u64;
+            weight_bits(&v, offset, len) == naive &&
+                weight_bits_and(&v, offset, &w, y_offset, len) == naive_and &&
+                weight_bits_and(&v, offset, &v, offset, len) == naive
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, usize, usize) -> bool);
+    }
+
+    #[test]
+    fn msb0_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, offset: usize, len: usize) -> bool {
+            let bits = v.len().min(w.len()) * 8;
+            let offset = offset % (bits + 1);
+            let len = len % (bits - offset + 1);
+            let y_offset = bits - offset - len;
+            let msb0 = |x: &[u8], i: usize| BitOrder::Msb0.get(x, i);
+            let naive = (0..len).filter(|&i| msb0(&v, offset + i)).count() as u64;
+            let naive_and = (0..len).filter(|&i| msb0(&v, offset + i) && msb0(&w, y_offset + i))
+                .count() as u64;
+            // reversing the bits of each byte swaps the orders
+            let reversed = v.iter().map(|b| b.reverse_bits()).collect::<Vec<_>>();
+            weight_bits_msb0(&v, offset, len) == naive &&
+                weight_bits(&reversed, offset, len) == naive &&
+                weight_bits_and_msb0(&v, offset, &w, y_offset, len) == naive_and
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, usize, usize) -> bool);
+    }
+
+    #[test]
+    fn positions_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, msb0: bool) -> bool {
+            let order = if msb0 { BitOrder::Msb0 } else { BitOrder::Lsb0 };
+            let w = v.iter().zip(w.iter().chain(std::iter::repeat(&0))).map(|(a, b)| a ^ b)
+                .collect::<Vec<_>>();
+            let ones = (0..8 * v.len()).filter(|&i| order.get(&v, i)).collect::<Vec<_>>();
+            let diffs = (0..8 * v.len()).filter(|&i| order.get(&v, i) != order.get(&w, i))
+                .collect::<Vec<_>>();
+            iter_ones(&v, order).eq(ones.iter().cloned()) &&
+                diff_positions(&v, &w, order).eq(diffs.iter().cloned()) &&
+                ones.iter().enumerate().all(|(r, &i)| {
+                    rank(&v, i, order) == r as u64 && select(&v, r as u64, order) == Some(i)
+                }) &&
+                select(&v, ones.len() as u64, order).is_none() &&
+                (0..8 * v.len()).all(|i| order.lsb0_position(i) / 8 == i / 8 &&
+                                     BitOrder::Lsb0.get(&v, order.lsb0_position(i)) == order.get(&v, i))
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, bool) -> bool);
+    }
+
+    #[test]
+    fn weight_bits_edges() {
+        let x = [0xFF; 3];
+        assert_eq!(weight_bits(&x, 24, 0), 0);
+        assert_eq!(weight_bits(&x, 7, 2), 2);
+        assert_eq!(weight_bits(&x, 1, 22), 22);
+        assert_eq!(weight_bits_and(&x, 3, &[0xFF; 16], 5, 21), 21);
+    }
+
+    #[test]
+    fn weight_rect_qc() {
+        fn prop(v: Vec<u8>, width: u8, padding: u8, rect: (usize, usize, usize, usize)) -> bool {
+            let (width, padding) = (width as usize % 40, padding as usize % 3);
+            let stride = width.div_ceil(8) + padding;
+            if stride == 0 || v.len() < width.div_ceil(8) {
+                return true
+            }
+            let rows = (v.len() - width.div_ceil(8)) / stride + 1;
+            let x = rect.0 % (width + 1);
+            let y = rect.1 % (rows + 1);
+            let rect = Rect { x, y, width: rect.2 % (width - x + 1), height: rect.3 % (rows - y + 1) };
+            let naive = (rect.y..rect.y + rect.height)
+                .flat_map(|r| (rect.x..rect.x + rect.width).map(move |c| (r, c)))
+                .filter(|&(r, c)| bit(&v[r * stride..], c))
+                .count() as u64;
+            weight_rect(&v, width, stride, rect) == naive
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, (usize, usize, usize, usize)) -> bool);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn popcount_integral_qc() {
+        fn prop(v: Vec<u8>, width: u8, padding: u8, rects: Vec<(usize, usize, usize, usize)>) -> bool {
+            let (width, padding) = (width as usize % 40, padding as usize % 3);
+            let stride = width.div_ceil(8) + padding;
+            if stride == 0 {
+                return true
+            }
+            let integral = PopcountIntegral::new(&v, width, stride);
+            let rows = integral.height();
+            rects.iter().all(|rect| {
+                let x = rect.0 % (width + 1);
+                let y = rect.1 % (rows + 1);
+                let rect = Rect { x, y, width: rect.2 % (width - x + 1), height: rect.3 % (rows - y + 1) };
+                integral.weight(rect) == weight_rect(&v, width, stride, rect)
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, Vec<(usize, usize, usize, usize)>) -> bool);
+    }
+
+    #[test]
+    fn match_template_qc() {
+        fn prop(image: Vec<u8>, width: u8, template: Vec<u8>, dims: (u8, u8),
+                max_offset: (u8, u8)) -> bool {
+            let width = width as usize % 30 + 1;
+            let height = image.len() / width.div_ceil(8);
+            let (t_width, t_height) = (dims.0 as usize % 20, dims.1 as usize % 4);
+            if template.len() < t_width.div_ceil(8) * t_height {
+                return true
+            }
+            let max_offset = (max_offset.0 as usize % 16, max_offset.1 as usize % 4);
+
+            let bits = |x: &[u8], w: usize, r: usize, c: usize| bit(&x[r * w.div_ceil(8)..], c);
+            let mut naive = None;
+            if t_width <= width && t_height <= height {
+                for dy in 0..=max_offset.1.min(height - t_height) {
+                    for dx in 0..=max_offset.0.min(width - t_width) {
+                        let d = (0..t_height).flat_map(|r| (0..t_width).map(move |c| (r, c)))
+                            .filter(|&(r, c)| bits(&image, width, dy + r, dx + c) != bits(&template, t_width, r, c))
+                            .count() as u64;
+                        if naive.is_none_or(|(_, _, best)| d < best) {
+                            naive = Some((dx, dy, d));
+                        }
+                    }
+                }
+            }
+            match_template(&image, (width, height), &template, (t_width, t_height), max_offset) == naive
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, u8, Vec<u8>, (u8, u8), (u8, u8)) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn weight_rect_out_of_range() {
+        weight_rect(&[0xFF; 8], 12, 2, Rect { x: 4, y: 2, width: 8, height: 3 });
+    }
+
+    #[test]
+    #[should_panic]
+    fn weight_bits_out_of_range() {
+        weight_bits(&[0xFF; 2], 9, 8);
+    }
+}
@@ -0,0 +1,157 @@
+//! Counts over `fixedbitset::FixedBitSet`s.
+//!
+//! These work directly on the blocks of the sets, which are counted
+//! in batches like `weight`, rather than iterating over their
+//! elements. Only the first `len()` bits of a set are ever counted,
+//! even if the unused bits of its last block have been set, such as
+//! by `FixedBitSet::with_capacity_and_blocks`.
+//!
+//! The set operations accept sets of different lengths, treating
+//! everything past the end of the shorter one as absent, as
+//! `FixedBitSet` does itself. With the `fixedbitset` feature,
+//! `FixedBitSet` also implements `HammingWeight` and
+//! `HammingDistance`, with the latter requiring equal lengths like
+//! `distance`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[cfg(feature = "fixedbitset")] extern crate fixedbitset;
+//! # extern crate hamming;
+//! # #[cfg(feature = "fixedbitset")] fn main() {
+//! use fixedbitset::FixedBitSet;
+//! use hamming::bitset;
+//!
+//! let a = FixedBitSet::with_capacity_and_blocks(100, vec![0b1011]);
+//! let b = FixedBitSet::with_capacity_and_blocks(70, vec![0b0110]);
+//! assert_eq!(bitset::weight(&a), 3);
+//! assert_eq!(bitset::intersection_count(&a, &b), 1);
+//! assert_eq!(bitset::union_count(&a, &b), 4);
+//! assert_eq!(bitset::difference_count(&a, &b), 2);
+//! assert_eq!(bitset::symmetric_difference_count(&a, &b), 3);
+//! # }
+//! # #[cfg(not(feature = "fixedbitset"))] fn main() {}
+//! ```
+
+use fixedbitset::FixedBitSet;
+
+use {HammingDistance, HammingWeight};
+
+const BITS: usize = usize::BITS as usize;
+
+/// Block `i` of `set`, with any bits past its end cleared.
+#[inline]
+fn block(set: &FixedBitSet, i: usize) -> u64 {
+    let valid = set.len().saturating_sub(i * BITS);
+    if valid == 0 {
+        return 0
+    }
+    let b = set.as_slice()[i] as u64;
+    if valid < BITS {
+        b & ((1 << valid) - 1)
+    } else {
+        b
+    }
+}
+
+fn count2<F: Fn(u64, u64) -> u64>(a: &FixedBitSet, b: &FixedBitSet, f: F) -> u64 {
+    let blocks = a.len().max(b.len()).div_ceil(BITS);
+    ::weight_iter_u64((0..blocks).map(|i| f(block(a, i), block(b, i))))
+}
+
+/// The number of elements of `set`.
+pub fn weight(set: &FixedBitSet) -> u64 {
+    ::weight_iter_u64((0..set.len().div_ceil(BITS)).map(|i| block(set, i)))
+}
+
+/// The number of elements in exactly one of `a` and `b`, that is, the
+/// Hamming distance between them.
+///
+/// Unlike `HammingDistance::hamming_distance`, the sets may have
+/// different lengths.
+pub fn distance(a: &FixedBitSet, b: &FixedBitSet) -> u64 {
+    symmetric_difference_count(a, b)
+}
+
+/// The number of elements in both `a` and `b`.
+pub fn intersection_count(a: &FixedBitSet, b: &FixedBitSet) -> u64 {
+    count2(a, b, |x, y| x & y)
+}
+
+/// The number of elements in either of `a` and `b`.
+pub fn union_count(a: &FixedBitSet, b: &FixedBitSet) -> u64 {
+    count2(a, b, |x, y| x | y)
+}
+
+/// The number of elements in `a` but not in `b`.
+pub fn difference_count(a: &FixedBitSet, b: &FixedBitSet) -> u64 {
+    count2(a, b, |x, y| x & !y)
+}
+
+/// The number of elements in exactly one of `a` and `b`.
+pub fn symmetric_difference_count(a: &FixedBitSet, b: &FixedBitSet) -> u64 {
+    count2(a, b, |x, y| x ^ y)
+}
+
+impl HammingWeight for FixedBitSet {
+    fn hamming_weight(&self) -> u64 {
+        weight(self)
+    }
+}
+
+impl HammingDistance for FixedBitSet {
+    /// # Panics
+    ///
+    /// The sets must have the same length.
+    fn hamming_distance(&self, other: &FixedBitSet) -> u64 {
+        assert_eq!(self.len(), other.len(), "sets must have the same length");
+        distance(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixedbitset::FixedBitSet;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn bitset_qc() {
+        fn prop(v: Vec<usize>, w: Vec<usize>, l: usize, m: usize) -> bool {
+            // unused bits of the last blocks are set
+            let a = FixedBitSet::with_capacity_and_blocks(l % 300, v);
+            let b = FixedBitSet::with_capacity_and_blocks(m % 300, w);
+            let c = b.clone();
+            let naive = |f: &dyn Fn(bool, bool) -> bool| {
+                (0..300).filter(|&i| f(a.contains(i), b.contains(i))).count() as u64
+            };
+            weight(&a) == naive(&|x, _| x) &&
+                a.hamming_weight() == weight(&a) &&
+                intersection_count(&a, &b) == naive(&|x, y| x && y) &&
+                union_count(&a, &b) == naive(&|x, y| x || y) &&
+                difference_count(&a, &b) == naive(&|x, y| x && !y) &&
+                symmetric_difference_count(&a, &b) == naive(&|x, y| x != y) &&
+                distance(&a, &b) == symmetric_difference_count(&a, &b) &&
+                b.hamming_distance(&c) == 0
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<usize>, Vec<usize>, usize, usize) -> bool);
+    }
+
+    #[test]
+    fn masking() {
+        let a = FixedBitSet::with_capacity_and_blocks(3, vec![!0]);
+        let b = FixedBitSet::with_capacity(3);
+        assert_eq!(weight(&a), 3);
+        assert_eq!(a.hamming_distance(&b), 3);
+        assert_eq!(union_count(&a, &FixedBitSet::with_capacity(1000)), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn length_mismatch() {
+        FixedBitSet::with_capacity(3).hamming_distance(&FixedBitSet::with_capacity(4));
+    }
+}
@@ -0,0 +1,148 @@
+use bitvec::domain::Domain;
+use bitvec::mem::BitRegister;
+use bitvec::order::BitOrder;
+use bitvec::slice::BitSlice;
+use bitvec::store::BitStore;
+
+use {HammingDistance, HammingWeight, Words};
+
+/// With the `bitvec` feature, the weight of a `BitSlice` stored in
+/// `u8`, `u16`, `u32` or `u64` elements, in any bit order.
+///
+/// The elements wholly covered by the slice are counted with `weight`,
+/// and only the partially covered ones at each end are masked.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "bitvec")] extern crate bitvec;
+/// # extern crate hamming;
+/// # #[cfg(feature = "bitvec")] fn main() {
+/// use bitvec::prelude::*;
+/// use hamming::HammingWeight;
+///
+/// let bits = bits![u8, Msb0; 1, 1, 0, 1, 0, 0, 0, 1, 1];
+/// assert_eq!(bits.hamming_weight(), 5);
+/// assert_eq!(bits[1..].hamming_weight(), 4);
+/// # }
+/// # #[cfg(not(feature = "bitvec"))] fn main() {}
+/// ```
+impl<T, O> HammingWeight for BitSlice<T, O>
+    where T: BitRegister + BitStore<Mem = T, Unalias = T> + Words, O: BitOrder
+{
+    fn hamming_weight(&self) -> u64 {
+        match self.domain() {
+            Domain::Enclave(elem) => ::weight(&[elem.load_value()]),
+            Domain::Region { head, body, tail } => {
+                head.map_or(0, |h| ::weight(&[h.load_value()])) +
+                    ::weight(body) +
+                    tail.map_or(0, |t| ::weight(&[t.load_value()]))
+            }
+        }
+    }
+}
+
+/// With the `bitvec` feature, the distance between two `BitSlice`s
+/// stored in `u8`, `u16`, `u32` or `u64` elements, in any bit order.
+///
+/// When both slices start at the same bit of their first elements,
+/// such as when they are both byte-aligned `u8` slices, the elements
+/// wholly covered by them are compared with `distance`. Otherwise,
+/// this has to compare them bit by bit, which is much slower.
+///
+/// # Panics
+///
+/// Like `distance`, this panics if the two slices have different
+/// lengths.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "bitvec")] extern crate bitvec;
+/// # extern crate hamming;
+/// # #[cfg(feature = "bitvec")] fn main() {
+/// use bitvec::prelude::*;
+/// use hamming::HammingDistance;
+///
+/// let x = &[!0u64; 2].view_bits::<Lsb0>()[..100];
+/// let y = &[0u64; 2].view_bits::<Lsb0>()[..100];
+/// assert_eq!(x.hamming_distance(y), 100);
+/// assert_eq!(x[..10].hamming_distance(&y[3..13]), 10);
+/// # }
+/// # #[cfg(not(feature = "bitvec"))] fn main() {}
+/// ```
+impl<T, O> HammingDistance for BitSlice<T, O>
+    where T: BitRegister + BitStore<Mem = T, Unalias = T> + Words, O: BitOrder
+{
+    fn hamming_distance(&self, other: &BitSlice<T, O>) -> u64 {
+        assert_eq!(self.len(), other.len(), "bit slices must have the same length");
+        let partial = |x: T, y: T| ::distance(&[x], &[y]);
+        match (self.domain(), other.domain()) {
+            (Domain::Enclave(x), Domain::Enclave(y)) if x.bounds() == y.bounds() => {
+                return partial(x.load_value(), y.load_value())
+            }
+            (Domain::Region { head: xh, body: xb, tail: xt },
+             Domain::Region { head: yh, body: yb, tail: yt })
+                if xh.as_ref().map(|h| h.bounds()) == yh.as_ref().map(|h| h.bounds()) &&
+                    xb.len() == yb.len() =>
+            {
+                // the same start and length means the same shape at the
+                // tail too.
+                let head = xh.zip(yh).map_or(0, |(x, y)| partial(x.load_value(), y.load_value()));
+                let tail = xt.zip(yt).map_or(0, |(x, y)| partial(x.load_value(), y.load_value()));
+                return head + ::distance(xb, yb) + tail
+            }
+            _ => {}
+        }
+        self.iter().by_vals().zip(other.iter().by_vals()).filter(|&(a, b)| a != b).count() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+    use bitvec::mem::BitRegister;
+    use quickcheck as qc;
+    use rand;
+    use {HammingDistance, HammingWeight};
+
+    #[test]
+    fn bitvec_qc() {
+        fn prop(v: Vec<u64>, w: Vec<u64>, start: usize, shift: usize) -> bool {
+            let l = v.len().min(w.len()) * 64;
+            // compare aligned slices, and ones shifted by `shift` bits
+            let start = start % (l + 1);
+            let shift = shift % (l - start + 1);
+            let end = l - shift;
+
+            fn check<T, O>(v: &BitSlice<T, O>, w: &BitSlice<T, O>, start: usize, shift: usize, end: usize) -> bool
+                where T: BitRegister + BitStore<Mem = T, Unalias = T> + ::Words, O: BitOrder
+            {
+                let count = |x: &BitSlice<T, O>| x.iter().filter(|b| **b).count() as u64;
+                let differ = |x: &BitSlice<T, O>, y: &BitSlice<T, O>| {
+                    x.iter().zip(y.iter()).filter(|(a, b)| **a != **b).count() as u64
+                };
+                let (x, y) = (&v[start..end], &w[start..end]);
+                let z = &w[start + shift..end + shift];
+                x.hamming_weight() == count(x) &&
+                    x.hamming_distance(y) == differ(x, y) &&
+                    x.hamming_distance(z) == differ(x, z)
+            }
+            let (vb, wb) = (v.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>(),
+                            w.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>());
+            check(v.view_bits::<Lsb0>(), w.view_bits::<Lsb0>(), start, shift, end) &&
+                check(v.view_bits::<Msb0>(), w.view_bits::<Msb0>(), start, shift, end) &&
+                check(vb.view_bits::<Lsb0>(), wb.view_bits::<Lsb0>(), start, shift, end) &&
+                check(vb.view_bits::<Msb0>(), wb.view_bits::<Msb0>(), start, shift, end)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 20))
+            .quickcheck(prop as fn(Vec<u64>, Vec<u64>, usize, usize) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bitvec_length_mismatch() {
+        bits![u8, Lsb0; 1, 0].hamming_distance(bits![u8, Lsb0; 1]);
+    }
+}
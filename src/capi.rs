@@ -0,0 +1,153 @@
+//! A C interface to the kernels in this crate.
+//!
+//! With the `capi` feature, these functions are exported unmangled
+//! with the C calling convention, so that building this crate as a
+//! `cdylib` or `staticlib`, for instance with
+//!
+//! ```text
+//! cargo rustc --release --features capi --crate-type cdylib
+//! ```
+//!
+//! gives a library that C, C++ and anything else with a C FFI can
+//! link against. They only use pointers, `size_t` and `uint64_t`, so
+//! a header can be generated with `cbindgen`, or written by hand:
+//!
+//! ```c
+//! uint64_t hamming_weight(const uint8_t *x, size_t len);
+//! uint64_t hamming_distance(const uint8_t *x, const uint8_t *y, size_t len);
+//! void hamming_scan(const uint8_t *query, const uint8_t *codes, size_t code_len,
+//!                   size_t count, uint64_t *out);
+//! void hamming_scan_u64(uint64_t query, const uint64_t *codes, size_t count,
+//!                       uint64_t *out);
+//! ```
+//!
+//! A pointer may be null when the corresponding length is zero.
+
+use core::slice;
+
+unsafe fn slice_or_empty<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
+
+unsafe fn slice_mut_or_empty<'a, T>(ptr: *mut T, len: usize) -> &'a mut [T] {
+    if len == 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(ptr, len)
+    }
+}
+
+/// Computes the Hamming weight of the `len` bytes at `x`, as
+/// `weight`.
+///
+/// # Safety
+///
+/// `x` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hamming_weight(x: *const u8, len: usize) -> u64 {
+    ::weight(slice_or_empty(x, len))
+}
+
+/// Computes the bitwise Hamming distance between the `len` bytes at
+/// `x` and at `y`, as `distance`.
+///
+/// # Safety
+///
+/// `x` and `y` must each point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hamming_distance(x: *const u8, y: *const u8, len: usize) -> u64 {
+    ::distance(slice_or_empty(x, len), slice_or_empty(y, len))
+}
+
+/// Computes the distance from the `code_len` bytes at `query` to each
+/// of the `count` codes of `code_len` bytes packed one after another
+/// at `codes`, storing the distance to code `i` in `out[i]`.
+///
+/// # Safety
+///
+/// `query` must point to `code_len` readable bytes, `codes` to
+/// `code_len * count` readable bytes, and `out` to `count` writable
+/// `uint64_t`s that don't overlap either of the inputs.
+#[no_mangle]
+pub unsafe extern "C" fn hamming_scan(query: *const u8, codes: *const u8, code_len: usize,
+                                      count: usize, out: *mut u64) {
+    let query = slice_or_empty(query, code_len);
+    let codes = slice_or_empty(codes, code_len * count);
+    let out = slice_mut_or_empty(out, count);
+    if code_len == 0 {
+        out.iter_mut().for_each(|o| *o = 0);
+        return
+    }
+    for (o, code) in out.iter_mut().zip(codes.chunks_exact(code_len)) {
+        *o = ::distance(query, code);
+    }
+}
+
+/// Computes the distance from `query` to each of the `count` 64-bit
+/// codes at `codes`, storing the distance to code `i` in `out[i]`, as
+/// `scan_u64`.
+///
+/// # Safety
+///
+/// `codes` must point to `count` readable `uint64_t`s, and `out` to
+/// `count` writable ones that don't overlap `codes`.
+#[no_mangle]
+pub unsafe extern "C" fn hamming_scan_u64(query: u64, codes: *const u64, count: usize,
+                                          out: *mut u64) {
+    ::scan_u64(query, slice_or_empty(codes, count), slice_mut_or_empty(out, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ptr;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn capi_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, code_len: u8) -> bool {
+            let code_len = code_len as usize % 8 + 1;
+            let count = w.len() / code_len;
+            let l = v.len().min(w.len());
+            if l < code_len {
+                return true
+            }
+            let mut out = vec![!0; count];
+            let words = w.iter().map(|&b| b as u64 * 0x0101_0101_0101_0101).collect::<Vec<_>>();
+            let mut out64 = vec![!0; words.len()];
+            let mut expected64 = vec![0; words.len()];
+            ::scan_u64(!0, &words, &mut expected64);
+            unsafe {
+                hamming_scan(v.as_ptr(), w.as_ptr(), code_len, count, out.as_mut_ptr());
+                hamming_scan_u64(!0, words.as_ptr(), words.len(), out64.as_mut_ptr());
+                hamming_weight(v.as_ptr(), v.len()) == ::weight(&v) &&
+                    hamming_distance(v.as_ptr(), w.as_ptr(), l) == ::distance(&v[..l], &w[..l]) &&
+                    out.iter().zip(w.chunks(code_len))
+                        .all(|(&d, code)| d == ::distance(&v[..code_len], code)) &&
+                    out64 == expected64
+            }
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, u8) -> bool);
+    }
+
+    #[test]
+    fn null_empty() {
+        unsafe {
+            assert_eq!(hamming_weight(ptr::null(), 0), 0);
+            assert_eq!(hamming_distance(ptr::null(), ptr::null(), 0), 0);
+            hamming_scan([1u8; 4].as_ptr(), ptr::null(), 4, 0, ptr::null_mut());
+            hamming_scan_u64(0, ptr::null(), 0, ptr::null_mut());
+
+            let mut out = [!0; 3];
+            hamming_scan(ptr::null(), ptr::null(), 0, 3, out.as_mut_ptr());
+            assert_eq!(out, [0; 3]);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use core::fmt;
+
+use bitmap::Rect;
+
+/// The reason a `try_` function rejected its arguments.
+///
+/// The distance and weight functions over slices and bitmaps, and the
+/// functions in `encode`, panic on invalid arguments, such as slices
+/// of different lengths or an output buffer of the wrong size. Each
+/// has a `try_` counterpart that returns one of these instead, and
+/// doesn't panic at all (unless a closure passed to it does). The
+/// panicking versions are just the `try_` ones with the error
+/// unwrapped, so the two always agree on what is valid.
+///
+/// With the `no-panic` feature, an optimised build fails to link if
+/// the compiler can't prove that `weight` and the `try_` functions
+/// over slices of bytes and words have no path that panics, whatever
+/// their inputs, with an error naming the function. Proving it needs
+/// the whole crate in one codegen unit, so CI runs the tests as
+/// `CARGO_PROFILE_RELEASE_CODEGEN_UNITS=1 cargo test --release
+/// --features no-panic`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::ArgumentError;
+///
+/// assert_eq!(hamming::try_distance(&[1u8, 2], &[3, 4]), Ok(1 + 2));
+/// assert_eq!(hamming::try_distance(&[1u8, 2], &[3]),
+///            Err(ArgumentError::LengthMismatch { x_len: 2, y_len: 1 }));
+///
+/// let mut out = [0; 2];
+/// assert_eq!(hamming::try_scan_u64(0, &[1, 3, 7], &mut out),
+///            Err(ArgumentError::OutputLength { len: 2, expected: 3 }));
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[non_exhaustive]
+pub enum ArgumentError {
+    /// Two inputs that must have the same length have different
+    /// lengths, in elements, or in rows for the functions over 2D
+    /// buffers.
+    LengthMismatch {
+        /// The length of the first input.
+        x_len: usize,
+        /// The length of the second input.
+        y_len: usize,
+    },
+    /// An output buffer has the wrong length for the inputs.
+    OutputLength {
+        /// The length of the output.
+        len: usize,
+        /// The length the inputs need.
+        expected: usize,
+    },
+    /// A size, such as a code length, stride or number of bits, is
+    /// zero or otherwise invalid.
+    InvalidSize {
+        /// What the size is of.
+        name: &'static str,
+        /// The size given.
+        value: usize,
+    },
+    /// The length of an input isn't a whole number of codes.
+    Ragged {
+        /// The length of the input, in bytes.
+        len: usize,
+        /// The length of each code, in bytes.
+        code_len: usize,
+    },
+    /// A range of bits extends past the end of a bitmap.
+    OutOfRange {
+        /// The first bit of the range.
+        offset: usize,
+        /// The number of bits in the range.
+        len: usize,
+        /// The number of bits in the bitmap.
+        bits: usize,
+    },
+    /// A rectangle extends past the edge of a 2D bitmap.
+    RectOutOfRange {
+        /// The rectangle.
+        rect: Rect,
+        /// The width of the bitmap, in bits.
+        width: usize,
+        /// The number of rows of the bitmap.
+        height: usize,
+    },
+    /// A value is too large to be encoded.
+    ValueTooLarge {
+        /// The position of the value in the input.
+        index: usize,
+        /// The value.
+        value: u64,
+        /// The largest value that can be encoded.
+        max: u64,
+    },
+    /// A range of values to quantize is empty or not a number.
+    EmptyRange,
+    /// The sizes of the inputs overflow a `usize`.
+    Overflow,
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArgumentError::LengthMismatch { x_len, y_len } => {
+                write!(f, "inputs have different lengths ({} and {})", x_len, y_len)
+            }
+            ArgumentError::OutputLength { len, expected } => {
+                write!(f, "output has length {}, but the inputs need {}", len, expected)
+            }
+            ArgumentError::InvalidSize { name, value } => write!(f, "invalid {} {}", name, value),
+            ArgumentError::Ragged { len, code_len } => {
+                write!(f, "{} bytes are not a whole number of {}-byte codes", len, code_len)
+            }
+            ArgumentError::OutOfRange { offset, len, bits } => {
+                write!(f, "{} bits at offset {} are out of range for {} bits", len, offset, bits)
+            }
+            ArgumentError::RectOutOfRange { rect, width, height } => {
+                write!(f, "{:?} is out of range for a {} by {} bitmap", rect, width, height)
+            }
+            ArgumentError::ValueTooLarge { index, value, max } => {
+                write!(f, "value {} at index {} is larger than the maximum {}", value, index, max)
+            }
+            ArgumentError::EmptyRange => write!(f, "empty range"),
+            ArgumentError::Overflow => write!(f, "sizes overflow a usize"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for ArgumentError {}
+
+/// The value of a `try_` function, for its panicking counterpart.
+#[inline]
+#[track_caller]
+pub(crate) fn unwrap<T>(result: Result<T, ArgumentError>) -> T {
+    match result {
+        Ok(x) => x,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Check that two inputs have the same length.
+#[inline]
+pub(crate) fn same_length(x_len: usize, y_len: usize) -> Result<(), ArgumentError> {
+    if x_len == y_len { Ok(()) } else { Err(ArgumentError::LengthMismatch { x_len, y_len }) }
+}
+
+/// Check that an output has the length `expected`.
+#[inline]
+pub(crate) fn output_length(len: usize, expected: usize) -> Result<(), ArgumentError> {
+    if len == expected { Ok(()) } else { Err(ArgumentError::OutputLength { len, expected }) }
+}
+
+/// Check that `len` bytes are a whole number of `code_len`-byte
+/// codes, and that `out_len` is the number of columns, one per bit.
+#[inline]
+pub(crate) fn check_matrix(len: usize, code_len: usize, out_len: usize) -> Result<(), ArgumentError> {
+    if code_len == 0 {
+        return Err(ArgumentError::InvalidSize { name: "code length", value: 0 })
+    }
+    if !len.is_multiple_of(code_len) {
+        return Err(ArgumentError::Ragged { len, code_len })
+    }
+    output_length(out_len, code_len.checked_mul(8).ok_or(ArgumentError::Overflow)?)
+}
+
+/// Evaluate `$body`, and with the `no-panic` feature, fail to link
+/// an optimised build unless the compiler can prove that `$body`
+/// doesn't unwind, in the manner of the `no-panic` crate.
+///
+/// This works by holding a guard whose destructor, which would run if
+/// `$body` unwound, calls a function that doesn't exist, and is
+/// forgotten afterwards. If the compiler can't remove the call, the
+/// link fails with a message naming `$name`. It only applies to
+/// functions compiled in this crate (generic ones are compiled in the
+/// crate that uses them), and only with optimisations, which are
+/// needed to prove anything.
+macro_rules! panic_free {
+    ($name: expr, $body: block) => {{
+        #[cfg(all(feature = "no-panic", not(debug_assertions)))]
+        let guard = {
+            struct Guard;
+            impl Drop for Guard {
+                #[inline(always)]
+                fn drop(&mut self) {
+                    extern "C" {
+                        #[link_name = concat!("\n\nERROR[hamming]: `", $name, "` may panic\n")]
+                        fn may_panic() -> !;
+                    }
+                    unsafe { may_panic() }
+                }
+            }
+            Guard
+        };
+        #[allow(clippy::redundant_closure_call)]
+        let result = (|| $body)();
+        #[cfg(all(feature = "no-panic", not(debug_assertions)))]
+        ::core::mem::forget(guard);
+        result
+    }}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArgumentError;
+
+    // With `--release --features no-panic`, this fails to link unless
+    // every function here is free of panics.
+    #[test]
+    fn errors() {
+        let mut out = [0; 8];
+        assert_eq!(::weight(&[1u8, 3]) + ::weight(&[1u16]) + ::weight(&[1u32]) + ::weight(&[1u64]) +
+                   ::weight(&[1u128]), 7);
+        assert_eq!(::try_distance(&[1u8, 2], &[3]), Err(ArgumentError::LengthMismatch { x_len: 2, y_len: 1 }));
+        assert_eq!(::try_distance(&[1u16, 2], &[3, 0]), Ok(2));
+        assert_eq!(::try_distance(&[1u32, 2], &[3, 0]), Ok(2));
+        assert_eq!(::try_distance(&[1u64, 2], &[3, 0]), Ok(2));
+        assert_eq!(::try_distance(&[1u128, 2], &[3]), Err(ArgumentError::LengthMismatch { x_len: 2, y_len: 1 }));
+        assert_eq!(::try_scan_u64(0, &[1, 3], &mut out), Err(ArgumentError::OutputLength { len: 8, expected: 2 }));
+        assert_eq!(::try_distance_pairs(&[(&[1], &[])], &mut out[..1]),
+                   Err(ArgumentError::LengthMismatch { x_len: 1, y_len: 0 }));
+        assert_eq!(::try_distance_rows(&[1, 2, 3], &[1, 2], 1, 0),
+                   Err(ArgumentError::InvalidSize { name: "row stride", value: 0 }));
+        assert_eq!(::try_distance_rows_each(&[1, 2, 3], &[1, 2, 3], 1, 2, &mut out),
+                   Err(ArgumentError::OutputLength { len: 8, expected: 2 }));
+        assert_eq!(::try_column_distances(&[1, 2, 3], &[1, 2, 3], 2, &mut out),
+                   Err(ArgumentError::Ragged { len: 3, code_len: 2 }));
+        assert_eq!(::try_column_distances(&[1, 2, 3], &[1, 2, 3], usize::MAX, &mut out),
+                   Err(ArgumentError::Ragged { len: 3, code_len: usize::MAX }));
+        assert_eq!(::try_column_distances(&[], &[], usize::MAX, &mut out), Err(ArgumentError::Overflow));
+        assert_eq!(::try_column_weights(&[1, 2, 3], 1, &mut out), Ok(()));
+        assert_eq!(::try_weight_strided(&[1, 2, 3], 0, 0), Err(ArgumentError::InvalidSize { name: "stride", value: 0 }));
+        assert_eq!(::try_weight_bits(&[1, 2], 9, 8), Err(ArgumentError::OutOfRange { offset: 9, len: 8, bits: 16 }));
+        assert_eq!(::try_weight_bits_msb0(&[1, 2], usize::MAX, 2),
+                   Err(ArgumentError::OutOfRange { offset: usize::MAX, len: 2, bits: 16 }));
+        assert_eq!(::try_weight_bits_and(&[1], 0, &[1, 2], 9, 8),
+                   Err(ArgumentError::OutOfRange { offset: 9, len: 8, bits: 16 }));
+        assert_eq!(::try_weight_bits_and_msb0(&[1], 1, &[1, 2], 9, 7), Ok(0));
+        assert_eq!(::try_rank(&[1, 2], 17, ::BitOrder::Lsb0), Err(ArgumentError::OutOfRange { offset: 0, len: 17, bits: 16 }));
+        let rect = ::Rect { x: 4, y: 2, width: 8, height: 3 };
+        assert_eq!(::try_weight_rect(&[0xFF; 8], 12, 2, rect),
+                   Err(ArgumentError::RectOutOfRange { rect, width: 12, height: 4 }));
+    }
+}
@@ -0,0 +1,682 @@
+//! Clustering of binary codes.
+//!
+//! This provides:
+//!
+//! - k-majority clustering (`KMajority`), the analogue of k-means for
+//!   Hamming space: codes are assigned to their nearest centroid, and
+//!   each centroid is replaced by the bitwise majority vote of its
+//!   members. See Grana et al., [A Fast Approach for Integrating ORB
+//!   Descriptors in the Bag of Words
+//!   Model](https://doi.org/10.1117/12.2008460).
+//! - batch nearest-centroid assignment (`assign_to_centroids`), the
+//!   inner loop of k-majority, for vector quantisation with existing
+//!   centroids.
+//! - hierarchical agglomerative clustering (`agglomerate`), which
+//!   repeatedly merges the two closest clusters, recording each merge
+//!   to form a dendrogram.
+//! - minimum spanning trees (`mst`), from which single-linkage
+//!   clusterings and the connected components at any threshold
+//!   follow.
+
+use alloc::vec::Vec;
+
+use Database;
+use group::UnionFind;
+use util::SplitMix64;
+
+/// How the initial centroids are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Seeding {
+    /// Use `k` distinct codes chosen uniformly at random.
+    Random,
+    /// Use a random code, and then repeatedly the code furthest from
+    /// all centroids chosen so far.
+    MaxMin,
+}
+
+/// The parameters of a k-majority clustering.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::cluster::{KMajority, Seeding};
+///
+/// let db = hamming::Database::from_packed(2, &[
+///     0x00, 0x00,  0x01, 0x00,  0x00, 0x80,
+///     0xFF, 0xFF,  0xFE, 0xFF,  0xFF, 0x7F,
+/// ]);
+/// let clustering = KMajority::new(2).seeding(Seeding::MaxMin).seed(1).fit(&db);
+/// assert!(clustering.converged);
+///
+/// let labels = &clustering.labels;
+/// assert!(labels[0] == labels[1] && labels[1] == labels[2]);
+/// assert!(labels[3] == labels[4] && labels[4] == labels[5]);
+/// assert!(labels[0] != labels[3]);
+/// assert_eq!(clustering.centroids.get(labels[3]), &[0xFF, 0xFF]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KMajority {
+    k: usize,
+    max_iterations: usize,
+    seeding: Seeding,
+    seed: u64,
+}
+
+/// The result of a clustering.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Clustering {
+    /// The centroid of each cluster.
+    pub centroids: Database,
+    /// The index of the cluster (and centroid) of each code.
+    pub labels: Vec<usize>,
+    /// The number of assignment/update rounds performed.
+    pub iterations: usize,
+    /// Whether the last round changed no assignments.
+    pub converged: bool,
+}
+
+impl KMajority {
+    /// Parameters for finding `k` clusters, with maxmin seeding, a
+    /// fixed seed of 0, and at most 100 iterations.
+    pub fn new(k: usize) -> KMajority {
+        KMajority { k, max_iterations: 100, seeding: Seeding::MaxMin, seed: 0 }
+    }
+
+    /// Set how the initial centroids are chosen.
+    pub fn seeding(mut self, seeding: Seeding) -> KMajority {
+        self.seeding = seeding;
+        self
+    }
+
+    /// Set the seed for the random choices during seeding.
+    pub fn seed(mut self, seed: u64) -> KMajority {
+        self.seed = seed;
+        self
+    }
+
+    /// Set the maximum number of assignment/update rounds.
+    pub fn max_iterations(mut self, max_iterations: usize) -> KMajority {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Cluster the codes in `db`.
+    ///
+    /// Each round assigns every code to its nearest centroid (ties
+    /// going to the lower index), and then sets each bit of each
+    /// centroid to the majority value of that bit among the cluster's
+    /// members. Bits with a tied vote, and clusters with no members,
+    /// keep their previous value.
+    ///
+    /// # Panics
+    ///
+    /// `k` must be at least 1 and at most `db.len()`.
+    pub fn fit(&self, db: &Database) -> Clustering {
+        let n = db.len();
+        assert!(self.k >= 1 && self.k <= n,
+                "cannot find {} clusters in {} codes", self.k, n);
+        let mut centroids = self.initial_centroids(db);
+        let mut labels = vec![usize::MAX; n];
+
+        let mut iterations = 0;
+        let mut converged = false;
+        while iterations < self.max_iterations && !converged {
+            iterations += 1;
+            converged = assign(db, &centroids, &mut labels);
+            centroids = update(db, &centroids, &labels);
+        }
+        Clustering { centroids, labels, iterations, converged }
+    }
+
+    fn initial_centroids(&self, db: &Database) -> Database {
+        let n = db.len();
+        let mut rng = SplitMix64::new(self.seed);
+        let mut chosen = Vec::with_capacity(self.k);
+        match self.seeding {
+            Seeding::Random => {
+                let mut indices = (0..n).collect::<Vec<_>>();
+                for i in 0..self.k {
+                    let j = i + rng.below(n - i);
+                    indices.swap(i, j);
+                }
+                chosen.extend_from_slice(&indices[..self.k]);
+            }
+            Seeding::MaxMin => {
+                chosen.push(rng.below(n));
+                let mut nearest = vec![0; n];
+                db.scan(db.get(chosen[0]), &mut nearest);
+                let mut column = vec![0; n];
+                while chosen.len() < self.k {
+                    let next = (0..n).max_by_key(|&i| (nearest[i], !i)).unwrap();
+                    chosen.push(next);
+                    db.scan(db.get(next), &mut column);
+                    for (near, &d) in nearest.iter_mut().zip(&column) {
+                        *near = (*near).min(d);
+                    }
+                }
+            }
+        }
+
+        let mut centroids = Database::padded(db.code_len());
+        for &i in &chosen {
+            centroids.push(db.get(i));
+        }
+        centroids
+    }
+}
+
+/// Assign each code to its nearest centroid, returning whether no
+/// label changed.
+fn assign(db: &Database, centroids: &Database, labels: &mut [usize]) -> bool {
+    let previous = labels.to_vec();
+    let mut distances = vec![0; db.len()];
+    assign_to_centroids(db, centroids, labels, &mut distances);
+    previous == labels
+}
+
+/// The number of codes assigned together, sharing each load of a
+/// centroid.
+const ASSIGN_TILE: usize = 64;
+
+/// Assign each code in `codes` to its nearest centroid, storing the
+/// index of the centroid in `out_labels` and the distance to it in
+/// `out_distances`, with ties broken by the lower index.
+///
+/// This is the assignment step of k-majority (or any vector
+/// quantisation), done far faster than comparing every code with every
+/// centroid. The codes are processed in tiles, so that each centroid
+/// is loaded once for a whole tile, and most comparisons are skipped:
+/// a centroid cannot be closer than the best so far if the triangle
+/// inequality, via the distances between the centroids, or the cached
+/// weights rule it out, and the rest are abandoned as soon as they are
+/// no better, as in `Database::top_k`.
+///
+/// # Panics
+///
+/// There must be at least one centroid if there are any codes, the
+/// codes and centroids must have the same length, and the outputs
+/// must have an element for each code.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::cluster::assign_to_centroids;
+/// use hamming::Database;
+///
+/// let codes = Database::from_packed(1, &[0x01, 0xFE, 0x0F, 0x00]);
+/// let centroids = Database::from_packed(1, &[0x00, 0xFF]);
+/// let (mut labels, mut distances) = ([0; 4], [0; 4]);
+/// assign_to_centroids(&codes, &centroids, &mut labels, &mut distances);
+/// assert_eq!(labels, [0, 1, 0, 0]);
+/// assert_eq!(distances, [1, 1, 4, 0]);
+/// ```
+pub fn assign_to_centroids(codes: &Database, centroids: &Database,
+                           out_labels: &mut [usize], out_distances: &mut [u64]) {
+    let (n, k) = (codes.len(), centroids.len());
+    assert!(k > 0 || n == 0, "cannot assign {} codes to no centroids", n);
+    assert_eq!(codes.code_len(), centroids.code_len(), "codes and centroids have different lengths");
+    assert_eq!(out_labels.len(), n, "labels length doesn't match the number of codes");
+    assert_eq!(out_distances.len(), n, "distances length doesn't match the number of codes");
+
+    // the distances between the centroids, to bound the distance to
+    // one given the distance to another
+    let mut between = vec![0; k * k];
+    for (c, row) in between.chunks_mut(k.max(1)).enumerate() {
+        centroids.scan(centroids.get(c), row);
+    }
+
+    for start in (0..n).step_by(ASSIGN_TILE) {
+        let end = (start + ASSIGN_TILE).min(n);
+        let (labels, distances) = (&mut out_labels[start..end], &mut out_distances[start..end]);
+        for (i, (label, distance)) in labels.iter_mut().zip(&mut *distances).enumerate() {
+            *label = 0;
+            *distance = ::distance(codes.get(start + i), centroids.get(0));
+        }
+        for c in 1..k {
+            let centroid_weight = centroids.weight(c);
+            for (i, (label, distance)) in labels.iter_mut().zip(&mut *distances).enumerate() {
+                // d(x, c) >= d(label, c) - d(x, label), and only a
+                // strictly smaller distance wins
+                let best = *distance;
+                if best == 0 || between[*label * k + c] >= 2 * best ||
+                    codes.weight(start + i).abs_diff(centroid_weight) >= best {
+                    continue
+                }
+                if let Some(d) = centroids.distance_bounded(codes.get(start + i), c, best - 1) {
+                    *label = c;
+                    *distance = d;
+                }
+            }
+        }
+    }
+}
+
+/// Compute the bitwise majority of each cluster.
+fn update(db: &Database, centroids: &Database, labels: &[usize]) -> Database {
+    let code_len = db.code_len();
+    let k = centroids.len();
+    let mut counts = vec![0usize; k * code_len * 8];
+    let mut sizes = vec![0usize; k];
+    for (i, &label) in labels.iter().enumerate() {
+        sizes[label] += 1;
+        let counts = &mut counts[label * code_len * 8..(label + 1) * code_len * 8];
+        for (byte, counts) in db.get(i).iter().zip(counts.chunks_mut(8)) {
+            for (bit, count) in counts.iter_mut().enumerate() {
+                *count += (byte >> bit) as usize & 1;
+            }
+        }
+    }
+
+    let mut updated = Database::padded(code_len);
+    let mut code = vec![0; code_len];
+    for c in 0..k {
+        code.copy_from_slice(centroids.get(c));
+        let counts = &counts[c * code_len * 8..(c + 1) * code_len * 8];
+        for (byte, counts) in code.iter_mut().zip(counts.chunks(8)) {
+            for (bit, &count) in counts.iter().enumerate() {
+                if 2 * count > sizes[c] {
+                    *byte |= 1 << bit;
+                } else if 2 * count < sizes[c] {
+                    *byte &= !(1 << bit);
+                }
+            }
+        }
+        updated.push(&code);
+    }
+    updated
+}
+
+/// How the distance between two clusters is derived from the
+/// distances between their codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Linkage {
+    /// The distance between the closest pair of codes.
+    Single,
+    /// The distance between the furthest pair of codes.
+    Complete,
+    /// The mean distance over all pairs of codes (UPGMA).
+    Average,
+}
+
+/// One step of an agglomerative clustering.
+///
+/// Clusters are numbered like SciPy's linkage matrices: the `n`
+/// original codes are clusters `0..n`, and the cluster formed by the
+/// `m`th merge is `n + m`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Merge {
+    /// The smaller of the two merged clusters.
+    pub left: usize,
+    /// The larger of the two merged clusters.
+    pub right: usize,
+    /// The linkage distance between the two clusters.
+    pub distance: f64,
+    /// The number of codes in the merged cluster.
+    pub size: usize,
+}
+
+/// Cluster the codes in `db` hierarchically, returning the `db.len() -
+/// 1` merges in order of increasing distance.
+///
+/// This computes every pairwise distance, and so uses quadratic
+/// memory; see `agglomerate_condensed`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::cluster::{agglomerate, Linkage};
+///
+/// let db = hamming::Database::from_packed(1, &[0x00, 0xFF, 0x01, 0x03]);
+/// let merges = agglomerate(&db, Linkage::Complete);
+/// assert_eq!(merges.iter().map(|m| (m.left, m.right, m.distance)).collect::<Vec<_>>(),
+///            vec![(0, 2, 1.0), (3, 4, 2.0), (1, 5, 8.0)]);
+/// ```
+pub fn agglomerate(db: &Database, linkage: Linkage) -> Vec<Merge> {
+    agglomerate_condensed(db.len(), ::pdist(db).as_slice(), linkage)
+}
+
+/// Cluster `n` codes hierarchically given their pairwise distances,
+/// returning the `n - 1` merges in order of increasing distance.
+///
+/// `distances` is a condensed distance matrix, as in SciPy and
+/// `CondensedMatrix`: the distance between codes `i < j` is at index
+/// `n * i - i * (i + 1) / 2 + j - i - 1`. Merges are found with the nearest-neighbour chain
+/// algorithm, in `O(n^2)` time, with ties broken towards lower
+/// indices.
+///
+/// # Panics
+///
+/// `distances` must have length `n * (n - 1) / 2`.
+pub fn agglomerate_condensed(n: usize, distances: &[u64], linkage: Linkage) -> Vec<Merge> {
+    assert_eq!(distances.len(), n * n.saturating_sub(1) / 2,
+               "condensed matrix for {} codes has the wrong length", n);
+    let index = |i: usize, j: usize| {
+        let (i, j) = if i < j { (i, j) } else { (j, i) };
+        n * i - i * (i + 1) / 2 + j - i - 1
+    };
+    let mut distances = distances.iter().map(|&d| d as f64).collect::<Vec<_>>();
+    let mut sizes = vec![1; n];
+    let mut active = vec![true; n];
+
+    // each merge is recorded by a code from each side, since cluster
+    // numbers depend on the final order.
+    let mut found = Vec::with_capacity(n.saturating_sub(1));
+    let mut chain: Vec<usize> = Vec::new();
+    while found.len() + 1 < n {
+        if chain.is_empty() {
+            chain.push(active.iter().position(|&a| a).unwrap());
+        }
+        let a = chain[chain.len() - 1];
+        let previous = if chain.len() >= 2 { Some(chain[chain.len() - 2]) } else { None };
+
+        // the nearest neighbour of `a`, preferring the previous link
+        // of the chain so that reciprocal neighbours are detected.
+        let mut best = previous;
+        let mut best_distance = previous.map_or(f64::INFINITY, |p| distances[index(a, p)]);
+        for b in 0..n {
+            if active[b] && b != a && distances[index(a, b)] < best_distance {
+                best = Some(b);
+                best_distance = distances[index(a, b)];
+            }
+        }
+        let b = best.unwrap();
+
+        if Some(b) != previous {
+            chain.push(b);
+            continue
+        }
+        chain.truncate(chain.len() - 2);
+        found.push((a, b, best_distance));
+
+        // the merged cluster lives in slot `a`.
+        let (size_a, size_b) = (sizes[a] as f64, sizes[b] as f64);
+        for k in 0..n {
+            if active[k] && k != a && k != b {
+                let (da, db) = (distances[index(a, k)], distances[index(b, k)]);
+                distances[index(a, k)] = match linkage {
+                    Linkage::Single => da.min(db),
+                    Linkage::Complete => da.max(db),
+                    Linkage::Average => (size_a * da + size_b * db) / (size_a + size_b),
+                };
+            }
+        }
+        sizes[a] += sizes[b];
+        active[b] = false;
+    }
+
+    found.sort_by(|x, y| x.2.partial_cmp(&y.2).unwrap());
+    let mut sets = UnionFind::new(n);
+    let mut ids = (0..n).collect::<Vec<_>>();
+    let mut sizes = vec![1; n];
+    found.into_iter().enumerate().map(|(m, (a, b, distance))| {
+        let (a, b) = (sets.find(a), sets.find(b));
+        let (left, right) = (ids[a].min(ids[b]), ids[a].max(ids[b]));
+        let size = sizes[a] + sizes[b];
+        sets.union(a, b);
+        let root = sets.find(a);
+        ids[root] = n + m;
+        sizes[root] = size;
+        Merge { left, right, distance, size }
+    }).collect()
+}
+
+/// Compute a minimum spanning tree of the complete graph on the codes
+/// in `db`, weighted by their distances, returning its `db.len() - 1`
+/// edges `(u, v, distance)` (or none for an empty database).
+///
+/// This is Prim's algorithm: the edges are in the order they were
+/// added, with `u` already in the tree and `v` the code joining it,
+/// starting from code 0. Each step scans the database once with
+/// `Database::scan` from the code just added, to update the distance
+/// from each remaining code to the tree, so this takes `O(n^2)`
+/// distance computations but only `O(n)` memory, unlike `agglomerate`,
+/// which stores every pairwise distance. Ties are broken towards the
+/// lowest `v`, and then the earliest `u`.
+///
+/// Sorting the edges by distance gives the merges of single-linkage
+/// clustering, and deleting the edges longer than a threshold leaves
+/// the groups of codes connected by chains of shorter links (as found
+/// by `group_within`).
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0xFF, 0x01, 0x7F]);
+/// let tree = hamming::cluster::mst(&db);
+/// assert_eq!(tree, vec![(0, 2, 1), (2, 3, 6), (3, 1, 1)]);
+/// ```
+pub fn mst(db: &Database) -> Vec<(usize, usize, u64)> {
+    let n = db.len();
+    let mut edges = Vec::with_capacity(n.saturating_sub(1));
+    if n == 0 {
+        return edges
+    }
+    // for each code not yet in the tree, its distance to the tree and
+    // the code in the tree at that distance
+    let mut nearest = vec![(u64::MAX, 0); n];
+    let mut in_tree = vec![false; n];
+    let mut row = vec![0; n];
+    let mut added = 0;
+    in_tree[0] = true;
+    for _ in 1..n {
+        db.scan(db.get(added), &mut row);
+        let mut next = None;
+        for v in 0..n {
+            if in_tree[v] {
+                continue
+            }
+            if row[v] < nearest[v].0 {
+                nearest[v] = (row[v], added);
+            }
+            if next.is_none_or(|next: usize| nearest[v].0 < nearest[next].0) {
+                next = Some(v);
+            }
+        }
+        let v = next.unwrap();
+        let (distance, u) = nearest[v];
+        edges.push((u, v, distance));
+        in_tree[v] = true;
+        added = v;
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KMajority, Seeding, assign_to_centroids, update};
+    use super::{agglomerate, agglomerate_condensed, mst, Linkage};
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn majority_update() {
+        let db = Database::from_packed(1, [0b0011, 0b0101, 0b0110, 0xF0, 0x0F]);
+        let centroids = Database::from_packed(1, [0xFF, 0x00, 0xAA]);
+        let updated = update(&db, &centroids, &[0, 0, 0, 1, 1]);
+        // cluster 0 has a strict majority for bits 0, 1 and 2; cluster
+        // 1 is tied everywhere; cluster 2 is empty.
+        assert_eq!(updated.as_bytes()[..1], [0b0111]);
+        assert_eq!(updated.get(1), &[0x00]);
+        assert_eq!(updated.get(2), &[0xAA]);
+    }
+
+    #[test]
+    fn assign_to_centroids_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            let codes = Database::from_packed(2, &v[..v.len() / 2 * 2]);
+            let centroids = Database::from_packed(2, &w[..w.len() / 2 * 2]);
+            if centroids.is_empty() {
+                return true
+            }
+            let mut labels = vec![!0; codes.len()];
+            let mut distances = vec![!0; codes.len()];
+            assign_to_centroids(&codes, &centroids, &mut labels, &mut distances);
+            (0..codes.len()).all(|i| {
+                let nearest = centroids.top_k(codes.get(i), 1)[0];
+                (labels[i], distances[i]) == nearest
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 500))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn separates_clusters() {
+        let mut db = Database::new(4);
+        for i in 0..60u32 {
+            let base: u32 = [0, 0xFFFF_0000, 0x0000_FFFF][i as usize % 3];
+            let noise = 1 << (i * 7 % 32);
+            db.push((base ^ noise).to_le_bytes());
+        }
+        for &seeding in &[Seeding::Random, Seeding::MaxMin] {
+            let clustering = KMajority::new(3).seeding(seeding).seed(3).fit(&db);
+            assert!(clustering.converged);
+            for (i, &label) in clustering.labels.iter().enumerate() {
+                assert_eq!(label, clustering.labels[i % 3]);
+            }
+            let mut centroids = (0..3).map(|c| clustering.centroids.get(c).to_vec()).collect::<Vec<_>>();
+            centroids.sort();
+            assert_eq!(centroids, [[0x00, 0x00, 0x00, 0x00],
+                                   [0x00, 0x00, 0xFF, 0xFF],
+                                   [0xFF, 0xFF, 0x00, 0x00]]);
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let db = Database::from_packed(1, (0..=255).collect::<Vec<u8>>());
+        let a = KMajority::new(5).seeding(Seeding::Random).seed(7).fit(&db);
+        let b = KMajority::new(5).seeding(Seeding::Random).seed(7).fit(&db);
+        assert_eq!(a, b);
+        assert!(a.iterations <= 100);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_many_clusters() {
+        KMajority::new(3).fit(&Database::from_packed(1, [1, 2]));
+    }
+
+    /// Merge the closest pair of clusters by brute force, returning
+    /// each merge distance.
+    fn naive_heights(db: &Database, linkage: Linkage) -> Vec<f64> {
+        let mut clusters = (0..db.len()).map(|i| vec![i]).collect::<Vec<_>>();
+        let mut heights = Vec::new();
+        while clusters.len() > 1 {
+            let mut best = (f64::INFINITY, 0, 0);
+            for x in 0..clusters.len() {
+                for y in x + 1..clusters.len() {
+                    let pairs = clusters[x].iter()
+                        .flat_map(|&i| clusters[y].iter().map(move |&j| (i, j)))
+                        .map(|(i, j)| ::distance(db.get(i), db.get(j)) as f64)
+                        .collect::<Vec<_>>();
+                    let d = match linkage {
+                        Linkage::Single => pairs.iter().cloned().fold(f64::INFINITY, f64::min),
+                        Linkage::Complete => pairs.iter().cloned().fold(0.0, f64::max),
+                        Linkage::Average => pairs.iter().sum::<f64>() / pairs.len() as f64,
+                    };
+                    if d < best.0 {
+                        best = (d, x, y);
+                    }
+                }
+            }
+            let (d, x, y) = best;
+            let merged = clusters.remove(y);
+            clusters[x].extend(merged);
+            heights.push(d);
+        }
+        heights
+    }
+
+    #[test]
+    fn agglomerate_qc() {
+        fn prop(v: Vec<u8>, linkage: u8) -> bool {
+            let linkage = [Linkage::Single, Linkage::Complete, Linkage::Average][linkage as usize % 3];
+            let db = Database::from_packed(1, &v[..v.len().min(12)]);
+            let n = db.len();
+            let merges = agglomerate(&db, linkage);
+
+            // a well-formed dendrogram...
+            let mut sizes = vec![1; n];
+            let mut used = vec![false; 2 * n];
+            let mut ok = merges.len() == n.saturating_sub(1);
+            for m in &merges {
+                ok &= m.left < m.right && m.right < sizes.len() &&
+                    !used[m.left] && !used[m.right] &&
+                    m.size == sizes[m.left] + sizes[m.right];
+                if !ok {
+                    return false
+                }
+                used[m.left] = true;
+                used[m.right] = true;
+                sizes.push(m.size);
+            }
+            ok &= merges.windows(2).all(|w| w[0].distance <= w[1].distance);
+
+            // ...with the same heights as merging the closest pair every
+            // time, which is unambiguous for single linkage.
+            if linkage == Linkage::Single {
+                let heights = merges.iter().map(|m| m.distance).collect::<Vec<_>>();
+                ok &= heights == naive_heights(&db, linkage);
+            }
+            ok
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+
+    #[test]
+    fn mst_qc() {
+        fn prop(v: Vec<u8>) -> bool {
+            let db = Database::from_packed(1, &v);
+            let n = db.len();
+            let tree = mst(&db);
+
+            // the edges join every code to code 0, one at a time...
+            let mut joined = vec![false; n];
+            if n > 0 {
+                joined[0] = true;
+            }
+            let spanning = tree.len() == n.saturating_sub(1) && tree.iter().all(|&(u, v, d)| {
+                let ok = joined[u] && !joined[v] && d == ::distance(db.get(u), db.get(v));
+                joined[v] = true;
+                ok
+            });
+
+            // ...with the sorted distances of single-linkage merges
+            let mut heights = tree.iter().map(|&(_, _, d)| d as f64).collect::<Vec<_>>();
+            heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            spanning && heights == naive_heights(&db, Linkage::Single)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 30))
+            .quickcheck(prop as fn(Vec<u8>) -> bool)
+    }
+
+    #[test]
+    fn linkages() {
+        // distinct distances, so every linkage is unambiguous.
+        let db = Database::from_packed(2, [
+            0x00, 0x00,  0x01, 0x00,  0x07, 0x00,  0xFF, 0x00,  0xFF, 0xFF,
+        ]);
+        for &linkage in &[Linkage::Single, Linkage::Complete, Linkage::Average] {
+            let heights = agglomerate(&db, linkage).iter().map(|m| m.distance).collect::<Vec<_>>();
+            assert_eq!(heights, naive_heights(&db, linkage));
+        }
+        let average = agglomerate(&db, Linkage::Average);
+        assert_eq!(average.iter().map(|m| (m.left, m.right, m.size)).collect::<Vec<_>>(),
+                   vec![(0, 1, 2), (2, 5, 3), (3, 6, 4), (4, 7, 5)]);
+    }
+
+    #[test]
+    fn condensed() {
+        assert_eq!(agglomerate_condensed(0, &[], Linkage::Single), vec![]);
+        assert_eq!(agglomerate_condensed(1, &[], Linkage::Single), vec![]);
+        let merges = agglomerate_condensed(3, &[5, 2, 4], Linkage::Average);
+        assert_eq!(merges.iter().map(|m| (m.left, m.right, m.distance)).collect::<Vec<_>>(),
+                   vec![(0, 2, 2.0), (1, 3, 4.5)]);
+    }
+}
@@ -0,0 +1,196 @@
+use core::{fmt, str};
+
+/// A 256-bit perceptual hash, such as a PDQ hash of an image or a
+/// TMK+PDQF hash of a video frame.
+///
+/// This is a fixed 32 bytes, so it is `Copy` and can be a `HashMap`
+/// key or stored inline, and comparisons use the unrolled
+/// `distance32`. Two codes are usually considered to be of the same
+/// content if their distance is at most some threshold (31 is the
+/// common choice for PDQ), which `is_match` checks; having a
+/// dedicated type avoids accidentally comparing codes from different
+/// hash functions, or truncated codes, as raw slices would allow.
+///
+/// Codes are written and parsed as 64 hexadecimal digits, two for
+/// each byte, in order. Parsing accepts either case, and formatting
+/// with `{}` or `{:x}` produces lower case.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::Code256;
+///
+/// let a: Code256 = "f8f8f0cee0f4a84f06370a22038f63f0b36e2ed596621e1d33e6b39c4e9c9b22"
+///     .parse()?;
+/// let b = Code256::from_hex("f8f8f0cee0f4a84f06370a22038f63f0b36e2ed596621e1d33e6b39c4e9c9b2d")?;
+/// assert_eq!(a.distance(&b), 4);
+/// assert!(a.is_match(&b, 31));
+/// assert!(!a.is_match(&Code256::new([0; 32]), 31));
+/// assert_eq!(b.to_string(), "f8f8f0cee0f4a84f06370a22038f63f0b36e2ed596621e1d33e6b39c4e9c9b2d");
+/// # Ok::<(), hamming::ParseCodeError>(())
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Code256([u8; 32]);
+
+impl Code256 {
+    /// Create a code with the given bytes.
+    pub const fn new(bytes: [u8; 32]) -> Code256 {
+        Code256(bytes)
+    }
+
+    /// The bytes of this code.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Parse a code from 64 hexadecimal digits.
+    pub fn from_hex(s: &str) -> Result<Code256, ParseCodeError> {
+        if s.len() != 64 {
+            return Err(ParseCodeError::InvalidLength { len: s.len() })
+        }
+        let digit = |i: usize| {
+            let c = s.as_bytes()[i];
+            (c as char).to_digit(16).map(|d| d as u8).ok_or(ParseCodeError::InvalidDigit { index: i })
+        };
+        let mut bytes = [0; 32];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = digit(2 * i)? << 4 | digit(2 * i + 1)?;
+        }
+        Ok(Code256(bytes))
+    }
+
+    /// The number of bits in which this code and `other` differ.
+    #[inline]
+    pub fn distance(&self, other: &Code256) -> u64 {
+        ::distance32(&self.0, &other.0)
+    }
+
+    /// Whether this code and `other` differ in at most `threshold`
+    /// bits.
+    #[inline]
+    pub fn is_match(&self, other: &Code256, threshold: u64) -> bool {
+        self.distance(other) <= threshold
+    }
+}
+
+impl From<[u8; 32]> for Code256 {
+    fn from(bytes: [u8; 32]) -> Code256 {
+        Code256(bytes)
+    }
+}
+
+impl From<Code256> for [u8; 32] {
+    fn from(code: Code256) -> [u8; 32] {
+        code.0
+    }
+}
+
+impl AsRef<[u8]> for Code256 {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl str::FromStr for Code256 {
+    type Err = ParseCodeError;
+
+    fn from_str(s: &str) -> Result<Code256, ParseCodeError> {
+        Code256::from_hex(s)
+    }
+}
+
+impl fmt::LowerHex for Code256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Code256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl fmt::Debug for Code256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Code256({:x})", self)
+    }
+}
+
+/// The reason a string could not be parsed as a `Code256`.
+#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Hash, Clone, Copy)]
+#[non_exhaustive]
+pub enum ParseCodeError {
+    /// The string isn't 64 bytes long.
+    InvalidLength {
+        /// The length of the string, in bytes.
+        len: usize,
+    },
+    /// The string has a byte that isn't a hexadecimal digit.
+    InvalidDigit {
+        /// The index of the byte.
+        index: usize,
+    },
+}
+
+impl fmt::Display for ParseCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseCodeError::InvalidLength { len } => {
+                write!(f, "expected 64 hexadecimal digits, found {} bytes", len)
+            }
+            ParseCodeError::InvalidDigit { index } => {
+                write!(f, "invalid hexadecimal digit at byte {}", index)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for ParseCodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Code256, ParseCodeError};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn hex_round_trip_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            if v.len() < 32 || w.len() < 32 {
+                return true
+            }
+            let mut bytes = [0; 32];
+            bytes.copy_from_slice(&v[..32]);
+            let a = Code256::new(bytes);
+            bytes.copy_from_slice(&w[..32]);
+            let b = Code256::from(bytes);
+            let upper = format!("{}", a).to_uppercase();
+            upper.parse() == Ok(a) &&
+                a.distance(&b) == ::distance(&v[..32], &w[..32]) &&
+                a.is_match(&b, a.distance(&b)) &&
+                (a.distance(&b) == 0 || !a.is_match(&b, a.distance(&b) - 1))
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("".parse::<Code256>(), Err(ParseCodeError::InvalidLength { len: 0 }));
+        let mut s = "0".repeat(64);
+        assert_eq!(s.parse::<Code256>(), Ok(Code256::default()));
+        s.push('0');
+        assert_eq!(Code256::from_hex(&s), Err(ParseCodeError::InvalidLength { len: 65 }));
+        s.replace_range(10..12, "0g");
+        assert_eq!(Code256::from_hex(&s[..64]), Err(ParseCodeError::InvalidDigit { index: 11 }));
+        // multibyte characters are never digits
+        s.replace_range(10..12, "é");
+        assert_eq!(Code256::from_hex(&s[..64]), Err(ParseCodeError::InvalidDigit { index: 10 }));
+    }
+}
@@ -0,0 +1,1034 @@
+use alloc::vec::Vec;
+use core::slice;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::thread;
+
+use {AsQuery, TopK};
+#[cfg(feature = "std")]
+use persist::{self, Kind};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The number of bytes compared in the first stage of a cascaded
+/// comparison.
+const PREFIX_LEN: usize = 16;
+/// The minimum code length for which comparisons are cascaded.
+const CASCADE_MIN_LEN: usize = 4 * PREFIX_LEN;
+
+/// A collection of equal-width codes, stored contiguously with the
+/// weight of each code cached.
+///
+/// Codes are stored in a single 8-byte aligned buffer. A database
+/// created with `Database::padded` additionally rounds the stride
+/// between codes up to a multiple of 8 bytes, so that every code
+/// starts on an 8-byte boundary, which guarantees that
+/// `distance_fast` never has to fall back for differing alignments
+/// between codes.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut db = hamming::Database::new(2);
+/// db.push(&[0x00, 0x00]);
+/// db.push(&[0xFF, 0x00]);
+/// db.push(&[0x0F, 0x01]);
+///
+/// assert_eq!(db.len(), 3);
+/// assert_eq!(db.weight(1), 8);
+///
+/// assert_eq!(db.top_k(&[0x0F, 0x00], 2), vec![(2, 1), (0, 4)]);
+/// assert_eq!(db.within(&[0x00, 0x00], 5), vec![(0, 0), (2, 5)]);
+/// ```
+///
+/// # Serialization
+///
+/// With the `serde` feature, a `Database` serializes as its code
+/// length, stride and code bytes. The weights are recomputed, and the
+/// layout validated, when deserializing. The indexes are likewise
+/// checked, and their hash tables rebuilt, so that a corrupted index
+/// is rejected.
+///
+/// With the `std` feature, `save` and `load` also store a database in
+/// a compact binary file format, with a version number, so that
+/// future releases can read it, and a checksum, so that a damaged
+/// file is rejected rather than giving wrong answers. The indexes
+/// have `save` and `load` methods using the same format.
+///
+/// # Concurrency
+///
+/// A `Database` is `Send` and `Sync`, and its searches only read it,
+/// so one database can be queried from many threads at once; wrap it
+/// in a `Shared` handle to share it between threads without copying.
+/// The same goes for `MmapDatabase` and all the indexes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "RawDatabase", try_from = "RawDatabase"))]
+pub struct Database {
+    code_len: usize,
+    stride: usize,
+    len: usize,
+    words: Vec<u64>,
+    weights: Vec<u64>,
+}
+
+impl Database {
+    /// Create an empty database of codes of `code_len` bytes, packed
+    /// with no padding between them.
+    pub fn new(code_len: usize) -> Database {
+        Database::with_stride(code_len, code_len)
+    }
+
+    /// Create an empty database of codes of `code_len` bytes, with
+    /// each code padded so that it starts on an 8-byte boundary.
+    pub fn padded(code_len: usize) -> Database {
+        Database::with_stride(code_len, code_len.div_ceil(8) * 8)
+    }
+
+    fn with_stride(code_len: usize, stride: usize) -> Database {
+        Database {
+            code_len,
+            stride,
+            len: 0,
+            words: Vec::new(),
+            weights: Vec::new(),
+        }
+    }
+
+    /// Create a packed database from `bytes`, which is interpreted as
+    /// consecutive codes of `code_len` bytes each.
+    ///
+    /// # Panics
+    ///
+    /// `bytes.len()` must be a multiple of `code_len`, and `code_len`
+    /// must be non-zero unless `bytes` is empty.
+    pub fn from_packed<B: AsRef<[u8]>>(code_len: usize, bytes: B) -> Database {
+        let bytes = bytes.as_ref();
+        let mut db = Database::new(code_len);
+        if bytes.is_empty() {
+            return db
+        }
+        assert!(code_len > 0 && bytes.len().is_multiple_of(code_len),
+                "byte length {} is not a multiple of the code length {}",
+                bytes.len(), code_len);
+        for code in bytes.chunks(code_len) {
+            db.push(code);
+        }
+        db
+    }
+
+    /// Create a packed database from `bytes`, like `from_packed`,
+    /// computing the weights of the codes with `threads` threads.
+    ///
+    /// With the `std` feature, the bytes are copied into the database
+    /// in one go, and the codes are split evenly between the threads
+    /// to compute their weights. The result is exactly that of
+    /// `from_packed`.
+    ///
+    /// # Panics
+    ///
+    /// As for `from_packed`, and `threads` must be non-zero.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let bytes = (0..10_000u32).map(|i| (i * 37) as u8).collect::<Vec<_>>();
+    /// let db = hamming::Database::from_packed_parallel(5, &bytes, 4);
+    /// assert_eq!(db, hamming::Database::from_packed(5, &bytes));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_packed_parallel<B: AsRef<[u8]>>(code_len: usize, bytes: B, threads: usize) -> Database {
+        assert!(threads > 0, "cannot build a database with no threads");
+        let bytes = bytes.as_ref();
+        let mut db = Database::new(code_len);
+        if bytes.is_empty() {
+            return db
+        }
+        assert!(code_len > 0 && bytes.len().is_multiple_of(code_len),
+                "byte length {} is not a multiple of the code length {}",
+                bytes.len(), code_len);
+        db.len = bytes.len() / code_len;
+        db.words.resize(bytes.len().div_ceil(8), 0);
+        db.bytes_mut().copy_from_slice(bytes);
+        db.weights.resize(db.len, 0);
+        let chunk = db.len.div_ceil(threads);
+        thread::scope(|scope| {
+            for (weights, codes) in db.weights.chunks_mut(chunk).zip(bytes.chunks(chunk * code_len)) {
+                scope.spawn(move || {
+                    for (weight, code) in weights.iter_mut().zip(codes.chunks(code_len)) {
+                        *weight = ::weight(code);
+                    }
+                });
+            }
+        });
+        db
+    }
+
+    /// Create a packed database from `codes`, each of which is
+    /// `code_len` bytes long.
+    ///
+    /// The codes can be anything that can be viewed as bytes, such as
+    /// `Vec<u8>`, arrays, or `bytes::Bytes`.
+    ///
+    /// # Panics
+    ///
+    /// Every code must be exactly `code_len` bytes long.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let codes = vec![vec![0x00, 0xFF], vec![0x0F, 0x0F]];
+    /// let db = hamming::Database::from_codes(2, &codes);
+    /// assert_eq!(db.get(1), [0x0F, 0x0F]);
+    /// assert_eq!(db.top_k(&codes[0], 1), [(0, 0)]);
+    /// ```
+    pub fn from_codes<I>(code_len: usize, codes: I) -> Database
+        where I: IntoIterator, I::Item: AsRef<[u8]>
+    {
+        let mut db = Database::new(code_len);
+        db.extend(codes);
+        db
+    }
+
+    /// Add `code` to the end of the database.
+    ///
+    /// # Panics
+    ///
+    /// `code` must be exactly `self.code_len()` bytes long.
+    pub fn push<C: AsRef<[u8]>>(&mut self, code: C) {
+        let code = code.as_ref();
+        assert_eq!(code.len(), self.code_len);
+        let start = self.len * self.stride;
+        let total = start + self.stride;
+        let words = total.div_ceil(8);
+        if words > self.words.len() {
+            self.words.resize(words, 0);
+        }
+        self.len += 1;
+        self.bytes_mut()[start..start + code.len()].copy_from_slice(code);
+        self.weights.push(::weight(code));
+    }
+
+    /// Add every code of `other` to the end of the database, in order,
+    /// so that code `i` of `other` becomes code `self.len() + i`.
+    ///
+    /// This copies the codes and their weights in one go when the two
+    /// databases have the same stride, rather than pushing them one by
+    /// one.
+    ///
+    /// # Panics
+    ///
+    /// `other` must have the same code length.
+    pub fn append(&mut self, other: &Database) {
+        assert!(self.code_len == other.code_len,
+                "cannot append codes of {} bytes to codes of {} bytes",
+                other.code_len, self.code_len);
+        if self.stride != other.stride {
+            return self.extend((0..other.len).map(|i| other.get(i)))
+        }
+        let start = self.len * self.stride;
+        let words = (start + other.len * self.stride).div_ceil(8);
+        if words > self.words.len() {
+            self.words.resize(words, 0);
+        }
+        self.len += other.len;
+        self.bytes_mut()[start..].copy_from_slice(other.as_bytes());
+        self.weights.extend_from_slice(&other.weights);
+    }
+
+    /// A database of the codes of `self` followed by those of `other`,
+    /// with the layout of `self`, as built by `append`.
+    ///
+    /// # Panics
+    ///
+    /// `other` must have the same code length.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let a = hamming::Database::from_packed(1, &[0x00, 0x0F]);
+    /// let b = hamming::Database::from_packed(1, &[0xFF]);
+    /// assert_eq!(a.merge(&b), hamming::Database::from_packed(1, &[0x00, 0x0F, 0xFF]));
+    /// ```
+    pub fn merge(&self, other: &Database) -> Database {
+        let mut db = self.clone();
+        db.append(other);
+        db
+    }
+
+    /// The number of codes stored.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no codes stored.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The length in bytes of each code.
+    pub fn code_len(&self) -> usize {
+        self.code_len
+    }
+
+    /// The distance in bytes between the starts of consecutive codes
+    /// in `as_bytes`.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The code at index `i`.
+    ///
+    /// # Panics
+    ///
+    /// `i` must be less than `self.len()`.
+    pub fn get(&self, i: usize) -> &[u8] {
+        assert!(i < self.len);
+        let start = i * self.stride;
+        &self.as_bytes()[start..start + self.code_len]
+    }
+
+    /// The cached weight of the code at index `i`.
+    ///
+    /// # Panics
+    ///
+    /// `i` must be less than `self.len()`.
+    pub fn weight(&self, i: usize) -> u64 {
+        self.weights[i]
+    }
+
+    /// The cached weights of every code, in order.
+    pub fn weights(&self) -> &[u64] {
+        &self.weights
+    }
+
+    /// The approximate heap memory used by the database, in bytes.
+    ///
+    /// This counts the capacity of the buffers, which may be more than
+    /// the codes and weights need.
+    pub fn memory_usage(&self) -> usize {
+        (self.words.capacity() + self.weights.capacity()) * 8
+    }
+
+    /// The underlying storage, including any padding between codes
+    /// (which is always zero).
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self.words.as_ptr() as *const u8,
+                                  self.len * self.stride)
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(self.words.as_mut_ptr() as *mut u8,
+                                      self.len * self.stride)
+        }
+    }
+
+    /// A borrowed view of the codes, which implements the searches.
+    pub(crate) fn codes(&self) -> Codes<'_> {
+        Codes {
+            bytes: self.as_bytes(),
+            code_len: self.code_len,
+            stride: self.stride,
+            weights: &self.weights,
+        }
+    }
+
+    /// Compute the distance from `query` to every code, storing the
+    /// distance to code `i` in `out[i]`.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.code_len()` bytes long, and `out` must
+    /// be `self.len()` elements long.
+    pub fn scan<Q: AsQuery>(&self, query: Q, out: &mut [u64]) {
+        self.codes().scan(query.query_bytes(), out)
+    }
+
+    pub(crate) fn distance_bounded(&self, query: &[u8], i: usize, bound: u64) -> Option<u64> {
+        self.codes().distance_bounded(query, i, bound)
+    }
+
+    /// Find every code within distance `radius` of `query`, returning
+    /// `(index, distance)` pairs in index order.
+    ///
+    /// Codes whose cached weight differs from the weight of `query`
+    /// by more than `radius` are skipped without being compared, since
+    /// `|weight(x) - weight(y)| <= distance(x, y)`. Long codes are
+    /// compared in two stages, skipping the bulk of any code whose
+    /// first 128 bits are already further than `radius` away.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.code_len()` bytes long.
+    pub fn within<Q: AsQuery>(&self, query: Q, radius: u64) -> Vec<(usize, u64)> {
+        self.codes().within(query, radius)
+    }
+
+    /// Find every code within distance `radius` of `query`, yielding
+    /// `(index, distance)` pairs in index order as the scan reaches
+    /// them.
+    ///
+    /// This is a lazy version of `within`: nothing is allocated, and
+    /// each call to `next` only scans as far as the next match, so
+    /// the caller can stop early, such as after the first match, or
+    /// stream the results elsewhere.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.code_len()` bytes long.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let mut db = hamming::Database::new(1);
+    /// for i in 0..=255u8 {
+    ///     db.push([i]);
+    /// }
+    ///
+    /// // is anything within 1 of 0b1000_0000, besides itself?
+    /// let mut matches = db.within_iter([0b1000_0000], 1).filter(|&(_, d)| d > 0);
+    /// assert_eq!(matches.next(), Some((0, 1)));
+    ///
+    /// assert_eq!(db.within_iter([0], 1).count(), 9);
+    /// ```
+    pub fn within_iter<'a, Q>(&'a self, query: Q, radius: u64) -> impl Iterator<Item = (usize, u64)> + 'a
+        where Q: AsQuery + 'a
+    {
+        self.codes().within_iter(query, radius)
+    }
+
+    /// Find the `k` codes closest to `query`, returning `(index,
+    /// distance)` pairs sorted by distance, with ties broken by the
+    /// lower index.
+    ///
+    /// Fewer than `k` pairs are returned if the database has fewer
+    /// than `k` codes.
+    ///
+    /// Once `k` candidates have been found, codes whose cached weight
+    /// shows they cannot be closer than the current `k`th candidate
+    /// are skipped without being compared, and long codes are
+    /// compared in two stages as in `within`.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.code_len()` bytes long.
+    pub fn top_k<Q: AsQuery>(&self, query: Q, k: usize) -> Vec<(usize, u64)> {
+        self.codes().top_k(query, k)
+    }
+
+    /// Find `k` codes close to `query`, allowing each returned
+    /// distance to be up to `epsilon` more than the exact one,
+    /// returning `(index, distance)` pairs sorted by distance, with
+    /// ties broken by the lower index.
+    ///
+    /// Every returned distance is exact, and the `j`th distance is at
+    /// most `epsilon` more than the `j`th distance returned by
+    /// `top_k`. In exchange, a code is only compared if it could be
+    /// more than `epsilon` closer than the current `k`th candidate,
+    /// and the scan stops as soon as the `k`th candidate is within
+    /// `epsilon` of the query, so a larger `epsilon` means fewer full
+    /// comparisons. An `epsilon` of 0 gives the same results as
+    /// `top_k`.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.code_len()` bytes long.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let mut db = hamming::Database::new(1);
+    /// db.push([0b0000_0011]);
+    /// db.push([0b0000_0001]);
+    /// db.push([0b0000_0000]);
+    ///
+    /// assert_eq!(db.top_k_approx([0], 1, 0), [(2, 0)]);
+    /// // the first code is close enough, so the rest are skipped
+    /// assert_eq!(db.top_k_approx([0], 1, 2), [(0, 2)]);
+    /// ```
+    pub fn top_k_approx<Q: AsQuery>(&self, query: Q, k: usize, epsilon: u64) -> Vec<(usize, u64)> {
+        self.codes().top_k_approx(query, k, epsilon)
+    }
+
+    /// Find every code within distance `radius` of `query` whose
+    /// index passes `filter`, yielding `(index, distance)` pairs in
+    /// index order, as `within_iter`.
+    ///
+    /// `filter` is called with each index before the code's bytes are
+    /// touched, so codes ruled out by other criteria (such as metadata
+    /// stored alongside the database) cost no distance computations.
+    /// A precomputed selection can be used with a closure like `|i|
+    /// selected[i]`.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.code_len()` bytes long.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let mut db = hamming::Database::new(1);
+    /// for i in 0..=255u8 {
+    ///     db.push([i]);
+    /// }
+    ///
+    /// let even = db.within_filtered([0], 1, |i| i % 2 == 0).collect::<Vec<_>>();
+    /// assert_eq!(even, [(0, 0), (2, 1), (4, 1), (8, 1), (16, 1), (32, 1), (64, 1), (128, 1)]);
+    /// ```
+    pub fn within_filtered<'a, Q, F>(&'a self, query: Q, radius: u64, filter: F)
+                                     -> impl Iterator<Item = (usize, u64)> + 'a
+        where Q: AsQuery + 'a, F: FnMut(usize) -> bool + 'a
+    {
+        self.codes().within_filtered(query, radius, filter)
+    }
+
+    /// Find (up to) `k` codes close to `query` among those whose index
+    /// passes `filter`, as `top_k_approx`, returning `(index,
+    /// distance)` pairs sorted by distance, with ties broken by the
+    /// lower index.
+    ///
+    /// `filter` is called with each index in order before the code's
+    /// bytes are touched, and an `epsilon` of 0 finds the exact
+    /// nearest codes among those that pass it.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.code_len()` bytes long.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let mut db = hamming::Database::new(1);
+    /// db.push([0b0000_0000]);
+    /// db.push([0b0000_0001]);
+    /// db.push([0b0000_0011]);
+    ///
+    /// let selected = [false, true, true];
+    /// assert_eq!(db.top_k_filtered([0], 1, 0, |i| selected[i]), [(1, 1)]);
+    /// ```
+    pub fn top_k_filtered<Q, F>(&self, query: Q, k: usize, epsilon: u64, filter: F) -> Vec<(usize, u64)>
+        where Q: AsQuery, F: FnMut(usize) -> bool
+    {
+        self.codes().top_k_filtered(query, k, epsilon, filter)
+    }
+
+    /// Write the database to `writer`, in the file format described
+    /// above.
+    ///
+    /// The file stores the code length, the layout of the codes and
+    /// their weights, so that `MmapDatabase::load` can use a saved
+    /// database without reading it. `writer` is written to in one go,
+    /// so it doesn't need buffering.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_packed(2, &[0x00, 0x00, 0xFF, 0x00, 0x0F, 0x01]);
+    /// let mut file = Vec::new();
+    /// db.save(&mut file)?;
+    /// assert_eq!(hamming::Database::load(&file[..])?, db);
+    ///
+    /// // damage is detected
+    /// file[60] ^= 1;
+    /// assert!(hamming::Database::load(&file[..]).is_err());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = persist::Writer::new();
+        self.write_payload(&mut w);
+        w.finish(Kind::Database, writer)
+    }
+
+    /// Read a database written by `save` from `reader`.
+    ///
+    /// An error of kind `InvalidData` is returned if the data isn't a
+    /// database file (such as a file saved by an index), is of an
+    /// unsupported version, or is damaged, and of kind
+    /// `UnexpectedEof` if it is truncated.
+    #[cfg(feature = "std")]
+    pub fn load<R: Read>(reader: R) -> io::Result<Database> {
+        let payload = persist::read(Kind::Database, reader)?;
+        Database::read_payload(&mut persist::Reader::new(&payload))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn write_payload(&self, w: &mut persist::Writer) {
+        w.usize(self.code_len);
+        w.usize(self.stride);
+        w.usize(self.len);
+        let codes = self.as_bytes();
+        w.bytes(codes);
+        w.bytes(&[0; 8][..codes.len().next_multiple_of(8) - codes.len()]);
+        for &weight in &self.weights {
+            w.u64(weight);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn read_payload(r: &mut persist::Reader<'_>) -> io::Result<Database> {
+        let (code_len, stride, len) = Database::read_layout(r)?;
+        let codes = r.bytes(len * stride)?;
+        r.bytes((len * stride).next_multiple_of(8) - len * stride)?;
+        let mut db = Database::with_stride(code_len, stride);
+        for i in 0..len {
+            db.push(&codes[i * stride..i * stride + code_len]);
+            if r.u64()? != db.weights[i] {
+                return Err(persist::invalid(format!("incorrect weight for code {}", i)))
+            }
+        }
+        Ok(db)
+    }
+
+    /// Read the code length, stride and number of codes from the
+    /// start of a database payload, checking that they are consistent
+    /// with the rest of it.
+    #[cfg(feature = "std")]
+    pub(crate) fn read_layout(r: &mut persist::Reader<'_>) -> io::Result<(usize, usize, usize)> {
+        let code_len = r.usize()?;
+        let stride = r.usize()?;
+        if stride != code_len && Some(stride) != code_len.checked_next_multiple_of(8) {
+            return Err(persist::invalid(format!("invalid stride {} for codes of length {}",
+                                                stride, code_len)))
+        }
+        // each code takes its stride and 8 bytes for its weight
+        let len = r.count(stride.saturating_add(8))?;
+        Ok((code_len, stride, len))
+    }
+}
+
+/// Add each code to the end of the database, as with `push`.
+impl<C: AsRef<[u8]>> Extend<C> for Database {
+    fn extend<I: IntoIterator<Item = C>>(&mut self, codes: I) {
+        for code in codes {
+            self.push(code);
+        }
+    }
+}
+
+/// A borrowed collection of equal-width codes, each `code_len` bytes
+/// and starting `stride` bytes after the previous one, with their
+/// weights.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Codes<'a> {
+    pub bytes: &'a [u8],
+    pub code_len: usize,
+    pub stride: usize,
+    pub weights: &'a [u64],
+}
+
+impl<'a> Codes<'a> {
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn get(&self, i: usize) -> &'a [u8] {
+        assert!(i < self.len());
+        let start = i * self.stride;
+        &self.bytes[start..start + self.code_len]
+    }
+
+    pub fn scan(&self, query: &[u8], out: &mut [u64]) {
+        assert_eq!(query.len(), self.code_len);
+        assert_eq!(out.len(), self.len());
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = ::distance(query, self.get(i));
+        }
+    }
+
+    /// The distance from `query` to the code at index `i`, or `None`
+    /// if it is larger than `bound`.
+    ///
+    /// For long codes, the distance of the first `PREFIX_LEN` bytes is
+    /// computed first, and the rest of the code is only touched if
+    /// that partial distance does not already exceed `bound`.
+    pub fn distance_bounded(&self, query: &[u8], i: usize, bound: u64) -> Option<u64> {
+        let code = self.get(i);
+        let d = if code.len() < CASCADE_MIN_LEN {
+            ::distance(query, code)
+        } else {
+            let (query_head, query_tail) = query.split_at(PREFIX_LEN);
+            let (code_head, code_tail) = code.split_at(PREFIX_LEN);
+            let head = ::distance(query_head, code_head);
+            if head > bound {
+                return None
+            }
+            head + ::distance(query_tail, code_tail)
+        };
+        if d <= bound { Some(d) } else { None }
+    }
+
+    pub fn within<Q: AsQuery>(&self, query: Q, radius: u64) -> Vec<(usize, u64)> {
+        let mut out = Vec::new();
+        self.within_into(query, radius, &mut out);
+        out
+    }
+
+    /// `within`, replacing the contents of `out` with the results.
+    pub fn within_into<Q: AsQuery>(&self, query: Q, radius: u64, out: &mut Vec<(usize, u64)>) {
+        out.clear();
+        out.extend(self.within_iter(query, radius));
+    }
+
+    /// `within`, computing each result only as it is needed.
+    pub fn within_iter<Q>(self, query: Q, radius: u64) -> impl Iterator<Item = (usize, u64)> + 'a
+        where Q: AsQuery + 'a
+    {
+        self.within_filtered(query, radius, |_| true)
+    }
+
+    /// `within_iter`, skipping the codes whose index fails `filter`.
+    pub fn within_filtered<Q, F>(self, query: Q, radius: u64, mut filter: F)
+                                 -> impl Iterator<Item = (usize, u64)> + 'a
+        where Q: AsQuery + 'a, F: FnMut(usize) -> bool + 'a
+    {
+        let query_weight = query.query_weight();
+        assert_eq!(query.query_bytes().len(), self.code_len);
+        (0..self.len())
+            .filter(move |&i| filter(i) && query_weight.abs_diff(self.weights[i]) <= radius)
+            .filter_map(move |i| {
+                self.distance_bounded(query.query_bytes(), i, radius).map(|d| (i, d))
+            })
+    }
+
+    pub fn top_k<Q: AsQuery>(&self, query: Q, k: usize) -> Vec<(usize, u64)> {
+        self.top_k_approx(query, k, 0)
+    }
+
+    pub fn top_k_approx<Q: AsQuery>(&self, query: Q, k: usize, epsilon: u64) -> Vec<(usize, u64)> {
+        self.top_k_filtered(query, k, epsilon, |_| true)
+    }
+
+    pub fn top_k_filtered<Q, F>(&self, query: Q, k: usize, epsilon: u64, filter: F) -> Vec<(usize, u64)>
+        where Q: AsQuery, F: FnMut(usize) -> bool
+    {
+        let mut out = Vec::new();
+        self.top_k_into(query, k, epsilon, filter, &mut TopK::new(k), &mut out);
+        out
+    }
+
+    /// `top_k_approx` over the codes whose index passes `filter`,
+    /// replacing the contents of `out` with the results, and using
+    /// `top` (which is left empty) to collect them.
+    pub fn top_k_into<Q, F>(&self, query: Q, k: usize, epsilon: u64, mut filter: F,
+                            top: &mut TopK, out: &mut Vec<(usize, u64)>)
+        where Q: AsQuery, F: FnMut(usize) -> bool
+    {
+        let query_weight = query.query_weight();
+        let query = query.query_bytes();
+        assert_eq!(query.len(), self.code_len);
+        top.reset(k);
+        if k > 0 {
+            for i in 0..self.len() {
+                if !filter(i) {
+                    continue
+                }
+                let worst = match top.bound() {
+                    Some(worst) => worst,
+                    None => {
+                        top.offer(i, ::distance(query, self.get(i)));
+                        continue
+                    }
+                };
+                // codes are visited in index order, so only those more
+                // than `epsilon` closer than the worst candidate are
+                // worth comparing, and, once there are none, the rest
+                // can be skipped.
+                if worst <= epsilon {
+                    break
+                }
+                if query_weight.abs_diff(self.weights[i]) >= worst - epsilon {
+                    continue
+                }
+                if let Some(d) = self.distance_bounded(query, i, worst - epsilon - 1) {
+                    top.offer(i, d);
+                }
+            }
+        }
+        top.drain_sorted(out);
+    }
+}
+
+/// The serialized form of a `Database`.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct RawDatabase {
+    code_len: usize,
+    stride: usize,
+    codes: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Database> for RawDatabase {
+    fn from(db: Database) -> RawDatabase {
+        let codes = (0..db.len()).flat_map(|i| db.get(i).iter().cloned()).collect();
+        RawDatabase { code_len: db.code_len, stride: db.stride, codes }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::core::convert::TryFrom<RawDatabase> for Database {
+    type Error = String;
+
+    fn try_from(raw: RawDatabase) -> Result<Database, String> {
+        if raw.stride != raw.code_len && raw.stride != raw.code_len.div_ceil(8) * 8 {
+            return Err(format!("invalid stride {} for codes of length {}",
+                               raw.stride, raw.code_len))
+        }
+        if raw.code_len == 0 && !raw.codes.is_empty() ||
+            raw.code_len > 0 && !raw.codes.len().is_multiple_of(raw.code_len) {
+            return Err(format!("{} bytes is not a whole number of codes of length {}",
+                               raw.codes.len(), raw.code_len))
+        }
+        let mut db = Database::with_stride(raw.code_len, raw.stride);
+        for code in raw.codes.chunks(raw.code_len.max(1)) {
+            db.push(code);
+        }
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Database;
+    use quickcheck as qc;
+    use rand;
+
+    fn naive(x: &[u8], y: &[u8]) -> u64 {
+        x.iter().zip(y).fold(0, |a, (b, c)| a + (*b ^ *c).count_ones() as u64)
+    }
+
+    #[test]
+    fn layout() {
+        let codes: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let packed = Database::from_packed(3, codes);
+        assert_eq!(packed.len(), 3);
+        assert_eq!(packed.stride(), 3);
+        assert_eq!(packed.as_bytes(), codes);
+        assert_eq!(packed.get(1), &[4, 5, 6]);
+        assert_eq!(packed.weights(), &[4, 5, 6]);
+        assert_eq!(Database::from_codes(3, codes.chunks(3)), packed);
+        let mut extended = Database::new(3);
+        extended.extend(codes.chunks(3).map(|c| c.to_vec()));
+        assert_eq!(extended, packed);
+
+        let mut padded = Database::padded(3);
+        for code in codes.chunks(3) {
+            padded.push(code);
+        }
+        assert_eq!(padded.stride(), 8);
+        assert_eq!(padded.get(2), &[7, 8, 9]);
+        for i in 0..padded.len() {
+            assert_eq!(padded.get(i).as_ptr() as usize % 8, 0);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let db = Database::from_packed(0, []);
+        assert!(db.is_empty());
+        assert_eq!(db.top_k([], 3), vec![]);
+        assert_eq!(db.within([], 3), vec![]);
+    }
+
+    fn check_search(query: &[u8], codes: &[Vec<u8>], radius: u64, k: usize, padded: bool) -> bool {
+        let code_len = query.len();
+        let mut db = if padded { Database::padded(code_len) } else { Database::new(code_len) };
+        for code in codes {
+            db.push(code);
+        }
+
+        let mut expected = codes.iter()
+            .map(|c| naive(query, c))
+            .enumerate()
+            .collect::<Vec<_>>();
+
+        let mut out = vec![0; db.len()];
+        db.scan(query, &mut out);
+        let scan_ok = out.iter().cloned().enumerate().eq(expected.iter().cloned());
+
+        let within = expected.iter().cloned().filter(|&(_, d)| d <= radius).collect::<Vec<_>>();
+        let within_ok = db.within(query, radius) == within &&
+            db.within_iter(query, radius).eq(within.iter().cloned());
+
+        expected.sort_by_key(|&(i, d)| (d, i));
+        expected.truncate(k);
+        let top_k_ok = db.top_k(query, k) == expected;
+
+        scan_ok && within_ok && top_k_ok
+    }
+
+    #[test]
+    fn search_qc() {
+        fn prop(v: Vec<u8>, q: u8, radius: u8, k: u8, padded: bool) -> bool {
+            let query = [q, q.wrapping_mul(3), !q];
+            let codes = v.chunks(3).filter(|c| c.len() == 3).map(|c| c.to_vec()).collect::<Vec<_>>();
+            check_search(&query, &codes, radius as u64 % 25, k as usize % 10, padded)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, u8, bool) -> bool)
+    }
+
+    #[test]
+    fn search_long_qc() {
+        // long enough to use the prefix cascade, with codes near the
+        // query so that both stages of it matter.
+        fn prop(v: Vec<u8>, radius: u8, k: u8, padded: bool) -> bool {
+            let code_len = 80;
+            let query = (0..code_len).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+            let codes = v.chunks(2).map(|c| {
+                let mut code = query.clone();
+                for &b in c {
+                    code[b as usize % code_len] ^= b;
+                }
+                code
+            }).collect::<Vec<_>>();
+            check_search(&query, &codes, radius as u64 % 20, k as usize % 10, padded)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, bool) -> bool)
+    }
+
+    #[test]
+    fn top_k_approx_qc() {
+        fn prop(v: Vec<u8>, q: u8, k: u8, epsilon: u8) -> bool {
+            let query = [q, !q];
+            let db = Database::from_codes(2, v.chunks(2).filter(|c| c.len() == 2));
+            let k = k as usize % 10;
+            let epsilon = epsilon as u64 % 6;
+            let exact = db.top_k(query, k);
+            let approx = db.top_k_approx(query, k, epsilon);
+            approx.len() == exact.len() &&
+                approx.iter().all(|&(i, d)| naive(&query, db.get(i)) == d) &&
+                approx.windows(2).all(|w| (w[0].1, w[0].0) < (w[1].1, w[1].0)) &&
+                approx.iter().zip(&exact).all(|(a, e)| a.1 <= e.1 + epsilon) &&
+                db.top_k_approx(query, k, 0) == exact
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, u8) -> bool)
+    }
+
+    #[test]
+    fn filtered_qc() {
+        fn prop(v: Vec<u8>, q: u8, radius: u8, k: u8, selected: Vec<bool>) -> bool {
+            let query = [q, !q];
+            let db = Database::from_codes(2, v.chunks(2).filter(|c| c.len() == 2));
+            let passes = |i: usize| selected.get(i).cloned().unwrap_or(false);
+            let radius = radius as u64 % 17;
+            let k = k as usize % 10;
+
+            let mut expected = (0..db.len())
+                .filter(|&i| passes(i))
+                .map(|i| (i, naive(&query, db.get(i))))
+                .collect::<Vec<_>>();
+            let within = expected.iter().cloned().filter(|&(_, d)| d <= radius);
+            let within_ok = db.within_filtered(query, radius, passes).eq(within);
+            expected.sort_by_key(|&(i, d)| (d, i));
+            expected.truncate(k);
+            within_ok && db.top_k_filtered(query, k, 0, passes) == expected
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, u8, Vec<bool>) -> bool)
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn save_load_qc() {
+        fn prop(v: Vec<u8>, code_len: u8, padded: bool) -> bool {
+            let code_len = code_len as usize % 10 + 1;
+            let mut db = if padded { Database::padded(code_len) } else { Database::new(code_len) };
+            db.extend(v.chunks(code_len).filter(|c| c.len() == code_len));
+            let mut file = Vec::new();
+            db.save(&mut file).unwrap();
+            let loaded = Database::load(&file[..]).unwrap();
+            loaded == db && loaded.stride() == db.stride()
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, bool) -> bool)
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn from_packed_parallel_qc() {
+        fn prop(v: Vec<u8>, code_len: u8, threads: u8) -> bool {
+            let code_len = code_len as usize % 5 + 1;
+            let v = &v[..v.len() - v.len() % code_len];
+            let threads = threads as usize % 8 + 1;
+            Database::from_packed_parallel(code_len, v, threads) == Database::from_packed(code_len, v)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8) -> bool)
+    }
+
+    #[test]
+    fn append_qc() {
+        fn prop(a: Vec<u8>, b: Vec<u8>, padded: (bool, bool)) -> bool {
+            let build = |bytes: &[u8], padded| {
+                let mut db = if padded { Database::padded(3) } else { Database::new(3) };
+                db.extend(bytes.chunks(3).filter(|c| c.len() == 3));
+                db
+            };
+            let (a, b) = (build(&a, padded.0), build(&b, padded.1));
+            let merged = a.merge(&b);
+            merged.stride() == a.stride() &&
+                merged.len() == a.len() + b.len() &&
+                (0..merged.len()).all(|i| {
+                    let code = if i < a.len() { a.get(i) } else { b.get(i - a.len()) };
+                    merged.get(i) == code && merged.weight(i) == ::weight(code)
+                })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, (bool, bool)) -> bool)
+    }
+
+    #[test]
+    fn weight_pruning() {
+        // every code except the last is far from the query by weight
+        // alone, so this exercises the pruned paths.
+        let mut db = Database::new(2);
+        for _ in 0..100 {
+            db.push([0xFF, 0xFF]);
+        }
+        db.push([0x01, 0x00]);
+        assert_eq!(db.within([0, 0], 1), vec![(100, 1)]);
+        assert_eq!(db.top_k([0, 0], 2), vec![(100, 1), (0, 16)]);
+        assert_eq!(db.top_k([0xFF, 0xFF], 2), vec![(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+        for &padded in &[false, true] {
+            let mut db = if padded { Database::padded(3) } else { Database::new(3) };
+            for code in [1u8, 2, 3, 4, 5, 6, 7, 8, 9].chunks(3) {
+                db.push(code);
+            }
+            let json = serde_json::to_string(&db).unwrap();
+            assert_eq!(serde_json::from_str::<Database>(&json).unwrap(), db);
+        }
+
+        let bad_stride = r#"{"code_len":3,"stride":5,"codes":[]}"#;
+        assert!(serde_json::from_str::<Database>(bad_stride).is_err());
+        let bad_len = r#"{"code_len":3,"stride":3,"codes":[1,2]}"#;
+        assert!(serde_json::from_str::<Database>(bad_len).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn push_wrong_length() {
+        Database::new(4).push([1, 2, 3]);
+    }
+}
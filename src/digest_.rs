@@ -0,0 +1,97 @@
+use digest::generic_array::{ArrayLength, GenericArray};
+use digest::{Output, OutputSizeUser};
+
+use {HammingDistance, HammingWeight, Words};
+
+/// With the `digest` feature, computes the Hamming weight of the
+/// output of the hash function `D`.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "digest")] extern crate sha2;
+/// # extern crate hamming;
+/// # #[cfg(feature = "digest")] fn main() {
+/// use sha2::{Digest, Sha256};
+///
+/// let hash = Sha256::digest(b"hello");
+/// assert_eq!(hamming::weight_digest::<Sha256>(&hash), hamming::weight(&hash[..]));
+/// # }
+/// # #[cfg(not(feature = "digest"))] fn main() {}
+/// ```
+pub fn weight_digest<D: OutputSizeUser>(x: &Output<D>) -> u64 {
+    ::weight(x.as_slice())
+}
+
+/// With the `digest` feature, computes the bitwise Hamming distance
+/// between two outputs of the hash function `D`.
+///
+/// Outputs of the same hash function always have the same length, so
+/// unlike `distance`, this cannot panic.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "digest")] extern crate sha2;
+/// # extern crate hamming;
+/// # #[cfg(feature = "digest")] fn main() {
+/// use sha2::{Digest, Sha256};
+///
+/// let a = Sha256::digest(b"hello");
+/// let b = Sha256::digest(b"hellp");
+/// // about half of the 256 bits differ
+/// let d = hamming::distance_digests::<Sha256>(&a, &b);
+/// assert!(64 < d && d < 192);
+/// # }
+/// # #[cfg(not(feature = "digest"))] fn main() {}
+/// ```
+pub fn distance_digests<D: OutputSizeUser>(a: &Output<D>, b: &Output<D>) -> u64 {
+    ::distance(a.as_slice(), b.as_slice())
+}
+
+/// With the `digest` feature, `GenericArray`s of the types accepted by
+/// `weight` implement `HammingWeight`, like arrays.
+impl<T: Words, N: ArrayLength<T>> HammingWeight for GenericArray<T, N> {
+    #[inline]
+    fn hamming_weight(&self) -> u64 {
+        ::weight(self.as_slice())
+    }
+}
+
+/// With the `digest` feature, `GenericArray`s of the types accepted by
+/// `distance` implement `HammingDistance`, like arrays.
+impl<T: Words, N: ArrayLength<T>> HammingDistance for GenericArray<T, N> {
+    #[inline]
+    fn hamming_distance(&self, other: &GenericArray<T, N>) -> u64 {
+        ::distance(self.as_slice(), other.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_digests, weight_digest};
+    use digest::generic_array::GenericArray;
+    use quickcheck as qc;
+    use rand;
+    use sha2::{Digest, Sha256, Sha512};
+    use {HammingDistance, HammingWeight};
+
+    #[test]
+    fn digest_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            let (a, b) = (Sha256::digest(&v), Sha256::digest(&w));
+            let (c, d) = (Sha512::digest(&v), Sha512::digest(&w));
+            weight_digest::<Sha256>(&a) == ::weight(&a[..]) &&
+                distance_digests::<Sha256>(&a, &b) == ::distance(&a[..], &b[..]) &&
+                distance_digests::<Sha512>(&c, &d) == ::distance(&c[..], &d[..]) &&
+                a.hamming_weight() == weight_digest::<Sha256>(&a) &&
+                c.hamming_distance(&d) == distance_digests::<Sha512>(&c, &d)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool);
+
+        let words: GenericArray<u64, _> = [!0u64, 1].into();
+        assert_eq!(words.hamming_weight(), 65);
+    }
+}
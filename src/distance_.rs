@@ -1,13 +1,60 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::{fmt, iter};
+
+use checked::{check_matrix, output_length, same_length, unwrap, ArgumentError};
+use Words;
+use util::VerticalCounter;
+
 fn naive(x: &[u8], y: &[u8]) -> u64 {
-    assert_eq!(x.len(), y.len());
+    debug_assert_eq!(x.len(), y.len());
     x.iter().zip(y).fold(0, |a, (b, c)| a + (*b ^ *c).count_ones() as u64)
 }
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Hash, Clone)]
-pub struct DistanceError {
-    _x: ()
+/// The reason a fallible distance function could not compute a
+/// distance.
+#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Hash, Clone, Copy)]
+#[non_exhaustive]
+pub enum DistanceError {
+    /// The slices passed to `distance_fast` start at different offsets
+    /// from 8-byte alignment, so their words cannot be loaded in step.
+    Misaligned {
+        /// The address of the first slice, modulo 8.
+        x_offset: usize,
+        /// The address of the second slice, modulo 8.
+        y_offset: usize,
+        /// The length of both slices.
+        len: usize,
+    },
+    /// The inputs to `distance_iter_exact` or a `DistanceAccumulator`
+    /// have different lengths.
+    LengthMismatch {
+        /// The length of the shorter input.
+        len: usize,
+        /// Whether the first input is the shorter.
+        x_shorter: bool,
+    },
 }
 
+impl fmt::Display for DistanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DistanceError::Misaligned { x_offset, y_offset, len } => {
+                write!(f, "slices of {} bytes have different alignments (offsets {} and {} mod 8)",
+                       len, x_offset, y_offset)
+            }
+            DistanceError::LengthMismatch { len, x_shorter } => {
+                let (shorter, longer) = if x_shorter { ("x", "y") } else { ("y", "x") };
+                write!(f, "`{}` ended after {} bytes, before `{}`", shorter, len, longer)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for DistanceError {}
+
 /// Computes the bitwise [Hamming
 /// distance](https://en.wikipedia.org/wiki/Hamming_distance) between
 /// `x` and `y`, that is, the number of bits where `x` and `y` differ,
@@ -60,16 +107,22 @@ pub struct DistanceError {
 /// assert_eq!(hamming::distance_fast(&x[1..1000 - 8], &y[8 + 1..]), Ok(8 * (1000 - 8 - 1)));
 ///
 /// // differing alignments
-/// assert!(hamming::distance_fast(&x[1..], &y[..999]).is_err());
+/// let (x, y) = (&x[1..], &y[..999]);
+/// assert_eq!(hamming::distance_fast(x, y),
+///            Err(hamming::DistanceError::Misaligned {
+///                x_offset: x.as_ptr() as usize % 8,
+///                y_offset: y.as_ptr() as usize % 8,
+///                len: 999,
+///            }));
 /// ```
 pub fn distance_fast(x: &[u8], y: &[u8]) -> Result<u64, DistanceError> {
     assert_eq!(x.len(), y.len());
+    aligned(x, y)
+}
 
-    const M1: u64 = 0x5555555555555555;
-    const M2: u64 = 0x3333333333333333;
-    const M4: u64 = 0x0F0F0F0F0F0F0F0F;
-    const M8: u64 = 0x00FF00FF00FF00FF;
-
+/// `distance_fast` for slices known to have the same length.
+#[inline]
+fn aligned(x: &[u8], y: &[u8]) -> Result<u64, DistanceError> {
     type T30 = [u64; 30];
 
     // can't fit a single T30 in
@@ -83,34 +136,22 @@ pub fn distance_fast(x: &[u8], y: &[u8]) -> Result<u64, DistanceError> {
     if head1.len() != head2.len() {
         // The arrays required different shift amounts, so we can't
         // use aligned loads for both slices.
-        return Err(DistanceError { _x: () });
+        return Err(DistanceError::Misaligned {
+            x_offset: x.as_ptr() as usize % 8,
+            y_offset: y.as_ptr() as usize % 8,
+            len: x.len(),
+        });
     }
 
     debug_assert_eq!(thirty1.len(), thirty2.len());
 
     let mut count = naive(head1, head2) + naive(tail1, tail2);
     for (array1, array2) in thirty1.iter().zip(thirty2) {
-        let mut acc = 0;
-        for j_ in 0..10 {
-            let j = j_ * 3;
-            let mut count1 = array1[j] ^ array2[j];
-            let mut count2 = array1[j + 1] ^ array2[j + 1];
-            let mut half1 = array1[j + 2] ^ array2[j + 2];
-            let mut half2 = half1;
-            half1 &= M1;
-            half2 = (half2 >> 1) & M1;
-            count1 -= (count1 >> 1) & M1;
-            count2 -= (count2 >> 1) & M1;
-            count1 += half1;
-            count2 += half2;
-            count1 = (count1 & M2) + ((count1 >> 2) & M2);
-            count1 += (count2 & M2) + ((count2 >> 2) & M2);
-            acc += (count1 & M4) + ((count1 >> 4) & M4);
-        }
-        acc = (acc & M8) + ((acc >> 8) & M8);
-        acc =  acc       +  (acc >> 16);
-        acc =  acc       +  (acc >> 32);
-        count += acc & 0xFFFF;
+        let mut xor = [0; 30];
+        for (x, (a, b)) in xor.iter_mut().zip(array1.iter().zip(array2)) {
+            *x = a ^ b;
+        }
+        count += ::weight_::merge30(&xor);
     }
     Ok(count)
 }
@@ -155,17 +196,736 @@ pub fn distance_fast(x: &[u8], y: &[u8]) -> Result<u64, DistanceError> {
 ///
 /// The benchmarks ensured that `x` and `y` had the same alignment.
 ///
+/// # Element types
+///
+/// `x` and `y` can be slices of any unsigned integer type (see
+/// `Words`), with the result being the same as for their bytes.
+///
 /// # Examples
 ///
 /// ```rust
-/// let x = vec![0xFF; 1000];
-/// let y = vec![0; 1000];
+/// let x = vec![0xFFu8; 1000];
+/// let y = vec![0u8; 1000];
 /// assert_eq!(hamming::distance(&x, &y), 8 * 1000);
+///
+/// assert_eq!(hamming::distance(&[0u32, 7], &[1, 0]), 1 + 3);
+/// ```
+pub fn distance<T: Words>(x: &[T], y: &[T]) -> u64 {
+    T::distance(x, y)
+}
+
+/// Computes the bitwise Hamming distance between `x` and `y`, as
+/// `distance` does, or returns an error if they have different
+/// lengths, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::try_distance(&[0xFFu8, 1], &[0x0F, 1]), Ok(4));
+/// assert!(hamming::try_distance(&[0u32, 7], &[1]).is_err());
+/// ```
+pub fn try_distance<T: Words>(x: &[T], y: &[T]) -> Result<u64, ArgumentError> {
+    T::try_distance(x, y)
+}
+
+/// `distance` for bytes.
+pub(crate) fn distance_bytes(x: &[u8], y: &[u8]) -> u64 {
+    unwrap(try_distance_bytes(x, y))
+}
+
+/// `try_distance` for bytes.
+pub(crate) fn try_distance_bytes(x: &[u8], y: &[u8]) -> Result<u64, ArgumentError> {
+    panic_free!("try_distance", {
+        same_length(x.len(), y.len())?;
+        Ok(distance_same_length(x, y))
+    })
+}
+
+/// `distance` for bytes known to have the same length.
+#[inline]
+fn distance_same_length(x: &[u8], y: &[u8]) -> u64 {
+    aligned(x, y).unwrap_or_else(|_| naive(x, y))
+}
+
+/// Computes the bitwise Hamming distance between two fixed-size
+/// arrays of bytes.
+///
+/// This is the same as `distance`, but the length is known at compile
+/// time, so the loop over 64-bit words is fully unrolled for small
+/// `N`, with no alignment analysis or length checks. This is best for
+/// short codes, like the 8 to 64 byte hashes and descriptors common in
+/// practice; for long arrays, `distance` is faster.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::distance_array(&[0xFF; 32], &[0x0F; 32]), 128);
+/// assert_eq!(hamming::distance_array(&[1, 3, 7], &[0, 3, 6]), 2);
 /// ```
-pub fn distance(x: &[u8], y: &[u8]) -> u64 {
-    distance_fast(x, y)
-        .ok()
-        .unwrap_or_else(|| naive(x, y))
+#[inline]
+pub fn distance_array<const N: usize>(x: &[u8; N], y: &[u8; N]) -> u64 {
+    let word = |c: &[u8]| u64::from_ne_bytes(c.try_into().unwrap());
+    let (chunks1, chunks2) = (x.chunks_exact(8), y.chunks_exact(8));
+    let tail = naive(chunks1.remainder(), chunks2.remainder());
+    chunks1.zip(chunks2).fold(tail, |a, (b, c)| a + (word(b) ^ word(c)).count_ones() as u64)
+}
+
+/// Computes the bitwise Hamming distance between `x` and `y` in a
+/// constant context.
+///
+/// This is a `const fn`, so it can be used to derive lookup tables,
+/// masks and other constants at compile time. It compares one byte at
+/// a time, so `distance` should be preferred at run time.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length, or else this panics (or
+/// fails to compile, when evaluated at compile time).
+///
+/// # Examples
+///
+/// ```rust
+/// const SYNC: [u8; 2] = [0b1010_1010, 0b1100_1100];
+/// const FLIPPED: [u8; 2] = [0b0101_0101, 0b1100_1100];
+/// const D: u64 = hamming::distance_const(&SYNC, &FLIPPED);
+/// assert_eq!(D, 8);
+/// ```
+pub const fn distance_const(x: &[u8], y: &[u8]) -> u64 {
+    assert!(x.len() == y.len(), "slices must have the same length");
+    let mut count = 0;
+    let mut i = 0;
+    while i < x.len() {
+        count += (x[i] ^ y[i]).count_ones() as u64;
+        i += 1;
+    }
+    count
+}
+
+/// Load `N` 64-bit words from `8 * N` bytes.
+#[inline(always)]
+fn load<const N: usize>(x: &[u8]) -> [u64; N] {
+    let mut words = [0; N];
+    for (w, c) in words.iter_mut().zip(x.chunks_exact(8)) {
+        *w = u64::from_ne_bytes(c.try_into().unwrap());
+    }
+    words
+}
+
+/// Computes the bitwise Hamming distance between two 32-byte (256-bit)
+/// codes, such as SHA-256 digests or ORB descriptors.
+///
+/// This compiles to four pairs of 64-bit loads, xors and popcounts,
+/// summed without any branches or loops.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut x = [0; 32];
+/// x[31] = 0b101;
+/// assert_eq!(hamming::distance32(&x, &[0; 32]), 2);
+/// ```
+#[inline]
+pub fn distance32(x: &[u8; 32], y: &[u8; 32]) -> u64 {
+    let (a, b) = (load::<4>(x), load::<4>(y));
+    let d = |i: usize| (a[i] ^ b[i]).count_ones() as u64;
+    (d(0) + d(1)) + (d(2) + d(3))
+}
+
+/// Computes the bitwise Hamming distance between two 64-byte (512-bit)
+/// codes, such as SHA-512 digests or FREAK/BRISK descriptors.
+///
+/// This compiles to eight pairs of 64-bit loads, xors and popcounts,
+/// summed without any branches or loops.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::distance64(&[0xFF; 64], &[0x7F; 64]), 64);
+/// ```
+#[inline]
+pub fn distance64(x: &[u8; 64], y: &[u8; 64]) -> u64 {
+    let (a, b) = (load::<8>(x), load::<8>(y));
+    let d = |i: usize| (a[i] ^ b[i]).count_ones() as u64;
+    ((d(0) + d(1)) + (d(2) + d(3))) + ((d(4) + d(5)) + (d(6) + d(7)))
+}
+
+/// The bitwise Hamming distance between two 64-bit codes.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::distance_u64(0xFF00, 0x0F0F), 8);
+/// ```
+#[inline]
+pub fn distance_u64(x: u64, y: u64) -> u64 {
+    (x ^ y).count_ones() as u64
+}
+
+/// The bitwise Hamming distance between two 128-bit codes.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::distance_u128(1 << 127, 1), 2);
+/// ```
+#[inline]
+pub fn distance_u128(x: u128, y: u128) -> u64 {
+    (x ^ y).count_ones() as u64
+}
+
+/// Compute the distance from `query` to every 64-bit code in
+/// `codes`, storing the distance to `codes[i]` in `out[i]`.
+///
+/// Codes are processed four at a time, with independent xors and
+/// popcounts that the processor can overlap.
+///
+/// # Panics
+///
+/// `out` must be the same length as `codes`.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut out = [0; 3];
+/// hamming::scan_u64(0b1010, &[0b1010, 0b0101, !0], &mut out);
+/// assert_eq!(out, [0, 4, 62]);
+/// ```
+pub fn scan_u64(query: u64, codes: &[u64], out: &mut [u64]) {
+    unwrap(try_scan_u64(query, codes, out))
+}
+
+/// As `scan_u64`, but returns an error if `out` isn't the same length
+/// as `codes`, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut out = [0; 2];
+/// assert_eq!(hamming::try_scan_u64(0, &[1, 3], &mut out), Ok(()));
+/// assert_eq!(out, [1, 2]);
+/// assert!(hamming::try_scan_u64(0, &[1, 3], &mut out[..1]).is_err());
+/// ```
+pub fn try_scan_u64(query: u64, codes: &[u64], out: &mut [u64]) -> Result<(), ArgumentError> {
+    panic_free!("try_scan_u64", {
+        output_length(out.len(), codes.len())?;
+        let mut codes4 = codes.chunks_exact(4);
+        let mut out4 = out.chunks_exact_mut(4);
+        for (o, c) in (&mut out4).zip(&mut codes4) {
+            o[0] = distance_u64(query, c[0]);
+            o[1] = distance_u64(query, c[1]);
+            o[2] = distance_u64(query, c[2]);
+            o[3] = distance_u64(query, c[3]);
+        }
+        for (o, c) in out4.into_remainder().iter_mut().zip(codes4.remainder()) {
+            *o = distance_u64(query, *c);
+        }
+        Ok(())
+    })
+}
+
+/// Find the `k` 64-bit codes in `codes` closest to `query`, returning
+/// `(index, distance)` pairs sorted by distance, with ties broken by
+/// the lower index.
+///
+/// # Examples
+///
+/// ```rust
+/// let codes = [0b1111, 0b0001, 0b0000, 0b0011];
+/// assert_eq!(hamming::top_k_u64(0, &codes, 2), vec![(2, 0), (1, 1)]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn top_k_u64(query: u64, codes: &[u64], k: usize) -> Vec<(usize, u64)> {
+    let mut top = ::TopK::new(k);
+    if k > 0 {
+        let mut distances = [0; 256];
+        for (block, chunk) in codes.chunks(distances.len()).enumerate() {
+            let distances = &mut distances[..chunk.len()];
+            scan_u64(query, chunk, distances);
+            for (j, &d) in distances.iter().enumerate() {
+                top.offer(block * 256 + j, d);
+            }
+        }
+    }
+    top.into_sorted_vec()
+}
+
+/// Computes the bitwise Hamming distance between the pairs of bytes
+/// produced by `pairs`.
+///
+/// This is for data that doesn't live in contiguous slices, such as
+/// ring buffers or decoded streams, and batches the xors into 64-bit
+/// words like `weight_iter`. Since each item is a pair, there is no
+/// length mismatch to detect: zipping two sources with
+/// `Iterator::zip` stops at the end of the shorter one, ignoring the
+/// rest of the longer. Use `distance_iter_exact` to treat that as an
+/// error instead.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::VecDeque;
+///
+/// let mut x = VecDeque::new();
+/// x.push_back(0x0F);
+/// x.push_front(0xFF);
+/// let y = [0x00u8, 0x00];
+/// assert_eq!(hamming::distance_iter(x.iter().cloned().zip(y.iter().cloned())), 12);
+/// ```
+pub fn distance_iter<I: IntoIterator<Item = (u8, u8)>>(pairs: I) -> u64 {
+    ::weight_iter(pairs.into_iter().map(|(a, b)| a ^ b))
+}
+
+/// Computes the bitwise Hamming distance between the bytes produced
+/// by `x` and `y`, requiring them to have the same length.
+///
+/// # Errors
+///
+/// If one iterator ends before the other, `Err` is returned. Both
+/// iterators have been advanced past the end of the shorter one.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::distance_iter_exact(vec![1, 2, 3], vec![1, 2, 0]), Ok(2));
+///
+/// let err = hamming::distance_iter_exact(vec![1, 2, 3], vec![1, 2]).unwrap_err();
+/// assert_eq!(err, hamming::DistanceError::LengthMismatch { len: 2, x_shorter: false });
+/// assert_eq!(err.to_string(), "`y` ended after 2 bytes, before `x`");
+/// ```
+pub fn distance_iter_exact<I, J>(x: I, y: J) -> Result<u64, DistanceError>
+    where I: IntoIterator<Item = u8>, J: IntoIterator<Item = u8>
+{
+    let (mut x, mut y) = (x.into_iter(), y.into_iter());
+    let mut len = 0;
+    let mut mismatch = None;
+    let count = ::weight_iter(iter::from_fn(|| {
+        match (x.next(), y.next()) {
+            (Some(a), Some(b)) => {
+                len += 1;
+                Some(a ^ b)
+            }
+            (None, None) => None,
+            (x, _) => {
+                mismatch = Some(DistanceError::LengthMismatch { len, x_shorter: x.is_none() });
+                None
+            }
+        }
+    }));
+    match mismatch {
+        Some(e) => Err(e),
+        None => Ok(count),
+    }
+}
+
+/// Computes the bitwise Hamming distance between the two codes of
+/// each pair in `pairs`, storing the distance of `pairs[i]` in
+/// `out[i]`.
+///
+/// This is for comparing many pre-matched candidates, such as the
+/// pairs produced by blocking in record linkage, rather than every
+/// code with every other. The lengths are all checked before anything
+/// is compared, so the loop computing the distances is just the
+/// `distance` kernel, with no checks or unwinding paths of its own.
+/// The pairs can have different lengths from each other.
+///
+/// # Panics
+///
+/// The two codes of every pair must have the same length, and `out`
+/// must have an element for each pair.
+///
+/// # Examples
+///
+/// ```rust
+/// let pairs: [(&[u8], &[u8]); 3] = [(&[0xFF], &[0x0F]), (&[1, 2, 3], &[1, 2, 0]), (&[], &[])];
+/// let mut out = [0; 3];
+/// hamming::distance_pairs(&pairs, &mut out);
+/// assert_eq!(out, [4, 2, 0]);
+/// ```
+pub fn distance_pairs(pairs: &[(&[u8], &[u8])], out: &mut [u64]) {
+    unwrap(try_distance_pairs(pairs, out))
+}
+
+/// As `distance_pairs`, but returns an error if the codes of a pair
+/// have different lengths or `out` has the wrong length, rather than
+/// panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut out = [0; 2];
+/// let pairs: [(&[u8], &[u8]); 2] = [(&[0xFF], &[0x0F]), (&[1, 2], &[1])];
+/// assert!(hamming::try_distance_pairs(&pairs, &mut out).is_err());
+/// assert_eq!(hamming::try_distance_pairs(&pairs[..1], &mut out[..1]), Ok(()));
+/// assert_eq!(out[0], 4);
+/// ```
+pub fn try_distance_pairs(pairs: &[(&[u8], &[u8])], out: &mut [u64]) -> Result<(), ArgumentError> {
+    panic_free!("try_distance_pairs", {
+        output_length(out.len(), pairs.len())?;
+        for &(x, y) in pairs {
+            same_length(x.len(), y.len())?;
+        }
+        for (o, &(x, y)) in out.iter_mut().zip(pairs) {
+            *o = distance_same_length(x, y);
+        }
+        Ok(())
+    })
+}
+
+/// Computes the bitwise Hamming distance between the two codes of
+/// each pair produced by `pairs`, as they are needed.
+///
+/// This is `distance_pairs` for pairs that aren't already collected in
+/// a slice, such as those generated from the candidates of an index
+/// lookup, yielding the distance of each pair in turn.
+///
+/// # Panics
+///
+/// The returned iterator panics on reaching a pair whose codes have
+/// different lengths.
+///
+/// # Examples
+///
+/// ```rust
+/// let codes: [&[u8]; 3] = [&[0x00, 0x00], &[0xFF, 0x00], &[0x0F, 0x01]];
+/// let candidates = [(0, 1), (1, 2), (0, 2)];
+/// let distances = hamming::distance_pairs_iter(candidates.iter().map(|&(i, j)| (codes[i], codes[j])))
+///     .collect::<Vec<_>>();
+/// assert_eq!(distances, [8, 5, 5]);
+/// ```
+pub fn distance_pairs_iter<'a, I>(pairs: I) -> impl Iterator<Item = u64> + 'a
+    where I: IntoIterator<Item = (&'a [u8], &'a [u8])>, I::IntoIter: 'a
+{
+    pairs.into_iter().map(|(x, y)| ::distance(x, y))
+}
+
+/// The rows of `len` bytes starting every `stride` bytes in `x`,
+/// where the last row may be missing its padding.
+fn rows(x: &[u8], len: usize, stride: usize) -> impl Iterator<Item = &[u8]> {
+    (0..row_count(x, len, stride)).filter_map(move |i| x.get(i * stride..i * stride + len))
+}
+
+fn row_count(x: &[u8], len: usize, stride: usize) -> usize {
+    if x.len() < len || stride == 0 { 0 } else { (x.len() - len) / stride + 1 }
+}
+
+/// Check the layout of two 2D buffers, returning their number of rows.
+fn check_rows(a: &[u8], b: &[u8], row_len: usize, row_stride: usize) -> Result<usize, ArgumentError> {
+    if row_stride == 0 || row_stride < row_len {
+        return Err(ArgumentError::InvalidSize { name: "row stride", value: row_stride })
+    }
+    let count = row_count(a, row_len, row_stride);
+    same_length(count, row_count(b, row_len, row_stride))?;
+    Ok(count)
+}
+
+/// Computes the total bitwise Hamming distance between the
+/// corresponding rows of two 2D buffers.
+///
+/// Each buffer holds rows of `row_len` bytes, starting every
+/// `row_stride` bytes (the pitch), so any padding between rows is
+/// skipped rather than compared. The padding after the last row may be
+/// omitted. `distance_rows_each` gives the distance of each row
+/// separately.
+///
+/// # Panics
+///
+/// `row_stride` must be non-zero and at least `row_len`, and the
+/// buffers must have the same number of rows.
+///
+/// # Examples
+///
+/// ```rust
+/// // 3-byte rows, padded to 4 bytes
+/// let a = [0xFF, 0x00, 0x01, 0xAA,
+///          0x0F, 0x00, 0x00];
+/// let b = [0x00, 0x00, 0x01, 0x55,
+///          0x0F, 0x01, 0x00, 0x55];
+/// assert_eq!(hamming::distance_rows(&a, &b, 3, 4), 8 + 1);
+/// ```
+pub fn distance_rows(a: &[u8], b: &[u8], row_len: usize, row_stride: usize) -> u64 {
+    unwrap(try_distance_rows(a, b, row_len, row_stride))
+}
+
+/// As `distance_rows`, but returns an error if the layout is invalid
+/// or the buffers have different numbers of rows, rather than
+/// panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let (a, b) = ([0xFF, 0x00, 0xAA], [0x0F, 0x00, 0x55]);
+/// assert_eq!(hamming::try_distance_rows(&a, &b, 1, 2), Ok(4 + 8));
+/// assert!(hamming::try_distance_rows(&a, &b, 2, 1).is_err());
+/// assert!(hamming::try_distance_rows(&a, &b[..1], 1, 2).is_err());
+/// ```
+pub fn try_distance_rows(a: &[u8], b: &[u8], row_len: usize, row_stride: usize)
+                         -> Result<u64, ArgumentError> {
+    panic_free!("try_distance_rows", {
+        check_rows(a, b, row_len, row_stride)?;
+        Ok(rows(a, row_len, row_stride).zip(rows(b, row_len, row_stride))
+            .map(|(x, y)| distance_same_length(x, y))
+            .sum())
+    })
+}
+
+/// Computes the bitwise Hamming distance between each pair of
+/// corresponding rows of two 2D buffers, storing the distance between
+/// row `i` of each in `out[i]`.
+///
+/// The buffers are laid out as for `distance_rows`.
+///
+/// # Panics
+///
+/// As for `distance_rows`, and `out` must have one element per row.
+///
+/// # Examples
+///
+/// ```rust
+/// let a = [0xFF, 0x00, 0x01, 0xAA,
+///          0x0F, 0x00, 0x00];
+/// let b = [0x00, 0x00, 0x01, 0x55,
+///          0x0F, 0x01, 0x00, 0x55];
+/// let mut out = [0; 2];
+/// hamming::distance_rows_each(&a, &b, 3, 4, &mut out);
+/// assert_eq!(out, [8, 1]);
+/// ```
+pub fn distance_rows_each(a: &[u8], b: &[u8], row_len: usize, row_stride: usize, out: &mut [u64]) {
+    unwrap(try_distance_rows_each(a, b, row_len, row_stride, out))
+}
+
+/// As `distance_rows_each`, but returns an error if the layout is
+/// invalid, the buffers have different numbers of rows, or `out` has
+/// the wrong length, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let (a, b) = ([0xFF, 0x00, 0xAA], [0x0F, 0x00, 0x55]);
+/// let mut out = [0; 2];
+/// assert_eq!(hamming::try_distance_rows_each(&a, &b, 1, 2, &mut out), Ok(()));
+/// assert_eq!(out, [4, 8]);
+/// assert!(hamming::try_distance_rows_each(&a, &b, 1, 2, &mut out[..1]).is_err());
+/// ```
+pub fn try_distance_rows_each(a: &[u8], b: &[u8], row_len: usize, row_stride: usize, out: &mut [u64])
+                              -> Result<(), ArgumentError> {
+    panic_free!("try_distance_rows_each", {
+        output_length(out.len(), check_rows(a, b, row_len, row_stride)?)?;
+        for (o, (x, y)) in out.iter_mut().zip(rows(a, row_len, row_stride).zip(rows(b, row_len, row_stride))) {
+            *o = distance_same_length(x, y);
+        }
+        Ok(())
+    })
+}
+
+/// Computes the bitwise Hamming distance between each column of two
+/// bit matrices, storing in `out[i]` the number of rows in which bit
+/// `i` of `x` and `y` differ.
+///
+/// The matrices consist of rows of `code_len` bytes stored one after
+/// another, and the two must have the same number of rows. Row `r` of
+/// `x` is compared with row `r` of `y`, and bit `i` of a row is bit
+/// `i % 8` of its byte `i / 8`. Summing `out` gives `distance(x, y)`,
+/// but this breaks it down by bit position, to show, say, which bits
+/// of a learned binary code disagree most often between two encodings
+/// of the same data.
+///
+/// The rows are counted 64 columns at a time with vertical
+/// counters, costing a few word operations per row rather than a
+/// branch per bit.
+///
+/// # Panics
+///
+/// `code_len` must be non-zero, `x` and `y` must have the same length,
+/// a whole number of rows, and `out` must have `8 * code_len`
+/// elements.
+///
+/// # Examples
+///
+/// ```rust
+/// let x = [0b0000_0011, 0b0000_0001, 0b0000_0000];
+/// let y = [0b0000_0001, 0b0000_0011, 0b1000_0010];
+/// let mut out = [0; 8];
+/// hamming::column_distances(&x, &y, 1, &mut out);
+/// assert_eq!(out, [0, 3, 0, 0, 0, 0, 0, 1]);
+/// ```
+pub fn column_distances(x: &[u8], y: &[u8], code_len: usize, out: &mut [u64]) {
+    unwrap(try_column_distances(x, y, code_len, out))
+}
+
+/// As `column_distances`, but returns an error if the matrices or
+/// `out` have the wrong lengths, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut out = [0; 8];
+/// assert_eq!(hamming::try_column_distances(&[0b01, 0b11], &[0b00, 0b00], 1, &mut out), Ok(()));
+/// assert_eq!(out[..2], [2, 1]);
+/// assert!(hamming::try_column_distances(&[0b01, 0b11], &[0b00], 1, &mut out).is_err());
+/// assert!(hamming::try_column_distances(&[0b01, 0b11], &[0b00, 0b00], 2, &mut out).is_err());
+/// ```
+pub fn try_column_distances(x: &[u8], y: &[u8], code_len: usize, out: &mut [u64])
+                            -> Result<(), ArgumentError> {
+    panic_free!("try_column_distances", {
+        check_matrix(x.len(), code_len, out.len())?;
+        same_length(x.len(), y.len())?;
+        for (start, out) in (0..code_len).step_by(8).zip(out.chunks_mut(64)) {
+            let mut counter = VerticalCounter::new();
+            for (a, b) in x.chunks(code_len).zip(y.chunks(code_len)) {
+                let mut word = [0; 8];
+                for ((w, &a), &b) in word.iter_mut().zip(a.iter().skip(start)).zip(b.iter().skip(start)) {
+                    *w = a ^ b;
+                }
+                counter.add(u64::from_le_bytes(word));
+            }
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = counter.get(i);
+            }
+        }
+        Ok(())
+    })
+}
+
+macro_rules! distance_slice {
+    ($name: ident, $try_name: ident, $t: ty) => {
+        #[doc = concat!("`distance` for slices of `", stringify!($t), "`s, whose alignment lets the")]
+        /// words be compared directly without going through bytes.
+        pub(crate) fn $name(x: &[$t], y: &[$t]) -> u64 {
+            unwrap($try_name(x, y))
+        }
+
+        #[doc = concat!("`try_distance` for slices of `", stringify!($t), "`s.")]
+        pub(crate) fn $try_name(x: &[$t], y: &[$t]) -> Result<u64, ArgumentError> {
+            panic_free!(stringify!($try_name), {
+                same_length(x.len(), y.len())?;
+                let naive = |x: &[$t], y: &[$t]| {
+                    x.iter().zip(y).fold(0, |a, (b, c)| a + (b ^ c).count_ones() as u64)
+                };
+                let (head1, middle1, tail1) = unsafe { x.align_to::<u64>() };
+                let (head2, middle2, tail2) = unsafe { y.align_to::<u64>() };
+                if head1.len() != head2.len() {
+                    return Ok(naive(x, y))
+                }
+                Ok(naive(head1, head2) + distance_words(middle1, middle2) + naive(tail1, tail2))
+            })
+        }
+    }
+}
+
+distance_slice!(distance_u16_slice, try_distance_u16_slice, u16);
+distance_slice!(distance_u32_slice, try_distance_u32_slice, u32);
+distance_slice!(distance_u64_slice, try_distance_u64_slice, u64);
+distance_slice!(distance_u128_slice, try_distance_u128_slice, u128);
+
+/// The distance between two equal-length slices of words, 30 at a
+/// time.
+pub(crate) fn distance_words(x: &[u64], y: &[u64]) -> u64 {
+    debug_assert_eq!(x.len(), y.len());
+    let (chunks1, chunks2) = (x.chunks_exact(30), y.chunks_exact(30));
+    let rest = chunks1.remainder().iter().zip(chunks2.remainder())
+        .fold(0, |a, (b, c)| a + (b ^ c).count_ones() as u64);
+    chunks1.zip(chunks2).fold(rest, |a, (b, c)| {
+        let mut xor = [0; 30];
+        for (x, (b, c)) in xor.iter_mut().zip(b.iter().zip(c)) {
+            *x = b ^ c;
+        }
+        a + ::weight_::merge30(&xor)
+    })
+}
+
+/// The number of set bits in `f(a, b)` over corresponding 64-bit
+/// words `a` of `x` and `b` of `y`, computed in one pass without
+/// materialising the combination.
+///
+/// This generalises `distance`, which is `fold_popcount(x, y, |a, b|
+/// a ^ b)`, to any bitwise combination, such as `a & b` for the size
+/// of an intersection or `!a & b` for the bits set only in `y`. It
+/// uses the same traversal: aligned loads and the tree-merging count
+/// when `x` and `y` have the same 8-byte alignment, and unaligned
+/// loads otherwise.
+///
+/// `f` should treat each bit position independently, as the bitwise
+/// operators do, since the way bytes are grouped into words depends
+/// on the alignment of the slices. The ends of the slices are padded
+/// out to whole words with zeros, and any bits `f` sets in the padding
+/// (as a `!` does) are not counted.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length, or else `fold_popcount`
+/// panics.
+///
+/// # Examples
+///
+/// ```rust
+/// let x = [0b1100u8, 0xFF, 0x0F];
+/// let y = [0b1010u8, 0x00, 0xFF];
+/// assert_eq!(hamming::fold_popcount(&x, &y, |a, b| a ^ b), hamming::distance(&x, &y));
+/// assert_eq!(hamming::fold_popcount(&x, &y, |a, b| a & b), 1 + 0 + 4);
+/// // the bits set in neither, which doesn't count past the end of the slices
+/// assert_eq!(hamming::fold_popcount(&x, &y, |a, b| !(a | b)), 1 + 4 + 0 + 0);
+/// ```
+pub fn fold_popcount<F: Fn(u64, u64) -> u64>(x: &[u8], y: &[u8], f: F) -> u64 {
+    unwrap(try_fold_popcount(x, y, f))
+}
+
+/// As `fold_popcount`, but returns an error if `x` and `y` have
+/// different lengths, rather than panicking.
+///
+/// This only panics if `f` does.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::try_fold_popcount(&[0b1100], &[0b1010], |a, b| a & b), Ok(1));
+/// assert!(hamming::try_fold_popcount(&[0b1100], &[], |a, b| a & b).is_err());
+/// ```
+pub fn try_fold_popcount<F: Fn(u64, u64) -> u64>(x: &[u8], y: &[u8], f: F) -> Result<u64, ArgumentError> {
+    same_length(x.len(), y.len())?;
+    let (head1, middle1, tail1) = unsafe { ::util::align_to::<_, u64>(x) };
+    let (head2, middle2, tail2) = unsafe { ::util::align_to::<_, u64>(y) };
+    if head1.len() != head2.len() {
+        return Ok(fold_bytes(x, y, &f))
+    }
+    Ok(fold_bytes(head1, head2, &f) + fold_words(middle1, middle2, &f) + fold_bytes(tail1, tail2, &f))
+}
+
+/// `fold_popcount` over equal-length slices of words, 30 at a time.
+fn fold_words<F: Fn(u64, u64) -> u64>(x: &[u64], y: &[u64], f: &F) -> u64 {
+    let (chunks1, chunks2) = (x.chunks_exact(30), y.chunks_exact(30));
+    let rest = chunks1.remainder().iter().zip(chunks2.remainder())
+        .fold(0, |a, (&b, &c)| a + f(b, c).count_ones() as u64);
+    chunks1.zip(chunks2).fold(rest, |a, (b, c)| {
+        let mut words = [0; 30];
+        for (w, (&b, &c)) in words.iter_mut().zip(b.iter().zip(c)) {
+            *w = f(b, c);
+        }
+        a + ::weight_::merge30(&words)
+    })
+}
+
+/// `fold_popcount` over equal-length byte slices with any alignment,
+/// using unaligned loads, 30 words at a time.
+fn fold_bytes<F: Fn(u64, u64) -> u64>(x: &[u8], y: &[u8], f: &F) -> u64 {
+    fn load(bytes: &[u8]) -> u64 {
+        let mut word = [0; 8];
+        word[..bytes.len()].copy_from_slice(bytes);
+        u64::from_le_bytes(word)
+    }
+    let (chunks1, chunks2) = (x.chunks_exact(8 * 30), y.chunks_exact(8 * 30));
+    let (rest1, rest2) = (chunks1.remainder(), chunks2.remainder());
+    let mut count = chunks1.zip(chunks2).fold(0, |a, (b, c)| {
+        let mut words = [0; 30];
+        for (w, (b, c)) in words.iter_mut().zip(b.chunks_exact(8).zip(c.chunks_exact(8))) {
+            *w = f(load(b), load(c));
+        }
+        a + ::weight_::merge30(&words)
+    });
+    for (b, c) in rest1.chunks(8).zip(rest2.chunks(8)) {
+        let mask = if b.len() == 8 { !0 } else { (1 << (8 * b.len())) - 1 };
+        count += (f(load(b), load(c)) & mask).count_ones() as u64;
+    }
+    count
 }
 
 #[cfg(test)]
@@ -206,6 +966,33 @@ mod tests {
             .quickcheck(prop as fn(Vec<u8>,Vec<u8>,u8) -> qc::TestResult)
     }
     #[test]
+    fn fold_popcount_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, misalign: (u8, u8)) -> qc::TestResult {
+            let (i, j) = (misalign.0 as usize % 8, misalign.1 as usize % 8);
+            let l = ::std::cmp::min(v.len().saturating_sub(i), w.len().saturating_sub(j));
+            let (x, y) = (&v[i..i + l], &w[j..j + l]);
+            let naive = |f: fn(u8, u8) -> u8| {
+                x.iter().zip(y).fold(0, |a, (&b, &c)| a + f(b, c).count_ones() as u64)
+            };
+            qc::TestResult::from_bool(
+                super::fold_popcount(x, y, |a, b| a ^ b) == super::naive(x, y) &&
+                super::fold_popcount(x, y, |a, b| a & b) == naive(|b, c| b & c) &&
+                super::fold_popcount(x, y, |a, b| !a & b) == naive(|b, c| !b & c) &&
+                super::fold_popcount(x, y, |a, b| !(a | b)) == naive(|b, c| !(b | c)))
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 10_000))
+            .quickcheck(prop as fn(Vec<u8>,Vec<u8>,(u8, u8)) -> qc::TestResult)
+    }
+    #[test]
+    fn distance_fast_misaligned() {
+        let v = vec![0u8; 1000];
+        let s = (8 - v.as_ptr() as usize % 8) % 8;
+        let err = super::distance_fast(&v[s + 1..s + 501], &v[s + 2..s + 502]).unwrap_err();
+        assert_eq!(err, super::DistanceError::Misaligned { x_offset: 1, y_offset: 2, len: 500 });
+        assert_eq!(err.to_string(), "slices of 500 bytes have different alignments (offsets 1 and 2 mod 8)");
+    }
+    #[test]
     fn distance_fast_smoke_huge() {
         let v = vec![0b1001_1101; 10234567];
         let w = vec![0b1111_1111; v.len()];
@@ -215,7 +1002,7 @@ mod tests {
     }
     #[test]
     fn distance_smoke() {
-        let v = vec![0; 10000];
+        let v = vec![0u8; 10000];
         let w = vec![0xFF; v.len()];
         for len_ in 0..99 {
             let len = len_ * 10;
@@ -227,4 +1014,204 @@ mod tests {
             }
         }
     }
+    #[test]
+    fn distance_slices_qc() {
+        fn prop(v: Vec<u64>, w: Vec<u64>, misalign: u8) -> bool {
+            let l = v.len().min(w.len());
+            let (v, w) = (&v[..l], &w[..l]);
+            let expected = v.iter().zip(w).fold(0, |a, (b, c)| a + (b ^ c).count_ones() as u64);
+            let to_u16 = |x: &[u64]| x.iter().flat_map(|&x| (0..4).map(move |i| (x >> (16 * i)) as u16)).collect::<Vec<_>>();
+            let to_u32 = |x: &[u64]| x.iter().flat_map(|&x| (0..2).map(move |i| (x >> (32 * i)) as u32)).collect::<Vec<_>>();
+            let to_u128 = |x: &[u64]| x.chunks(2).map(|c| c.iter().rev().fold(0, |a, &b| a << 64 | b as u128)).collect::<Vec<_>>();
+
+            // misaligned relative to each other
+            let (x16, y16) = (to_u16(v), to_u16(w));
+            let m = (misalign as usize % 4).min(x16.len());
+            let expected16 = x16[m..].iter().zip(&y16[..x16.len() - m])
+                .fold(0, |a, (b, c)| a + (b ^ c).count_ones() as u64);
+
+            super::distance_u64_slice(v, w) == expected &&
+                super::distance_u32_slice(&to_u32(v), &to_u32(w)) == expected &&
+                super::distance_u16_slice(&x16, &y16) == expected &&
+                super::distance_u128_slice(&to_u128(v), &to_u128(w)) == expected &&
+                super::distance_u16_slice(&x16[m..], &y16[..x16.len() - m]) == expected16
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u64>, Vec<u64>, u8) -> bool)
+    }
+
+    #[test]
+    fn distance_array_qc() {
+        use core::convert::TryInto;
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            let (mut a, mut b) = ([0u8; 45], [0u8; 45]);
+            for (a, x) in a.iter_mut().zip(&v) {
+                *a = *x;
+            }
+            for (b, y) in b.iter_mut().zip(&w) {
+                *b = *y;
+            }
+            let (c, d): ([u8; 16], [u8; 16]) = (a[..16].try_into().unwrap(), b[..16].try_into().unwrap());
+            super::distance_array(&a, &b) == super::naive(&a, &b) &&
+                super::distance_array(&c, &d) == super::naive(&c, &d)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool);
+        assert_eq!(super::distance_array(&[], &[]), 0);
+    }
+
+    #[test]
+    fn distance_pairs_qc() {
+        fn prop(v: Vec<(Vec<u8>, Vec<u8>)>) -> bool {
+            let pairs = v.iter().map(|(x, y)| {
+                let l = x.len().min(y.len());
+                (&x[..l], &y[..l])
+            }).collect::<Vec<_>>();
+            let expected = pairs.iter().map(|&(x, y)| super::naive(x, y)).collect::<Vec<_>>();
+            let mut out = vec![!0; pairs.len()];
+            super::distance_pairs(&pairs, &mut out);
+            out == expected &&
+                super::distance_pairs_iter(pairs.iter().cloned()).collect::<Vec<_>>() == expected
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<(Vec<u8>, Vec<u8>)>) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn distance_pairs_mismatch() {
+        let pairs: [(&[u8], &[u8]); 2] = [(&[0], &[0]), (&[0, 0], &[0])];
+        super::distance_pairs(&pairs, &mut [0; 2]);
+    }
+
+    #[test]
+    fn distance_rows_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, row_len: u8, padding: u8) -> bool {
+            let (row_len, padding) = (row_len as usize % 20, padding as usize % 5);
+            let stride = (row_len + padding).max(1);
+            let l = v.len().min(w.len());
+            let n = if l < row_len { 0 } else { (l - row_len) / stride + 1 };
+            // `b` stops at the end of its last row, without padding
+            let (a, b) = (&v[..l], &w[..if n == 0 { 0 } else { (n - 1) * stride + row_len }]);
+            let expected = (0..n).map(|i| {
+                super::naive(&a[i * stride..i * stride + row_len], &b[i * stride..i * stride + row_len])
+            }).collect::<Vec<_>>();
+            let mut out = vec![!0; n];
+            super::distance_rows_each(a, b, row_len, stride, &mut out);
+            out == expected && super::distance_rows(a, b, row_len, stride) == expected.iter().sum::<u64>()
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, u8, u8) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn distance_rows_mismatch() {
+        super::distance_rows(&[0; 8], &[0; 12], 3, 4);
+    }
+
+    #[test]
+    fn column_distances_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, code_len: u8) -> bool {
+            let code_len = code_len as usize % 20 + 1;
+            let l = v.len().min(w.len()) / code_len * code_len;
+            let (x, y) = (&v[..l], &w[..l]);
+            let mut out = vec![!0; 8 * code_len];
+            super::column_distances(x, y, code_len, &mut out);
+            out.iter().enumerate().all(|(i, &count)| {
+                let naive = x.chunks(code_len).zip(y.chunks(code_len))
+                    .filter(|&(a, b)| (a[i / 8] ^ b[i / 8]) >> (i % 8) & 1 == 1)
+                    .count();
+                count == naive as u64
+            }) && out.iter().sum::<u64>() == super::naive(x, y)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, u8) -> bool);
+    }
+
+    #[test]
+    fn distance_const_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            let l = v.len().min(w.len());
+            super::distance_const(&v[..l], &w[..l]) == super::naive(&v[..l], &w[..l])
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool);
+        const D: u64 = super::distance_const(&[0xFF, 3], &[0x0F, 1]);
+        assert_eq!(D, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn distance_const_length_mismatch() {
+        super::distance_const(&[1, 2], &[1]);
+    }
+
+    #[test]
+    fn distance32_64_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            let (mut a, mut b) = ([0u8; 64], [0u8; 64]);
+            for (a, x) in a.iter_mut().zip(&v) {
+                *a = *x;
+            }
+            for (b, y) in b.iter_mut().zip(&w) {
+                *b = *y;
+            }
+            let (mut c, mut d) = ([0u8; 32], [0u8; 32]);
+            c.copy_from_slice(&a[32..]);
+            d.copy_from_slice(&b[32..]);
+            super::distance64(&a, &b) == super::naive(&a, &b) &&
+                super::distance32(&c, &d) == super::naive(&c, &d)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn scan_u64_qc() {
+        fn prop(codes: Vec<u64>, query: u64, k: u8) -> bool {
+            let mut out = vec![0; codes.len()];
+            super::scan_u64(query, &codes, &mut out);
+            let scan_ok = codes.iter().zip(&out)
+                .all(|(&c, &d)| d == super::distance(&[c], &[query]));
+
+            let k = k as usize % 12;
+            let mut expected = out.iter().cloned().enumerate().collect::<Vec<_>>();
+            expected.sort_by_key(|&(i, d)| (d, i));
+            expected.truncate(k);
+            scan_ok && super::top_k_u64(query, &codes, k) == expected
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u64>, u64, u8) -> bool);
+
+        // more than one block, with ties across blocks
+        let codes = (0..1000u64).map(|i| i % 7).collect::<Vec<_>>();
+        assert_eq!(super::top_k_u64(0, &codes, 3), vec![(0, 0), (7, 0), (14, 0)]);
+    }
+
+    #[test]
+    fn distance_iter_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            let l = v.len().min(w.len());
+            let expected = super::naive(&v[..l], &w[..l]);
+            let pairs = v.iter().cloned().zip(w.iter().cloned());
+            let exact = super::distance_iter_exact(v.iter().cloned(), w.iter().cloned());
+            super::distance_iter(pairs) == expected &&
+                exact.is_ok() == (v.len() == w.len()) &&
+                exact.map_or(true, |d| d == expected) &&
+                super::distance_iter_exact(v.iter().cloned(), v.iter().cloned()) == Ok(0)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool);
+    }
 }
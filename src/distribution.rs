@@ -0,0 +1,267 @@
+use alloc::vec::Vec;
+
+use util::SplitMix64;
+use Database;
+
+/// A histogram of Hamming distances, for summarising a distance
+/// distribution without storing the distances.
+///
+/// Distances between codes of `b` bits take only the `b + 1` values
+/// `0..=b`, so a count for each is an exact summary in `O(b)` memory,
+/// however many distances are added: unlike a general quantile sketch
+/// (such as a t-digest), its quantiles have no approximation error.
+/// Histograms filled separately (for example by several threads, or
+/// on several machines) can be combined with `merge`.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut h = hamming::DistanceHistogram::new(8);
+/// for &d in &[3, 1, 4, 1, 5] {
+///     h.add(d);
+/// }
+/// assert_eq!(h.count(), 5);
+/// assert_eq!(h.quantile(0.5), Some(3));
+/// assert_eq!(h.quantiles(&[0.0, 1.0]), [1, 5]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistanceHistogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl DistanceHistogram {
+    /// Create an empty histogram of distances between codes of `bits`
+    /// bits.
+    pub fn new(bits: usize) -> DistanceHistogram {
+        DistanceHistogram { counts: vec![0; bits + 1], total: 0 }
+    }
+
+    /// Count one occurrence of `distance`.
+    ///
+    /// # Panics
+    ///
+    /// `distance` must be at most the number of bits.
+    pub fn add(&mut self, distance: u64) {
+        assert!(distance < self.counts.len() as u64,
+                "distance {} is larger than {} bits", distance, self.counts.len() - 1);
+        self.counts[distance as usize] += 1;
+        self.total += 1;
+    }
+
+    /// Add the counts of `other` to this histogram.
+    ///
+    /// # Panics
+    ///
+    /// `other` must be a histogram for the same number of bits.
+    pub fn merge(&mut self, other: &DistanceHistogram) {
+        assert!(self.counts.len() == other.counts.len(),
+                "cannot merge histograms of {} and {} bits",
+                self.counts.len() - 1, other.counts.len() - 1);
+        for (c, &o) in self.counts.iter_mut().zip(&other.counts) {
+            *c += o;
+        }
+        self.total += other.total;
+    }
+
+    /// The number of distances counted.
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// The number of occurrences of each distance, where element `d`
+    /// counts distance `d`.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The `q`-quantile of the distances counted: the smallest
+    /// distance `d` such that at least a fraction `q` of them are at
+    /// most `d`, or `None` if there are none.
+    ///
+    /// The 0-quantile is the smallest distance and the 1-quantile the
+    /// largest.
+    ///
+    /// # Panics
+    ///
+    /// `q` must be between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        assert!((0.0..=1.0).contains(&q), "quantile {} is not between 0 and 1", q);
+        if self.total == 0 {
+            return None
+        }
+        // the rank `ceil(q * total)`, at least 1, without `f64::ceil`
+        // (which needs `std`)
+        let exact = q * self.total as f64;
+        let mut rank = exact as u64;
+        if (rank as f64) < exact {
+            rank += 1;
+        }
+        let rank = rank.clamp(1, self.total);
+
+        let mut seen = 0;
+        self.counts.iter().position(|&c| {
+            seen += c;
+            seen >= rank
+        }).map(|d| d as u64)
+    }
+
+    /// The quantile for each element of `qs`, as by `quantile`.
+    ///
+    /// # Panics
+    ///
+    /// There must be at least one distance counted, and every element
+    /// of `qs` must be between 0 and 1.
+    pub fn quantiles(&self, qs: &[f64]) -> Vec<u64> {
+        assert!(self.total > 0, "cannot take quantiles of no distances");
+        qs.iter().map(|&q| self.quantile(q).unwrap()).collect()
+    }
+}
+
+/// The histogram of the distances between every pair of distinct
+/// codes in `db`.
+///
+/// This compares all `n (n - 1) / 2` pairs of the `n` codes, in
+/// `O(n^2)` time but only `O(b)` memory for `b`-bit codes; for large
+/// databases, `distance_histogram_sampled` is much faster.
+pub fn distance_histogram(db: &Database) -> DistanceHistogram {
+    let mut histogram = DistanceHistogram::new(db.code_len() * 8);
+    for i in 0..db.len() {
+        let code = db.get(i);
+        for j in i + 1..db.len() {
+            histogram.add(::distance(code, db.get(j)));
+        }
+    }
+    histogram
+}
+
+/// The histogram of the distances between `pairs` pairs of distinct
+/// codes of `db`, chosen uniformly at random (with replacement) by a
+/// generator seeded with `seed`.
+///
+/// The quantiles of this estimate those of `distance_histogram`, in
+/// `O(pairs)` time. A quantile's error depends on the number of pairs
+/// sampled but not on the size of the database: with 10,000 pairs,
+/// the fraction of all pairs below an estimated quantile is within
+/// about 0.01 of the fraction asked for.
+///
+/// # Panics
+///
+/// `db` must have at least two codes, unless `pairs` is 0.
+pub fn distance_histogram_sampled(db: &Database, pairs: usize, seed: u64) -> DistanceHistogram {
+    let n = db.len();
+    assert!(pairs == 0 || n >= 2, "cannot sample pairs of {} codes", n);
+    let mut histogram = DistanceHistogram::new(db.code_len() * 8);
+    let mut rng = SplitMix64::new(seed);
+    for _ in 0..pairs {
+        let i = rng.below(n);
+        let mut j = rng.below(n - 1);
+        if j >= i {
+            j += 1;
+        }
+        histogram.add(::distance(db.get(i), db.get(j)));
+    }
+    histogram
+}
+
+/// The number of pairs compared by `distance_quantiles`.
+const QUANTILE_PAIRS: usize = 1 << 20;
+
+/// The quantiles `qs` of the distances between pairs of distinct
+/// codes of `db`, for choosing thresholds such as the radius below
+/// which codes count as duplicates.
+///
+/// These are exact, from `distance_histogram`, for databases of up to
+/// about 1,500 codes, and otherwise estimated from a fixed sample of
+/// about a million pairs with `distance_histogram_sampled`, to within
+/// about 0.001 of each `q`. Either way, this never stores the
+/// distances, and its time is bounded independently of the size of
+/// the database. Use those functions directly to trade speed for
+/// accuracy, or to combine the distances of several databases.
+///
+/// # Panics
+///
+/// `db` must have at least two codes, and every element of `qs` must
+/// be between 0 and 1.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x01, 0x03, 0xFF]);
+/// // the distances are 1, 2, 8, 1, 7 and 6
+/// assert_eq!(hamming::distance_quantiles(&db, &[0.0, 0.5, 1.0]), [1, 2, 8]);
+/// ```
+pub fn distance_quantiles(db: &Database, qs: &[f64]) -> Vec<u64> {
+    let n = db.len();
+    let all_pairs = (n as u128 * n.saturating_sub(1) as u128) / 2;
+    let histogram = if all_pairs <= QUANTILE_PAIRS as u128 {
+        distance_histogram(db)
+    } else {
+        distance_histogram_sampled(db, QUANTILE_PAIRS, 0x5EED_D157)
+    };
+    histogram.quantiles(qs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_histogram, distance_histogram_sampled, distance_quantiles, DistanceHistogram};
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn quantiles_qc() {
+        fn prop(v: Vec<u8>, qs: Vec<u16>) -> bool {
+            let db = Database::from_packed(1, &v);
+            let mut distances = Vec::new();
+            for i in 0..v.len() {
+                for j in i + 1..v.len() {
+                    distances.push(::distance(&v[i..i + 1], &v[j..j + 1]));
+                }
+            }
+            distances.sort();
+            let histogram = distance_histogram(&db);
+            if distances.is_empty() {
+                return histogram.count() == 0 && histogram.quantile(0.5).is_none()
+            }
+            let qs = qs.iter().map(|&q| q as f64 / u16::MAX as f64).collect::<Vec<_>>();
+            // the smallest distance with at least a fraction q at or
+            // below it
+            let expected = qs.iter().map(|&q| {
+                *distances.iter()
+                    .find(|&&d| distances.iter().filter(|&&e| e <= d).count() as f64 >=
+                          q * distances.len() as f64)
+                    .unwrap()
+            }).collect::<Vec<_>>();
+            histogram.count() == distances.len() as u64 &&
+                histogram.quantiles(&qs) == expected &&
+                distance_quantiles(&db, &qs) == expected
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 50))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u16>) -> bool)
+    }
+
+    #[test]
+    fn sampled() {
+        let db = Database::from_codes(2, (0..2000u16).map(|i| i.wrapping_mul(40503).to_le_bytes()));
+        let exact = distance_histogram(&db).quantiles(&[0.1, 0.5, 0.9]);
+        let sampled = distance_histogram_sampled(&db, 20_000, 1);
+        assert_eq!(sampled.count(), 20_000);
+        for (q, e) in sampled.quantiles(&[0.1, 0.5, 0.9]).into_iter().zip(exact) {
+            assert!(q.abs_diff(e) <= 1, "{} vs {}", q, e);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = DistanceHistogram::new(4);
+        let mut b = DistanceHistogram::new(4);
+        a.add(1);
+        b.add(4);
+        b.add(1);
+        a.merge(&b);
+        assert_eq!(a.counts(), [0, 2, 0, 0, 1]);
+        assert_eq!(a.count(), 3);
+    }
+}
@@ -0,0 +1,670 @@
+//! Error-correcting Hamming codes.
+//!
+//! The Hamming(7,4) code stores 4 data bits in a 7-bit codeword with
+//! 3 parity bits, arranged so that any two codewords are at distance
+//! at least 3 from each other. Decoding finds the nearest codeword,
+//! and so corrects any single flipped bit.
+//!
+//! Codewords use the classic layout: numbering the bits from 1, the
+//! parity bits are at positions 1, 2 and 4, and the data bits at
+//! positions 3, 5, 6 and 7, so the syndrome of a codeword with one
+//! flipped bit is the position of that bit. Position `p` is stored in
+//! bit `p - 1` of a `u8`, and data bit `i` is bit `i` of the nibble.
+//!
+//! The extended (SECDED) variants add an overall parity bit, raising
+//! the minimum distance to 4: single errors are still corrected, and
+//! double errors are detected rather than miscorrected. Both
+//! SECDED(8,4) and the (72,64) code protecting 64-bit words in ECC
+//! memory are provided.
+//!
+//! For other linear codes, `syndrome` and `correct` work with any
+//! parity-check matrix, and `majority_decode` handles the simplest
+//! code of all, repetition.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hamming::ecc;
+//!
+//! let codeword = ecc::encode_7_4(0b1011);
+//! assert_eq!(ecc::decode_7_4(codeword), (0b1011, false));
+//! assert_eq!(ecc::decode_7_4(codeword ^ 0b0010000), (0b1011, true));
+//!
+//! // codewords are packed back to back in bulk
+//! let data = b"hello";
+//! let mut encoded = [0; 9];
+//! assert_eq!(ecc::encoded_len_7_4(data.len()), encoded.len());
+//! ecc::encode_bytes_7_4(data, &mut encoded);
+//! encoded[3] ^= 0x40;
+//!
+//! let mut decoded = [0; 5];
+//! assert_eq!(ecc::decode_bytes_7_4(&encoded, &mut decoded), 1);
+//! assert_eq!(&decoded, data);
+//! ```
+
+use util::VerticalCounter;
+
+/// The codeword of each nibble.
+static ENCODE_7_4: [u8; 16] = encode_table();
+
+/// The corrected nibble of each 7-bit word, with the top bit set when
+/// a correction was made.
+static DECODE_7_4: [u8; 128] = decode_table();
+
+const fn encode_7_4_(data: u8) -> u8 {
+    let d = [data & 1, data >> 1 & 1, data >> 2 & 1, data >> 3 & 1];
+    let p1 = d[0] ^ d[1] ^ d[3];
+    let p2 = d[0] ^ d[2] ^ d[3];
+    let p3 = d[1] ^ d[2] ^ d[3];
+    p1 | p2 << 1 | d[0] << 2 | p3 << 3 | d[1] << 4 | d[2] << 5 | d[3] << 6
+}
+
+/// The XOR of the (1-based) positions of the set bits of `codeword`.
+const fn syndrome_7_4(codeword: u8) -> u8 {
+    let mut syndrome = 0;
+    let mut p = 1;
+    while p <= 7 {
+        if codeword >> (p - 1) & 1 == 1 {
+            syndrome ^= p;
+        }
+        p += 1;
+    }
+    syndrome
+}
+
+const fn extract_7_4(codeword: u8) -> u8 {
+    (codeword >> 2 & 1) | (codeword >> 3 & 0b1110)
+}
+
+const fn encode_table() -> [u8; 16] {
+    let mut table = [0; 16];
+    let mut i = 0;
+    while i < 16 {
+        table[i] = encode_7_4_(i as u8);
+        i += 1;
+    }
+    table
+}
+
+const fn decode_table() -> [u8; 128] {
+    let mut table = [0; 128];
+    let mut i = 0;
+    while i < 128 {
+        let syndrome = syndrome_7_4(i as u8);
+        table[i] = if syndrome == 0 {
+            extract_7_4(i as u8)
+        } else {
+            extract_7_4(i as u8 ^ 1 << (syndrome - 1)) | 0x80
+        };
+        i += 1;
+    }
+    table
+}
+
+/// Encode the low 4 bits of `data` as a 7-bit Hamming codeword.
+///
+/// The high bits of `data` are ignored, and the high bit of the
+/// result is `0`.
+pub fn encode_7_4(data: u8) -> u8 {
+    ENCODE_7_4[(data & 0xF) as usize]
+}
+
+/// Decode the low 7 bits of `codeword`, returning the data nibble
+/// and whether a bit was corrected.
+///
+/// Any single flipped bit is corrected. Two or more flipped bits are
+/// indistinguishable from a single flip of a different codeword, and
+/// so decode to the wrong data.
+pub fn decode_7_4(codeword: u8) -> (u8, bool) {
+    let decoded = DECODE_7_4[(codeword & 0x7F) as usize];
+    (decoded & 0xF, decoded & 0x80 != 0)
+}
+
+/// The number of bytes needed to hold the packed Hamming(7,4)
+/// encoding of `len` bytes, i.e. `2 * len` 7-bit codewords.
+pub fn encoded_len_7_4(len: usize) -> usize {
+    (7 * len).div_ceil(4)
+}
+
+/// Encode each byte of `data` as two Hamming(7,4) codewords (low
+/// nibble first), packed into `out` as a stream of 7-bit chunks.
+///
+/// Codeword `j` occupies bits `7 * j..7 * j + 7` of `out`, where bit
+/// `i` is bit `i % 8` of byte `i / 8`. Any unused bits at the end are
+/// cleared.
+///
+/// # Panics
+///
+/// `out` must be exactly `encoded_len_7_4(data.len())` bytes long.
+pub fn encode_bytes_7_4(data: &[u8], out: &mut [u8]) {
+    assert_eq!(out.len(), encoded_len_7_4(data.len()));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut out_bytes = out.iter_mut();
+    for &byte in data {
+        buffer |= (encode_7_4(byte) as u32 | (encode_7_4(byte >> 4) as u32) << 7) << bits;
+        bits += 14;
+        while bits >= 8 {
+            *out_bytes.next().unwrap() = buffer as u8;
+            buffer >>= 8;
+            bits -= 8;
+        }
+    }
+    if bits > 0 {
+        *out_bytes.next().unwrap() = buffer as u8;
+    }
+}
+
+/// Decode the packed Hamming(7,4) codewords in `codewords` into
+/// `out`, returning the number of codewords that had a bit corrected.
+///
+/// This is the inverse of `encode_bytes_7_4`, correcting up to one
+/// flipped bit in each codeword. Unused bits at the end of
+/// `codewords` are ignored.
+///
+/// # Panics
+///
+/// `codewords` must be exactly `encoded_len_7_4(out.len())` bytes
+/// long.
+pub fn decode_bytes_7_4(codewords: &[u8], out: &mut [u8]) -> usize {
+    assert_eq!(codewords.len(), encoded_len_7_4(out.len()));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut corrected = 0;
+    let mut in_bytes = codewords.iter();
+    for byte in out {
+        while bits < 14 {
+            buffer |= (*in_bytes.next().unwrap() as u32) << bits;
+            bits += 8;
+        }
+        let (low, fixed_low) = decode_7_4(buffer as u8);
+        let (high, fixed_high) = decode_7_4((buffer >> 7) as u8);
+        *byte = low | high << 4;
+        corrected += fixed_low as usize + fixed_high as usize;
+        buffer >>= 14;
+        bits -= 14;
+    }
+    corrected
+}
+
+/// The outcome of decoding a SECDED codeword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// The codeword was valid.
+    Clean,
+    /// A single flipped bit was corrected.
+    Corrected,
+    /// Two (or more) flipped bits were detected, and the data could
+    /// not be recovered.
+    Uncorrectable,
+}
+
+/// Encode the low 4 bits of `data` as an 8-bit extended Hamming
+/// codeword: the Hamming(7,4) codeword, with the parity of its bits
+/// as bit 7.
+pub fn encode_8_4(data: u8) -> u8 {
+    let codeword = encode_7_4(data);
+    codeword | ((codeword.count_ones() & 1) as u8) << 7
+}
+
+/// Decode an 8-bit extended Hamming codeword, returning the data
+/// nibble and what was found.
+///
+/// Data from an `Uncorrectable` codeword is meaningless.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::ecc::{self, Status};
+///
+/// let codeword = ecc::encode_8_4(0b0110);
+/// assert_eq!(ecc::decode_8_4(codeword), (0b0110, Status::Clean));
+/// assert_eq!(ecc::decode_8_4(codeword ^ 0b0100_0000), (0b0110, Status::Corrected));
+/// assert_eq!(ecc::decode_8_4(codeword ^ 0b0100_0001).1, Status::Uncorrectable);
+/// ```
+pub fn decode_8_4(codeword: u8) -> (u8, Status) {
+    let odd = codeword.count_ones() & 1 == 1;
+    let (data, fixed) = decode_7_4(codeword);
+    let status = match (fixed, odd) {
+        (false, false) => Status::Clean,
+        // either a single error in the first 7 bits, or in the parity
+        // bit itself.
+        (_, true) => Status::Corrected,
+        (true, false) => Status::Uncorrectable,
+    };
+    (data, status)
+}
+
+/// The position (from 1, skipping powers of two) of each data bit in
+/// the (72,64) codeword.
+static POSITIONS_72_64: [u8; 64] = positions_72_64();
+
+/// For each of the 7 Hamming check bits, the data bits it covers.
+static MASKS_72_64: [u64; 7] = masks_72_64();
+
+const fn positions_72_64() -> [u8; 64] {
+    let mut positions = [0; 64];
+    let mut j = 0;
+    let mut p: u8 = 3;
+    while j < 64 {
+        if !p.is_power_of_two() {
+            positions[j] = p;
+            j += 1;
+        }
+        p += 1;
+    }
+    positions
+}
+
+const fn masks_72_64() -> [u64; 7] {
+    let positions = positions_72_64();
+    let mut masks = [0; 7];
+    let mut j = 0;
+    while j < 64 {
+        let mut i = 0;
+        while i < 7 {
+            if positions[j] >> i & 1 == 1 {
+                masks[i] |= 1 << j;
+            }
+            i += 1;
+        }
+        j += 1;
+    }
+    masks
+}
+
+/// The 7 Hamming check bits of `data`.
+fn hamming_checks_72_64(data: u64) -> u8 {
+    MASKS_72_64.iter().enumerate()
+        .fold(0, |check, (i, &mask)| check | (((data & mask).count_ones() & 1) as u8) << i)
+}
+
+/// Compute the 8 check bits protecting `data` with the (72,64)
+/// SECDED code.
+///
+/// Numbering the bits of the 72-bit codeword from 1, bit `i` of the
+/// check byte (for `i < 7`) is the parity bit at position `2^i`, and
+/// the 64 data bits fill the other positions up to 71 in order. Bit 7
+/// is the parity of the whole codeword.
+pub fn encode_72_64(data: u64) -> u8 {
+    let check = hamming_checks_72_64(data);
+    let parity = (data.count_ones() + check.count_ones()) & 1;
+    check | (parity as u8) << 7
+}
+
+/// Decode a 64-bit word and its (72,64) SECDED check byte, returning
+/// the corrected word and what was found.
+///
+/// A single flipped bit, in either `data` or `check`, is corrected.
+/// Data from an `Uncorrectable` codeword is returned as received.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::ecc::{self, Status};
+///
+/// let data = 0x0123_4567_89AB_CDEF;
+/// let check = ecc::encode_72_64(data);
+/// assert_eq!(ecc::decode_72_64(data, check), (data, Status::Clean));
+/// assert_eq!(ecc::decode_72_64(data ^ 1 << 40, check), (data, Status::Corrected));
+/// assert_eq!(ecc::decode_72_64(data, check ^ 0x80), (data, Status::Corrected));
+/// assert_eq!(ecc::decode_72_64(data ^ 1 << 40, check ^ 1).1, Status::Uncorrectable);
+/// ```
+pub fn decode_72_64(data: u64, check: u8) -> (u64, Status) {
+    let syndrome = (hamming_checks_72_64(data) ^ check) & 0x7F;
+    let odd = (data.count_ones() + check.count_ones()) & 1 == 1;
+    match (syndrome, odd) {
+        (0, false) => (data, Status::Clean),
+        // the error is in a check bit, so the data is intact.
+        (0, true) => (data, Status::Corrected),
+        (s, true) if s.is_power_of_two() => (data, Status::Corrected),
+        (s, true) => {
+            match POSITIONS_72_64.iter().position(|&p| p == s) {
+                Some(j) => (data ^ 1 << j, Status::Corrected),
+                // a position past the end of the codeword
+                None => (data, Status::Uncorrectable),
+            }
+        }
+        (_, false) => (data, Status::Uncorrectable),
+    }
+}
+
+/// Decode a repetition code by majority vote, storing into `out` the
+/// bits set in more than half of `copies`, and returning the number of
+/// bit positions where the copies did not all agree.
+///
+/// With `n` copies, up to `(n - 1) / 2` errors in each position are
+/// corrected. For an even number of copies, a tied vote gives a `0`
+/// bit. The votes are tallied 64 positions at a time with bit-sliced
+/// counters.
+///
+/// # Panics
+///
+/// Every copy must be the same length as `out`.
+///
+/// # Examples
+///
+/// ```rust
+/// let copies: [&[u8]; 3] = [&[0b1100, 0xFF], &[0b1010, 0xFF], &[0b1110, 0x7F]];
+/// let mut out = [0; 2];
+/// assert_eq!(hamming::ecc::majority_decode(&copies, &mut out), 3);
+/// assert_eq!(out, [0b1110, 0xFF]);
+/// ```
+pub fn majority_decode(copies: &[&[u8]], out: &mut [u8]) -> u64 {
+    for copy in copies {
+        assert_eq!(copy.len(), out.len(), "copies must be as long as the output");
+    }
+    let threshold = copies.len() as u64 / 2 + 1;
+    let mut disagreements = 0;
+    for (start, chunk) in out.chunks_mut(8).enumerate().map(|(i, c)| (8 * i, c)) {
+        let mut counter = VerticalCounter::new();
+        let (mut any, mut all) = (0, !0);
+        for copy in copies {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(&copy[start..start + chunk.len()]);
+            let word = u64::from_le_bytes(word);
+            counter.add(word);
+            any |= word;
+            all &= word;
+        }
+        let majority = counter.at_least(threshold).to_le_bytes();
+        chunk.copy_from_slice(&majority[..chunk.len()]);
+        if !copies.is_empty() {
+            disagreements += (any ^ all).count_ones() as u64;
+        }
+    }
+    disagreements
+}
+
+/// Compute the syndrome of `codeword` under the parity-check matrix
+/// `parity_check`, storing it in `syndrome`.
+///
+/// `parity_check` holds the rows of the matrix one after another,
+/// each packed into `codeword.len()` bytes, and bit `j` of `syndrome`
+/// is the parity of the AND of row `j` with `codeword`, i.e. the
+/// matrix-vector product over GF(2). Unused bits at the end of
+/// `syndrome` are cleared. The syndrome is zero exactly when
+/// `codeword` is a codeword.
+///
+/// # Panics
+///
+/// `parity_check` must be a whole number of rows, and `syndrome` must
+/// be exactly large enough to hold one bit per row.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::ecc;
+///
+/// // the parity-check matrix of Hamming(7,4)
+/// let h = [0b1010101, 0b1100110, 0b1111000];
+/// let mut syndrome = [0];
+/// ecc::syndrome(&h, &[ecc::encode_7_4(0b1001)], &mut syndrome);
+/// assert_eq!(syndrome, [0]);
+/// ecc::syndrome(&h, &[ecc::encode_7_4(0b1001) ^ 1 << 4], &mut syndrome);
+/// // the 1-based position of the flipped bit
+/// assert_eq!(syndrome, [5]);
+/// ```
+pub fn syndrome(parity_check: &[u8], codeword: &[u8], syndrome: &mut [u8]) {
+    let rows = check_matrix(parity_check, codeword.len(), syndrome.len());
+    for byte in syndrome.iter_mut() {
+        *byte = 0;
+    }
+    for j in 0..rows {
+        let row = &parity_check[j * codeword.len()..(j + 1) * codeword.len()];
+        let parity = row.iter().zip(codeword).fold(0, |acc, (a, b)| acc ^ (a & b)).count_ones() & 1;
+        syndrome[j / 8] |= (parity as u8) << (j % 8);
+    }
+}
+
+/// Correct a single flipped bit of `codeword`, given its `syndrome`
+/// under `parity_check` (as computed by `syndrome`).
+///
+/// A flip of bit `i` changes the syndrome by column `i` of the
+/// matrix, so a non-zero syndrome equal to column `i` is corrected by
+/// flipping bit `i` back. For this to be unambiguous the columns must
+/// be distinct and non-zero, as for any code of minimum distance at
+/// least 3. If no column matches, nothing is changed and the codeword
+/// is `Uncorrectable`.
+///
+/// # Panics
+///
+/// `parity_check` and `syndrome` must be as for `syndrome`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::ecc::{self, Status};
+///
+/// let h = [0b1010101, 0b1100110, 0b1111000];
+/// let mut codeword = [ecc::encode_7_4(0b1001) ^ 1 << 4];
+/// let mut syndrome = [0];
+/// ecc::syndrome(&h, &codeword, &mut syndrome);
+/// assert_eq!(ecc::correct(&h, &mut codeword, &syndrome), Status::Corrected);
+/// assert_eq!(codeword, [ecc::encode_7_4(0b1001)]);
+/// ```
+pub fn correct(parity_check: &[u8], codeword: &mut [u8], syndrome: &[u8]) -> Status {
+    let len = codeword.len();
+    let rows = check_matrix(parity_check, len, syndrome.len());
+    if syndrome.iter().all(|&b| b == 0) {
+        return Status::Clean
+    }
+    let bit = |bytes: &[u8], i: usize| bytes[i / 8] >> (i % 8) & 1;
+    let column = (0..8 * len).find(|&i| {
+        (0..rows).all(|j| bit(&parity_check[j * len..], i) == bit(syndrome, j))
+    });
+    match column {
+        Some(i) => {
+            codeword[i / 8] ^= 1 << (i % 8);
+            Status::Corrected
+        }
+        None => Status::Uncorrectable,
+    }
+}
+
+/// Check the dimensions of a parity-check matrix with rows of `len`
+/// bytes and a syndrome of `syndrome_len` bytes, returning the number
+/// of rows.
+fn check_matrix(parity_check: &[u8], len: usize, syndrome_len: usize) -> usize {
+    assert!(len > 0 && parity_check.len().is_multiple_of(len),
+            "{} bytes are not a whole number of {}-byte rows", parity_check.len(), len);
+    let rows = parity_check.len() / len;
+    assert_eq!(syndrome_len, rows.div_ceil(8),
+               "a syndrome of {} bytes cannot hold {} bits", syndrome_len, rows);
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn codewords() {
+        // the standard generator, with data bits at positions 3, 5, 6, 7
+        assert_eq!(encode_7_4(0b0000), 0b0000000);
+        assert_eq!(encode_7_4(0b0001), 0b0000111);
+        assert_eq!(encode_7_4(0b1111), 0b1111111);
+        for a in 0..16 {
+            assert_eq!(syndrome_7_4(encode_7_4(a)), 0);
+            assert_eq!(decode_7_4(encode_7_4(a)), (a, false));
+            for b in 0..a {
+                assert!(::distance(&[encode_7_4(a)], &[encode_7_4(b)]) >= 3);
+            }
+        }
+    }
+
+    #[test]
+    fn single_errors() {
+        for a in 0..16 {
+            for p in 0..7 {
+                let flipped = encode_7_4(a) ^ 1 << p;
+                assert_eq!(syndrome_7_4(flipped), p + 1);
+                assert_eq!(decode_7_4(flipped), (a, true));
+            }
+        }
+    }
+
+    #[test]
+    fn bytes_qc() {
+        fn prop(data: Vec<u8>, flips: Vec<usize>) -> bool {
+            let mut encoded = vec![0; encoded_len_7_4(data.len())];
+            encode_bytes_7_4(&data, &mut encoded);
+
+            // flip at most one bit in each of some codewords
+            let codewords = 2 * data.len();
+            let mut flipped = vec![false; codewords];
+            for &f in &flips {
+                if codewords > 0 && !flipped[f % codewords] {
+                    let bit = 7 * (f % codewords) + f / codewords % 7;
+                    encoded[bit / 8] ^= 1 << (bit % 8);
+                    flipped[f % codewords] = true;
+                }
+            }
+
+            let mut decoded = vec![0; data.len()];
+            let corrected = decode_bytes_7_4(&encoded, &mut decoded);
+            decoded == data && corrected == flipped.iter().filter(|&&f| f).count()
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<usize>) -> bool)
+    }
+
+    #[test]
+    fn bytes_layout() {
+        let mut encoded = [0xFF; 4];
+        encode_bytes_7_4(&[0x10, 0x0F], &mut encoded);
+        let words = [0b0000000, 0b0000111, 0b1111111, 0b0000000];
+        let packed = words.iter().enumerate().fold(0u32, |acc, (j, &w)| acc | w << (7 * j));
+        assert_eq!(encoded, packed.to_le_bytes());
+    }
+
+    #[test]
+    fn secded_8_4() {
+        for a in 0..16 {
+            let codeword = encode_8_4(a);
+            assert_eq!(codeword & 0x7F, encode_7_4(a));
+            assert_eq!(codeword.count_ones() % 2, 0);
+            assert_eq!(decode_8_4(codeword), (a, Status::Clean));
+            for p in 0..8 {
+                assert_eq!(decode_8_4(codeword ^ 1 << p), (a, Status::Corrected));
+                for q in 0..p {
+                    assert_eq!(decode_8_4(codeword ^ 1 << p ^ 1 << q).1, Status::Uncorrectable);
+                }
+            }
+            for b in 0..a {
+                assert!(::distance(&[codeword], &[encode_8_4(b)]) >= 4);
+            }
+        }
+    }
+
+    #[test]
+    fn layout_72_64() {
+        assert_eq!(POSITIONS_72_64[..5], [3, 5, 6, 7, 9]);
+        assert_eq!(POSITIONS_72_64[63], 71);
+        // data bit 0 is at position 3 = 0b11
+        assert_eq!(encode_72_64(1), 0b1000_0011);
+        assert_eq!(encode_72_64(0), 0);
+    }
+
+    #[test]
+    fn secded_72_64_qc() {
+        fn prop(data: u64, p: u8, q: u8) -> bool {
+            // flip bits `p` and `q` of the 72-bit codeword, with bits
+            // 64.. being the check byte
+            let flip = |data: u64, check: u8, bit: u8| {
+                let bit = bit % 72;
+                if bit < 64 { (data ^ 1 << bit, check) } else { (data, check ^ 1 << (bit - 64)) }
+            };
+            let check = encode_72_64(data);
+            let (d1, c1) = flip(data, check, p);
+            let (d2, c2) = flip(d1, c1, q);
+
+            decode_72_64(data, check) == (data, Status::Clean) &&
+                decode_72_64(d1, c1) == (data, Status::Corrected) &&
+                (p % 72 == q % 72 || decode_72_64(d2, c2) == (d2, Status::Uncorrectable))
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(u64, u8, u8) -> bool)
+    }
+
+    #[test]
+    fn syndrome_7_4_matrix() {
+        let h = [0b1010101, 0b1100110, 0b1111000];
+        for word in 0..128u8 {
+            let mut s = [0xFF];
+            syndrome(&h, &[word], &mut s);
+            assert_eq!(s, [syndrome_7_4(word)]);
+
+            let mut corrected = [word];
+            let status = correct(&h, &mut corrected, &s);
+            let (data, fixed) = decode_7_4(word);
+            assert_eq!(status, if fixed { Status::Corrected } else { Status::Clean });
+            assert_eq!(extract_7_4(corrected[0]), data);
+        }
+    }
+
+    #[test]
+    fn syndrome_qc() {
+        fn prop(h: Vec<u8>, codeword: Vec<u8>) -> bool {
+            if codeword.is_empty() {
+                return true
+            }
+            let len = codeword.len();
+            let rows = h.len() / len;
+            let h = &h[..rows * len];
+            let mut s = vec![0xFF; rows.div_ceil(8)];
+            syndrome(h, &codeword, &mut s);
+
+            let bit = |bytes: &[u8], i: usize| bytes[i / 8] >> (i % 8) & 1;
+            let naive = (0..rows).all(|j| {
+                let parity = (0..8 * len).fold(0, |p, i| p ^ (bit(&h[j * len..], i) & bit(&codeword, i)));
+                bit(&s, j) == parity
+            });
+            naive && (rows.is_multiple_of(8) || s[rows / 8] >> (rows % 8) == 0)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool)
+    }
+
+    #[test]
+    fn correct_uncorrectable() {
+        // two identical columns, and no column equal to 0b11
+        let h = [0b0011, 0b0100];
+        let mut codeword = [0b0001];
+        let mut s = [0];
+        syndrome(&h, &codeword, &mut s);
+        assert_eq!(s, [0b01]);
+        assert_eq!(correct(&h, &mut codeword, &s), Status::Corrected);
+        assert_eq!(codeword, [0]);
+        assert_eq!(correct(&h, &mut codeword, &[0b11]), Status::Uncorrectable);
+        assert_eq!(codeword, [0]);
+    }
+
+    #[test]
+    fn majority_decode_qc() {
+        fn prop(v: Vec<u8>, n: u8) -> bool {
+            let n = n as usize % 8;
+            let len = v.len() / n.max(1);
+            let copies = (0..n).map(|c| &v[c * len..(c + 1) * len]).collect::<Vec<_>>();
+            let mut out = vec![0xAA; len];
+            let disagreements = majority_decode(&copies, &mut out);
+
+            let bit = |bytes: &[u8], i: usize| bytes[i / 8] >> (i % 8) & 1;
+            let mut naive = 0;
+            let ok = (0..8 * len).all(|i| {
+                let set = copies.iter().filter(|c| bit(c, i) == 1).count();
+                naive += (set != 0 && set != n) as u64;
+                bit(&out, i) == (2 * set > n) as u8
+            });
+            ok && disagreements == naive
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 200))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+}
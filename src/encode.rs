@@ -0,0 +1,428 @@
+//! Encodings of numbers as binary codes whose Hamming distances
+//! reflect how close the numbers are.
+//!
+//! Plain binary does not: 7 (`0111`) and 8 (`1000`) are neighbours,
+//! but differ in every bit, so quantised sensor readings, genes of a
+//! genetic algorithm, or other ordinal values stored in binary give
+//! misleading distances. In the reflected binary Gray code, consecutive
+//! values differ in exactly one bit, so a small change in the value
+//! only ever flips one bit at a time.
+//!
+//! A thermometer (or unary) code goes further, representing a value
+//! `x` between 0 and `max` by setting the first `x` of `max` bits, so
+//! that the Hamming distance between two codes is exactly the
+//! difference of their values, and between buffers of several such
+//! codes, the L1 distance between the vectors of values. This makes
+//! the searches and indexes of this crate usable for ordinal features,
+//! at the cost of `max` bits per value; `quantize` maps floats onto
+//! the levels.
+//!
+//! Values packed into buffers take `bits` bits each, back to back,
+//! with value `k` in bits `k * bits..(k + 1) * bits` and bit `i` of
+//! the buffer being bit `i % 8` of its byte `i / 8`, least significant
+//! bit of the value first.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hamming::encode::{from_gray, to_gray};
+//!
+//! assert_eq!(hamming::distance(&[7u8], &[8]), 4);
+//! assert_eq!(hamming::distance(&[to_gray(7) as u8], &[to_gray(8) as u8]), 1);
+//! assert_eq!(from_gray(to_gray(12345)), 12345);
+//!
+//! // L1 distance, via thermometer codes
+//! let (mut a, mut b) = ([0; 3], [0; 3]);
+//! hamming::encode::encode_thermometer(&[2, 7, 5], 8, &mut a);
+//! hamming::encode::encode_thermometer(&[6, 7, 0], 8, &mut b);
+//! assert_eq!(hamming::distance(&a, &b), 4 + 0 + 5);
+//! ```
+
+use core::cmp::Ordering;
+
+use checked::{output_length, unwrap, ArgumentError};
+
+/// The reflected binary Gray code of `x`, `x ^ (x >> 1)`.
+///
+/// The codes of `x` and `x + 1` differ in exactly one bit, and the
+/// codes of values less than `2^b` are also less than `2^b`.
+pub fn to_gray(x: u64) -> u64 {
+    x ^ (x >> 1)
+}
+
+/// The value whose Gray code is `g`, the inverse of `to_gray`.
+pub fn from_gray(g: u64) -> u64 {
+    // each bit is the xor of itself and every higher bit of `g`
+    let mut x = g;
+    for shift in [1, 2, 4, 8, 16, 32] {
+        x ^= x >> shift;
+    }
+    x
+}
+
+/// Check that `bytes` bytes exactly hold `values` values of `bits`
+/// bits each, for `bits` up to `max_bits`.
+fn check_packing(values: usize, bits: usize, max_bits: usize, bytes: usize) -> Result<(), ArgumentError> {
+    if bits == 0 || bits > max_bits {
+        return Err(ArgumentError::InvalidSize { name: "number of bits", value: bits })
+    }
+    let expected = values.checked_mul(bits).ok_or(ArgumentError::Overflow)?.div_ceil(8);
+    output_length(bytes, expected)
+}
+
+/// Check that every one of `values` is at most `max`.
+fn check_values(values: &[u64], max: u64) -> Result<(), ArgumentError> {
+    match values.iter().position(|&x| x > max) {
+        Some(index) => Err(ArgumentError::ValueTooLarge { index, value: values[index], max }),
+        None => Ok(()),
+    }
+}
+
+/// Pack the Gray code of each of `values` into `out`, in `bits` bits
+/// each.
+///
+/// Any padding bits at the end of the last byte are zero, so
+/// `distance` between two buffers of the same length is the sum of
+/// the distances between their codes.
+///
+/// # Panics
+///
+/// `bits` must be between 1 and 64, each value must fit in `bits`
+/// bits, and `out` must be exactly `(values.len() * bits).div_ceil(8)`
+/// bytes long.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::encode_gray;
+///
+/// let (mut a, mut b) = ([0], [0]);
+/// encode_gray(&[3, 15], 4, &mut a);
+/// encode_gray(&[4, 14], 4, &mut b);
+/// assert_eq!(hamming::distance(&a, &b), 2);
+/// ```
+pub fn encode_gray(values: &[u64], bits: usize, out: &mut [u8]) {
+    unwrap(try_encode_gray(values, bits, out))
+}
+
+/// As `encode_gray`, but returns an error if `bits` is out of range, a
+/// value doesn't fit, or `out` has the wrong length, rather than
+/// panicking. `out` is left unchanged on error.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::try_encode_gray;
+///
+/// let mut out = [0];
+/// assert_eq!(try_encode_gray(&[3, 15], 4, &mut out), Ok(()));
+/// assert!(try_encode_gray(&[3, 16], 4, &mut out).is_err());
+/// assert!(try_encode_gray(&[3, 15, 1], 4, &mut out).is_err());
+/// ```
+pub fn try_encode_gray(values: &[u64], bits: usize, out: &mut [u8]) -> Result<(), ArgumentError> {
+    check_packing(values.len(), bits, 64, out.len())?;
+    check_values(values, !0 >> (64 - bits))?;
+    for o in out.iter_mut() {
+        *o = 0;
+    }
+    for (k, &x) in values.iter().enumerate() {
+        let g = to_gray(x);
+        for i in 0..bits {
+            let position = k * bits + i;
+            out[position / 8] |= ((g >> i & 1) as u8) << (position % 8);
+        }
+    }
+    Ok(())
+}
+
+/// Unpack the `bits`-bit Gray codes in `codes` into `out`, the inverse
+/// of `encode_gray`.
+///
+/// # Panics
+///
+/// `bits` must be between 1 and 64, and `codes` must be exactly
+/// `(out.len() * bits).div_ceil(8)` bytes long.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::{decode_gray, encode_gray};
+///
+/// let mut codes = [0; 3];
+/// encode_gray(&[1000, 1001], 11, &mut codes);
+/// let mut values = [0; 2];
+/// decode_gray(&codes, 11, &mut values);
+/// assert_eq!(values, [1000, 1001]);
+/// ```
+pub fn decode_gray(codes: &[u8], bits: usize, out: &mut [u64]) {
+    unwrap(try_decode_gray(codes, bits, out))
+}
+
+/// As `decode_gray`, but returns an error if `bits` is out of range or
+/// `codes` has the wrong length, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::try_decode_gray;
+///
+/// let mut values = [0; 2];
+/// assert_eq!(try_decode_gray(&[0b1000_0010], 4, &mut values), Ok(()));
+/// assert_eq!(values, [3, 15]);
+/// assert!(try_decode_gray(&[0b1000_0010], 65, &mut values).is_err());
+/// ```
+pub fn try_decode_gray(codes: &[u8], bits: usize, out: &mut [u64]) -> Result<(), ArgumentError> {
+    check_packing(out.len(), bits, 64, codes.len())?;
+    for (k, o) in out.iter_mut().enumerate() {
+        let g = (0..bits).fold(0, |g, i| {
+            let position = k * bits + i;
+            g | ((codes[position / 8] >> (position % 8) & 1) as u64) << i
+        });
+        *o = from_gray(g);
+    }
+    Ok(())
+}
+
+/// Set the `len` bits of `out` starting at bit `start`.
+fn set_bits(out: &mut [u8], start: usize, len: usize) {
+    let end = start + len;
+    let mut i = start;
+    while i < end {
+        if i.is_multiple_of(8) && end - i >= 8 {
+            out[i / 8] = 0xFF;
+            i += 8;
+        } else {
+            out[i / 8] |= 1 << (i % 8);
+            i += 1;
+        }
+    }
+}
+
+/// Pack the thermometer code of each of `values` into `out`, in `max`
+/// bits each: the first `x` bits of the code of `x` are set, and the
+/// rest clear.
+///
+/// The distance between the codes of `x` and `y` is `|x - y|`, and
+/// between two buffers, the L1 distance between their values. Any
+/// padding bits at the end of the last byte are zero.
+///
+/// # Panics
+///
+/// `max` must be non-zero, each value must be at most `max`, and
+/// `out` must be exactly `(values.len() * max).div_ceil(8)` bytes
+/// long.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::encode_thermometer;
+///
+/// let mut code = [0; 2];
+/// encode_thermometer(&[3, 0, 4], 4, &mut code);
+/// assert_eq!(code, [0b0000_0111, 0b0000_1111]);
+/// ```
+pub fn encode_thermometer(values: &[u64], max: usize, out: &mut [u8]) {
+    unwrap(try_encode_thermometer(values, max, out))
+}
+
+/// As `encode_thermometer`, but returns an error if `max` is zero, a
+/// value is larger than it, or `out` has the wrong length, rather than
+/// panicking. `out` is left unchanged on error.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::try_encode_thermometer;
+///
+/// let mut code = [0; 2];
+/// assert_eq!(try_encode_thermometer(&[3, 0, 4], 4, &mut code), Ok(()));
+/// assert!(try_encode_thermometer(&[3, 0, 5], 4, &mut code).is_err());
+/// assert!(try_encode_thermometer(&[3, 0, 4], 0, &mut code).is_err());
+/// ```
+pub fn try_encode_thermometer(values: &[u64], max: usize, out: &mut [u8]) -> Result<(), ArgumentError> {
+    check_packing(values.len(), max, usize::MAX, out.len())?;
+    check_values(values, max as u64)?;
+    for o in out.iter_mut() {
+        *o = 0;
+    }
+    for (k, &x) in values.iter().enumerate() {
+        set_bits(out, k * max, x as usize);
+    }
+    Ok(())
+}
+
+/// Unpack the `max`-bit thermometer codes in `codes` into `out`, the
+/// inverse of `encode_thermometer`.
+///
+/// Each value is decoded as the number of set bits in its code, which
+/// is also the nearest valid value to a code that has been corrupted
+/// (it minimises the distance to the corrupted code).
+///
+/// # Panics
+///
+/// `max` must be non-zero, and `codes` must be exactly `(out.len() *
+/// max).div_ceil(8)` bytes long.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::decode_thermometer;
+///
+/// let mut values = [0; 3];
+/// decode_thermometer(&[0b0000_0111, 0b0000_1111], 4, &mut values);
+/// assert_eq!(values, [3, 0, 4]);
+/// ```
+pub fn decode_thermometer(codes: &[u8], max: usize, out: &mut [u64]) {
+    unwrap(try_decode_thermometer(codes, max, out))
+}
+
+/// As `decode_thermometer`, but returns an error if `max` is zero or
+/// `codes` has the wrong length, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::try_decode_thermometer;
+///
+/// let mut values = [0; 3];
+/// assert_eq!(try_decode_thermometer(&[0b0000_0111, 0b0000_1111], 4, &mut values), Ok(()));
+/// assert_eq!(values, [3, 0, 4]);
+/// assert!(try_decode_thermometer(&[0b0000_0111], 4, &mut values).is_err());
+/// ```
+pub fn try_decode_thermometer(codes: &[u8], max: usize, out: &mut [u64]) -> Result<(), ArgumentError> {
+    check_packing(out.len(), max, usize::MAX, codes.len())?;
+    for (k, o) in out.iter_mut().enumerate() {
+        *o = ::try_weight_bits(codes, k * max, max)?;
+    }
+    Ok(())
+}
+
+/// Quantise `x` to one of the levels `0..=max`, by spacing them evenly
+/// from `low` (level 0) to `high` (level `max`) and rounding to the
+/// nearest, for `encode_thermometer`.
+///
+/// Values outside the range are clamped to it, and NaN is mapped to
+/// level 0.
+///
+/// # Panics
+///
+/// `low` must be less than `high`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::quantize;
+///
+/// assert_eq!(quantize(0.26, 0.0, 1.0, 4), 1);
+/// assert_eq!(quantize(0.4, 0.0, 1.0, 4), 2);
+/// assert_eq!(quantize(7.0, 0.0, 1.0, 4), 4);
+/// assert_eq!(quantize(-1.0, 0.0, 1.0, 4), 0);
+/// ```
+pub fn quantize(x: f32, low: f32, high: f32, max: usize) -> u64 {
+    unwrap(try_quantize(x, low, high, max))
+}
+
+/// As `quantize`, but returns an error if `low` isn't less than
+/// `high`, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::encode::try_quantize;
+///
+/// assert_eq!(try_quantize(0.4, 0.0, 1.0, 4), Ok(2));
+/// assert!(try_quantize(0.4, 1.0, 1.0, 4).is_err());
+/// assert!(try_quantize(0.4, f32::NAN, 1.0, 4).is_err());
+/// ```
+pub fn try_quantize(x: f32, low: f32, high: f32, max: usize) -> Result<u64, ArgumentError> {
+    if low.partial_cmp(&high) != Some(Ordering::Less) {
+        return Err(ArgumentError::EmptyRange)
+    }
+    let t = (x - low) / (high - low);
+    Ok(if t.is_nan() || t <= 0.0 {
+        0
+    } else if t >= 1.0 {
+        max as u64
+    } else {
+        // rounding, without `f32::round` (which needs `std`)
+        ((t * max as f32 + 0.5) as u64).min(max as u64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_gray, decode_thermometer, encode_gray, encode_thermometer, from_gray, quantize, to_gray};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn gray_qc() {
+        fn prop(x: u64) -> bool {
+            let next = x.wrapping_add(1);
+            from_gray(to_gray(x)) == x && (to_gray(x) ^ to_gray(next)).count_ones() == 1
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(u64) -> bool);
+        assert_eq!((0..8).map(to_gray).collect::<Vec<_>>(), [0, 1, 3, 2, 6, 7, 5, 4]);
+    }
+
+    #[test]
+    fn packed_qc() {
+        fn prop(v: Vec<u64>, bits: u8) -> bool {
+            let bits = bits as usize % 64 + 1;
+            let mask = if bits == 64 { !0 } else { (1 << bits) - 1 };
+            let values = v.iter().map(|&x| x & mask).collect::<Vec<_>>();
+            let mut codes = vec![0xFF; (values.len() * bits).div_ceil(8)];
+            encode_gray(&values, bits, &mut codes);
+            let mut decoded = vec![0; values.len()];
+            decode_gray(&codes, bits, &mut decoded);
+
+            // each value stepping by one moves its code by one bit
+            let stepped = values.iter().map(|&x| if x == mask { x - 1 } else { x + 1 })
+                .collect::<Vec<_>>();
+            let mut stepped_codes = vec![0; codes.len()];
+            encode_gray(&stepped, bits, &mut stepped_codes);
+            decoded == values &&
+                ::weight(&codes) == values.iter().map(|&x| to_gray(x).count_ones() as u64).sum::<u64>() &&
+                ::distance(&codes, &stepped_codes) == values.len() as u64
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u64>, u8) -> bool);
+    }
+
+    #[test]
+    fn thermometer_qc() {
+        fn prop(v: Vec<(u8, u8)>, max: u8) -> bool {
+            let max = max as usize % 20 + 1;
+            let a = v.iter().map(|&(x, _)| x as u64 % (max as u64 + 1)).collect::<Vec<_>>();
+            let b = v.iter().map(|&(_, y)| y as u64 % (max as u64 + 1)).collect::<Vec<_>>();
+            let len = (v.len() * max).div_ceil(8);
+            let (mut code_a, mut code_b) = (vec![0xFF; len], vec![0; len]);
+            encode_thermometer(&a, max, &mut code_a);
+            encode_thermometer(&b, max, &mut code_b);
+            let mut decoded = vec![0; v.len()];
+            decode_thermometer(&code_a, max, &mut decoded);
+            decoded == a &&
+                ::distance(&code_a, &code_b) == a.iter().zip(&b).map(|(x, y)| x.abs_diff(*y)).sum::<u64>()
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<(u8, u8)>, u8) -> bool);
+    }
+
+    #[test]
+    fn quantize_levels() {
+        let levels = (0..=100).map(|i| quantize(i as f32 / 100.0, 0.0, 1.0, 10)).collect::<Vec<_>>();
+        assert!(levels.windows(2).all(|w| w[0] <= w[1] && w[1] - w[0] <= 1));
+        assert_eq!((levels[0], levels[50], levels[100]), (0, 5, 10));
+        assert_eq!(quantize(f32::NAN, 0.0, 1.0, 10), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_wide() {
+        encode_gray(&[16], 4, &mut [0]);
+    }
+}
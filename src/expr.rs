@@ -0,0 +1,248 @@
+//! Lazy bitwise expressions over byte slices, evaluated in a single
+//! fused pass.
+//!
+//! Bitmap-index queries often need the weight of a combination of
+//! several bitmaps, like `(a & !deleted) ^ b`. Computing that with
+//! temporary buffers reads and writes every intermediate result;
+//! instead, `bits` wraps each operand, the operators `&`, `|`, `^`
+//! and `!` build an expression from them without doing any work, and
+//! `popcount` (or `eval_into`) then streams over all the operands at
+//! once, 64 bits at a time, combining each word in registers and
+//! never materialising an intermediate. The expression types are
+//! plain structs of their operands, so the whole expression is
+//! monomorphised into a single loop.
+//!
+//! Bit `i` of an operand is bit `i % 8` of its byte `i / 8`, as
+//! everywhere else in this crate, and `!` only inverts the bits of
+//! the operand, never any padding beyond its last byte. Combining
+//! operands of different lengths panics.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hamming::expr::{bits, popcount, BitExpr};
+//!
+//! let a = [0b1111_0000u8, 0xFF];
+//! let mask = [0b1100_0000u8, 0x0F];
+//! let b = [0b0000_0001u8, 0x00];
+//!
+//! // (a & !mask) ^ b = [0b0011_0001, 0xF0]
+//! assert_eq!(popcount((bits(&a) & !bits(&mask)) ^ bits(&b)), 3 + 4);
+//!
+//! let mut out = [0; 2];
+//! ((bits(&a) & !bits(&mask)) ^ bits(&b)).eval_into(&mut out);
+//! assert_eq!(out, [0b0011_0001, 0xF0]);
+//! ```
+
+use core::convert::TryInto;
+use core::ops;
+
+/// A bitwise expression over byte slices of a common length.
+///
+/// This is implemented by the operands made with `bits`, and by the
+/// results of combining them with `&`, `|`, `^` and `!`.
+pub trait BitExpr: Copy {
+    /// The length of the expression in bytes.
+    fn len(&self) -> usize;
+
+    /// Whether the expression is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value of the 64 bits starting at byte `8 * i`, as a
+    /// little-endian word. The bits of the last word past the end of
+    /// the expression are unspecified.
+    #[doc(hidden)]
+    fn word(&self, i: usize) -> u64;
+
+    /// The number of set bits in the value of the expression.
+    fn popcount(self) -> u64 {
+        let len = self.len();
+        let whole = len / 8;
+        let mut total = (0..whole).map(|i| self.word(i).count_ones() as u64).sum::<u64>();
+        if !len.is_multiple_of(8) {
+            let mask = (1u64 << (8 * (len % 8))) - 1;
+            total += (self.word(whole) & mask).count_ones() as u64;
+        }
+        total
+    }
+
+    /// Store the value of the expression in `out`.
+    ///
+    /// # Panics
+    ///
+    /// `out` must be `self.len()` bytes long.
+    fn eval_into(self, out: &mut [u8]) {
+        assert_eq!(out.len(), self.len(), "output length doesn't match the expression");
+        for (i, chunk) in out.chunks_mut(8).enumerate() {
+            let word = self.word(i).to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+}
+
+/// The number of set bits in the value of `expr`, computed in a
+/// single pass over its operands.
+///
+/// This is `expr.popcount()`, as a function so that queries read like
+/// `popcount((a & !mask) ^ b)`.
+pub fn popcount<E: BitExpr>(expr: E) -> u64 {
+    expr.popcount()
+}
+
+/// Wrap `x` as an operand of a bitwise expression.
+pub fn bits(x: &[u8]) -> Bits<'_> {
+    Bits(x)
+}
+
+/// A byte slice as an operand of a bitwise expression, made by `bits`.
+#[derive(Debug, Clone, Copy)]
+pub struct Bits<'a>(&'a [u8]);
+
+impl<'a> BitExpr for Bits<'a> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    fn word(&self, i: usize) -> u64 {
+        let x = self.0;
+        let start = 8 * i;
+        if start + 8 <= x.len() {
+            u64::from_le_bytes(x[start..start + 8].try_into().unwrap())
+        } else {
+            let mut buf = [0; 8];
+            buf[..x.len() - start].copy_from_slice(&x[start..]);
+            u64::from_le_bytes(buf)
+        }
+    }
+}
+
+fn check_lengths<A: BitExpr, B: BitExpr>(a: &A, b: &B) {
+    assert_eq!(a.len(), b.len(), "operands have different lengths");
+}
+
+macro_rules! binary {
+    ($($name: ident, $op: tt, $doc: expr;)*) => {
+        $(
+            #[doc = $doc]
+            #[derive(Debug, Clone, Copy)]
+            pub struct $name<A, B>(A, B);
+
+            impl<A: BitExpr, B: BitExpr> BitExpr for $name<A, B> {
+                #[inline]
+                fn len(&self) -> usize {
+                    self.0.len()
+                }
+
+                #[inline]
+                fn word(&self, i: usize) -> u64 {
+                    self.0.word(i) $op self.1.word(i)
+                }
+            }
+        )*
+    }
+}
+
+binary! {
+    And, &, "The bitwise and of two expressions, made with `&`.";
+    Or, |, "The bitwise or of two expressions, made with `|`.";
+    Xor, ^, "The bitwise exclusive or of two expressions, made with `^`.";
+}
+
+/// The bitwise complement of an expression, made with `!`.
+#[derive(Debug, Clone, Copy)]
+pub struct Not<A>(A);
+
+impl<A: BitExpr> BitExpr for Not<A> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    fn word(&self, i: usize) -> u64 {
+        !self.0.word(i)
+    }
+}
+
+// the operators, for every expression type
+macro_rules! operators {
+    ($([$($params: tt)*] $t: ty;)*) => {
+        $(
+            impl<$($params)* R: BitExpr> ops::BitAnd<R> for $t {
+                type Output = And<Self, R>;
+                fn bitand(self, r: R) -> And<Self, R> {
+                    check_lengths(&self, &r);
+                    And(self, r)
+                }
+            }
+
+            impl<$($params)* R: BitExpr> ops::BitOr<R> for $t {
+                type Output = Or<Self, R>;
+                fn bitor(self, r: R) -> Or<Self, R> {
+                    check_lengths(&self, &r);
+                    Or(self, r)
+                }
+            }
+
+            impl<$($params)* R: BitExpr> ops::BitXor<R> for $t {
+                type Output = Xor<Self, R>;
+                fn bitxor(self, r: R) -> Xor<Self, R> {
+                    check_lengths(&self, &r);
+                    Xor(self, r)
+                }
+            }
+
+            impl<$($params)*> ops::Not for $t {
+                type Output = Not<Self>;
+                fn not(self) -> Not<Self> {
+                    Not(self)
+                }
+            }
+        )*
+    }
+}
+
+operators! {
+    ['a,] Bits<'a>;
+    [A: BitExpr, B: BitExpr,] And<A, B>;
+    [A: BitExpr, B: BitExpr,] Or<A, B>;
+    [A: BitExpr, B: BitExpr,] Xor<A, B>;
+    [A: BitExpr,] Not<A>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bits, popcount, BitExpr};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn expressions_qc() {
+        fn prop(v: Vec<(u8, u8, u8)>) -> bool {
+            let a = v.iter().map(|t| t.0).collect::<Vec<_>>();
+            let b = v.iter().map(|t| t.1).collect::<Vec<_>>();
+            let c = v.iter().map(|t| t.2).collect::<Vec<_>>();
+            let expected = v.iter().map(|&(a, b, c)| ((a & !b) ^ c) | !(a | c)).collect::<Vec<_>>();
+
+            let expr = ((bits(&a) & !bits(&b)) ^ bits(&c)) | !(bits(&a) | bits(&c));
+            let mut out = vec![0x55; v.len()];
+            expr.eval_into(&mut out);
+            out == expected && popcount(expr) == ::weight(&expected) && expr.len() == v.len() &&
+                popcount(bits(&a) ^ bits(&b)) == ::distance(&a, &b) &&
+                popcount(!bits(&a)) == 8 * a.len() as u64 - ::weight(&a)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<(u8, u8, u8)>) -> bool)
+    }
+
+    #[test]
+    #[should_panic]
+    fn different_lengths() {
+        let _ = bits(&[1, 2]) & bits(&[1]);
+    }
+}
@@ -0,0 +1,120 @@
+use core::future::Future;
+
+use futures_util::future::{self, Either, FutureExt};
+use futures_util::stream::{self, Stream, StreamExt};
+
+use {DistanceAccumulator, DistanceError, WeightAccumulator};
+
+/// With the `futures` feature, computes the Hamming weight of all the
+/// chunks produced by `stream`.
+///
+/// The chunks can be anything implementing `AsRef<[u8]>`, such as the
+/// `Bytes` of a request body, and are fed to a `WeightAccumulator` as
+/// they arrive, so nothing is buffered and no thread is blocked
+/// waiting for the data. A fallible stream can be converted with
+/// `TryStreamExt::into_stream`, or counted with `TryStreamExt::try_fold`
+/// and a `WeightAccumulator` directly.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "futures")] extern crate futures_executor;
+/// # #[cfg(feature = "futures")] extern crate futures_util;
+/// # extern crate hamming;
+/// # #[cfg(feature = "futures")] fn main() {
+/// use futures_util::stream;
+///
+/// let chunks = stream::iter(vec![vec![0xFFu8; 3], vec![], vec![0x01; 13]]);
+/// let weight = futures_executor::block_on(hamming::weight_stream(chunks));
+/// assert_eq!(weight, 3 * 8 + 13);
+/// # }
+/// # #[cfg(not(feature = "futures"))] fn main() {}
+/// ```
+pub fn weight_stream<S>(stream: S) -> impl Future<Output = u64>
+    where S: Stream, S::Item: AsRef<[u8]>
+{
+    stream
+        .fold(WeightAccumulator::new(), |mut acc, chunk| {
+            acc.update(chunk.as_ref());
+            future::ready(acc)
+        })
+        .map(|acc| acc.finish())
+}
+
+/// With the `futures` feature, computes the bitwise Hamming distance
+/// between the concatenated chunks of `x` and of `y`.
+///
+/// The two streams are polled concurrently, and can split their data
+/// into chunks differently: each chunk is compared with the other
+/// stream's data as soon as it is available, by a
+/// `DistanceAccumulator`, and only the data that one stream has
+/// produced beyond the other is held in memory.
+///
+/// If the streams have different total lengths,
+/// `DistanceError::LengthMismatch` is returned.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "futures")] extern crate futures_executor;
+/// # #[cfg(feature = "futures")] extern crate futures_util;
+/// # extern crate hamming;
+/// # #[cfg(feature = "futures")] fn main() {
+/// use futures_util::stream;
+///
+/// let x = stream::iter(vec![vec![0xFFu8, 0x00], vec![0x0F]]);
+/// let y = stream::iter(vec![vec![0x0Fu8], vec![0x00, 0x0F]]);
+/// let distance = futures_executor::block_on(hamming::distance_streams(x, y));
+/// assert_eq!(distance, Ok(4));
+/// # }
+/// # #[cfg(not(feature = "futures"))] fn main() {}
+/// ```
+pub fn distance_streams<S, T>(x: S, y: T) -> impl Future<Output = Result<u64, DistanceError>>
+    where S: Stream, S::Item: AsRef<[u8]>, T: Stream, T::Item: AsRef<[u8]>
+{
+    stream::select(x.map(Either::Left), y.map(Either::Right))
+        .fold(DistanceAccumulator::new(), |mut acc, chunk| {
+            match chunk {
+                Either::Left(x) => acc.update(x.as_ref(), &[]),
+                Either::Right(y) => acc.update(&[], y.as_ref()),
+            }
+            future::ready(acc)
+        })
+        .map(|acc| acc.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_executor::block_on;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn streams_qc() {
+        fn prop(v: Vec<Vec<u8>>, w: Vec<Vec<u8>>) -> bool {
+            let (x, y) = (v.concat(), w.concat());
+            let expected = if x.len() == y.len() {
+                Ok(::distance(&x, &y))
+            } else {
+                Err(DistanceError::LengthMismatch {
+                    len: x.len().min(y.len()),
+                    x_shorter: x.len() < y.len(),
+                })
+            };
+            block_on(weight_stream(stream::iter(&v))) == ::weight(&x) &&
+                block_on(distance_streams(stream::iter(&v), stream::iter(&w))) == expected
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<Vec<u8>>, Vec<Vec<u8>>) -> bool);
+    }
+
+    #[test]
+    fn same_data_different_chunks() {
+        let data = (0..1000u32).map(|i| (i * 37) as u8).collect::<Vec<_>>();
+        let x = stream::iter(data.chunks(7));
+        let y = stream::iter(data.chunks(100));
+        assert_eq!(block_on(distance_streams(x, y)), Ok(0));
+    }
+}
@@ -0,0 +1,413 @@
+//! Distance computations on a GPU, via `wgpu`.
+//!
+//! With the `gpu` feature, a `Gpu` runs the one-to-many (`scan`) and
+//! many-to-many (`cross_distances`) kernels as compute shaders on
+//! whatever adapter `wgpu` finds: Vulkan, Metal, DirectX 12 or OpenGL.
+//! Each GPU thread computes one distance, so this is worthwhile for
+//! large batches, such as scanning many millions of 256-bit codes,
+//! where it can be many times faster than the CPU. Small batches are
+//! dominated by the cost of copying the data to and from the GPU, so
+//! those below a size threshold are computed on the CPU instead.
+//!
+//! The `submit_` methods start a computation and return immediately,
+//! with a `PendingDistances` that can be waited on, or `.await`ed.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[cfg(feature = "gpu")] extern crate hamming;
+//! # #[cfg(feature = "gpu")] fn main() {
+//! use hamming::gpu::Gpu;
+//!
+//! // `None` if there is no usable adapter
+//! if let Some(gpu) = Gpu::new() {
+//!     let codes = [0x00, 0x00, 0xFF, 0x00, 0x0F, 0x01];
+//!     let mut out = [0; 3];
+//!     gpu.scan(&[0x0F, 0x00], &codes, &mut out);
+//!     assert_eq!(out, [4, 4, 1]);
+//! }
+//! # }
+//! # #[cfg(not(feature = "gpu"))] fn main() {}
+//! ```
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+const SHADER: &str = "
+struct Params {
+    words: u32,
+    codes: u32,
+    queries: u32,
+    unused: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> queries: array<u32>;
+@group(0) @binding(2) var<storage, read> codes: array<u32>;
+@group(0) @binding(3) var<storage, read_write> out: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    let q = id.y;
+    if (i >= params.codes || q >= params.queries) {
+        return;
+    }
+    var d = 0u;
+    for (var w = 0u; w < params.words; w++) {
+        d += countOneBits(queries[q * params.words + w] ^ codes[i * params.words + w]);
+    }
+    out[q * params.codes + i] = d;
+}
+";
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The default for `Gpu::threshold`.
+pub const DEFAULT_THRESHOLD: usize = 1 << 24;
+
+/// A GPU device set up to compute distances.
+#[derive(Debug)]
+pub struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    threshold: usize,
+}
+
+impl Gpu {
+    /// Set up the default adapter for computation, blocking until it
+    /// is ready, or return `None` if there is no adapter or it can't
+    /// be used.
+    pub fn new() -> Option<Gpu> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })).ok()?;
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("hamming"),
+            required_limits: adapter.limits(),
+            ..Default::default()
+        })).ok()?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("hamming"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("hamming"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Some(Gpu { device, queue, pipeline, threshold: DEFAULT_THRESHOLD })
+    }
+
+    /// The amount of work below which distances are computed on the
+    /// CPU rather than the GPU, as the product of the number of pairs
+    /// of codes and their length in bytes.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Set the amount of work below which distances are computed on
+    /// the CPU, where 0 means always using the GPU.
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+    }
+
+    /// Compute the distance from `query` to each of the codes of
+    /// `query.len()` bytes packed one after another in `codes`,
+    /// storing the distance to code `i` in `out[i]`, and blocking
+    /// until they are all done.
+    ///
+    /// # Panics
+    ///
+    /// `codes` must be a whole number of codes, and `out` must have
+    /// one element per code.
+    pub fn scan(&self, query: &[u8], codes: &[u8], out: &mut [u64]) {
+        self.cross_distances(query, codes, query.len(), out)
+    }
+
+    /// Compute the distance between each code in `a` and each code in
+    /// `b`, storing the distance between codes `i` and `j` in `out[i *
+    /// m + j]`, where `m` is the number of codes in `b`, and blocking
+    /// until they are all done.
+    ///
+    /// # Panics
+    ///
+    /// `a` and `b` must be whole numbers of codes of `code_len` bytes,
+    /// and `out` must have one element per pair.
+    pub fn cross_distances(&self, a: &[u8], b: &[u8], code_len: usize, out: &mut [u64]) {
+        let pending = self.submit_cross_distances(a, b, code_len);
+        assert_eq!(out.len(), pending.len, "output has the wrong length");
+        out.copy_from_slice(&pending.wait());
+    }
+
+    /// Start computing the distance from `query` to each of the codes
+    /// in `codes`, as `scan`, returning without waiting for the
+    /// results.
+    pub fn submit_scan(&self, query: &[u8], codes: &[u8]) -> PendingDistances {
+        self.submit_cross_distances(query, codes, query.len())
+    }
+
+    /// Start computing the distance between each code in `a` and each
+    /// code in `b`, as `cross_distances`, returning without waiting
+    /// for the results.
+    pub fn submit_cross_distances(&self, a: &[u8], b: &[u8], code_len: usize) -> PendingDistances {
+        let count = |x: &[u8]| {
+            if x.is_empty() {
+                return 0
+            }
+            assert!(code_len > 0 && x.len().is_multiple_of(code_len),
+                    "{} bytes are not a whole number of {}-byte codes", x.len(), code_len);
+            x.len() / code_len
+        };
+        let (n, m) = (count(a), count(b));
+        let mut pending = PendingDistances {
+            device: self.device.clone(),
+            len: n * m,
+            cols: m,
+            cpu: Vec::new(),
+            batches: Vec::new(),
+        };
+        if n * m * code_len < self.threshold || n * m * code_len == 0 {
+            pending.cpu = if code_len == 0 {
+                vec![0; n * m]
+            } else {
+                a.chunks_exact(code_len)
+                    .flat_map(|x| b.chunks_exact(code_len).map(move |y| ::distance(x, y)))
+                    .collect()
+            };
+            return pending
+        }
+
+        let limits = self.device.limits();
+        let binding = (limits.max_storage_buffer_binding_size as u64)
+            .min(limits.max_buffer_size) as usize;
+        let dispatch = limits.max_compute_workgroups_per_dimension as usize;
+        let words = code_len.div_ceil(4);
+        assert!(words * 4 <= binding, "codes of {} bytes are too long for the GPU", code_len);
+        let rows = n.min(dispatch).min(binding / (words * 4));
+        let cols = m.min(dispatch * WORKGROUP_SIZE as usize)
+            .min(binding / (words * 4))
+            .min(binding / (4 * rows))
+            .max(1);
+
+        for a_start in (0..n).step_by(rows) {
+            let a_len = rows.min(n - a_start);
+            let queries = self.upload(&a[a_start * code_len..(a_start + a_len) * code_len], code_len);
+            for b_start in (0..m).step_by(cols) {
+                let b_len = cols.min(m - b_start);
+                let codes = self.upload(&b[b_start * code_len..(b_start + b_len) * code_len], code_len);
+                pending.batches.push(self.dispatch(&queries, &codes, words, a_start, a_len,
+                                                   b_start, b_len));
+            }
+        }
+        pending
+    }
+
+    /// Copy `codes` to the GPU, padding each to a whole number of
+    /// 32-bit words.
+    fn upload(&self, codes: &[u8], code_len: usize) -> wgpu::Buffer {
+        let contents = if code_len.is_multiple_of(4) {
+            Cow::Borrowed(codes)
+        } else {
+            let padded = code_len.div_ceil(4) * 4;
+            let mut words = vec![0; codes.len() / code_len * padded];
+            for (w, c) in words.chunks_exact_mut(padded).zip(codes.chunks_exact(code_len)) {
+                w[..code_len].copy_from_slice(c);
+            }
+            Cow::Owned(words)
+        };
+        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("hamming codes"),
+            contents: &contents,
+            usage: wgpu::BufferUsages::STORAGE,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn dispatch(&self, queries: &wgpu::Buffer, codes: &wgpu::Buffer, words: usize,
+                a_start: usize, a_len: usize, b_start: usize, b_len: usize) -> Batch {
+        let params = [words as u32, b_len as u32, a_len as u32, 0]
+            .iter()
+            .flat_map(|p| p.to_le_bytes())
+            .collect::<Vec<u8>>();
+        let params = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("hamming params"),
+            contents: &params,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let size = (a_len * b_len * 4) as u64;
+        let out = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hamming distances"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hamming readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("hamming"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: params.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: queries.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: codes.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: out.as_entire_binding() },
+            ],
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups((b_len as u32).div_ceil(WORKGROUP_SIZE), a_len as u32, 1);
+        }
+        encoder.copy_buffer_to_buffer(&out, 0, &readback, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let ready = Arc::new(AtomicBool::new(false));
+        let flag = ready.clone();
+        readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            result.expect("failed to read distances from the GPU");
+            flag.store(true, Ordering::Release);
+        });
+        Batch { readback, ready, a_start, a_len, b_start, b_len }
+    }
+}
+
+#[derive(Debug)]
+struct Batch {
+    readback: wgpu::Buffer,
+    ready: Arc<AtomicBool>,
+    a_start: usize,
+    a_len: usize,
+    b_start: usize,
+    b_len: usize,
+}
+
+/// Distances being computed by a `Gpu`.
+///
+/// Call `wait` to block until they are done, or `.await` this in an
+/// async context. Since a GPU only reports progress when it is asked,
+/// as a `Future` this checks the device every time it is polled, and
+/// immediately asks to be polled again if the work isn't done yet.
+#[derive(Debug)]
+pub struct PendingDistances {
+    device: wgpu::Device,
+    len: usize,
+    cols: usize,
+    // the results, when computed on the CPU
+    cpu: Vec<u64>,
+    batches: Vec<Batch>,
+}
+
+impl PendingDistances {
+    /// Block until the distances are computed, and return them.
+    pub fn wait(self) -> Vec<u64> {
+        if !self.batches.is_empty() {
+            self.device.poll(wgpu::PollType::wait_indefinitely()).expect("failed to wait for the GPU");
+        }
+        self.collect()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.batches.iter().all(|b| b.ready.load(Ordering::Acquire))
+    }
+
+    fn collect(self) -> Vec<u64> {
+        if self.batches.is_empty() {
+            return self.cpu
+        }
+        let mut out = vec![0; self.len];
+        for batch in &self.batches {
+            let view = batch.readback.slice(..).get_mapped_range();
+            let mut distances = view.chunks_exact(4)
+                .map(|d| u32::from_le_bytes([d[0], d[1], d[2], d[3]]) as u64);
+            for i in batch.a_start..batch.a_start + batch.a_len {
+                let row = &mut out[i * self.cols + batch.b_start..][..batch.b_len];
+                for (o, d) in row.iter_mut().zip(&mut distances) {
+                    *o = d;
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Future for PendingDistances {
+    type Output = Vec<u64>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Vec<u64>> {
+        let this = self.get_mut();
+        if !this.batches.is_empty() {
+            let _ = this.device.poll(wgpu::PollType::Poll);
+        }
+        if this.is_ready() {
+            let done = PendingDistances {
+                device: this.device.clone(),
+                len: this.len,
+                cols: this.cols,
+                cpu: ::std::mem::take(&mut this.cpu),
+                batches: ::std::mem::take(&mut this.batches),
+            };
+            Poll::Ready(done.collect())
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_executor::block_on;
+
+    fn naive(a: &[u8], b: &[u8], code_len: usize) -> Vec<u64> {
+        a.chunks(code_len)
+            .flat_map(|x| b.chunks(code_len).map(move |y| ::distance(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn gpu_matches_cpu() {
+        let mut gpu = match Gpu::new() {
+            Some(gpu) => gpu,
+            // nothing to test without an adapter
+            None => return,
+        };
+        let bytes = (0..20_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect::<Vec<_>>();
+        for &threshold in &[0, DEFAULT_THRESHOLD] {
+            gpu.set_threshold(threshold);
+            for &code_len in &[1, 3, 4, 32, 33] {
+                let (a, b) = (&bytes[..code_len * 7], &bytes[1000..1000 + code_len * 500]);
+                let mut out = vec![!0; 7 * 500];
+                gpu.cross_distances(a, b, code_len, &mut out);
+                assert_eq!(out, naive(a, b, code_len));
+
+                let mut out = vec![!0; 500];
+                gpu.scan(&a[..code_len], b, &mut out);
+                assert_eq!(out, naive(&a[..code_len], b, code_len));
+                assert_eq!(block_on(gpu.submit_scan(&a[..code_len], b)), out);
+            }
+        }
+        assert_eq!(gpu.submit_cross_distances(&[], &[1, 2], 2).wait(), Vec::<u64>::new());
+        assert_eq!(gpu.submit_cross_distances(&[], &[], 0).wait(), Vec::<u64>::new());
+    }
+}
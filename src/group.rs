@@ -0,0 +1,144 @@
+use alloc::vec::Vec;
+
+use Database;
+
+/// Group the codes in `db` into the connected components of the graph
+/// joining every pair of codes within distance `threshold` of each
+/// other.
+///
+/// That is, two codes are in the same group if there is a chain of
+/// codes between them with each step at most `threshold` apart. Each
+/// group lists the indices of its codes in increasing order, and the
+/// groups are ordered by their first index. Every code is in exactly
+/// one group, so codes with no neighbours form singleton groups.
+///
+/// Codes are visited in order of weight, so only pairs whose weights
+/// differ by at most `threshold` are ever considered, and pairs
+/// already known to be in the same group are never compared.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0b0000, 0b1111, 0b0001, 0b0111, 0xF0]);
+/// assert_eq!(hamming::group_within(&db, 1),
+///            vec![vec![0, 2], vec![1, 3], vec![4]]);
+/// ```
+pub fn group_within(db: &Database, threshold: u64) -> Vec<Vec<usize>> {
+    let n = db.len();
+    let weights = db.weights();
+    let mut order = (0..n).collect::<Vec<_>>();
+    order.sort_by_key(|&i| (weights[i], i));
+
+    let mut sets = UnionFind::new(n);
+    for (a, &i) in order.iter().enumerate() {
+        let code = db.get(i);
+        for &j in &order[a + 1..] {
+            if weights[j] - weights[i] > threshold {
+                break
+            }
+            if sets.find(i) != sets.find(j) &&
+                db.distance_bounded(code, j, threshold).is_some() {
+                sets.union(i, j);
+            }
+        }
+    }
+    sets.into_groups()
+}
+
+/// A disjoint-set forest over `0..n`, with path halving and union by
+/// size.
+#[derive(Debug, Clone)]
+pub(crate) struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> UnionFind {
+        UnionFind { parent: (0..n).collect(), size: vec![1; n] }
+    }
+
+    pub fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    /// Merge the sets containing `x` and `y`, returning whether they
+    /// were previously separate.
+    pub fn union(&mut self, x: usize, y: usize) -> bool {
+        let (mut x, mut y) = (self.find(x), self.find(y));
+        if x == y {
+            return false
+        }
+        if self.size[x] < self.size[y] {
+            ::core::mem::swap(&mut x, &mut y);
+        }
+        self.parent[y] = x;
+        self.size[x] += self.size[y];
+        true
+    }
+
+    /// Every set, each in increasing order, ordered by their smallest
+    /// elements.
+    pub fn into_groups(mut self) -> Vec<Vec<usize>> {
+        let n = self.parent.len();
+        let mut group_of_root = vec![usize::MAX; n];
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for x in 0..n {
+            let root = self.find(x);
+            if group_of_root[root] == usize::MAX {
+                group_of_root[root] = groups.len();
+                groups.push(Vec::new());
+            }
+            groups[group_of_root[root]].push(x);
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group_within, UnionFind};
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    /// Flood-fill components by brute force.
+    fn naive(db: &Database, threshold: u64) -> Vec<Vec<usize>> {
+        let n = db.len();
+        let mut sets = UnionFind::new(n);
+        for i in 0..n {
+            for j in 0..n {
+                if ::distance(db.get(i), db.get(j)) <= threshold {
+                    sets.union(i, j);
+                }
+            }
+        }
+        sets.into_groups()
+    }
+
+    #[test]
+    fn group_within_qc() {
+        fn prop(v: Vec<u8>, threshold: u8) -> bool {
+            let db = Database::from_packed(2, &v[..v.len() / 2 * 2]);
+            let threshold = threshold as u64 % 8;
+            group_within(&db, threshold) == naive(&db, threshold)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 200))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+
+    #[test]
+    fn union_find() {
+        let mut sets = UnionFind::new(5);
+        assert!(sets.union(3, 1));
+        assert!(sets.union(4, 1));
+        assert!(!sets.union(3, 4));
+        assert_eq!(sets.find(3), sets.find(4));
+        assert_eq!(sets.into_groups(), vec![vec![0], vec![1, 3, 4], vec![2]]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Cardinalities between dense bitmaps and `roaring::RoaringBitmap`s.
+//!
+//! A dense bitmap is a `&[u8]` containing the value `i` when bit `i %
+//! 8` of byte `i / 8` is set, as everywhere else in this crate. These
+//! functions compare one to a `RoaringBitmap` without converting
+//! either side to the other's representation: the intersection walks
+//! whichever of the two has fewer values in common range and looks
+//! each one up in the other, and the rest follows from that and the
+//! sizes of the two sets.
+//!
+//! This requires the `roaring` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[cfg(feature = "roaring")] extern crate roaring;
+//! # extern crate hamming;
+//! # #[cfg(feature = "roaring")] fn main() {
+//! use hamming::hybrid;
+//! use roaring::RoaringBitmap;
+//!
+//! let dense = [0b0000_1111u8, 0b1];
+//! let roaring = [2, 3, 8, 1000].iter().cloned().collect::<RoaringBitmap>();
+//! assert_eq!(hybrid::intersection_len(&dense, &roaring), 3);
+//! assert_eq!(hybrid::union_len(&dense, &roaring), 6);
+//! assert_eq!(hybrid::symmetric_difference_len(&dense, &roaring), 3);
+//! # }
+//! # #[cfg(not(feature = "roaring"))] fn main() {}
+//! ```
+
+use core::convert::TryInto;
+
+use roaring::bitmap::Iter;
+use roaring::RoaringBitmap;
+
+fn contains(dense: &[u8], i: u32) -> bool {
+    dense.get(i as usize / 8).is_some_and(|b| b >> (i % 8) & 1 == 1)
+}
+
+/// The values of `roaring` that are within the range of `dense`, and
+/// how many there are.
+fn overlapping<'a>(dense: &[u8], roaring: &'a RoaringBitmap) -> (Iter<'a>, u64) {
+    let end: Result<u32, _> = (dense.len() as u64 * 8).try_into();
+    match end {
+        Ok(end) => (roaring.range(..end), roaring.range_cardinality(..end)),
+        Err(_) => (roaring.iter(), roaring.len()),
+    }
+}
+
+/// The number of values in both `dense` and `roaring`.
+pub fn intersection_len(dense: &[u8], roaring: &RoaringBitmap) -> u64 {
+    let (overlap, len) = overlapping(dense, roaring);
+    if len <= ::weight(dense) {
+        return overlap.filter(|&i| contains(dense, i)).count() as u64
+    }
+    let mut count = 0;
+    // values past u32::MAX can't be in `roaring`
+    let dense = &dense[..dense.len().min(1 << 29)];
+    for (j, chunk) in dense.chunks(8).enumerate() {
+        let mut bytes = [0; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let mut word = u64::from_le_bytes(bytes);
+        while word != 0 {
+            let i = j as u32 * 64 + word.trailing_zeros();
+            count += roaring.contains(i) as u64;
+            word &= word - 1;
+        }
+    }
+    count
+}
+
+/// The number of values in either of `dense` and `roaring`.
+pub fn union_len(dense: &[u8], roaring: &RoaringBitmap) -> u64 {
+    ::weight(dense) + roaring.len() - intersection_len(dense, roaring)
+}
+
+/// The number of values in exactly one of `dense` and `roaring`.
+pub fn symmetric_difference_len(dense: &[u8], roaring: &RoaringBitmap) -> u64 {
+    ::weight(dense) + roaring.len() - 2 * intersection_len(dense, roaring)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck as qc;
+    use rand;
+    use roaring::RoaringBitmap;
+
+    #[test]
+    fn hybrid_qc() {
+        fn prop(dense: Vec<u8>, values: Vec<u16>) -> bool {
+            let roaring = values.iter().map(|&x| x as u32 % 1000).collect::<RoaringBitmap>();
+            let naive = |f: &dyn Fn(bool, bool) -> bool| {
+                (0..1000.max(dense.len() as u32 * 8)).filter(|&i| f(contains(&dense, i), roaring.contains(i))).count() as u64
+            };
+            intersection_len(&dense, &roaring) == naive(&|x, y| x && y) &&
+                union_len(&dense, &roaring) == naive(&|x, y| x || y) &&
+                symmetric_difference_len(&dense, &roaring) == naive(&|x, y| x != y)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 200))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u16>) -> bool);
+    }
+
+    #[test]
+    fn both_strategies() {
+        let dense = [0xFF; 16];
+        let few = [3, 100, 127, 128].iter().cloned().collect::<RoaringBitmap>();
+        let many = (0..10_000).collect::<RoaringBitmap>();
+        assert_eq!(intersection_len(&dense, &few), 3);
+        assert_eq!(intersection_len(&dense, &many), 128);
+        assert_eq!(union_len(&dense, &many), 10_000);
+        assert_eq!(symmetric_difference_len(&[0; 4], &many), 10_000);
+    }
+}
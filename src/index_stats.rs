@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::mem;
+
+/// Occupancy statistics for one hash table of a `MultiIndex` or
+/// `BitSamplingIndex`, for tuning their parameters.
+///
+/// A table whose codes are spread over many small buckets filters
+/// well, while one dominated by a few huge buckets compares most of
+/// the database on every query that lands in them: `largest` and
+/// `expected_probe_size` show this directly, and `histogram` shows the
+/// whole distribution.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x00, 0x00, 0x01, 0xFF]);
+/// let index = hamming::MultiIndex::new(db, 1);
+/// let stats = &index.bucket_stats()[0];
+/// assert_eq!((stats.buckets(), stats.entries(), stats.largest()), (3, 5, 3));
+/// // two buckets of one code, one of three
+/// assert_eq!(stats.histogram(), [2, 1]);
+/// assert_eq!(stats.expected_probe_size(), (1.0 + 1.0 + 9.0) / 5.0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BucketStats {
+    buckets: usize,
+    entries: usize,
+    largest: usize,
+    histogram: Vec<usize>,
+    sum_of_squares: u64,
+}
+
+impl BucketStats {
+    pub(crate) fn new(table: &HashMap<u64, Vec<usize>>) -> BucketStats {
+        let mut stats = BucketStats::default();
+        for bucket in table.values() {
+            let size = bucket.len();
+            stats.buckets += 1;
+            stats.entries += size;
+            stats.largest = stats.largest.max(size);
+            stats.sum_of_squares += size as u64 * size as u64;
+            let class = (usize::BITS - 1 - size.leading_zeros()) as usize;
+            if stats.histogram.len() <= class {
+                stats.histogram.resize(class + 1, 0);
+            }
+            stats.histogram[class] += 1;
+        }
+        stats
+    }
+
+    /// The number of non-empty buckets.
+    pub fn buckets(&self) -> usize {
+        self.buckets
+    }
+
+    /// The number of entries in all the buckets, which is the number
+    /// of codes in the index.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// The number of entries in the largest bucket.
+    pub fn largest(&self) -> usize {
+        self.largest
+    }
+
+    /// A histogram of bucket sizes, where element `j` is the number of
+    /// buckets with between `2^j` and `2^(j + 1) - 1` entries.
+    pub fn histogram(&self) -> &[usize] {
+        &self.histogram
+    }
+
+    /// The mean number of entries in a non-empty bucket.
+    pub fn mean_size(&self) -> f64 {
+        if self.buckets == 0 { 0.0 } else { self.entries as f64 / self.buckets as f64 }
+    }
+
+    /// The expected size of the bucket holding an entry chosen
+    /// uniformly at random, which is the expected number of codes
+    /// found by probing the bucket of a query distributed like the
+    /// indexed codes.
+    pub fn expected_probe_size(&self) -> f64 {
+        if self.entries == 0 { 0.0 } else { self.sum_of_squares as f64 / self.entries as f64 }
+    }
+
+    /// An estimate of the number of codes found by probing the bucket
+    /// of a query distributed like the indexed codes, and `others`
+    /// other buckets of a table keyed by `bits` bits, assuming those
+    /// are as full as a uniform spread of the codes would make them.
+    pub(crate) fn expected_candidates(&self, bits: usize, others: f64) -> f64 {
+        self.expected_probe_size() + others * self.entries as f64 / (bits as f64).exp2()
+    }
+}
+
+/// The approximate heap memory used by `table`, in bytes.
+pub(crate) fn table_memory(table: &HashMap<u64, Vec<usize>>) -> usize {
+    // each slot has a control byte besides the key and bucket
+    table.capacity() * (mem::size_of::<(u64, Vec<usize>)>() + 1) +
+        table.values().map(|b| b.capacity() * mem::size_of::<usize>()).sum::<usize>()
+}
+
+/// The number of ways to choose up to `k` of `n` bits, as a float.
+pub(crate) fn ball_size(n: usize, k: usize) -> f64 {
+    let mut term = 1.0;
+    let mut total = 1.0;
+    for j in 0..k.min(n) {
+        term = term * (n - j) as f64 / (j + 1) as f64;
+        total += term;
+    }
+    total
+}
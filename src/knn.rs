@@ -0,0 +1,150 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::thread;
+
+use {Database, TopK};
+
+/// The number of codes in each block of the pairwise comparisons.
+const KNN_TILE: usize = 64;
+
+/// Build the k-nearest-neighbour graph of the codes in `db`, returning
+/// for each code the `(index, distance)` pairs of the `k` other codes
+/// closest to it, sorted by distance with ties broken by the lower
+/// index, as `Database::top_k` would.
+///
+/// Each list has fewer than `k` entries if there are `k` or fewer
+/// codes. This is the input to graph-based clustering and to the
+/// construction of graph indexes, and is much faster than a `top_k`
+/// for each code: every pair of codes is compared at most once, in
+/// blocks that stay in cache, and a comparison is skipped or abandoned
+/// once the cached weights or partial distance show it can improve
+/// neither code's list.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x01, 0xFF, 0x03]);
+/// let graph = hamming::knn_graph(&db, 2);
+/// assert_eq!(graph[0], vec![(1, 1), (3, 2)]);
+/// assert_eq!(graph[2], vec![(3, 6), (1, 7)]);
+/// ```
+pub fn knn_graph(db: &Database, k: usize) -> Vec<Vec<(usize, u64)>> {
+    let n = db.len();
+    let mut neighbours = (0..n).map(|_| TopK::new(k)).collect::<Vec<_>>();
+    if k > 0 {
+        let weights = db.weights();
+        for i_start in (0..n).step_by(KNN_TILE) {
+            for j_start in (i_start..n).step_by(KNN_TILE) {
+                for i in i_start..(i_start + KNN_TILE).min(n) {
+                    let code = db.get(i);
+                    for j in (i + 1).max(j_start)..(j_start + KNN_TILE).min(n) {
+                        // the largest distance that could still improve
+                        // either list
+                        let bound = match (neighbours[i].bound(), neighbours[j].bound()) {
+                            (Some(x), Some(y)) => x.max(y),
+                            _ => u64::MAX,
+                        };
+                        if weights[i].abs_diff(weights[j]) > bound {
+                            continue
+                        }
+                        if let Some(d) = db.distance_bounded(code, j, bound) {
+                            neighbours[i].offer(j, d);
+                            neighbours[j].offer(i, d);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    neighbours.into_iter().map(TopK::into_sorted_vec).collect()
+}
+
+/// Build the k-nearest-neighbour graph of the codes in `db`, like
+/// `knn_graph`, using `threads` threads.
+///
+/// With the `std` feature, the codes are split between the threads,
+/// and each finds the neighbours of its codes with the pruned search
+/// of `Database::top_k`. The result is exactly that of `knn_graph`.
+///
+/// # Panics
+///
+/// `threads` must be non-zero.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x01, 0xFF, 0x03]);
+/// assert_eq!(hamming::knn_graph_parallel(&db, 2, 4), hamming::knn_graph(&db, 2));
+/// ```
+#[cfg(feature = "std")]
+pub fn knn_graph_parallel(db: &Database, k: usize, threads: usize) -> Vec<Vec<(usize, u64)>> {
+    assert!(threads > 0, "cannot build a graph with no threads");
+    let mut graph = vec![Vec::new(); db.len()];
+    let chunk = db.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        for (c, rows) in graph.chunks_mut(chunk).enumerate() {
+            scope.spawn(move || {
+                for (i, row) in (c * chunk..).zip(rows) {
+                    // the code itself may or may not be among its
+                    // `k + 1` nearest, if it has duplicates
+                    *row = db.top_k(db.get(i), k + 1);
+                    row.retain(|&(j, _)| j != i);
+                    row.truncate(k);
+                }
+            });
+        }
+    });
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::knn_graph;
+    #[cfg(feature = "std")]
+    use super::knn_graph_parallel;
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn knn_graph_qc() {
+        fn prop(v: Vec<u8>, k: u8) -> bool {
+            let k = k as usize % 6;
+            let db = Database::from_packed(1, &v);
+            let graph = knn_graph(&db, k);
+            graph.len() == db.len() && graph.iter().enumerate().all(|(i, row)| {
+                let mut naive = (0..db.len()).filter(|&j| j != i)
+                    .map(|j| (::distance(db.get(i), db.get(j)), j))
+                    .collect::<Vec<_>>();
+                naive.sort();
+                naive.truncate(k);
+                *row == naive.into_iter().map(|(d, j)| (j, d)).collect::<Vec<_>>()
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 200))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn parallel_qc() {
+        fn prop(v: Vec<u8>, k: u8, threads: u8) -> bool {
+            let (k, threads) = (k as usize % 6, threads as usize % 8 + 1);
+            let db = Database::from_packed(2, &v[..v.len() / 2 * 2]);
+            knn_graph_parallel(&db, k, threads) == knn_graph(&db, k)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 300))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8) -> bool);
+    }
+
+    #[test]
+    fn many_blocks() {
+        // more codes than fit in one block, with many duplicates
+        let db = Database::from_packed(1, (0..500).map(|i| (i % 7) as u8).collect::<Vec<_>>());
+        let graph = knn_graph(&db, 3);
+        assert_eq!(graph[0], vec![(7, 0), (14, 0), (21, 0)]);
+        assert_eq!(graph[497], vec![(0, 0), (7, 0), (14, 0)]);
+    }
+}
@@ -7,27 +7,245 @@
 //!
 //! ```toml
 //! [dependencies]
-//! hamming = "0.1"
+//! hamming = "0.2"
 //! ```
 //!
 //! # Examples
 //!
 //! ```rust
-//! assert_eq!(hamming::weight(&[1, 0xFF, 1, 0xFF]), 1 + 8 + 1 + 8);
-//! assert_eq!(hamming::distance(&[1, 0xFF], &[0xFF, 1]), 7 + 7);
+//! assert_eq!(hamming::weight(&[1u8, 0xFF, 1, 0xFF]), 1 + 8 + 1 + 8);
+//! assert_eq!(hamming::distance(&[1u8, 0xFF], &[0xFF, 1]), 7 + 7);
 //! ```
 
 #![deny(warnings)]
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
-#[cfg(test)] extern crate core;
+#[cfg(any(test, feature = "std"))] extern crate core;
+#[cfg(feature = "alloc")] #[macro_use] extern crate alloc;
 #[cfg(test)] extern crate quickcheck;
 #[cfg(test)] extern crate rand;
+#[cfg(feature = "serde")] extern crate serde;
+#[cfg(feature = "memmap")] extern crate memmap2;
+#[cfg(feature = "space")] extern crate space;
+#[cfg(feature = "acap")] extern crate acap;
+#[cfg(feature = "libm")] extern crate libm;
+#[cfg(feature = "ndarray")] extern crate ndarray;
+#[cfg(feature = "bitvec")] extern crate bitvec;
+#[cfg(feature = "fixedbitset")] extern crate fixedbitset;
+#[cfg(feature = "roaring")] extern crate roaring;
+#[cfg(feature = "bytes")] extern crate bytes;
+#[cfg(feature = "bytemuck")] extern crate bytemuck;
+#[cfg(feature = "digest")] extern crate digest;
+#[cfg(feature = "wasm-bindgen")] extern crate wasm_bindgen;
+#[cfg(feature = "python")] extern crate pyo3;
+#[cfg(feature = "futures")] extern crate futures_util;
+#[cfg(feature = "gpu")] extern crate wgpu;
+#[cfg(feature = "gpu")] extern crate pollster;
+#[cfg(feature = "rand")] extern crate rand_core;
+#[cfg(all(test, feature = "serde"))] extern crate serde_json;
+#[cfg(all(test, feature = "digest"))] extern crate sha2;
+#[cfg(all(test, feature = "rand"))] extern crate rand_pcg;
+#[cfg(all(test, any(feature = "futures", feature = "gpu")))] extern crate futures_executor;
+
+#[macro_use]
+mod checked;
+pub use checked::ArgumentError;
+
+mod words;
+pub use words::Words;
+
+mod traits;
+pub use traits::{HammingDistance, HammingWeight};
+
+#[cfg(feature = "bitvec")]
+mod bitvec_;
 
 mod weight_;
-pub use weight_::weight;
+pub use weight_::{try_column_weights, try_weight_strided, try_weights_many};
+pub use weight_::{column_weights, weight, weight_array, weight_chunks, weight_const, weight_iter, weight_iter_u64, weight_strided, weights_many};
+#[cfg(feature = "std")]
+pub use weight_::weight_reader;
 
 mod distance_;
-pub use distance_::{distance, distance_fast};
+pub use distance_::{distance, distance_array, distance_const, distance_fast, distance32, distance64, DistanceError};
+pub use distance_::{distance_u64, distance_u128, scan_u64};
+pub use distance_::{try_column_distances, try_distance, try_distance_pairs, try_distance_rows, try_distance_rows_each, try_fold_popcount, try_scan_u64};
+pub use distance_::{distance_iter, distance_iter_exact, distance_pairs, distance_pairs_iter};
+pub use distance_::{column_distances, distance_rows, distance_rows_each, fold_popcount};
+#[cfg(feature = "alloc")]
+pub use distance_::top_k_u64;
+
+mod bitmap;
+pub use bitmap::{match_template, weight_bits, weight_bits_and, weight_rect, Rect};
+pub use bitmap::{diff_positions, iter_ones, rank, select, weight_bits_and_msb0, weight_bits_msb0, BitOrder};
+pub use bitmap::{try_match_template, try_rank, try_weight_bits, try_weight_bits_and, try_weight_bits_and_msb0, try_weight_bits_msb0, try_weight_rect};
+#[cfg(feature = "alloc")]
+pub use bitmap::PopcountIntegral;
+
+mod code256;
+pub use code256::{Code256, ParseCodeError};
+
+#[cfg(feature = "bytemuck")]
+mod pod;
+#[cfg(feature = "bytemuck")]
+pub use pod::{distance_pod, weight_pod};
+
+#[cfg(feature = "digest")]
+mod digest_;
+#[cfg(feature = "digest")]
+pub use digest_::{distance_digests, weight_digest};
+
+mod metric;
+pub use metric::{Hamming, HammingCode};
+
+mod ball;
+pub use ball::visit_ball;
+#[cfg(feature = "alloc")]
+pub use ball::{ball, Ball};
+
+mod binarize;
+pub use binarize::{binarize, binarize_thresholds};
+#[cfg(feature = "alloc")]
+pub use binarize::medians;
+#[cfg(feature = "std")]
+pub use binarize::{RandomHyperplanes, RandomRotation};
+
+pub mod simhash;
+
+pub mod phash;
+
+pub mod ecc;
+
+pub mod encode;
+
+pub mod expr;
+
+pub mod arch;
+
+#[cfg(feature = "libm")]
+pub mod stats;
+
+#[cfg(feature = "ndarray")]
+pub mod array;
+
+#[cfg(feature = "fixedbitset")]
+pub mod bitset;
+
+#[cfg(feature = "roaring")]
+pub mod hybrid;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+#[cfg(feature = "alloc")]
+mod database;
+#[cfg(feature = "alloc")]
+pub use database::Database;
+
+mod query;
+pub use query::AsQuery;
+#[cfg(feature = "alloc")]
+pub use query::Query;
+
+#[cfg(feature = "alloc")]
+mod top_k;
+#[cfg(feature = "alloc")]
+pub use top_k::TopK;
+
+#[cfg(feature = "alloc")]
+mod shared;
+#[cfg(feature = "alloc")]
+pub use shared::Shared;
+
+#[cfg(feature = "alloc")]
+mod scanner;
+#[cfg(feature = "alloc")]
+pub use scanner::Scanner;
+
+mod stream;
+pub use stream::WeightAccumulator;
+#[cfg(feature = "alloc")]
+pub use stream::DistanceAccumulator;
+
+#[cfg(feature = "futures")]
+mod futures_;
+#[cfg(feature = "futures")]
+pub use futures_::{distance_streams, weight_stream};
+#[cfg(feature = "std")]
+pub use stream::WeightWriter;
+
+#[cfg(feature = "memmap")]
+mod mmap;
+#[cfg(feature = "memmap")]
+pub use mmap::{distance_files, distance_files_parallel, weight_file, weight_file_parallel, MmapDatabase};
+
+#[cfg(feature = "alloc")]
+mod matrix;
+#[cfg(feature = "alloc")]
+pub use matrix::{pdist, pdist_as, CondensedMatrix, DistanceElement, SaturatingU8, ScaledU8};
+#[cfg(feature = "std")]
+pub use matrix::{write_pdist_csv, write_phylip};
+
+#[cfg(feature = "alloc")]
+mod distribution;
+#[cfg(feature = "alloc")]
+pub use distribution::{distance_histogram, distance_histogram_sampled, distance_quantiles, DistanceHistogram};
+
+#[cfg(feature = "alloc")]
+mod pairs;
+#[cfg(feature = "alloc")]
+pub use pairs::{closest_pair, farthest_pair, farthest_pair_approx};
+
+#[cfg(feature = "alloc")]
+mod group;
+#[cfg(feature = "alloc")]
+pub use group::group_within;
+
+#[cfg(feature = "alloc")]
+mod knn;
+#[cfg(feature = "alloc")]
+pub use knn::knn_graph;
+#[cfg(feature = "std")]
+pub use knn::knn_graph_parallel;
+
+#[cfg(feature = "alloc")]
+pub mod cluster;
+
+#[cfg(feature = "std")]
+mod persist;
+
+#[cfg(feature = "std")]
+mod index_stats;
+#[cfg(feature = "std")]
+pub use index_stats::BucketStats;
+
+#[cfg(feature = "std")]
+mod mih;
+#[cfg(feature = "std")]
+pub use mih::MultiIndex;
+
+#[cfg(feature = "std")]
+mod lsh;
+#[cfg(feature = "std")]
+pub use lsh::BitSamplingIndex;
+
+#[cfg(feature = "alloc")]
+mod vp_tree;
+#[cfg(feature = "alloc")]
+pub use vp_tree::VpTree;
+
+#[cfg(feature = "alloc")]
+mod pivot;
+#[cfg(feature = "alloc")]
+pub use pivot::PivotIndex;
 
 mod util;
@@ -0,0 +1,694 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::mem;
+
+use {AsQuery, Database, TopK};
+use index_stats::{self, BucketStats};
+use mih::{read_removed, write_removed};
+use persist::{self, Kind};
+use top_k;
+use util::{for_each_at_distance, SplitMix64};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An approximate nearest-neighbour index using locality-sensitive
+/// hashing by bit sampling.
+///
+/// Each of `L` tables hashes codes by a random sample of their bits,
+/// so codes that are close to each other are likely to collide in at
+/// least one table. Queries gather the codes colliding with the query
+/// in (up to) `probes` tables, and rank them with the exact
+/// `distance`.
+///
+/// Every returned distance is exact, but some neighbours may be
+/// missed. Increasing the number of tables raises recall at the cost
+/// of memory, and decreasing the number of sampled bits raises recall
+/// at the cost of comparing more candidates.
+///
+/// Multiprobe querying (see `set_multiprobe`) also gathers the codes
+/// in buckets whose keys differ from the query's in a few bits, which
+/// raises recall without the memory of more tables. See Lv, Josephson,
+/// Wang, Charikar and Li, [Multi-Probe LSH: Efficient Indexing for
+/// High-Dimensional Similarity
+/// Search](https://www.cs.princeton.edu/cass/papers/mplsh_vldb07.pdf).
+///
+/// Codes can be added and removed after the index is built, without
+/// rebuilding it.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut db = hamming::Database::new(4);
+/// db.push(&[0x00, 0x00, 0x00, 0x00]);
+/// db.push(&[0xFF, 0xFF, 0xFF, 0xFF]);
+/// db.push(&[0x00, 0x01, 0x00, 0x00]);
+///
+/// let index = hamming::BitSamplingIndex::new(db, 8, 6, 0x5EED);
+/// // identical codes always collide.
+/// assert_eq!(index.top_k(&[0xFF, 0xFF, 0xFF, 0xFF], 1), vec![(1, 0)]);
+///
+/// let mut index = index;
+/// assert!(index.remove(1));
+/// assert_eq!(index.top_k(&[0xFF, 0xFF, 0xFF, 0xFF], 1), vec![]);
+/// assert_eq!(index.insert(&[0xFF, 0xFF, 0xFF, 0xFF]), 3);
+/// assert_eq!(index.top_k(&[0xFF, 0xFF, 0xFF, 0xFF], 1), vec![(3, 0)]);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawBitSamplingIndex"))]
+pub struct BitSamplingIndex {
+    db: Database,
+    samples: Vec<Vec<usize>>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    tables: Vec<HashMap<u64, Vec<usize>>>,
+    probes: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    removed: HashSet<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    flips: usize,
+}
+
+impl BitSamplingIndex {
+    /// Index the codes in `db` with `tables` hash tables, each keyed
+    /// by `bits` distinct bit positions chosen pseudo-randomly from
+    /// `seed`.
+    ///
+    /// All tables are probed by default, see `set_probes`.
+    ///
+    /// # Panics
+    ///
+    /// `bits` must be non-zero, at most 64 and at most the number of
+    /// bits in each code.
+    pub fn new(db: Database, tables: usize, bits: usize, seed: u64) -> BitSamplingIndex {
+        let code_bits = db.code_len() * 8;
+        assert!(bits > 0 && bits <= 64 && bits <= code_bits,
+                "cannot sample {} bits from {}-bit codes", bits, code_bits);
+
+        let mut rng = SplitMix64::new(seed);
+        let samples = (0..tables).map(|_| {
+            // a partial Fisher-Yates shuffle
+            let mut positions = (0..code_bits).collect::<Vec<_>>();
+            for i in 0..bits {
+                let j = i + rng.below(code_bits - i);
+                positions.swap(i, j);
+            }
+            positions.truncate(bits);
+            positions
+        }).collect::<Vec<_>>();
+        BitSamplingIndex::from_samples(db, samples)
+    }
+
+    fn from_samples(db: Database, samples: Vec<Vec<usize>>) -> BitSamplingIndex {
+        let tables = samples.len();
+        let mut index = BitSamplingIndex {
+            db,
+            samples,
+            tables: vec![HashMap::new(); tables],
+            probes: tables,
+            removed: HashSet::new(),
+            flips: 0,
+        };
+        for i in 0..index.db.len() {
+            index.add_keys(i);
+        }
+        index
+    }
+
+    /// Write the index to `writer`, in the file format of
+    /// `Database::save`.
+    ///
+    /// The file stores the database, the sampled bit positions, the
+    /// `probes` and `multiprobe` settings and the removed codes, and
+    /// `load` rebuilds the hash tables from them.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_packed(1, &[0x00, 0x01, 0xFF]);
+    /// let index = hamming::BitSamplingIndex::new(db, 4, 3, 0x5EED);
+    ///
+    /// let mut file = Vec::new();
+    /// index.save(&mut file)?;
+    /// let loaded = hamming::BitSamplingIndex::load(&file[..])?;
+    /// assert_eq!(loaded.within(&[0x01], 8), index.within(&[0x01], 8));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = persist::Writer::new();
+        self.db.write_payload(&mut w);
+        w.usize(self.probes);
+        w.usize(self.flips);
+        w.usize(self.samples.len());
+        w.usize(self.samples.first().map_or(0, |s| s.len()));
+        for &p in self.samples.iter().flatten() {
+            w.usize(p);
+        }
+        write_removed(&mut w, &self.removed);
+        w.finish(Kind::BitSamplingIndex, writer)
+    }
+
+    /// Read an index written by `save` from `reader`.
+    ///
+    /// Errors are returned as for `Database::load`.
+    pub fn load<R: Read>(reader: R) -> io::Result<BitSamplingIndex> {
+        let payload = persist::read(Kind::BitSamplingIndex, reader)?;
+        let mut r = persist::Reader::new(&payload);
+        let db = Database::read_payload(&mut r)?;
+        let probes = r.usize()?;
+        let flips = r.usize()?;
+        let tables = r.usize()?;
+        let bits = r.usize()?;
+        let code_bits = db.code_len() * 8;
+        // every table holds at least one bit position, so the payload
+        // bounds the number of tables
+        if (bits == 0 && tables > 0) || bits > 64 || bits > code_bits {
+            return Err(persist::invalid(format!("cannot sample {} bits from {}-bit codes",
+                                                bits, code_bits)))
+        }
+        if tables.saturating_mul(bits).saturating_mul(8) > r.remaining() {
+            return Err(persist::invalid(format!("{} tables is too many for the payload", tables)))
+        }
+        let samples = (0..tables).map(|_| {
+            (0..bits).map(|_| {
+                let p = r.usize()?;
+                if p >= code_bits {
+                    return Err(persist::invalid(format!("bit {} out of range for {}-bit codes",
+                                                        p, code_bits)))
+                }
+                Ok(p)
+            }).collect()
+        }).collect::<io::Result<Vec<_>>>()?;
+        let removed = read_removed(&mut r, db.len())?;
+        let mut index = BitSamplingIndex::from_samples(db, samples);
+        index.probes = probes;
+        index.flips = flips;
+        for i in removed {
+            index.remove(i);
+        }
+        Ok(index)
+    }
+
+    fn add_keys(&mut self, i: usize) {
+        let code = self.db.get(i);
+        for (map, sample) in self.tables.iter_mut().zip(&self.samples) {
+            map.entry(key(code, sample)).or_insert_with(Vec::new).push(i);
+        }
+    }
+
+    /// Add `code` to the index, returning its index in `database()`.
+    ///
+    /// This takes expected `O(L * bits)` time for `L` tables each
+    /// sampling `bits` bits (amortized, as the database and hash
+    /// tables grow).
+    ///
+    /// # Panics
+    ///
+    /// `code` must be `self.database().code_len()` bytes long.
+    pub fn insert<C: AsRef<[u8]>>(&mut self, code: C) -> usize {
+        let i = self.db.len();
+        self.db.push(code);
+        self.add_keys(i);
+        i
+    }
+
+    /// Remove the code at index `i` from the index, so that searches
+    /// no longer return it, returning whether it was present.
+    ///
+    /// The indices of the other codes don't change: the code stays in
+    /// `database()`, as a tombstone. This takes `O(L * (bits + b))`
+    /// time for `L` tables each sampling `bits` bits, where `b` is
+    /// the size of the largest bucket containing the code.
+    ///
+    /// # Panics
+    ///
+    /// `i` must be less than `self.database().len()`.
+    pub fn remove(&mut self, i: usize) -> bool {
+        assert!(i < self.db.len(), "index {} out of range for {} codes", i, self.db.len());
+        if !self.removed.insert(i) {
+            return false
+        }
+        let code = self.db.get(i);
+        for (map, sample) in self.tables.iter_mut().zip(&self.samples) {
+            let key = key(code, sample);
+            let empty = map.get_mut(&key).is_some_and(|bucket| {
+                bucket.retain(|&j| j != i);
+                bucket.is_empty()
+            });
+            if empty {
+                map.remove(&key);
+            }
+        }
+        true
+    }
+
+    /// Add every code of `other` to the index, including its removed
+    /// codes (which stay removed), so that code `i` of
+    /// `other.database()` becomes code `self.database().len() + i`.
+    ///
+    /// The codes are rekeyed with the parameters of `self`, so `other`
+    /// can have been built with any parameters. This takes expected
+    /// `O(L * bits)` time per code of `other`, for `L` tables each
+    /// sampling `bits` bits.
+    ///
+    /// # Panics
+    ///
+    /// `other` must index codes of the same length.
+    pub fn append(&mut self, other: &BitSamplingIndex) {
+        let offset = self.db.len();
+        self.db.append(&other.db);
+        for i in 0..other.db.len() {
+            if other.removed.contains(&i) {
+                self.removed.insert(offset + i);
+            } else {
+                self.add_keys(offset + i);
+            }
+        }
+    }
+
+    /// An index of the codes of `self` followed by those of `other`,
+    /// with the parameters of `self`, as built by `append`.
+    ///
+    /// This allows building an index of a large collection in pieces,
+    /// such as on separate machines, and combining them. To update an
+    /// index that is being queried, merge into a `Shared` handle's
+    /// copy with `Shared::make_mut`: readers holding other handles
+    /// keep a consistent snapshot of the old index until they drop it.
+    ///
+    /// # Panics
+    ///
+    /// `other` must index codes of the same length.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let shards = [[0x00u8, 0x0F], [0xF0, 0xFF]].iter().map(|codes| {
+    ///     let shard = hamming::Database::from_packed(1, codes);
+    ///     hamming::BitSamplingIndex::new(shard, 4, 4, 0x5EED)
+    /// }).collect::<Vec<_>>();
+    /// let index = shards[0].merge(&shards[1]);
+    /// assert_eq!(index.len(), 4);
+    /// assert_eq!(index.top_k(&[0xF1], 1), [(2, 1)]);
+    /// ```
+    pub fn merge(&self, other: &BitSamplingIndex) -> BitSamplingIndex {
+        let mut index = self.clone();
+        index.append(other);
+        index
+    }
+
+    /// Whether the code at index `i` is in the index, that is, `i` is
+    /// less than `self.database().len()` and the code hasn't been
+    /// removed.
+    pub fn contains(&self, i: usize) -> bool {
+        i < self.db.len() && !self.removed.contains(&i)
+    }
+
+    /// The number of codes in the index, not counting removed ones.
+    pub fn len(&self) -> usize {
+        self.db.len() - self.removed.len()
+    }
+
+    /// Whether every code has been removed from the index (or it
+    /// never had any).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The indexed codes, including any that have been removed.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// The number of tables probed by each query.
+    pub fn probes(&self) -> usize {
+        self.probes
+    }
+
+    /// Set the number of tables probed by each query, trading recall
+    /// for speed. Values larger than the number of tables probe every
+    /// table.
+    pub fn set_probes(&mut self, probes: usize) {
+        self.probes = probes;
+    }
+
+    /// The number of sampled bits in which the buckets probed in each
+    /// table may differ from the query's key.
+    pub fn multiprobe(&self) -> usize {
+        self.flips
+    }
+
+    /// Probe, in each probed table, every bucket whose key differs
+    /// from the query's key in at most `flips` of the sampled bits,
+    /// rather than only the query's own bucket.
+    ///
+    /// This finds codes that differ from the query in a few of the
+    /// sampled bits, which would otherwise need more tables to catch,
+    /// at the cost of `sum(C(bits, j) for j in 0..=flips)` bucket
+    /// lookups per table: buckets are visited in order of increasing
+    /// perturbation, so the nearest buckets are tried first. The
+    /// default of 0 probes just the query's bucket.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_packed(1, &[0b0000_0000, 0b0000_0001]);
+    /// let mut index = hamming::BitSamplingIndex::new(db, 1, 8, 0);
+    /// assert_eq!(index.within(&[0], 8), vec![(0, 0)]);
+    ///
+    /// index.set_multiprobe(1);
+    /// assert_eq!(index.within(&[0], 8), vec![(0, 0), (1, 1)]);
+    /// ```
+    pub fn set_multiprobe(&mut self, flips: usize) {
+        self.flips = flips;
+    }
+
+    /// Occupancy statistics for each hash table.
+    pub fn bucket_stats(&self) -> Vec<BucketStats> {
+        self.tables.iter().map(BucketStats::new).collect()
+    }
+
+    /// The approximate heap memory used by the index, including its
+    /// database, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.db.memory_usage() +
+            self.tables.iter().map(index_stats::table_memory).sum::<usize>() +
+            self.samples.iter().map(|s| s.capacity()).sum::<usize>() * mem::size_of::<usize>() +
+            self.removed.capacity() * (mem::size_of::<usize>() + 1)
+    }
+
+    /// An estimate of the number of codes that a query distributed
+    /// like the indexed codes compares in full, with the current
+    /// `probes` and `multiprobe` settings.
+    ///
+    /// Each probed table contributes the `expected_probe_size` of its
+    /// query bucket, and the other buckets probed by `multiprobe` are
+    /// assumed to be as full as a uniform spread of the codes would
+    /// make them. A code found in several tables is counted once for
+    /// each, so the estimate is capped at `self.len()`.
+    pub fn expected_candidates(&self) -> f64 {
+        let estimate = self.tables.iter().zip(&self.samples).take(self.probes)
+            .map(|(table, sample)| {
+                let others = index_stats::ball_size(sample.len(), self.flips) - 1.0;
+                BucketStats::new(table).expected_candidates(sample.len(), others)
+            })
+            .sum::<f64>();
+        estimate.min(self.len() as f64)
+    }
+
+    /// The indices of every code in a probed bucket of any probed
+    /// table, in the order they're found.
+    fn candidates(&self, query: &[u8]) -> Vec<usize> {
+        assert_eq!(query.len(), self.db.code_len());
+        let mut seen = vec![false; self.db.len()];
+        let mut candidates = Vec::new();
+        let tables = self.tables.iter().zip(&self.samples).take(self.probes);
+        let keys = tables.clone().map(|(_, sample)| key(query, sample)).collect::<Vec<_>>();
+        for flips in 0..self.flips + 1 {
+            for ((map, sample), &key) in tables.clone().zip(&keys) {
+                for_each_at_distance(key, sample.len(), flips, |key| {
+                    if let Some(bucket) = map.get(&key) {
+                        for &i in bucket {
+                            if !seen[i] {
+                                seen[i] = true;
+                                candidates.push(i);
+                            }
+                        }
+                    }
+                })
+            }
+        }
+        candidates
+    }
+
+    /// Find codes within distance `radius` of `query`, returning
+    /// `(index, distance)` pairs in index order.
+    ///
+    /// This is approximate: some codes within `radius` may be
+    /// missed.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn within<Q: AsQuery>(&self, query: Q, radius: u64) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        let mut result = self.candidates(query).into_iter()
+            .filter_map(|i| self.db.distance_bounded(query, i, radius).map(|d| (i, d)))
+            .collect::<Vec<_>>();
+        top_k::sort_by_index(&mut result);
+        result
+    }
+
+    /// Find (up to) `k` codes close to `query`, returning `(index,
+    /// distance)` pairs sorted by distance, with ties broken by the
+    /// lower index.
+    ///
+    /// This is approximate: closer codes than those returned may
+    /// exist, and fewer than `k` pairs may be returned.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn top_k<Q: AsQuery>(&self, query: Q, k: usize) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        let mut top = TopK::new(k);
+        for i in self.candidates(query) {
+            top.offer(i, ::distance(query, self.db.get(i)));
+        }
+        top.into_sorted_vec()
+    }
+}
+
+/// Concatenate the bits of `code` at each of `positions`.
+/// The serialized form of a `BitSamplingIndex`. The hash tables
+/// aren't stored, but rebuilt from the codes when deserializing.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawBitSamplingIndex {
+    db: Database,
+    samples: Vec<Vec<usize>>,
+    probes: usize,
+    #[serde(default)]
+    removed: HashSet<usize>,
+    #[serde(default)]
+    flips: usize,
+}
+
+#[cfg(feature = "serde")]
+impl ::core::convert::TryFrom<RawBitSamplingIndex> for BitSamplingIndex {
+    type Error = String;
+
+    fn try_from(raw: RawBitSamplingIndex) -> Result<BitSamplingIndex, String> {
+        let code_bits = raw.db.code_len() * 8;
+        let bits = raw.samples.first().map_or(0, Vec::len);
+        if raw.samples.iter().any(|sample| sample.len() != bits) ||
+            (bits == 0 && !raw.samples.is_empty()) || bits > 64 || bits > code_bits {
+            return Err(format!("cannot sample {} bits from {}-bit codes", bits, code_bits))
+        }
+        if let Some(p) = raw.samples.iter().flatten().find(|&&p| p >= code_bits) {
+            return Err(format!("bit {} out of range for {}-bit codes", p, code_bits))
+        }
+        if let Some(i) = raw.removed.iter().find(|&&i| i >= raw.db.len()) {
+            return Err(format!("removed index {} out of range for {} codes", i, raw.db.len()))
+        }
+        let mut index = BitSamplingIndex::from_samples(raw.db, raw.samples);
+        index.probes = raw.probes;
+        index.flips = raw.flips;
+        for i in raw.removed {
+            index.remove(i);
+        }
+        Ok(index)
+    }
+}
+
+fn key(code: &[u8], positions: &[usize]) -> u64 {
+    positions.iter().enumerate().fold(0, |key, (j, &p)| {
+        key | (((code[p / 8] >> (p % 8)) & 1) as u64) << j
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{BitSamplingIndex, key};
+    use persist::{self, Kind};
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn key_smoke() {
+        let code = [0b1000_0001, 0b0000_0010];
+        assert_eq!(key(&code, &[]), 0);
+        assert_eq!(key(&code, &[0, 7, 9]), 0b111);
+        assert_eq!(key(&code, &[1, 0, 2, 9]), 0b1010);
+    }
+
+    #[test]
+    fn search_qc() {
+        fn prop(v: Vec<u8>, radius: u8, k: u8, probes: u8, seed: u64) -> bool {
+            let code_len = 4;
+            let query = [0x12, 0x34, 0x56, 0x78];
+            let mut db = Database::new(code_len);
+            for c in v.chunks(2) {
+                let mut code = query;
+                for &b in c {
+                    code[b as usize % code_len] ^= b;
+                }
+                db.push(code);
+            }
+
+            let mut index = BitSamplingIndex::new(db.clone(), 6, 8, seed);
+            index.set_probes(probes as usize % 8);
+            let radius = radius as u64 % 16;
+            let k = k as usize % 10;
+
+            // results are a correct subset of the exact ones...
+            let exact = db.within(query, radius);
+            let within = index.within(query, radius);
+            let within_ok = within.iter().all(|r| exact.contains(r));
+            let top_k = index.top_k(query, k);
+            let top_k_ok = top_k.len() <= k &&
+                top_k.iter().all(|&(i, d)| db.within(query, d).contains(&(i, d))) &&
+                top_k.windows(2).all(|w| (w[0].1, w[0].0) < (w[1].1, w[1].0));
+
+            // ...and exact duplicates are always found.
+            let duplicates_ok = index.probes() == 0 ||
+                db.within(query, 0).iter().all(|r| within.contains(r));
+
+            within_ok && top_k_ok && duplicates_ok
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, u8, u64) -> bool)
+    }
+
+    #[test]
+    fn multiprobe_qc() {
+        fn prop(v: Vec<(u8, u8)>, flips: u8, seed: u64) -> bool {
+            let query = [0x5A, 0xC3];
+            let db = Database::from_codes(2, v.iter().map(|&(a, b)| [a, b]));
+            let mut index = BitSamplingIndex::new(db.clone(), 3, 10, seed);
+            let flips = flips as usize % 4;
+            let fewer = index.within(query, 16);
+            index.set_multiprobe(flips);
+            let more = index.within(query, 16);
+
+            // probing more buckets only adds candidates, and finds
+            // every code that differs from the query in at most
+            // `flips` bits.
+            fewer.iter().all(|r| more.contains(r)) &&
+                db.within(query, flips as u64).iter().all(|r| more.contains(r)) &&
+                more.iter().all(|&(i, d)| ::distance(&query, db.get(i)) == d)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<(u8, u8)>, u8, u64) -> bool)
+    }
+
+    #[test]
+    fn merge_qc() {
+        fn prop(a: Vec<(u8, u8)>, b: Vec<(u8, u8)>, remove: usize) -> bool {
+            let codes = |v: &[(u8, u8)]| Database::from_codes(2, v.iter().map(|&(x, y)| [x, y]));
+            let a = BitSamplingIndex::new(codes(&a), 3, 5, 1);
+            let mut b = BitSamplingIndex::new(codes(&b), 2, 7, 2);
+            if !b.database().is_empty() {
+                b.remove(remove % b.database().len());
+            }
+            let merged = a.merge(&b);
+            let mut whole = BitSamplingIndex::new(a.database().merge(b.database()), 3, 5, 1);
+            for i in 0..b.database().len() {
+                if !b.contains(i) {
+                    whole.remove(a.database().len() + i);
+                }
+            }
+            merged.tables == whole.tables && merged.removed == whole.removed
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<(u8, u8)>, Vec<(u8, u8)>, usize) -> bool)
+    }
+
+    #[test]
+    fn insert_remove_qc() {
+        fn prop(v: Vec<(u8, u8)>, split: usize, removals: Vec<usize>, seed: u64) -> bool {
+            let v = v.into_iter().map(|(a, b)| [a, b]).collect::<Vec<_>>();
+            let db = Database::from_codes(2, &v);
+            let split = if v.is_empty() { 0 } else { split % v.len() };
+            let prefix = Database::from_codes(2, &v[..split]);
+            let mut index = BitSamplingIndex::new(prefix, 4, 6, seed);
+            for code in &v[split..] {
+                index.insert(code);
+            }
+            for &r in &removals {
+                if !v.is_empty() {
+                    index.remove(r % v.len());
+                }
+            }
+            // exact duplicates are found if and only if they haven't
+            // been removed.
+            v.iter().all(|code| {
+                let expected = db.within(code, 0).into_iter()
+                    .filter(|&(i, _)| index.contains(i))
+                    .collect::<Vec<_>>();
+                index.within(code, 0) == expected
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<(u8, u8)>, usize, Vec<usize>, u64) -> bool)
+    }
+
+    #[test]
+    fn deterministic() {
+        let db = Database::from_packed(2, [1, 2, 3, 4, 5, 6, 7, 8]);
+        let a = BitSamplingIndex::new(db.clone(), 4, 5, 1);
+        let b = BitSamplingIndex::new(db, 4, 5, 1);
+        assert_eq!(a.samples, b.samples);
+        assert!(a.samples.iter().all(|s| s.len() == 5 && s.iter().all(|&p| p < 16)));
+    }
+
+    #[test]
+    fn load_rejects_empty_samples() {
+        // zero-bit tables take no space, so their count can't be
+        // checked against the payload.
+        let db = Database::from_packed(1, [1, 2, 3]);
+        let mut w = persist::Writer::new();
+        db.write_payload(&mut w);
+        for &n in &[1, 0, usize::MAX >> 8, 0, 0] { // probes, flips, tables, bits, removed
+            w.usize(n);
+        }
+        let mut file = Vec::new();
+        w.finish(Kind::BitSamplingIndex, &mut file).unwrap();
+        assert_eq!(BitSamplingIndex::load(&file[..]).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+        let db = Database::from_packed(2, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        let mut index = BitSamplingIndex::new(db, 4, 6, 0x5EED);
+        index.set_multiprobe(2);
+        index.remove(1);
+        let json = serde_json::to_string(&index).unwrap();
+        let loaded = serde_json::from_str::<BitSamplingIndex>(&json).unwrap();
+        assert_eq!(loaded.within([3, 3], 16), index.within([3, 3], 16));
+        assert_eq!(loaded.tables, index.tables);
+        assert_eq!(loaded.multiprobe(), 2);
+
+        let corrupt = json.replacen("\"samples\":[[", "\"samples\":[[16,", 1);
+        assert!(serde_json::from_str::<BitSamplingIndex>(&corrupt).is_err());
+        let corrupt = json.replacen("\"samples\":[[", "\"samples\":[[1000", 1);
+        assert!(serde_json::from_str::<BitSamplingIndex>(&corrupt).is_err());
+        let removed = json.replacen("\"removed\":[1]", "\"removed\":[5]", 1);
+        assert!(serde_json::from_str::<BitSamplingIndex>(&removed).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot sample 0 bits")]
+    fn new_zero_bits() {
+        BitSamplingIndex::new(Database::from_packed(1, [1, 2, 3]), 1, 0, 0);
+    }
+}
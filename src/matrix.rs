@@ -0,0 +1,441 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use Database;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The types that distance matrices can be stored in: `u8`, `u16`,
+/// `u32` and `u64`, and the coarse `SaturatingU8` and `ScaledU8`.
+///
+/// This trait is sealed, and cannot be implemented outside this
+/// crate. The narrower integer types can cut the memory of a large
+/// matrix by a factor of two to eight: a `u16` holds the distance
+/// between any two codes of up to 8191 bytes. Functions producing a
+/// matrix of a narrower integer type check that every possible
+/// distance between the codes fits, so values are never silently
+/// truncated. The quantised types instead fit any distance into a
+/// byte, losing precision, for filtering huge numbers of pairs where
+/// only a rough distance is needed.
+pub trait DistanceElement: private::Sealed + Copy + Default + Eq + Hash + fmt::Debug {
+    /// The largest distance this type can hold exactly, or `u64::MAX`
+    /// for the quantised types, which accept any distance.
+    const MAX_DISTANCE: u64;
+    #[doc(hidden)]
+    fn from_distance(d: u64, max_distance: u64) -> Self;
+}
+
+macro_rules! distance_element {
+    ($($t: ty),*) => {
+        $(
+            impl private::Sealed for $t {}
+            impl DistanceElement for $t {
+                const MAX_DISTANCE: u64 = <$t>::MAX as u64;
+                #[inline]
+                fn from_distance(d: u64, _: u64) -> $t {
+                    d as $t
+                }
+            }
+        )*
+    }
+}
+
+distance_element!(u8, u16, u32, u64);
+
+/// A distance clamped to a byte: distances up to 254 are stored
+/// exactly, and anything larger as 255.
+///
+/// As a `DistanceElement`, this suits filtering for close pairs of
+/// long codes, where every distance beyond some small threshold is as
+/// good as infinite.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::SaturatingU8;
+///
+/// let db = hamming::Database::from_packed(64, [[0x00; 64], [0xFF; 64]].concat());
+/// assert_eq!(hamming::pdist_as::<SaturatingU8>(&db).as_slice(), &[SaturatingU8(255)]);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SaturatingU8(pub u8);
+
+impl private::Sealed for SaturatingU8 {}
+impl DistanceElement for SaturatingU8 {
+    const MAX_DISTANCE: u64 = u64::MAX;
+    #[inline]
+    fn from_distance(d: u64, _: u64) -> SaturatingU8 {
+        SaturatingU8(d.min(255) as u8)
+    }
+}
+
+/// A distance scaled to a byte: the fraction of the bits of the codes
+/// that differ, rounded to the nearest multiple of 1/255.
+///
+/// As a `DistanceElement`, this keeps the whole range of distances,
+/// at a resolution of a 255th of the code length, so `0` is always
+/// identical codes and `255` complementary ones.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::ScaledU8;
+///
+/// let db = hamming::Database::from_packed(64, [[0x00; 64], [0x0F; 64]].concat());
+/// // half of the 512 bits differ
+/// assert_eq!(hamming::pdist_as::<ScaledU8>(&db).as_slice(), &[ScaledU8(128)]);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScaledU8(pub u8);
+
+impl private::Sealed for ScaledU8 {}
+impl DistanceElement for ScaledU8 {
+    const MAX_DISTANCE: u64 = u64::MAX;
+    #[inline]
+    fn from_distance(d: u64, max_distance: u64) -> ScaledU8 {
+        if max_distance == 0 {
+            ScaledU8(0)
+        } else {
+            ScaledU8(((d as u128 * 255 + max_distance as u128 / 2) / max_distance as u128) as u8)
+        }
+    }
+}
+
+/// Check that every distance between codes of `code_len` bytes fits in
+/// a `T`, returning the largest possible distance.
+pub(crate) fn check_element<T: DistanceElement>(code_len: usize) -> u64 {
+    let max_distance = (code_len as u64).saturating_mul(8);
+    assert!(max_distance <= T::MAX_DISTANCE,
+            "distances between {} byte codes may not fit in {}",
+            code_len, ::core::any::type_name::<T>());
+    max_distance
+}
+
+/// The distances between every pair of `n` codes, stored as the upper
+/// triangle of the distance matrix.
+///
+/// Since the matrix is symmetric with zeros on its diagonal, this
+/// stores just the `n * (n - 1) / 2` distances between codes `i < j`,
+/// in the condensed order of SciPy's `pdist`: row by row, each row
+/// from the diagonal onwards. `get` looks up either `(i, j)` or `(j,
+/// i)`, and `row` expands a whole row of the square matrix, so there
+/// is no need to write the index arithmetic by hand. With the `alloc`
+/// feature, `pdist` computes one of these for the codes of a
+/// `Database`, and `pdist_as` one storing the distances as a
+/// narrower `DistanceElement`, such as `u16`.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x0F, 0xFF]);
+/// let matrix = hamming::pdist(&db);
+/// assert_eq!(matrix.len(), 3);
+/// assert_eq!(matrix.as_slice(), &[4, 8, 4]);
+/// assert_eq!((matrix.get(0, 2), matrix.get(2, 0), matrix.get(1, 1)), (8, 8, 0));
+/// assert_eq!(matrix.row(1), vec![4, 0, 4]);
+/// assert_eq!(matrix.iter().collect::<Vec<_>>(), vec![(0, 1, 4), (0, 2, 8), (1, 2, 4)]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CondensedMatrix<T: DistanceElement = u64> {
+    n: usize,
+    distances: Vec<T>,
+}
+
+impl<T: DistanceElement> CondensedMatrix<T> {
+    /// Wrap the condensed distances between `n` codes.
+    ///
+    /// # Panics
+    ///
+    /// `distances` must have length `n * (n - 1) / 2`.
+    pub fn new(n: usize, distances: Vec<T>) -> CondensedMatrix<T> {
+        assert_eq!(distances.len(), n * n.saturating_sub(1) / 2,
+                   "condensed matrix for {} codes has the wrong length", n);
+        CondensedMatrix { n, distances }
+    }
+
+    /// The number of codes, that is, the number of rows and columns of
+    /// the full matrix.
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    /// Whether there are no codes.
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    fn index(&self, i: usize, j: usize) -> usize {
+        let (i, j) = if i < j { (i, j) } else { (j, i) };
+        self.n * i - i * (i + 1) / 2 + j - i - 1
+    }
+
+    /// The distance between codes `i` and `j`, which is `0` if they
+    /// are equal.
+    ///
+    /// # Panics
+    ///
+    /// `i` and `j` must be less than `self.len()`.
+    pub fn get(&self, i: usize, j: usize) -> T {
+        assert!(i < self.n && j < self.n,
+                "({}, {}) is out of range for a matrix of {} codes", i, j, self.n);
+        if i == j { T::default() } else { self.distances[self.index(i, j)] }
+    }
+
+    /// The distances from code `i` to every code, that is, row `i` of
+    /// the full matrix.
+    ///
+    /// # Panics
+    ///
+    /// `i` must be less than `self.len()`.
+    pub fn row(&self, i: usize) -> Vec<T> {
+        (0..self.n).map(|j| self.get(i, j)).collect()
+    }
+
+    /// Iterate over the distances between codes `i < j`, as `(i, j,
+    /// distance)`, in the order they are stored.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
+        let n = self.n;
+        (0..n).flat_map(move |i| (i + 1..n).map(move |j| (i, j)))
+            .zip(&self.distances)
+            .map(|((i, j), &d)| (i, j, d))
+    }
+
+    /// The condensed distances.
+    pub fn as_slice(&self) -> &[T] {
+        &self.distances
+    }
+
+    /// Unwrap this, returning the condensed distances.
+    pub fn into_vec(self) -> Vec<T> {
+        self.distances
+    }
+}
+
+/// Compute the distance between every pair of codes in `codes`.
+///
+/// With the `alloc` feature, this computes the `n * (n - 1) / 2`
+/// distances between codes `i < j`, one row of the upper triangle at
+/// a time. See `CondensedMatrix`.
+pub fn pdist(codes: &Database) -> CondensedMatrix {
+    pdist_as(codes)
+}
+
+/// Compute the distance between every pair of codes in `codes`, like
+/// `pdist`, storing them as `T`s.
+///
+/// # Panics
+///
+/// The largest possible distance between the codes, `8 *
+/// codes.code_len()`, must fit in a `T`, unless it is one of the
+/// quantised types.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x0F, 0xFF]);
+/// let matrix = hamming::pdist_as::<u16>(&db);
+/// assert_eq!(matrix.as_slice(), &[4u16, 8, 4]);
+/// ```
+pub fn pdist_as<T: DistanceElement>(codes: &Database) -> CondensedMatrix<T> {
+    let max_distance = check_element::<T>(codes.code_len());
+    let n = codes.len();
+    let mut distances = Vec::with_capacity(n * n.saturating_sub(1) / 2);
+    let mut row = vec![0; n];
+    for i in 0..n {
+        codes.scan(codes.get(i), &mut row);
+        distances.extend(row[i + 1..].iter().map(|&d| T::from_distance(d, max_distance)));
+    }
+    CondensedMatrix { n, distances }
+}
+
+/// Write every row of the pairwise distance matrix of `codes` to
+/// `writer`, calling `write_row` with the index and distances of each.
+#[cfg(feature = "std")]
+fn write_rows<W, F>(codes: &Database, writer: W, mut write_row: F) -> io::Result<()>
+    where W: Write, F: FnMut(&mut io::BufWriter<W>, usize, &[u64]) -> io::Result<()>
+{
+    let mut writer = io::BufWriter::new(writer);
+    let mut row = vec![0; codes.len()];
+    for i in 0..codes.len() {
+        codes.scan(codes.get(i), &mut row);
+        write_row(&mut writer, i, &row)?;
+    }
+    writer.flush()
+}
+
+/// Write the matrix of the distances between every pair of codes in
+/// `codes` to `writer`, as comma-separated values.
+///
+/// With the `std` feature, this writes one line for each code, with
+/// its distances to every code in order, and no header. The matrix
+/// is streamed one row at a time, so only a row's worth of distances
+/// is ever in memory, however many codes there are. The output is
+/// buffered, so `writer` doesn't need to be.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x0F, 0xFF]);
+/// let mut csv = Vec::new();
+/// hamming::write_pdist_csv(&db, &mut csv)?;
+/// assert_eq!(String::from_utf8(csv).unwrap(), "0,4,8\n4,0,4\n8,4,0\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(feature = "std")]
+pub fn write_pdist_csv<W: Write>(codes: &Database, writer: W) -> io::Result<()> {
+    write_rows(codes, writer, |writer, _, row| {
+        for (j, d) in row.iter().enumerate() {
+            if j > 0 {
+                writer.write_all(b",")?;
+            }
+            write!(writer, "{}", d)?;
+        }
+        writer.write_all(b"\n")
+    })
+}
+
+/// Write the matrix of the distances between every pair of codes in
+/// `codes` to `writer`, as a PHYLIP distance matrix.
+///
+/// With the `std` feature, this writes the number of codes, and then
+/// one line for each code, with its name and its distances to every
+/// code in order, in the square format read by PHYLIP's `neighbor`
+/// and `fitch` and by most other phylogenetics tools. Each code is
+/// named by its index, padded to the 10 characters that strict PHYLIP
+/// requires. As for `write_pdist_csv`, the matrix is streamed one row
+/// at a time, and the output is buffered.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x0F, 0xFF]);
+/// let mut phylip = Vec::new();
+/// hamming::write_phylip(&db, &mut phylip)?;
+/// assert_eq!(String::from_utf8(phylip).unwrap(),
+///            "3\n0          0 4 8\n1          4 0 4\n2          8 4 0\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(feature = "std")]
+pub fn write_phylip<W: Write>(codes: &Database, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "{}", codes.len())?;
+    write_rows(codes, writer, |writer, i, row| {
+        write!(writer, "{:<10}", i)?;
+        for d in row {
+            write!(writer, " {}", d)?;
+        }
+        writer.write_all(b"\n")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pdist, pdist_as, CondensedMatrix, SaturatingU8, ScaledU8};
+    #[cfg(feature = "std")]
+    use super::{write_pdist_csv, write_phylip};
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn pdist_qc() {
+        fn prop(v: Vec<u8>) -> bool {
+            let db = Database::from_packed(1, &v);
+            let matrix = pdist(&db);
+            let n = v.len();
+            matrix.len() == n &&
+                matrix.iter().count() == n * n.saturating_sub(1) / 2 &&
+                matrix.iter().all(|(i, j, d)| i < j && d == matrix.get(j, i)) &&
+                (0..n).all(|i| {
+                    let row = matrix.row(i);
+                    (0..n).all(|j| row[j] == ::distance(&[v[i]], &[v[j]]) && row[j] == matrix.get(i, j))
+                })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn narrow_elements() {
+        // the longest codes whose distances fit in a u16
+        let mut codes = vec![0xFF; 8191];
+        codes.extend_from_slice(&[0x00; 8191]);
+        codes.extend_from_slice(&[0x0F; 8191]);
+        let db = Database::from_packed(8191, codes);
+        assert_eq!(pdist(&db).as_slice(), &[65528, 32764, 32764]);
+        assert_eq!(pdist_as::<u16>(&db).as_slice(), &[65528, 32764, 32764]);
+        assert_eq!(pdist_as::<u32>(&db).as_slice(), &[65528, 32764, 32764]);
+        let db = Database::from_packed(2, [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(pdist_as::<u16>(&db).into_vec(), vec![16]);
+        assert_eq!(pdist_as::<u16>(&db).get(1, 1), 0);
+    }
+
+    #[test]
+    fn quantised_qc() {
+        fn prop(v: Vec<u8>, code_len: u8) -> bool {
+            let code_len = code_len as usize % 70 + 1;
+            let db = Database::from_packed(code_len, &v[..v.len() / code_len * code_len]);
+            let exact = pdist(&db);
+            let saturated = pdist_as::<SaturatingU8>(&db);
+            let scaled = pdist_as::<ScaledU8>(&db);
+            let bits = (8 * code_len) as f64;
+            let (exact, saturated, scaled) = (exact.as_slice(), saturated.as_slice(), scaled.as_slice());
+            exact.iter().zip(saturated).zip(scaled).all(|((&e, &s), &q)| {
+                s == SaturatingU8(e.min(255) as u8) &&
+                    (q.0 as f64 / 255.0 - e as f64 / bits).abs() <= 0.5 / 255.0 + 1e-12
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 500))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn element_too_narrow_u8() {
+        pdist_as::<u8>(&Database::new(32));
+    }
+
+    #[test]
+    #[should_panic]
+    fn element_too_narrow() {
+        pdist_as::<u16>(&Database::new(8192));
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_length() {
+        CondensedMatrix::new(4, vec![0u64; 5]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn writers_qc() {
+        fn prop(v: Vec<u8>) -> bool {
+            let db = Database::from_packed(1, &v);
+            let (mut csv, mut phylip) = (Vec::new(), Vec::new());
+            write_pdist_csv(&db, &mut csv).unwrap();
+            write_phylip(&db, &mut phylip).unwrap();
+            let (csv, phylip) = (String::from_utf8(csv).unwrap(), String::from_utf8(phylip).unwrap());
+
+            let mut phylip_lines = phylip.lines();
+            phylip_lines.next() == Some(&*v.len().to_string()) &&
+                csv.lines().count() == v.len() &&
+                csv.lines().zip(phylip_lines).enumerate().all(|(i, (c, p))| {
+                    let expected = v.iter().map(|&y| ::distance(&[v[i]], &[y])).collect::<Vec<_>>();
+                    let c = c.split(',').map(|d| d.parse().unwrap()).collect::<Vec<u64>>();
+                    let (name, p) = p.split_at(10);
+                    let p = p.split_whitespace().map(|d| d.parse().unwrap()).collect::<Vec<u64>>();
+                    name.trim_end() == i.to_string() && c == expected && p == expected
+                })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>) -> bool);
+    }
+}
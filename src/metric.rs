@@ -0,0 +1,106 @@
+#[cfg(feature = "acap")]
+use acap;
+#[cfg(feature = "space")]
+use space;
+
+/// The Hamming distance between byte strings, as a metric for
+/// nearest-neighbour libraries.
+///
+/// With the `space` feature, this implements `space::Metric` for any
+/// `AsRef<[u8]>` point type, so it can be used directly with crates
+/// built on `space`, such as `hnsw`.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "space")] extern crate space;
+/// # extern crate hamming;
+/// # #[cfg(feature = "space")] fn main() {
+/// use space::Metric;
+/// let d = hamming::Hamming.distance(&[0xFFu8, 0x00], &[0x0F, 0x01]);
+/// assert_eq!(d, 5);
+/// # }
+/// # #[cfg(not(feature = "space"))] fn main() {}
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Hamming;
+
+#[cfg(feature = "space")]
+impl<P: AsRef<[u8]>> space::Metric<P> for Hamming {
+    type Unit = u64;
+
+    fn distance(&self, a: &P, b: &P) -> u64 {
+        ::distance(a.as_ref(), b.as_ref())
+    }
+}
+
+/// A code compared by Hamming distance, for nearest-neighbour
+/// libraries that define distances on the points themselves.
+///
+/// With the `acap` feature, this implements `acap::Proximity` and
+/// `acap::Metric` (with `i64` distances, as `acap` requires signed
+/// values) between any two `HammingCode`s wrapping `AsRef<[u8]>`
+/// types.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "acap")] extern crate acap;
+/// # extern crate hamming;
+/// # #[cfg(feature = "acap")] fn main() {
+/// use acap::Proximity;
+/// use hamming::HammingCode;
+/// let d = HammingCode(vec![0xFFu8, 0x00]).distance(&HammingCode([0x0F, 0x01]));
+/// assert_eq!(d, 5);
+/// # }
+/// # #[cfg(not(feature = "acap"))] fn main() {}
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HammingCode<T>(pub T);
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for HammingCode<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(feature = "acap")]
+impl<T: AsRef<[u8]>, U: AsRef<[u8]>> acap::Proximity<HammingCode<U>> for HammingCode<T> {
+    type Distance = i64;
+
+    fn distance(&self, other: &HammingCode<U>) -> i64 {
+        ::distance(self.as_ref(), other.as_ref()) as i64
+    }
+}
+
+#[cfg(feature = "acap")]
+impl<T: AsRef<[u8]>, U: AsRef<[u8]>> acap::Metric<HammingCode<U>> for HammingCode<T> {}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(feature = "space")]
+    fn space_metric() {
+        use space::Metric;
+        use super::Hamming;
+        let a = vec![0b1010_1010u8; 100];
+        let b = vec![0b0101_0101u8; 100];
+        assert_eq!(Hamming.distance(&a, &b), 800);
+        assert_eq!(Hamming.distance(&a, &a), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "acap")]
+    fn acap_exhaustive_search() {
+        use acap::{NearestNeighbors, exhaustive::ExhaustiveSearch};
+        use super::HammingCode;
+        let mut search = ExhaustiveSearch::new();
+        search.push(HammingCode([0x00u8, 0x00]));
+        search.push(HammingCode([0xFF, 0x00]));
+        search.push(HammingCode([0x0F, 0x01]));
+
+        let nearest = search.nearest(&HammingCode([0x0F, 0x00])).unwrap();
+        assert_eq!(nearest.item, &HammingCode([0x0F, 0x01]));
+        assert_eq!(nearest.distance, 1);
+    }
+}
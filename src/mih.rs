@@ -0,0 +1,785 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::thread;
+use std::collections::{HashMap, HashSet};
+
+use {AsQuery, Database, TopK};
+use top_k;
+use index_stats::{self, BucketStats};
+use persist::{self, Kind};
+use util::for_each_at_distance;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An exact nearest-neighbour index using multi-index hashing.
+///
+/// Each code is split into `m` disjoint substrings, and each
+/// substring is used as a key into its own hash table. By the
+/// pigeonhole principle, if two codes are within distance `r` then at
+/// least one pair of their corresponding substrings is within
+/// distance `r / m`, so only codes in buckets near the query's
+/// substrings need to be compared. See Norouzi, Punjani and Fleet,
+/// [Fast Exact Search in Hamming Space with Multi-Index
+/// Hashing](https://arxiv.org/abs/1307.2982).
+///
+/// A search whose radius is large enough that probing would take
+/// longer than a linear scan falls back to scanning the database.
+///
+/// Results are identical to the corresponding `Database` methods,
+/// ignoring any codes that have been `remove`d. Codes can be added
+/// and removed after the index is built, without rebuilding it.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut db = hamming::Database::new(4);
+/// db.push(&[0x00, 0x00, 0x00, 0x00]);
+/// db.push(&[0xFF, 0xFF, 0xFF, 0xFF]);
+/// db.push(&[0x01, 0x00, 0x10, 0x00]);
+///
+/// let index = hamming::MultiIndex::new(db, 4);
+/// assert_eq!(index.within(&[0, 0, 0, 0], 2), vec![(0, 0), (2, 2)]);
+/// assert_eq!(index.top_k(&[0xFF, 0xFF, 0xFF, 0x7F], 1), vec![(1, 1)]);
+///
+/// let mut index = index;
+/// assert_eq!(index.insert(&[0x00, 0x00, 0x00, 0x80]), 3);
+/// assert!(index.remove(0));
+/// assert_eq!(index.within(&[0, 0, 0, 0], 2), vec![(2, 2), (3, 1)]);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawMultiIndex"))]
+pub struct MultiIndex {
+    db: Database,
+    substrings: Vec<(usize, usize)>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    tables: Vec<HashMap<u64, Vec<usize>>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    removed: HashSet<usize>,
+}
+
+impl MultiIndex {
+    /// Index the codes in `db`, splitting each one into `m`
+    /// substrings of (nearly) equal bit length.
+    ///
+    /// # Panics
+    ///
+    /// `m` must be at least 1 and at most the number of bits in each
+    /// code, and no substring may be longer than 64 bits (that is,
+    /// `m` must be at least `(8 * db.code_len()).div_ceil(64)`).
+    pub fn new(db: Database, m: usize) -> MultiIndex {
+        let substrings = split(db.code_len() * 8, m);
+        let mut index = MultiIndex {
+            db,
+            substrings,
+            tables: vec![HashMap::new(); m],
+            removed: HashSet::new(),
+        };
+        for i in 0..index.db.len() {
+            index.add_keys(i);
+        }
+        index
+    }
+
+    /// Index the codes in `db`, like `new`, using `threads` threads.
+    ///
+    /// The hash tables of the substrings are split between the
+    /// threads and filled in parallel, so at most `m` threads are
+    /// used. The result is exactly that of `new`.
+    ///
+    /// # Panics
+    ///
+    /// As for `new`, and `threads` must be non-zero.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_codes(4, (0..1000u32).map(|i| (i * 7919).to_le_bytes()));
+    /// let index = hamming::MultiIndex::new_parallel(db.clone(), 4, 4);
+    /// let query = 1234u32.to_le_bytes();
+    /// assert_eq!(index.within(query, 6), db.within(query, 6));
+    /// ```
+    pub fn new_parallel(db: Database, m: usize, threads: usize) -> MultiIndex {
+        assert!(threads > 0, "cannot build an index with no threads");
+        let substrings = split(db.code_len() * 8, m);
+        let mut tables = vec![HashMap::new(); m];
+        let chunk = m.div_ceil(threads);
+        thread::scope(|scope| {
+            for (tables, substrings) in tables.chunks_mut(chunk).zip(substrings.chunks(chunk)) {
+                let db = &db;
+                scope.spawn(move || {
+                    for (table, &(start, len)) in tables.iter_mut().zip(substrings) {
+                        for i in 0..db.len() {
+                            table.entry(extract(db.get(i), start, len))
+                                .or_insert_with(Vec::new)
+                                .push(i);
+                        }
+                    }
+                });
+            }
+        });
+        MultiIndex { db, substrings, tables, removed: HashSet::new() }
+    }
+
+    fn add_keys(&mut self, i: usize) {
+        let code = self.db.get(i);
+        for (table, &(start, len)) in self.tables.iter_mut().zip(&self.substrings) {
+            table.entry(extract(code, start, len)).or_insert_with(Vec::new).push(i);
+        }
+    }
+
+    /// Add `code` to the index, returning its index in `database()`.
+    ///
+    /// This takes expected `O(m)` time for `m` substrings (amortized,
+    /// as the database and hash tables grow).
+    ///
+    /// # Panics
+    ///
+    /// `code` must be `self.database().code_len()` bytes long.
+    pub fn insert<C: AsRef<[u8]>>(&mut self, code: C) -> usize {
+        let i = self.db.len();
+        self.db.push(code);
+        self.add_keys(i);
+        i
+    }
+
+    /// Remove the code at index `i` from the index, so that searches
+    /// no longer return it, returning whether it was present.
+    ///
+    /// The indices of the other codes don't change: the code stays in
+    /// `database()`, as a tombstone. This takes `O(m * b)` time for
+    /// `m` substrings, where `b` is the size of the largest bucket
+    /// containing the code.
+    ///
+    /// # Panics
+    ///
+    /// `i` must be less than `self.database().len()`.
+    pub fn remove(&mut self, i: usize) -> bool {
+        assert!(i < self.db.len(), "index {} out of range for {} codes", i, self.db.len());
+        if !self.removed.insert(i) {
+            return false
+        }
+        let code = self.db.get(i);
+        for (table, &(start, len)) in self.tables.iter_mut().zip(&self.substrings) {
+            let key = extract(code, start, len);
+            let empty = table.get_mut(&key).is_some_and(|bucket| {
+                bucket.retain(|&j| j != i);
+                bucket.is_empty()
+            });
+            if empty {
+                table.remove(&key);
+            }
+        }
+        true
+    }
+
+    /// Add every code of `other` to the index, including its removed
+    /// codes (which stay removed), so that code `i` of
+    /// `other.database()` becomes code `self.database().len() + i`.
+    ///
+    /// The codes are rekeyed with the parameters of `self`, so `other`
+    /// can have been built with any parameters. This takes expected
+    /// `O(m)` time per code of `other`, for `m` substrings.
+    ///
+    /// # Panics
+    ///
+    /// `other` must index codes of the same length.
+    pub fn append(&mut self, other: &MultiIndex) {
+        let offset = self.db.len();
+        self.db.append(&other.db);
+        for i in 0..other.db.len() {
+            if other.removed.contains(&i) {
+                self.removed.insert(offset + i);
+            } else {
+                self.add_keys(offset + i);
+            }
+        }
+    }
+
+    /// An index of the codes of `self` followed by those of `other`,
+    /// with the parameters of `self`, as built by `append`.
+    ///
+    /// This allows building an index of a large collection in pieces,
+    /// such as on separate machines, and combining them. To update an
+    /// index that is being queried, merge into a `Shared` handle's
+    /// copy with `Shared::make_mut`: readers holding other handles
+    /// keep a consistent snapshot of the old index until they drop it.
+    ///
+    /// # Panics
+    ///
+    /// `other` must index codes of the same length.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let shards = [[0x00u8, 0x0F], [0xF0, 0xFF]].iter().map(|codes| {
+    ///     let shard = hamming::Database::from_packed(1, codes);
+    ///     hamming::MultiIndex::new(shard, 2)
+    /// }).collect::<Vec<_>>();
+    /// let index = shards[0].merge(&shards[1]);
+    /// assert_eq!(index.len(), 4);
+    /// assert_eq!(index.top_k(&[0xF1], 1), [(2, 1)]);
+    /// ```
+    pub fn merge(&self, other: &MultiIndex) -> MultiIndex {
+        let mut index = self.clone();
+        index.append(other);
+        index
+    }
+
+    /// Whether the code at index `i` is in the index, that is, `i` is
+    /// less than `self.database().len()` and the code hasn't been
+    /// removed.
+    pub fn contains(&self, i: usize) -> bool {
+        i < self.db.len() && !self.removed.contains(&i)
+    }
+
+    /// The number of codes in the index, not counting removed ones.
+    pub fn len(&self) -> usize {
+        self.db.len() - self.removed.len()
+    }
+
+    /// Whether every code has been removed from the index (or it
+    /// never had any).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the index to `writer`, in the file format of
+    /// `Database::save`.
+    ///
+    /// The file stores the database, the number of substrings and the
+    /// removed codes, and `load` rebuilds the hash tables from them.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_packed(1, &[0x00, 0x01, 0xFF]);
+    /// let mut index = hamming::MultiIndex::new(db, 2);
+    /// index.remove(1);
+    ///
+    /// let mut file = Vec::new();
+    /// index.save(&mut file)?;
+    /// let loaded = hamming::MultiIndex::load(&file[..])?;
+    /// assert_eq!(loaded.within(&[0x00], 8), vec![(0, 0), (2, 8)]);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = persist::Writer::new();
+        self.db.write_payload(&mut w);
+        w.usize(self.substrings.len());
+        write_removed(&mut w, &self.removed);
+        w.finish(Kind::MultiIndex, writer)
+    }
+
+    /// Read an index written by `save` from `reader`.
+    ///
+    /// Errors are returned as for `Database::load`.
+    pub fn load<R: Read>(reader: R) -> io::Result<MultiIndex> {
+        let payload = persist::read(Kind::MultiIndex, reader)?;
+        let mut r = persist::Reader::new(&payload);
+        let db = Database::read_payload(&mut r)?;
+        let m = r.usize()?;
+        check_split(db.code_len() * 8, m).map_err(persist::invalid)?;
+        let removed = read_removed(&mut r, db.len())?;
+        let mut index = MultiIndex::new(db, m);
+        for i in removed {
+            index.remove(i);
+        }
+        Ok(index)
+    }
+
+    /// The indexed codes, including any that have been removed.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// The number of substrings each code is split into.
+    pub fn substrings(&self) -> usize {
+        self.substrings.len()
+    }
+
+    /// Occupancy statistics for the hash table of each substring.
+    pub fn bucket_stats(&self) -> Vec<BucketStats> {
+        self.tables.iter().map(BucketStats::new).collect()
+    }
+
+    /// The approximate heap memory used by the index, including its
+    /// database, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.db.memory_usage() +
+            self.tables.iter().map(index_stats::table_memory).sum::<usize>() +
+            self.removed.capacity() * (mem::size_of::<usize>() + 1)
+    }
+
+    /// An estimate of the number of codes that `within(_, radius)`
+    /// compares in full, for a query distributed like the indexed
+    /// codes.
+    ///
+    /// Each table probes the query's bucket, whose expected size is
+    /// `expected_probe_size`, and every key within `radius / m` of it,
+    /// for `m` substrings, which are assumed to be as full as a
+    /// uniform spread of the codes would make them. A code found in
+    /// several tables is counted once for each, so the estimate is
+    /// capped at `self.len()`. Comparing it with `self.len()`, the
+    /// cost of a linear scan, for different values of `m` shows which
+    /// suits a radius best.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let codes = (0..1000u64)
+    ///     .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15).to_le_bytes());
+    /// let db = hamming::Database::from_codes(8, codes);
+    /// let index = hamming::MultiIndex::new(db, 4);
+    /// assert!(index.expected_candidates(3) < 10.0);
+    /// assert!(index.expected_candidates(40) == 1000.0);
+    /// ```
+    pub fn expected_candidates(&self, radius: u64) -> f64 {
+        let sub_radius = radius as usize / self.substrings.len();
+        let estimate = self.tables.iter().zip(&self.substrings)
+            .map(|(table, &(_, len))| {
+                let others = index_stats::ball_size(len, sub_radius) - 1.0;
+                BucketStats::new(table).expected_candidates(len, others)
+            })
+            .sum::<f64>();
+        estimate.min(self.len() as f64)
+    }
+
+    /// Call `f` with each candidate index in the bucket of table `j`
+    /// whose key is exactly `dist` away from the query's substring,
+    /// the first time each index is seen, returning the number of keys
+    /// looked up or compared.
+    ///
+    /// There are `C(len, dist)` keys at distance `dist` from a
+    /// `len`-bit substring, which can be far more than the table has
+    /// buckets, so then the buckets are walked instead.
+    fn probe<F>(&self, keys: &[u64], j: usize, dist: usize, seen: &mut [bool], mut f: F) -> usize
+        where F: FnMut(usize)
+    {
+        let table = &self.tables[j];
+        let mut visit = |bucket: &[usize]| {
+            for &i in bucket {
+                if !seen[i] {
+                    seen[i] = true;
+                    f(i)
+                }
+            }
+        };
+        let keys_at_distance = binomial(self.substrings[j].1, dist);
+        if keys_at_distance > table.len() as f64 {
+            for (&key, bucket) in table {
+                if (key ^ keys[j]).count_ones() as usize == dist {
+                    visit(bucket)
+                }
+            }
+            table.len()
+        } else {
+            for_each_at_distance(keys[j], self.substrings[j].1, dist, |key| {
+                if let Some(bucket) = table.get(&key) {
+                    visit(bucket)
+                }
+            });
+            keys_at_distance as usize
+        }
+    }
+
+    fn keys(&self, query: &[u8]) -> Vec<u64> {
+        assert_eq!(query.len(), self.db.code_len());
+        self.substrings.iter().map(|&(start, len)| extract(query, start, len)).collect()
+    }
+
+    /// Find every code within distance `radius` of `query`, returning
+    /// `(index, distance)` pairs in index order.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn within<Q: AsQuery>(&self, query: Q, radius: u64) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        let keys = self.keys(query);
+        let sub_radius = radius as usize / self.substrings.len();
+
+        // for a large radius, probing can take longer than comparing
+        // every code directly
+        let probe_cost = self.tables.iter().zip(&self.substrings)
+            .map(|(table, &(_, len))| {
+                (0..cmp::min(sub_radius, len) + 1)
+                    .map(|dist| binomial(len, dist).min(table.len() as f64))
+                    .sum::<f64>()
+            })
+            .sum::<f64>();
+        if probe_cost > self.db.len() as f64 {
+            return self.db.within_filtered(query, radius, |i| !self.removed.contains(&i)).collect()
+        }
+
+        let mut seen = vec![false; self.db.len()];
+        let mut result = Vec::new();
+        for j in 0..self.substrings.len() {
+            for dist in 0..cmp::min(sub_radius, self.substrings[j].1) + 1 {
+                self.probe(&keys, j, dist, &mut seen, |i| {
+                    if let Some(d) = self.db.distance_bounded(query, i, radius) {
+                        result.push((i, d))
+                    }
+                });
+            }
+        }
+        top_k::sort_by_index(&mut result);
+        result
+    }
+
+    /// Find the `k` codes closest to `query`, returning `(index,
+    /// distance)` pairs sorted by distance, with ties broken by the
+    /// lower index.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn top_k<Q: AsQuery>(&self, query: Q, k: usize) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        let keys = self.keys(query);
+        let m = self.substrings.len() as u64;
+        let n = self.len();
+        let mut seen = vec![false; self.db.len()];
+        let mut seen_count = 0;
+        let mut cost = 0;
+        let mut top = TopK::new(k);
+        if k > 0 {
+            for dist in 0.. {
+                for j in 0..self.substrings.len() {
+                    if dist > self.substrings[j].1 {
+                        continue
+                    }
+                    // once probing has cost as much as comparing every
+                    // code directly, finish with a scan instead, so the
+                    // search is never much slower than one.
+                    if cost > self.db.len() {
+                        return self.db.top_k_filtered(query, k, 0, |i| !self.removed.contains(&i))
+                    }
+                    cost += self.probe(&keys, j, dist, &mut seen, |i| {
+                        seen_count += 1;
+                        let d = ::distance(query, self.db.get(i));
+                        top.offer(i, d);
+                    })
+                }
+                // every unseen code differs by more than `dist` in
+                // every substring, and so is at least `m * (dist + 1)`
+                // away.
+                let done = top.bound().is_some_and(|d| d < m * (dist as u64 + 1));
+                if done || seen_count == n {
+                    break
+                }
+            }
+        }
+        top.into_sorted_vec()
+    }
+}
+
+/// The number of ways to choose `k` of `n` items, as a float so that
+/// it can't overflow.
+fn binomial(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0
+    }
+    (0..k).fold(1.0, |c, j| c * (n - j) as f64 / (j + 1) as f64)
+}
+
+/// Split `bits` bits into `m` substrings of (nearly) equal length,
+/// returning the start and length of each.
+fn split(bits: usize, m: usize) -> Vec<(usize, usize)> {
+    assert!(m >= 1 && m <= bits,
+            "cannot split {}-bit codes into {} substrings", bits, m);
+    let substrings = (0..m)
+        .map(|j| (j * bits / m, (j + 1) * bits / m - j * bits / m))
+        .collect::<Vec<_>>();
+    assert!(substrings.iter().all(|&(_, len)| len <= 64),
+            "{} substrings of {}-bit codes are longer than 64 bits", m, bits);
+    substrings
+}
+
+/// Check that `bits`-bit codes can be split into `m` substrings, as
+/// `split` requires.
+fn check_split(bits: usize, m: usize) -> Result<(), String> {
+    if m == 0 || m > bits || bits.div_ceil(m) > 64 {
+        return Err(format!("cannot split {}-bit codes into {} substrings", bits, m))
+    }
+    Ok(())
+}
+
+/// The serialized form of a `MultiIndex`. The hash tables aren't
+/// stored, but rebuilt from the codes when deserializing.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawMultiIndex {
+    db: Database,
+    substrings: Vec<(usize, usize)>,
+    #[serde(default)]
+    removed: HashSet<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl ::core::convert::TryFrom<RawMultiIndex> for MultiIndex {
+    type Error = String;
+
+    fn try_from(raw: RawMultiIndex) -> Result<MultiIndex, String> {
+        let (bits, m) = (raw.db.code_len() * 8, raw.substrings.len());
+        check_split(bits, m)?;
+        if raw.substrings != split(bits, m) {
+            return Err(format!("invalid substrings for {}-bit codes", bits))
+        }
+        if let Some(i) = raw.removed.iter().find(|&&i| i >= raw.db.len()) {
+            return Err(format!("removed index {} out of range for {} codes", i, raw.db.len()))
+        }
+        let mut index = MultiIndex::new(raw.db, m);
+        for i in raw.removed {
+            index.remove(i);
+        }
+        Ok(index)
+    }
+}
+
+/// Write the indices of the removed codes of an index to `w`, in
+/// increasing order.
+pub(crate) fn write_removed(w: &mut persist::Writer, removed: &HashSet<usize>) {
+    let mut removed = removed.iter().cloned().collect::<Vec<_>>();
+    removed.sort_unstable();
+    w.usize(removed.len());
+    for i in removed {
+        w.usize(i);
+    }
+}
+
+/// Read the indices written by `write_removed`, which must all be
+/// less than `len`.
+pub(crate) fn read_removed(r: &mut persist::Reader<'_>, len: usize) -> io::Result<Vec<usize>> {
+    let count = r.count(8)?;
+    (0..count).map(|_| {
+        let i = r.usize()?;
+        if i >= len {
+            return Err(persist::invalid(format!("removed index {} out of range for {} codes",
+                                                i, len)))
+        }
+        Ok(i)
+    }).collect()
+}
+
+/// Read the `len` bits of `code` starting at bit `start` (counting
+/// from the least significant bit of the first byte) into a `u64`.
+fn extract(code: &[u8], start: usize, len: usize) -> u64 {
+    let mut key = 0;
+    let mut got = 0;
+    let mut bit = start;
+    while got < len {
+        let shift = bit % 8;
+        let take = cmp::min(8 - shift, len - got);
+        let byte = (code[bit / 8] >> shift) as u64 & ((1 << take) - 1);
+        key |= byte << got;
+        got += take;
+        bit += take;
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MultiIndex, extract};
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn extract_smoke() {
+        let code = [0b1010_0101, 0b1100_0011, 0xFF];
+        assert_eq!(extract(&code, 0, 8), 0b1010_0101);
+        assert_eq!(extract(&code, 4, 8), 0b0011_1010);
+        assert_eq!(extract(&code, 6, 3), 0b110);
+        assert_eq!(extract(&code, 0, 24), 0xFF_C3_A5);
+        assert_eq!(extract(&code, 3, 0), 0);
+    }
+
+    #[test]
+    fn search_qc() {
+        fn prop(v: Vec<u8>, m: u8, radius: u8, k: u8) -> bool {
+            let code_len = 5;
+            let query = [0x12, 0x34, 0x56, 0x78, 0x9A];
+            let mut db = Database::new(code_len);
+            for c in v.chunks(2) {
+                let mut code = query;
+                for &b in c {
+                    code[b as usize % code_len] ^= b;
+                }
+                db.push(code);
+            }
+
+            // at least 4 substrings keeps the probing small.
+            let index = MultiIndex::new(db.clone(), 4 + m as usize % 5);
+            let radius = radius as u64 % 16;
+            let k = k as usize % 10;
+            index.within(query, radius) == db.within(query, radius) &&
+                index.top_k(query, k) == db.top_k(query, k)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, u8) -> bool)
+    }
+
+    #[test]
+    fn merge_qc() {
+        fn prop(a: Vec<(u8, u8)>, b: Vec<(u8, u8)>, removals: Vec<usize>) -> bool {
+            let shard = |v: &[(u8, u8)], m| {
+                let db = Database::from_codes(2, v.iter().map(|&(x, y)| [x, y]));
+                let mut index = MultiIndex::new(db, m);
+                for &r in &removals {
+                    if !index.database().is_empty() {
+                        index.remove(r % index.database().len());
+                    }
+                }
+                index
+            };
+            let (a, b) = (shard(&a, 3), shard(&b, 5));
+            let merged = a.merge(&b);
+            let mut whole = MultiIndex::new(a.database().merge(b.database()), 3);
+            for i in 0..a.database().len() + b.database().len() {
+                let n = a.database().len();
+                let live = if i < n { a.contains(i) } else { b.contains(i - n) };
+                if !live {
+                    whole.remove(i);
+                }
+            }
+            merged.tables == whole.tables && merged.removed == whole.removed
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<(u8, u8)>, Vec<(u8, u8)>, Vec<usize>) -> bool)
+    }
+
+    #[test]
+    fn insert_remove_qc() {
+        fn prop(v: Vec<u8>, split: usize, removals: Vec<usize>, radius: u8, k: u8) -> bool {
+            let code_len = 3;
+            let query = [0x12, 0x34, 0x56];
+            let mut db = Database::new(code_len);
+            for c in v.chunks(2) {
+                let mut code = query;
+                for &b in c {
+                    code[b as usize % code_len] ^= b;
+                }
+                db.push(code);
+            }
+            let split = if db.is_empty() { 0 } else { split % db.len() };
+            let prefix = Database::from_codes(code_len, (0..split).map(|i| db.get(i)));
+            let mut index = MultiIndex::new(prefix, 4);
+            for i in split..db.len() {
+                assert_eq!(index.insert(db.get(i)), i);
+            }
+            for &r in &removals {
+                if !db.is_empty() {
+                    let r = r % db.len();
+                    let was_present = index.contains(r);
+                    if index.remove(r) != was_present || index.contains(r) {
+                        return false
+                    }
+                }
+            }
+
+            let radius = radius as u64 % 16;
+            let k = k as usize % 10;
+            let live = |&(i, _): &(usize, u64)| index.contains(i);
+            let within = db.within(query, radius).into_iter()
+                .filter(&live).collect::<Vec<_>>();
+            let mut top_k = db.top_k(query, db.len()).into_iter()
+                .filter(&live).collect::<Vec<_>>();
+            top_k.truncate(k);
+            index.len() == (0..db.len()).filter(|&i| index.contains(i)).count() &&
+                index.within(query, radius) == within && index.top_k(query, k) == top_k
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 500))
+            .quickcheck(prop as fn(Vec<u8>, usize, Vec<usize>, u8, u8) -> bool)
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+        let db = Database::from_packed(2, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        let index = MultiIndex::new(db, 4);
+        let json = serde_json::to_string(&index).unwrap();
+        let loaded = serde_json::from_str::<MultiIndex>(&json).unwrap();
+        assert_eq!(loaded.within([3, 3], 3), index.within([3, 3], 3));
+        assert_eq!(loaded.tables, index.tables);
+
+        let corrupt = json.replacen("\"substrings\":[[0,4]", "\"substrings\":[[0,5]", 1);
+        assert!(serde_json::from_str::<MultiIndex>(&corrupt).is_err());
+        let removed = json.replacen("\"removed\":[]", "\"removed\":[5]", 1);
+        assert!(serde_json::from_str::<MultiIndex>(&removed).is_err());
+        let removed = json.replacen("\"removed\":[]", "\"removed\":[4]", 1);
+        assert!(!serde_json::from_str::<MultiIndex>(&removed).unwrap().contains(4));
+    }
+
+    #[test]
+    fn bucket_stats_qc() {
+        fn prop(v: Vec<u8>, removals: Vec<usize>) -> bool {
+            let mut index = MultiIndex::new(Database::from_packed(1, &v), 2);
+            for &r in &removals {
+                if !v.is_empty() {
+                    index.remove(r % v.len());
+                }
+            }
+            let n = index.len();
+            index.bucket_stats().iter().all(|stats| {
+                stats.entries() == n &&
+                    stats.histogram().iter().sum::<usize>() == stats.buckets() &&
+                    stats.largest() as f64 >= stats.expected_probe_size() &&
+                    stats.expected_probe_size() >= stats.mean_size()
+            }) && index.expected_candidates(0) <= n as f64 &&
+                index.expected_candidates(8) == n as f64
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<usize>) -> bool)
+    }
+
+    #[test]
+    fn new_parallel() {
+        let db = Database::from_codes(3, (0..5000u32).map(|i| {
+            let x = i.wrapping_mul(0x9E37_79B9);
+            [x as u8, (x >> 8) as u8, (x >> 16) as u8]
+        }));
+        let index = MultiIndex::new(db.clone(), 5);
+        for &threads in &[1, 2, 5, 16] {
+            assert_eq!(MultiIndex::new_parallel(db.clone(), 5, threads).tables, index.tables);
+        }
+    }
+
+    #[test]
+    fn long_codes_large_radius() {
+        // probing every key within the sub-radius of these substrings
+        // would take billions of lookups
+        let mut rng = ::util::SplitMix64::new(1);
+        let db = Database::from_codes(16, (0..2000).map(|_| {
+            let (a, b) = (rng.next_u64(), rng.next_u64());
+            [a.to_le_bytes(), b.to_le_bytes()].concat()
+        }));
+        let query = [0; 16];
+        for &m in &[2, 4] {
+            let mut index = MultiIndex::new(db.clone(), m);
+            assert_eq!(index.top_k(query, 1), db.top_k(query, 1));
+            assert_eq!(index.top_k(query, 5), db.top_k(query, 5));
+            assert_eq!(index.within(query, 30), db.within(query, 30));
+            assert_eq!(index.within(query, 50), db.within(query, 50));
+
+            let nearest = db.top_k(query, 2);
+            index.remove(nearest[0].0);
+            assert_eq!(index.top_k(query, 1), &nearest[1..]);
+            assert!(!index.within(query, 50).contains(&nearest[0]));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn substrings_too_long() {
+        MultiIndex::new(Database::new(16), 1);
+    }
+}
@@ -0,0 +1,560 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::slice;
+use std::thread;
+
+use memmap2::Mmap;
+
+use database::Codes;
+use persist::{self, Kind};
+use {AsQuery, Database};
+
+/// A read-only collection of equal-width codes stored in a
+/// memory-mapped file, searchable like a `Database`.
+///
+/// The file must consist of (optionally) a header of `offset` bytes,
+/// followed by the codes, each `code_len` bytes long and starting
+/// `stride` bytes after the previous one; this is exactly the layout
+/// of `Database::as_bytes`. The codes are never copied into memory,
+/// only their weights are computed (with one pass over the file) when
+/// it is opened.
+///
+/// Alternatively, `load` maps a file written by `Database::save`,
+/// which includes the weights, and uses both the codes and the weights
+/// in place, so that even a huge database is ready to search
+/// immediately, and processes mapping the same file share the
+/// operating system's page cache rather than each having a copy.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn main() -> std::io::Result<()> {
+/// use std::io::Write;
+///
+/// let path = std::env::temp_dir().join(format!("hamming-mmap-doc-example-{}", std::process::id()));
+/// std::fs::File::create(&path)?.write_all(&[0x00, 0x00, 0xFF, 0x00, 0x0F, 0x01])?;
+///
+/// // the file is private to this process, and left alone while mapped
+/// let db = unsafe { hamming::MmapDatabase::open(&std::fs::File::open(&path)?, 2, 2, 0)? };
+/// assert_eq!(db.len(), 3);
+/// assert_eq!(db.top_k(&[0x0F, 0x00], 2), vec![(2, 1), (0, 4)]);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MmapDatabase {
+    map: Mmap,
+    offset: usize,
+    code_len: usize,
+    stride: usize,
+    weights: Weights,
+}
+
+/// The weights of the codes of an `MmapDatabase`.
+#[derive(Debug)]
+enum Weights {
+    Owned(Vec<u64>),
+    // `len` native-endian `u64`s at `offset` in the mapping, which is
+    // 8-byte aligned
+    Mapped { offset: usize, len: usize },
+}
+
+impl MmapDatabase {
+    /// Map `file` and interpret its contents after the first `offset`
+    /// bytes as codes of `code_len` bytes each, starting every
+    /// `stride` bytes.
+    ///
+    /// An error of kind `InvalidData` is returned if the contents
+    /// after `offset` are not a whole number of strides, if `stride`
+    /// is smaller than `code_len`, or if `stride` is a multiple of 8
+    /// (as in a `Database::padded` layout) but the codes do not start
+    /// on 8-byte boundaries in memory, which would defeat the point of
+    /// the padding.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated (by this process or
+    /// any other) for as long as the returned `MmapDatabase` exists.
+    /// The mapping is read as an ordinary `&[u8]`, so any change to
+    /// the underlying file, including one that shrinks it, is
+    /// undefined behaviour, as for `memmap2::Mmap::map`.
+    pub unsafe fn open(file: &File, code_len: usize, stride: usize, offset: usize)
+                -> io::Result<MmapDatabase> {
+        let map = Mmap::map(file)?;
+        MmapDatabase::from_map(map, code_len, stride, offset)
+    }
+
+    fn from_map(map: Mmap, code_len: usize, stride: usize, offset: usize)
+                -> io::Result<MmapDatabase> {
+        fn invalid(msg: String) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+        if stride < code_len {
+            return Err(invalid(format!("stride {} is smaller than the code length {}",
+                                       stride, code_len)))
+        }
+        let body = map.get(offset..)
+            .ok_or_else(|| invalid(format!("offset {} is beyond the end of the file ({} bytes)",
+                                           offset, map.len())))?;
+        if stride == 0 && !body.is_empty() || stride > 0 && !body.len().is_multiple_of(stride) {
+            return Err(invalid(format!("{} bytes is not a whole number of {}-byte strides",
+                                       body.len(), stride)))
+        }
+        if stride > 0 && stride.is_multiple_of(8) && !(body.as_ptr() as usize).is_multiple_of(8) {
+            return Err(invalid(format!("codes at offset {} are not 8-byte aligned", offset)))
+        }
+
+        let n = body.len().checked_div(stride).unwrap_or(0);
+        let weights = (0..n).map(|i| ::weight(&body[i * stride..i * stride + code_len])).collect();
+        Ok(MmapDatabase { map, offset, code_len, stride, weights: Weights::Owned(weights) })
+    }
+
+    /// Map `file`, which must have been written by `Database::save`,
+    /// and use the codes and weights it holds in place.
+    ///
+    /// Only the header and layout of the file are checked, which
+    /// takes constant time, so the whole file isn't read until it is
+    /// searched. The checksum can be checked with `verify`. An error
+    /// of kind `InvalidData` is returned for a file that isn't a
+    /// database file, or is of an unsupported version, and of kind
+    /// `UnexpectedEof` for one that has been truncated.
+    ///
+    /// On big-endian targets, the weights are converted from the
+    /// little-endian file format into memory, and only the codes are
+    /// used in place.
+    ///
+    /// # Safety
+    ///
+    /// As for `open`, the file must not be modified or truncated for
+    /// as long as the returned `MmapDatabase` exists. Beyond the codes,
+    /// this also covers the weights, which are read in place as
+    /// `&[u64]`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # fn main() -> std::io::Result<()> {
+    /// let path = std::env::temp_dir().join(format!("hamming-mmap-load-doc-example-{}", std::process::id()));
+    /// let db = hamming::Database::from_packed(2, &[0x00, 0x00, 0xFF, 0x00, 0x0F, 0x01]);
+    /// db.save(std::fs::File::create(&path)?)?;
+    ///
+    /// let mapped = unsafe { hamming::MmapDatabase::load(&std::fs::File::open(&path)?)? };
+    /// mapped.verify()?;
+    /// assert_eq!(mapped.weights(), db.weights());
+    /// assert_eq!(mapped.top_k(&[0x0F, 0x00], 2), vec![(2, 1), (0, 4)]);
+    /// # std::fs::remove_file(&path)
+    /// # }
+    /// ```
+    pub unsafe fn load(file: &File) -> io::Result<MmapDatabase> {
+        let map = Mmap::map(file)?;
+        let payload = persist::payload(&map, Kind::Database)?;
+        let mut r = persist::Reader::new(payload);
+        let (code_len, stride, len) = Database::read_layout(&mut r)?;
+        let offset = persist::HEADER_LEN + (payload.len() - r.remaining());
+        r.bytes((len * stride).next_multiple_of(8))?;
+        let weights_offset = persist::HEADER_LEN + (payload.len() - r.remaining());
+        let weights = r.bytes(len * 8)?;
+
+        let weights = if cfg!(target_endian = "little") &&
+            (weights.as_ptr() as usize).is_multiple_of(8) {
+            Weights::Mapped { offset: weights_offset, len }
+        } else {
+            Weights::Owned(weights.chunks(8).map(|w| {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(w);
+                u64::from_le_bytes(bytes)
+            }).collect())
+        };
+        Ok(MmapDatabase { map, offset, code_len, stride, weights })
+    }
+
+    /// Check the checksum of a file mapped by `load`, which reads the
+    /// whole file.
+    ///
+    /// An error of kind `InvalidData` is returned if it doesn't match,
+    /// or if this database was mapped by `open` rather than `load`,
+    /// and so has no checksum.
+    pub fn verify(&self) -> io::Result<()> {
+        persist::payload(&self.map, Kind::Database)?;
+        persist::verify(&self.map)
+    }
+
+    fn codes(&self) -> Codes<'_> {
+        Codes {
+            bytes: &self.map[self.offset..self.offset + self.len() * self.stride],
+            code_len: self.code_len,
+            stride: self.stride,
+            weights: self.weights(),
+        }
+    }
+
+    /// The number of codes stored.
+    pub fn len(&self) -> usize {
+        self.weights().len()
+    }
+
+    /// Whether there are no codes stored.
+    pub fn is_empty(&self) -> bool {
+        self.weights().is_empty()
+    }
+
+    /// The length in bytes of each code.
+    pub fn code_len(&self) -> usize {
+        self.code_len
+    }
+
+    /// The distance in bytes between the starts of consecutive codes.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The code at index `i`.
+    ///
+    /// # Panics
+    ///
+    /// `i` must be less than `self.len()`.
+    pub fn get(&self, i: usize) -> &[u8] {
+        self.codes().get(i)
+    }
+
+    /// The weights of every code, in order.
+    pub fn weights(&self) -> &[u64] {
+        match self.weights {
+            Weights::Owned(ref weights) => weights,
+            // checked to be aligned, in bounds and in the target's byte
+            // order by `load`
+            Weights::Mapped { offset, len } => unsafe {
+                slice::from_raw_parts(self.map[offset..].as_ptr() as *const u64, len)
+            },
+        }
+    }
+
+    /// Compute the distance from `query` to every code, as
+    /// `Database::scan`.
+    pub fn scan<Q: AsQuery>(&self, query: Q, out: &mut [u64]) {
+        self.codes().scan(query.query_bytes(), out)
+    }
+
+    /// Find every code within distance `radius` of `query`, as
+    /// `Database::within`.
+    pub fn within<Q: AsQuery>(&self, query: Q, radius: u64) -> Vec<(usize, u64)> {
+        self.codes().within(query, radius)
+    }
+
+    /// Find every code within distance `radius` of `query`, lazily,
+    /// as `Database::within_iter`.
+    pub fn within_iter<'a, Q>(&'a self, query: Q, radius: u64) -> impl Iterator<Item = (usize, u64)> + 'a
+        where Q: AsQuery + 'a
+    {
+        self.codes().within_iter(query, radius)
+    }
+
+    /// Find the `k` codes closest to `query`, as `Database::top_k`.
+    pub fn top_k<Q: AsQuery>(&self, query: Q, k: usize) -> Vec<(usize, u64)> {
+        self.codes().top_k(query, k)
+    }
+
+    /// Find `k` codes close to `query`, allowing each returned
+    /// distance to be up to `epsilon` more than the exact one, as
+    /// `Database::top_k_approx`.
+    pub fn top_k_approx<Q: AsQuery>(&self, query: Q, k: usize, epsilon: u64) -> Vec<(usize, u64)> {
+        self.codes().top_k_approx(query, k, epsilon)
+    }
+
+    /// Find every code within distance `radius` of `query` whose
+    /// index passes `filter`, lazily, as `Database::within_filtered`.
+    pub fn within_filtered<'a, Q, F>(&'a self, query: Q, radius: u64, filter: F)
+                                     -> impl Iterator<Item = (usize, u64)> + 'a
+        where Q: AsQuery + 'a, F: FnMut(usize) -> bool + 'a
+    {
+        self.codes().within_filtered(query, radius, filter)
+    }
+
+    /// Find (up to) `k` codes close to `query` among those whose index
+    /// passes `filter`, as `Database::top_k_filtered`.
+    pub fn top_k_filtered<Q, F>(&self, query: Q, k: usize, epsilon: u64, filter: F) -> Vec<(usize, u64)>
+        where Q: AsQuery, F: FnMut(usize) -> bool
+    {
+        self.codes().top_k_filtered(query, k, epsilon, filter)
+    }
+}
+
+/// Map the file at `path` for a single pass from start to end.
+///
+/// # Safety
+///
+/// The file must not be modified while the mapping exists.
+unsafe fn map_sequential(path: &Path) -> io::Result<Mmap> {
+    let map = Mmap::map(&File::open(path)?)?;
+    #[cfg(unix)]
+    map.advise(memmap2::Advice::Sequential)?;
+    Ok(map)
+}
+
+/// Sum `f` over `threads` contiguous ranges covering `0..len`, each
+/// on its own thread.
+fn sum_parallel<F>(len: usize, threads: usize, f: F) -> u64
+    where F: Fn(Range<usize>) -> u64 + Sync
+{
+    let chunk = len.div_ceil(threads).max(1);
+    let f = &f;
+    thread::scope(|scope| {
+        let counts = (0..len).step_by(chunk)
+            .map(|start| scope.spawn(move || f(start..len.min(start + chunk))))
+            .collect::<Vec<_>>();
+        counts.into_iter().map(|count| count.join().unwrap()).sum()
+    })
+}
+
+/// Map the files at `a` and `b` for comparison, checking they're the
+/// same length.
+///
+/// # Safety
+///
+/// Neither file may be modified while the mappings exist.
+unsafe fn map_pair(a: &Path, b: &Path) -> io::Result<(Mmap, Mmap)> {
+    let (a, b) = (map_sequential(a)?, map_sequential(b)?);
+    if a.len() != b.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("files have different lengths ({} and {} bytes)",
+                                          a.len(), b.len())))
+    }
+    Ok((a, b))
+}
+
+/// Computes the Hamming weight of the contents of the file at `path`.
+///
+/// With the `memmap` feature, the file is memory-mapped (with the
+/// operating system advised that it will be read sequentially) and
+/// counted with `weight` in a single call, so even very large files
+/// are counted at full speed without being copied into memory.
+///
+/// # Safety
+///
+/// The file must not be modified or truncated (by this process or
+/// any other) until this returns, as for `MmapDatabase::open`.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join(format!("hamming-weight-file-doc-{}", std::process::id()));
+/// std::fs::write(&path, [0xFF, 0x01, 0x00])?;
+/// assert_eq!(unsafe { hamming::weight_file(&path)? }, 9);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub unsafe fn weight_file<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+    Ok(::weight(&map_sequential(path.as_ref())?))
+}
+
+/// Computes the Hamming weight of the contents of the file at `path`,
+/// like `weight_file`, using `threads` threads.
+///
+/// The file is split into `threads` contiguous pieces, each counted
+/// with `weight` on its own thread, which helps when the file is
+/// already in the page cache or on storage faster than a single core
+/// can count.
+///
+/// # Panics
+///
+/// `threads` must be non-zero.
+///
+/// # Safety
+///
+/// As for `weight_file`.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn main() -> std::io::Result<()> {
+/// let path = std::env::temp_dir().join(format!("hamming-weight-file-parallel-doc-{}", std::process::id()));
+/// std::fs::write(&path, vec![0x11; 10_000])?;
+/// assert_eq!(unsafe { hamming::weight_file_parallel(&path, 4)? }, 20_000);
+/// # std::fs::remove_file(&path)
+/// # }
+/// ```
+pub unsafe fn weight_file_parallel<P: AsRef<Path>>(path: P, threads: usize) -> io::Result<u64> {
+    assert!(threads > 0, "cannot count with no threads");
+    let map = map_sequential(path.as_ref())?;
+    Ok(sum_parallel(map.len(), threads, |range| ::weight(&map[range])))
+}
+
+/// Computes the bitwise Hamming distance between the contents of the
+/// files at `a` and `b`.
+///
+/// With the `memmap` feature, both files are memory-mapped, as with
+/// `weight_file`, and compared with `distance`. An error of kind
+/// `InvalidData` is returned if they have different lengths.
+///
+/// # Safety
+///
+/// Neither file may be modified or truncated until this returns, as
+/// for `MmapDatabase::open`.
+///
+/// # Examples
+///
+/// ```rust
+/// # fn main() -> std::io::Result<()> {
+/// let dir = std::env::temp_dir();
+/// let (a, b) = (dir.join(format!("hamming-distance-files-doc-a-{}", std::process::id())),
+///               dir.join(format!("hamming-distance-files-doc-b-{}", std::process::id())));
+/// std::fs::write(&a, [0xFF, 0x01])?;
+/// std::fs::write(&b, [0x0F, 0x01])?;
+/// assert_eq!(unsafe { hamming::distance_files(&a, &b)? }, 4);
+/// # std::fs::remove_file(&a)?;
+/// # std::fs::remove_file(&b)
+/// # }
+/// ```
+pub unsafe fn distance_files<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> io::Result<u64> {
+    let (a, b) = map_pair(a.as_ref(), b.as_ref())?;
+    Ok(::distance(&a, &b))
+}
+
+/// Computes the bitwise Hamming distance between the contents of the
+/// files at `a` and `b`, like `distance_files`, using `threads`
+/// threads.
+///
+/// Both files are split into the same `threads` contiguous pieces,
+/// each pair compared with `distance` on its own thread.
+///
+/// # Panics
+///
+/// `threads` must be non-zero.
+///
+/// # Safety
+///
+/// As for `distance_files`.
+pub unsafe fn distance_files_parallel<P, Q>(a: P, b: Q, threads: usize) -> io::Result<u64>
+    where P: AsRef<Path>, Q: AsRef<Path>
+{
+    assert!(threads > 0, "cannot compare with no threads");
+    let (a, b) = map_pair(a.as_ref(), b.as_ref())?;
+    Ok(sum_parallel(a.len(), threads, |range| ::distance(&a[range.clone()], &b[range])))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::{ErrorKind, Write};
+    use std::path::PathBuf;
+
+    use super::{distance_files, distance_files_parallel, weight_file, weight_file_parallel, MmapDatabase};
+    use Database;
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = ::std::env::temp_dir().join(format!("hamming-mmap-{}-{}", name, ::std::process::id()));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn matches_database() {
+        let mut db = Database::padded(11);
+        for i in 0..50u8 {
+            let code = (0..11).map(|j| i.wrapping_mul(j + 17) ^ j).collect::<Vec<_>>();
+            db.push(&code);
+        }
+        let mut contents = vec![0xAA; 16];
+        contents.extend_from_slice(db.as_bytes());
+        let path = temp_file("matches", &contents);
+
+        let mapped = unsafe { MmapDatabase::open(&File::open(&path).unwrap(), 11, db.stride(), 16).unwrap() };
+        assert_eq!(mapped.len(), db.len());
+        assert_eq!(mapped.weights(), db.weights());
+        let query = db.get(7);
+        assert_eq!(mapped.within(query, 30), db.within(query, 30));
+        assert_eq!(mapped.top_k(query, 5), db.top_k(query, 5));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        let path = temp_file("invalid", &[0; 20]);
+        let file = File::open(&path).unwrap();
+        for &(code_len, stride, offset) in &[(3, 3, 0), // partial code
+                                             (4, 2, 0), // stride too small
+                                             (8, 8, 4), // misaligned
+                                             (1, 1, 21)] { // offset too large
+            let err = unsafe { MmapDatabase::open(&file, code_len, stride, offset).unwrap_err() };
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+        assert_eq!(unsafe { MmapDatabase::open(&file, 3, 4, 0).unwrap().len() }, 5);
+        assert_eq!(unsafe { MmapDatabase::open(&file, 1, 1, 20).unwrap().len() }, 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_saved() {
+        for &(padded, code_len) in &[(false, 3), (true, 11), (true, 0)] {
+            let mut db = if padded { Database::padded(code_len) } else { Database::new(code_len) };
+            for i in 0..37u8 {
+                let code = (0..code_len as u8).map(|j| i.wrapping_mul(j + 17) ^ j).collect::<Vec<_>>();
+                db.push(&code);
+            }
+            let mut contents = Vec::new();
+            db.save(&mut contents).unwrap();
+            let path = temp_file("load", &contents);
+
+            let mapped = unsafe { MmapDatabase::load(&File::open(&path).unwrap()).unwrap() };
+            mapped.verify().unwrap();
+            assert_eq!((mapped.len(), mapped.code_len(), mapped.stride()),
+                       (db.len(), db.code_len(), db.stride()));
+            assert_eq!(mapped.weights(), db.weights());
+            if cfg!(target_endian = "little") {
+                assert!(matches!(mapped.weights, super::Weights::Mapped { .. }));
+            }
+            let query = db.get(7);
+            assert_eq!(mapped.within(query, 12), db.within(query, 12));
+            assert_eq!(mapped.top_k(query, 5), db.top_k(query, 5));
+
+            // damage is only found by `verify`
+            let last = contents.len() - 9;
+            contents[last] ^= 1;
+            File::create(&path).unwrap().write_all(&contents).unwrap();
+            let damaged = unsafe { MmapDatabase::load(&File::open(&path).unwrap()).unwrap() };
+            assert_eq!(damaged.verify().unwrap_err().kind(), ErrorKind::InvalidData);
+            File::create(&path).unwrap().write_all(&contents[..last]).unwrap();
+            let err = unsafe { MmapDatabase::load(&File::open(&path).unwrap()).unwrap_err() };
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+            fs::remove_file(&path).unwrap();
+        }
+
+        let path = temp_file("load-raw", &[0; 64]);
+        let raw = unsafe { MmapDatabase::open(&File::open(&path).unwrap(), 8, 8, 0).unwrap() };
+        assert_eq!(raw.verify().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(unsafe { MmapDatabase::load(&File::open(&path).unwrap()).is_err() });
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files() {
+        let contents = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+        let flipped = contents.iter().map(|&b| b ^ 0x11).collect::<Vec<_>>();
+        let (a, b) = (temp_file("file-a", &contents), temp_file("file-b", &flipped));
+        let (short, empty) = (temp_file("file-short", &contents[1..]), temp_file("file-empty", &[]));
+
+        unsafe {
+            assert_eq!(weight_file(&a).unwrap(), ::weight(&contents));
+            assert_eq!(weight_file(&empty).unwrap(), 0);
+            assert_eq!(distance_files(&a, &b).unwrap(), 2 * 100_000);
+            assert_eq!(distance_files(&empty, &empty).unwrap(), 0);
+            assert_eq!(distance_files(&a, &short).unwrap_err().kind(), ErrorKind::InvalidData);
+            assert_eq!(weight_file(a.with_extension("missing")).unwrap_err().kind(), ErrorKind::NotFound);
+
+            for &threads in &[1, 3, 8] {
+                assert_eq!(weight_file_parallel(&a, threads).unwrap(), ::weight(&contents));
+                assert_eq!(weight_file_parallel(&empty, threads).unwrap(), 0);
+                assert_eq!(distance_files_parallel(&a, &b, threads).unwrap(), 2 * 100_000);
+                assert_eq!(distance_files_parallel(&empty, &empty, threads).unwrap(), 0);
+                assert_eq!(distance_files_parallel(&a, &short, threads).unwrap_err().kind(),
+                           ErrorKind::InvalidData);
+            }
+        }
+        for path in &[a, b, short, empty] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
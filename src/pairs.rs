@@ -0,0 +1,208 @@
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use Database;
+
+/// The indices of the codes of `db` sorted by weight, with ties in
+/// index order.
+fn by_weight(db: &Database) -> Vec<usize> {
+    let weights = db.weights();
+    let mut order = (0..db.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| (weights[i], i));
+    order
+}
+
+/// Find the closest pair of distinct codes in `db`, returning their
+/// indices `(i, j)`, with `i < j`, and their distance, or `None` if
+/// there are fewer than two codes.
+///
+/// If several pairs are equally close, this returns the one with the
+/// lowest `i`, and then the lowest `j`.
+///
+/// The distance between two codes is at least the difference of
+/// their weights, so the codes are swept in order of weight, and each
+/// is only compared with the following codes whose weights are within
+/// the best distance found so far, with each comparison abandoned
+/// early (for long codes) once it exceeds that distance. As the best
+/// distance shrinks, so does the window of codes compared, so this is
+/// much faster than comparing every pair whenever the weights are
+/// spread more widely than the closest distance; it only degrades to
+/// comparing every pair when all the codes have about the same
+/// weight.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x00, 0xF0, 0xFF, 0x0F, 0xF1]);
+/// assert_eq!(hamming::closest_pair(&db), Some((1, 4, 1)));
+/// assert_eq!(hamming::closest_pair(&hamming::Database::new(1)), None);
+/// ```
+pub fn closest_pair(db: &Database) -> Option<(usize, usize, u64)> {
+    let weights = db.weights();
+    let order = by_weight(db);
+    let mut best: Option<(u64, usize, usize)> = None;
+    for (a, &i) in order.iter().enumerate() {
+        let code = db.get(i);
+        for &j in &order[a + 1..] {
+            let bound = best.map_or(u64::MAX, |(d, _, _)| d);
+            if weights[j] - weights[i] > bound {
+                // every later code is at least this far away
+                break
+            }
+            if let Some(d) = db.distance_bounded(code, j, bound) {
+                let candidate = (d, i.min(j), i.max(j));
+                if best.is_none_or(|best| candidate < best) {
+                    best = Some(candidate);
+                }
+            }
+        }
+    }
+    best.map(|(d, i, j)| (i, j, d))
+}
+
+/// The code of `db` farthest from code `i`, other than `i` itself,
+/// with its distance, using `distances` as scratch space. Ties go to
+/// the lowest index.
+fn farthest_from(db: &Database, i: usize, distances: &mut [u64]) -> (usize, u64) {
+    db.scan(db.get(i), distances);
+    distances.iter().cloned().enumerate()
+        .filter(|&(j, _)| j != i)
+        .fold((usize::MAX, 0), |best, (j, d)| if best.0 == usize::MAX || d > best.1 { (j, d) } else { best })
+}
+
+/// Find a pair of distinct codes of `db` that are far apart, returning
+/// their indices `(i, j)`, with `i < j`, and their distance, or `None`
+/// if there are fewer than two codes.
+///
+/// This is the two-sweep heuristic: find the code `a` farthest from
+/// the first code, then the code `b` farthest from `a`. It takes just
+/// two scans of the database, and the triangle inequality guarantees
+/// that the distance found is at least half of the largest (that of
+/// `farthest_pair`), while in practice it is usually the largest or
+/// close to it. The two codes are good initial centres for
+/// clustering.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x01, 0x03, 0xFE, 0x00]);
+/// assert_eq!(hamming::farthest_pair_approx(&db), Some((0, 2, 8)));
+/// ```
+pub fn farthest_pair_approx(db: &Database) -> Option<(usize, usize, u64)> {
+    if db.len() < 2 {
+        return None
+    }
+    let mut distances = vec![0; db.len()];
+    let (a, _) = farthest_from(db, 0, &mut distances);
+    let (b, d) = farthest_from(db, a, &mut distances);
+    Some((a.min(b), a.max(b), d))
+}
+
+/// Find the farthest pair of distinct codes in `db`, returning their
+/// indices `(i, j)`, with `i < j`, and their distance (the diameter of
+/// the set of codes), or `None` if there are fewer than two codes.
+///
+/// If several pairs are equally far apart, this returns the one with
+/// the lowest `i`, and then the lowest `j`.
+///
+/// Codes of `b` bits with weights `x` and `y` are at most `min(x + y,
+/// 2b - x - y)` apart, so the search starts from the pair found by
+/// `farthest_pair_approx`, and only compares the pairs whose weights
+/// allow them to be at least that far apart, sweeping the codes in
+/// order of weight and skipping any code whose weight rules out every
+/// partner. This is much faster than comparing every pair when the
+/// weights are spread, but every pair can still need comparing when
+/// they aren't.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(1, &[0x01, 0x03, 0xFE, 0x00, 0xFF]);
+/// assert_eq!(hamming::farthest_pair(&db), Some((0, 2, 8)));
+/// assert_eq!(hamming::farthest_pair(&hamming::Database::new(1)), None);
+/// ```
+pub fn farthest_pair(db: &Database) -> Option<(usize, usize, u64)> {
+    let (i, j, d) = farthest_pair_approx(db)?;
+    // order by decreasing distance, then increasing indices
+    let mut best = (Reverse(d), i, j);
+    let bits = 8 * db.code_len() as u64;
+    let bound = |x: u64, y: u64| (x + y).min(2 * bits - x - y);
+
+    let weights = db.weights();
+    let order = by_weight(db);
+    let (lightest, heaviest) = (weights[order[0]], weights[order[order.len() - 1]]);
+    for (a, &i) in order.iter().enumerate() {
+        let w = weights[i];
+        // the most that any partner of `i` could be at: the bound is
+        // largest for partners of weight `bits - w`
+        let target = (bits - w).clamp(lightest, heaviest);
+        if bound(w, target) < (best.0).0 {
+            continue
+        }
+        let code = db.get(i);
+        for &j in &order[a + 1..] {
+            if bound(w, weights[j]) < (best.0).0 {
+                continue
+            }
+            let candidate = (Reverse(::distance(code, db.get(j))), i.min(j), i.max(j));
+            if candidate < best {
+                best = candidate;
+            }
+        }
+    }
+    Some((best.1, best.2, (best.0).0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest_pair, farthest_pair, farthest_pair_approx};
+    use core::cmp::Reverse;
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    /// The pair minimising `key` over every pair of codes, by brute
+    /// force.
+    fn brute_force<K: Ord>(db: &Database, key: impl Fn(u64, usize, usize) -> K)
+                               -> Option<(usize, usize, u64)> {
+        (0..db.len())
+            .flat_map(|i| (i + 1..db.len()).map(move |j| (i, j)))
+            .map(|(i, j)| (i, j, ::distance(db.get(i), db.get(j))))
+            .min_by_key(|&(i, j, d)| key(d, i, j))
+    }
+
+    #[test]
+    fn closest_pair_qc() {
+        fn prop(v: Vec<u8>, code_len: u8) -> bool {
+            let code_len = code_len as usize % 40 + 1;
+            let v = &v[..v.len() - v.len() % code_len];
+            let db = Database::from_packed(code_len, v);
+            closest_pair(&db) == brute_force(&db, |d, i, j| (d, i, j))
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 500))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+
+    #[test]
+    fn farthest_pair_qc() {
+        fn prop(v: Vec<u8>, code_len: u8) -> bool {
+            let code_len = code_len as usize % 40 + 1;
+            let v = &v[..v.len() - v.len() % code_len];
+            let db = Database::from_packed(code_len, v);
+            let exact = farthest_pair(&db);
+            let approx = farthest_pair_approx(&db);
+            exact == brute_force(&db, |d, i, j| (Reverse(d), i, j)) &&
+                match (exact, approx) {
+                    (Some((_, _, max)), Some((i, j, d))) => {
+                        i < j && d == ::distance(db.get(i), db.get(j)) && 2 * d >= max
+                    }
+                    (None, None) => true,
+                    _ => false,
+                }
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 500))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+}
@@ -0,0 +1,292 @@
+//! The versioned binary file format shared by `Database::save` and
+//! the `save` methods of the indexes.
+//!
+//! A file is a 32-byte header, a payload, and a checksum:
+//!
+//! | bytes | contents |
+//! |-------|----------|
+//! | 8 | the magic number `HAMMING\0` |
+//! | 2 | the major version, currently 1 |
+//! | 2 | the minor version, currently 0 |
+//! | 4 | the byte-order mark `0x01020304` |
+//! | 4 | the kind of data in the payload (database or index type) |
+//! | 4 | reserved, zero |
+//! | 8 | the payload length in bytes, `n` |
+//! | `n` | the payload, padded to 8-byte alignment where needed |
+//! | 8 | the 64-bit FNV-1a hash of everything before it |
+//!
+//! Every integer is little-endian, whatever the platform, and the
+//! byte-order mark is there so that a file mangled by a big-endian
+//! writer is rejected rather than misread. A reader rejects other
+//! major versions, and accepts newer minor versions of its own,
+//! ignoring any payload bytes after the fields it knows: minor
+//! versions may only append fields.
+//!
+//! The payload of a database is its code length, stride and number
+//! of codes, each as a `u64`, then its codes laid out as in
+//! `Database::as_bytes`, zero-padded to a multiple of 8 bytes, then
+//! the weight of each code as a `u64`. Since the header and each of
+//! these fields are multiples of 8 bytes long, the codes of a padded
+//! database and the weights are 8-byte aligned in a file mapped into
+//! memory, so `MmapDatabase::load` can use them in place.
+
+use std::io::{self, Read, Write};
+
+const MAGIC: [u8; 8] = *b"HAMMING\0";
+const MAJOR: u16 = 1;
+const MINOR: u16 = 0;
+const BYTE_ORDER_MARK: u32 = 0x0102_0304;
+/// The length of the header, and so the offset of the payload.
+pub const HEADER_LEN: usize = 32;
+
+/// The type stored in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Database = 1,
+    MultiIndex = 2,
+    BitSamplingIndex = 3,
+    VpTree = 4,
+    PivotIndex = 5,
+}
+
+pub fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |h, &b| (h ^ b as u64).wrapping_mul(0x100_0000_01B3))
+}
+
+/// A payload under construction.
+#[derive(Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub fn u64(&mut self, x: u64) {
+        self.buf.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, x: usize) {
+        self.u64(x as u64)
+    }
+
+    pub fn bytes(&mut self, x: &[u8]) {
+        self.buf.extend_from_slice(x);
+    }
+
+    /// Write the file holding this payload to `writer`.
+    pub fn finish<W: Write>(self, kind: Kind, mut writer: W) -> io::Result<()> {
+        let mut file = Vec::with_capacity(HEADER_LEN + self.buf.len() + 8);
+        file.extend_from_slice(&MAGIC);
+        file.extend_from_slice(&MAJOR.to_le_bytes());
+        file.extend_from_slice(&MINOR.to_le_bytes());
+        file.extend_from_slice(&BYTE_ORDER_MARK.to_le_bytes());
+        file.extend_from_slice(&(kind as u32).to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&(self.buf.len() as u64).to_le_bytes());
+        file.extend_from_slice(&self.buf);
+        let checksum = fnv1a(&file);
+        file.extend_from_slice(&checksum.to_le_bytes());
+        writer.write_all(&file)?;
+        writer.flush()
+    }
+}
+
+/// Check that `header`, the first `HEADER_LEN` bytes of a file, is
+/// that of a file holding a `kind`, returning the payload length.
+fn check_header(header: &[u8], kind: Kind) -> io::Result<u64> {
+    let u16_at = |start: usize| u16::from_le_bytes([header[start], header[start + 1]]);
+    let u32_at = |start: usize| u32::from_le_bytes([header[start], header[start + 1],
+                                                    header[start + 2], header[start + 3]]);
+    if header[..8] != MAGIC {
+        return Err(invalid("not a hamming file".to_string()))
+    }
+    let (major, minor) = (u16_at(8), u16_at(10));
+    if major != MAJOR {
+        return Err(invalid(format!("unsupported format version {}.{}", major, minor)))
+    }
+    if u32_at(12) != BYTE_ORDER_MARK {
+        return Err(invalid("invalid byte-order mark".to_string()))
+    }
+    if u32_at(16) != kind as u32 {
+        return Err(invalid(format!("expected a {:?}, found kind {}", kind, u32_at(16))))
+    }
+    let mut len = [0; 8];
+    len.copy_from_slice(&header[24..32]);
+    Ok(u64::from_le_bytes(len))
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated hamming file")
+}
+
+/// The payload of `file`, a whole file holding a `kind`, which starts
+/// at offset `HEADER_LEN`.
+///
+/// This only checks the header and the length, not the checksum, so
+/// it takes constant time.
+#[cfg(feature = "memmap")]
+pub fn payload(file: &[u8], kind: Kind) -> io::Result<&[u8]> {
+    if file.len() < HEADER_LEN {
+        return Err(truncated())
+    }
+    let len = check_header(&file[..HEADER_LEN], kind)?;
+    if ((file.len() - HEADER_LEN) as u64) < len.saturating_add(8) {
+        return Err(truncated())
+    }
+    Ok(&file[HEADER_LEN..HEADER_LEN + len as usize])
+}
+
+/// Check the checksum of `file`, a whole file with a valid header.
+pub fn verify(file: &[u8]) -> io::Result<()> {
+    let mut len = [0; 8];
+    len.copy_from_slice(&file[24..32]);
+    let body_end = HEADER_LEN + u64::from_le_bytes(len) as usize;
+    let mut checksum = [0; 8];
+    checksum.copy_from_slice(&file[body_end..body_end + 8]);
+    if u64::from_le_bytes(checksum) != fnv1a(&file[..body_end]) {
+        return Err(invalid("checksum mismatch".to_string()))
+    }
+    Ok(())
+}
+
+/// Read a whole file from `reader`, check that it is intact and holds
+/// a `kind`, and return its payload.
+pub fn read<R: Read>(kind: Kind, reader: R) -> io::Result<Vec<u8>> {
+    let mut reader = reader;
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    let len = check_header(&header, kind)?;
+    let mut file = header.to_vec();
+    reader.take(len.saturating_add(8)).read_to_end(&mut file)?;
+    if ((file.len() - HEADER_LEN) as u64) < len.saturating_add(8) {
+        return Err(truncated())
+    }
+    verify(&file)?;
+    file.truncate(file.len() - 8);
+    file.drain(..HEADER_LEN);
+    Ok(file)
+}
+
+/// A cursor over a payload.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    /// The number of bytes not yet read.
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.data.len() {
+            return Err(invalid(format!("payload ends {} bytes early", len - self.data.len())))
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let mut x = [0; 8];
+        x.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(x))
+    }
+
+    pub fn usize(&mut self) -> io::Result<usize> {
+        let x = self.u64()?;
+        if x > usize::MAX as u64 {
+            return Err(invalid(format!("{} is too large for this platform", x)))
+        }
+        Ok(x as usize)
+    }
+
+    /// Read a count of items that each take at least `item_len` bytes
+    /// of what remains, rejecting counts that can't fit, so that a
+    /// corrupt count can't trigger a huge allocation.
+    pub fn count(&mut self, item_len: usize) -> io::Result<usize> {
+        let n = self.usize()?;
+        if n.saturating_mul(item_len) > self.remaining() {
+            return Err(invalid(format!("count {} is too large for the payload", n)))
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, Kind, Reader, Writer};
+    use std::io::ErrorKind;
+
+    fn file(payload: &[u8]) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.bytes(payload);
+        let mut out = Vec::new();
+        w.finish(Kind::VpTree, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut w = Writer::new();
+        w.u64(0x0102_0304_0506_0708);
+        w.bytes(b"codes");
+        let mut out = Vec::new();
+        w.finish(Kind::Database, &mut out).unwrap();
+        assert_eq!(&out[..8], b"HAMMING\0");
+        assert_eq!(out.len(), 32 + 13 + 8);
+
+        let payload = read(Kind::Database, &out[..]).unwrap();
+        let mut r = Reader::new(&payload);
+        assert_eq!(r.u64().unwrap(), 0x0102_0304_0506_0708);
+        assert_eq!(r.bytes(5).unwrap(), b"codes");
+        assert!(r.bytes(1).is_err());
+    }
+
+    #[test]
+    fn corruption() {
+        let good = file(&[1, 2, 3, 4, 5]);
+        assert!(read(Kind::VpTree, &good[..]).is_ok());
+        assert_eq!(read(Kind::PivotIndex, &good[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // flipping any bit is detected
+        for i in 0..good.len() * 8 {
+            let mut bad = good.clone();
+            bad[i / 8] ^= 1 << (i % 8);
+            assert!(read(Kind::VpTree, &bad[..]).is_err(), "bit {}", i);
+        }
+        for len in 0..good.len() {
+            assert!(read(Kind::VpTree, &good[..len]).is_err(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn newer_minor_version() {
+        let mut future = file(&[1, 2, 3]);
+        future[10] = 7;
+        let end = future.len() - 8;
+        let checksum = super::fnv1a(&future[..end]);
+        future[end..].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(read(Kind::VpTree, &future[..]).unwrap(), [1, 2, 3]);
+
+        future[8] = 2;
+        assert!(read(Kind::VpTree, &future[..]).is_err());
+    }
+
+    #[test]
+    fn huge_counts() {
+        let mut r = Reader::new(&[0xFF; 8]);
+        assert!(r.count(1).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Perceptual hashes of images, for finding near-duplicates.
+//!
+//! These compute 64-bit fingerprints of grayscale images, such that
+//! resized, recompressed or slightly edited copies of an image have
+//! fingerprints at a small Hamming distance. Each image is first
+//! shrunk to a tiny thumbnail by averaging blocks of pixels, which
+//! discards the detail that such edits change, and then:
+//!
+//! - `ahash` (average hash) sets the bits of the 8 by 8 thumbnail's
+//!   pixels that are brighter than its mean,
+//! - `dhash` (difference hash) sets the bits of the pixels of a 9 by 8
+//!   thumbnail that are darker than their right neighbour, which
+//!   tends to be more robust to changes of brightness and contrast.
+//!
+//! There's no image decoding here: pass the pixels as one byte of
+//! luminance each, row by row, as produced by most image libraries'
+//! grayscale conversions. Bit `8 * row + column` of a fingerprint
+//! describes that cell of the thumbnail, and fingerprints can be
+//! compared with `distance` on their little-endian bytes, or stored
+//! in a `Database` or the indexes the same way. See Neal Krawetz,
+//! [Kind of Like That](https://www.hackerfactor.com/blog/index.php?/archives/529-Kind-of-Like-That.html).
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hamming::phash;
+//!
+//! // a 16 by 16 horizontal gradient, and a brighter copy
+//! let image = (0..256).map(|i| (i % 16 * 10) as u8).collect::<Vec<_>>();
+//! let brighter = image.iter().map(|&p| p + 50).collect::<Vec<_>>();
+//!
+//! let (a, b) = (phash::dhash(&image, 16, 16), phash::dhash(&brighter, 16, 16));
+//! assert_eq!(hamming::distance(&a.to_le_bytes(), &b.to_le_bytes()), 0);
+//! assert_eq!(phash::ahash(&image, 16, 16), phash::ahash(&brighter, 16, 16));
+//! ```
+
+/// Shrink the `width` by `height` image `pixels` to the `W` by `H`
+/// thumbnail of the sums of the blocks of pixels it covers, along with
+/// the number of pixels in each block.
+fn shrink<const W: usize, const H: usize>(pixels: &[u8], width: usize, height: usize)
+                                          -> [[(u64, u64); W]; H] {
+    assert!(width > 0 && height > 0, "a {} by {} image is empty", width, height);
+    assert_eq!(pixels.len(), width * height,
+               "{} pixels is not a {} by {} image", pixels.len(), width, height);
+    // the blocks cover every pixel, and each have at least one, even
+    // for images smaller than the thumbnail
+    let block = |i: usize, n: usize, len: usize| {
+        let start = (i * len / n).min(len - 1);
+        start..((i + 1) * len / n).max(start + 1)
+    };
+    let mut thumbnail = [[(0, 0); W]; H];
+    for (r, row) in thumbnail.iter_mut().enumerate() {
+        for y in block(r, H, height) {
+            let line = &pixels[y * width..(y + 1) * width];
+            for (c, cell) in row.iter_mut().enumerate() {
+                let xs = block(c, W, width);
+                cell.0 += line[xs.clone()].iter().map(|&p| p as u64).sum::<u64>();
+                cell.1 += xs.len() as u64;
+            }
+        }
+    }
+    thumbnail
+}
+
+/// Compute the average hash of the `width` by `height` grayscale
+/// image `pixels`, stored row by row.
+///
+/// Bit `8 * row + column` is set if that cell of an 8 by 8 thumbnail
+/// of the image is brighter than the thumbnail's mean.
+///
+/// # Panics
+///
+/// The image must be non-empty, and `pixels` must have `width *
+/// height` elements.
+///
+/// # Examples
+///
+/// ```rust
+/// // the left half of the image is bright
+/// let image = (0..32 * 8).map(|i| if i % 32 < 16 { 255 } else { 0 }).collect::<Vec<_>>();
+/// assert_eq!(hamming::phash::ahash(&image, 32, 8), 0x0F0F_0F0F_0F0F_0F0F);
+/// ```
+pub fn ahash(pixels: &[u8], width: usize, height: usize) -> u64 {
+    let thumbnail = shrink::<8, 8>(pixels, width, height);
+    let mean = |&(sum, count): &(u64, u64)| sum as f64 / count as f64;
+    let total = thumbnail.iter().flatten().map(mean).sum::<f64>();
+    thumbnail.iter().flatten().enumerate()
+        .fold(0, |hash, (i, cell)| hash | ((64.0 * mean(cell) > total) as u64) << i)
+}
+
+/// Compute the difference hash of the `width` by `height` grayscale
+/// image `pixels`, stored row by row.
+///
+/// Bit `8 * row + column` is set if that cell of a 9 by 8 thumbnail of
+/// the image is darker than the cell to its right.
+///
+/// # Panics
+///
+/// The image must be non-empty, and `pixels` must have `width *
+/// height` elements.
+///
+/// # Examples
+///
+/// ```rust
+/// // brightening to the right
+/// let image = (0..90 * 8).map(|i| (i % 90) as u8).collect::<Vec<_>>();
+/// assert_eq!(hamming::phash::dhash(&image, 90, 8), !0);
+/// ```
+pub fn dhash(pixels: &[u8], width: usize, height: usize) -> u64 {
+    let thumbnail = shrink::<9, 8>(pixels, width, height);
+    thumbnail.iter()
+        .flat_map(|row| row.windows(2))
+        .enumerate()
+        .fold(0, |hash, (i, pair)| {
+            let ((left, left_count), (right, right_count)) = (pair[0], pair[1]);
+            hash | ((left * right_count < right * left_count) as u64) << i
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ahash, dhash};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn uniform() {
+        for &(w, h) in &[(1, 1), (3, 5), (8, 8), (100, 37)] {
+            let image = vec![123; w * h];
+            assert_eq!(ahash(&image, w, h), 0);
+            assert_eq!(dhash(&image, w, h), 0);
+        }
+    }
+
+    /// Scale the `width` pixel wide `image` up by `scale` in each
+    /// direction.
+    fn enlarge(image: &[u8], width: usize, scale: usize) -> Vec<u8> {
+        image.chunks(width)
+            .flat_map(|row| vec![row; scale])
+            .flat_map(|row| row.iter().flat_map(|&p| vec![p; scale]))
+            .collect()
+    }
+
+    #[test]
+    fn resize_qc() {
+        // scaling an image up doesn't change its thumbnails, if it is
+        // a multiple of their size
+        fn prop(pixels: Vec<u8>, scale: u8) -> bool {
+            let scale = scale as usize % 4 + 1;
+            if pixels.len() < 72 {
+                return true
+            }
+            let (small, image) = (&pixels[..64], &pixels[..72]);
+            ahash(small, 8, 8) == ahash(&enlarge(small, 8, scale), 8 * scale, 8 * scale) &&
+                dhash(image, 9, 8) == dhash(&enlarge(image, 9, scale), 9 * scale, 8 * scale)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool);
+    }
+
+    #[test]
+    fn small_images() {
+        // every cell of the thumbnail covers some pixel
+        assert_eq!(ahash(&[0, 255], 2, 1), 0xF0F0_F0F0_F0F0_F0F0);
+        assert_eq!(dhash(&[0, 255], 2, 1), 0x1010_1010_1010_1010);
+    }
+}
@@ -0,0 +1,340 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+use {AsQuery, Database, TopK};
+#[cfg(feature = "std")]
+use persist::{self, Kind};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An exact nearest-neighbour index filtering candidates by their
+/// precomputed distances to a small set of pivots (LAESA).
+///
+/// The distance from every code to each pivot is computed when the
+/// index is built. At query time, only the distances from the query
+/// to the pivots are computed, and the triangle inequality gives
+/// `|distance(q, p) - distance(c, p)| <= distance(q, c)` for each
+/// pivot `p`, so many codes `c` can be ruled out without touching
+/// their bytes. See Micó, Oncina and Vidal, [A new version of the
+/// Nearest-Neighbour Approximating and Eliminating Search Algorithm
+/// (AESA) with linear preprocessing time and memory
+/// requirements](https://doi.org/10.1016/0167-8655(94)90095-7).
+///
+/// Pivots are chosen greedily to be far from each other, starting
+/// from the first code. Results are identical to the corresponding
+/// `Database` methods.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut db = hamming::Database::new(2);
+/// db.push(&[0x00, 0x00]);
+/// db.push(&[0xFF, 0xFF]);
+/// db.push(&[0x01, 0x10]);
+///
+/// let index = hamming::PivotIndex::new(db, 2);
+/// assert_eq!(index.within(&[0, 0], 2), vec![(0, 0), (2, 2)]);
+/// assert_eq!(index.top_k(&[0xFF, 0x7F], 1), vec![(1, 1)]);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawPivotIndex"))]
+pub struct PivotIndex {
+    db: Database,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    pivots: Database,
+    pivot_indices: Vec<usize>,
+    // row-major: the distances from code `i` to every pivot are at
+    // `i * pivots.len()..`.
+    table: Vec<u64>,
+}
+
+impl PivotIndex {
+    /// Index the codes in `db`, using (up to) `pivots` of them as
+    /// pivots.
+    ///
+    /// No two pivots are equal, so there are fewer than `pivots` if
+    /// `db` holds fewer distinct codes.
+    pub fn new(db: Database, pivots: usize) -> PivotIndex {
+        let n = db.len();
+        let mut pivot_db = Database::padded(db.code_len());
+        let mut pivot_indices = Vec::new();
+        // the distance from each code to its nearest pivot so far
+        let mut nearest = vec![u64::MAX; n];
+        let mut columns = Vec::new();
+        let mut next = 0;
+        while pivot_indices.len() < pivots.min(n) {
+            pivot_indices.push(next);
+            pivot_db.push(db.get(next));
+
+            let mut column = vec![0; n];
+            db.scan(db.get(next), &mut column);
+            for (near, &d) in nearest.iter_mut().zip(&column) {
+                *near = (*near).min(d);
+            }
+            columns.push(column);
+            // the code furthest from every existing pivot, unless
+            // every code is already a pivot or a duplicate of one
+            next = (0..n).max_by_key(|&i| (nearest[i], !i)).unwrap_or(0);
+            if nearest.get(next) == Some(&0) {
+                break
+            }
+        }
+
+        let p = pivot_indices.len();
+        let mut table = vec![0; n * p];
+        for (j, column) in columns.iter().enumerate() {
+            for (i, &d) in column.iter().enumerate() {
+                table[i * p + j] = d;
+            }
+        }
+        PivotIndex { db, pivots: pivot_db, pivot_indices, table }
+    }
+
+    /// Write the index to `writer`, in the file format of
+    /// `Database::save`.
+    ///
+    /// The file stores the database, the pivots and the table of
+    /// distances to them, so `load` needn't compute any distances.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_packed(1, &[0x00, 0x01, 0xFF, 0x0F]);
+    /// let index = hamming::PivotIndex::new(db, 2);
+    ///
+    /// let mut file = Vec::new();
+    /// index.save(&mut file)?;
+    /// let loaded = hamming::PivotIndex::load(&file[..])?;
+    /// assert_eq!(loaded.pivots(), index.pivots());
+    /// assert_eq!(loaded.top_k(&[0x07], 2), index.top_k(&[0x07], 2));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = persist::Writer::new();
+        self.db.write_payload(&mut w);
+        w.usize(self.pivot_indices.len());
+        for &i in &self.pivot_indices {
+            w.usize(i);
+        }
+        for &d in &self.table {
+            w.u64(d);
+        }
+        w.finish(Kind::PivotIndex, writer)
+    }
+
+    /// Read an index written by `save` from `reader`.
+    ///
+    /// Errors are returned as for `Database::load`.
+    #[cfg(feature = "std")]
+    pub fn load<R: Read>(reader: R) -> io::Result<PivotIndex> {
+        let payload = persist::read(Kind::PivotIndex, reader)?;
+        let mut r = persist::Reader::new(&payload);
+        let db = Database::read_payload(&mut r)?;
+        let p = r.count(8)?;
+        let mut pivots = Database::padded(db.code_len());
+        let pivot_indices = (0..p).map(|_| {
+            let i = r.usize()?;
+            if i >= db.len() {
+                return Err(persist::invalid(format!("pivot {} out of range for {} codes",
+                                                    i, db.len())))
+            }
+            pivots.push(db.get(i));
+            Ok(i)
+        }).collect::<io::Result<Vec<_>>>()?;
+        if db.len().saturating_mul(p).saturating_mul(8) > r.remaining() {
+            return Err(persist::invalid("missing distances to the pivots".to_string()))
+        }
+        let table = (0..db.len() * p).map(|_| r.u64()).collect::<io::Result<Vec<_>>>()?;
+        Ok(PivotIndex { db, pivots, pivot_indices, table })
+    }
+
+    /// The indexed codes.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// The indices of the codes used as pivots.
+    pub fn pivots(&self) -> &[usize] {
+        &self.pivot_indices
+    }
+
+    /// The approximate heap memory used by the index, including its
+    /// database, in bytes.
+    ///
+    /// Besides the database, this is dominated by the table of
+    /// distances to the pivots, of 8 bytes per code per pivot.
+    pub fn memory_usage(&self) -> usize {
+        self.db.memory_usage() + self.pivots.memory_usage() +
+            (self.pivot_indices.capacity() + self.table.capacity()) * 8
+    }
+
+    /// The distances from `query` to each pivot.
+    fn query_distances(&self, query: &[u8]) -> Vec<u64> {
+        assert_eq!(query.len(), self.db.code_len());
+        let mut distances = vec![0; self.pivots.len()];
+        self.pivots.scan(query, &mut distances);
+        distances
+    }
+
+    /// A lower bound on the distance from the query to code `i`.
+    fn lower_bound(&self, query_distances: &[u64], i: usize) -> u64 {
+        let p = query_distances.len();
+        self.table[i * p..(i + 1) * p].iter()
+            .zip(query_distances)
+            .map(|(&a, &b)| a.abs_diff(b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Find every code within distance `radius` of `query`, returning
+    /// `(index, distance)` pairs in index order.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn within<Q: AsQuery>(&self, query: Q, radius: u64) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        let query_distances = self.query_distances(query);
+        (0..self.db.len())
+            .filter(|&i| self.lower_bound(&query_distances, i) <= radius)
+            .filter_map(|i| self.db.distance_bounded(query, i, radius).map(|d| (i, d)))
+            .collect()
+    }
+
+    /// Find the `k` codes closest to `query`, returning `(index,
+    /// distance)` pairs sorted by distance, with ties broken by the
+    /// lower index.
+    ///
+    /// Candidates are compared in order of increasing lower bound,
+    /// stopping once no remaining candidate can improve the result.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn top_k<Q: AsQuery>(&self, query: Q, k: usize) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        let query_distances = self.query_distances(query);
+        let mut candidates = (0..self.db.len())
+            .map(|i| (self.lower_bound(&query_distances, i), i))
+            .collect::<Vec<_>>();
+        candidates.sort();
+
+        let mut top = TopK::new(k);
+        if k > 0 {
+            for (bound, i) in candidates {
+                if !top.admits(bound) {
+                    break
+                }
+                top.offer(i, ::distance(query, self.db.get(i)));
+            }
+        }
+        top.into_sorted_vec()
+    }
+}
+
+/// The serialized form of a `PivotIndex`. The pivots are copied from
+/// the codes, and the size of the table checked, when deserializing.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawPivotIndex {
+    db: Database,
+    pivot_indices: Vec<usize>,
+    table: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl ::core::convert::TryFrom<RawPivotIndex> for PivotIndex {
+    type Error = String;
+
+    fn try_from(raw: RawPivotIndex) -> Result<PivotIndex, String> {
+        let mut pivots = Database::padded(raw.db.code_len());
+        for &i in &raw.pivot_indices {
+            if i >= raw.db.len() {
+                return Err(format!("pivot {} out of range for {} codes", i, raw.db.len()))
+            }
+            pivots.push(raw.db.get(i));
+        }
+        if raw.db.len().checked_mul(raw.pivot_indices.len()) != Some(raw.table.len()) {
+            return Err(format!("{} distances for {} codes and {} pivots",
+                               raw.table.len(), raw.db.len(), raw.pivot_indices.len()))
+        }
+        Ok(PivotIndex { db: raw.db, pivots, pivot_indices: raw.pivot_indices, table: raw.table })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PivotIndex;
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn search_qc() {
+        fn prop(v: Vec<u8>, pivots: u8, radius: u8, k: u8) -> bool {
+            let code_len = 3;
+            let query = [0x12, 0x34, 0x56];
+            let mut db = Database::new(code_len);
+            for c in v.chunks(2) {
+                let mut code = query;
+                for &b in c {
+                    code[b as usize % code_len] ^= b;
+                }
+                db.push(code);
+            }
+
+            let index = PivotIndex::new(db.clone(), pivots as usize % 6);
+            let radius = radius as u64 % 16;
+            let k = k as usize % 10;
+            index.within(query, radius) == db.within(query, radius) &&
+                index.top_k(query, k) == db.top_k(query, k)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8, u8) -> bool)
+    }
+
+    #[test]
+    fn pivot_selection() {
+        let db = Database::from_packed(1, [0x00, 0x01, 0xFF, 0x0F, 0xFE]);
+        // 0xFF is furthest from 0x00, then 0x0F is furthest from both.
+        assert_eq!(PivotIndex::new(db.clone(), 3).pivots(), &[0, 2, 3]);
+        assert_eq!(PivotIndex::new(db.clone(), 10).pivots().len(), 5);
+        assert_eq!(PivotIndex::new(db, 0).pivots(), &[0usize; 0]);
+    }
+
+    #[test]
+    fn duplicate_pivots() {
+        let codes = (0..1000).map(|i| [[0x00], [0xFF], [0x0F]][i % 3]);
+        let db = Database::from_codes(1, codes);
+        let index = PivotIndex::new(db.clone(), 10);
+        assert_eq!(index.pivots(), &[0, 1, 2]);
+        assert_eq!(index.table.len(), 3 * 1000);
+        assert_eq!(index.top_k([0x0E], 2), db.top_k([0x0E], 2));
+
+        let same = PivotIndex::new(Database::from_packed(2, [7; 200]), 5);
+        assert_eq!(same.pivots(), &[0]);
+        assert_eq!(same.within([7, 7], 0).len(), 100);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+        let db = Database::from_packed(1, [0x00, 0x01, 0xFF, 0x0F, 0xFE]);
+        let index = PivotIndex::new(db, 3);
+        let json = serde_json::to_string(&index).unwrap();
+        let loaded = serde_json::from_str::<PivotIndex>(&json).unwrap();
+        assert_eq!(loaded.pivots, index.pivots);
+        assert_eq!(loaded.top_k([0x0E], 2), index.top_k([0x0E], 2));
+
+        let corrupt = json.replacen("\"pivot_indices\":[0", "\"pivot_indices\":[5", 1);
+        assert!(serde_json::from_str::<PivotIndex>(&corrupt).is_err());
+        let short = json.replacen("\"table\":[0,", "\"table\":[", 1);
+        assert!(serde_json::from_str::<PivotIndex>(&short).is_err());
+    }
+}
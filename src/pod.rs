@@ -0,0 +1,79 @@
+use bytemuck::{cast_slice, Pod};
+
+use distance_::distance_bytes;
+use weight_::weight_bytes;
+
+/// With the `bytemuck` feature, computes the Hamming weight of the
+/// bytes of `x`, for any plain-old-data element type.
+///
+/// This views `x` as bytes with `bytemuck::cast_slice`, which is free,
+/// and then uses `weight`.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "bytemuck")] extern crate bytemuck;
+/// # extern crate hamming;
+/// # #[cfg(feature = "bytemuck")] fn main() {
+/// #[derive(Clone, Copy)]
+/// #[repr(C)]
+/// struct Flags {
+///     status: u16,
+///     errors: u16,
+///     readings: [u8; 4],
+/// }
+/// unsafe impl bytemuck::Zeroable for Flags {}
+/// unsafe impl bytemuck::Pod for Flags {}
+///
+/// let flags = [Flags { status: 0xFF, errors: 1, readings: [1, 2, 4, 8] }; 3];
+/// assert_eq!(hamming::weight_pod(&flags), 3 * (8 + 1 + 4));
+/// # }
+/// # #[cfg(not(feature = "bytemuck"))] fn main() {}
+/// ```
+pub fn weight_pod<T: Pod>(x: &[T]) -> u64 {
+    weight_bytes(cast_slice(x))
+}
+
+/// With the `bytemuck` feature, computes the bitwise Hamming distance
+/// between the bytes of `x` and `y`, for any plain-old-data element
+/// type.
+///
+/// The bytes of each element are in native byte order, so for
+/// multi-byte elements the bit numbering differs between
+/// architectures, but the distance does not.
+///
+/// # Panics
+///
+/// `x` and `y` must have the same length.
+pub fn distance_pod<T: Pod>(x: &[T], y: &[T]) -> u64 {
+    assert_eq!(x.len(), y.len());
+    distance_bytes(cast_slice(x), cast_slice(y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_pod, weight_pod};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn pod_qc() {
+        fn prop(v: Vec<u32>, w: Vec<u32>) -> bool {
+            let l = v.len().min(w.len());
+            let (v, w) = (&v[..l], &w[..l]);
+            let pairs = v.iter().map(|&x| [x as u16, (x >> 16) as u16]).collect::<Vec<_>>();
+            weight_pod(v) == ::weight(v) &&
+                weight_pod(&pairs) == ::weight(v) &&
+                distance_pod(v, w) == ::distance(v, w)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u32>, Vec<u32>) -> bool)
+    }
+
+    #[test]
+    #[should_panic]
+    fn distance_pod_mismatch() {
+        distance_pod(&[1u32, 2], &[1]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Python bindings, via PyO3.
+//!
+//! With the `python` feature, this crate defines a Python extension
+//! module named `hamming`, which can be built with `maturin`, or by
+//! hand with
+//!
+//! ```text
+//! PYO3_BUILD_EXTENSION_MODULE=1 cargo rustc --release --features python --crate-type cdylib
+//! cp target/release/libhamming.so hamming.so
+//! ```
+//!
+//! It exports `weight`, `distance`, `cdist` and `top_k`, which take
+//! anything supporting the buffer protocol with `uint8` elements,
+//! such as NumPy arrays, `bytes` and `memoryview`s:
+//!
+//! ```python
+//! import numpy as np
+//! import hamming
+//!
+//! a = np.array([[1, 0xFF], [0, 0]], dtype=np.uint8)
+//! b = np.array([[3, 0xFF], [1, 0], [0, 1]], dtype=np.uint8)
+//!
+//! hamming.weight(bytes([1, 0xFF]))            # 9
+//! hamming.distance(a[0], b[0])    # 1
+//!
+//! out = np.empty((len(a), len(b)), dtype=np.uint64)
+//! hamming.cdist(a, b, out)        # out is [[1, 8, 8], [10, 1, 1]]
+//!
+//! hamming.top_k(bytes(2), b, 2)       # [(1, 1), (2, 1)]
+//! ```
+//!
+//! The arrays are read in place, without copying, so they must be
+//! C-contiguous: `np.ascontiguousarray` makes a contiguous copy of an
+//! array that isn't. `cdist` writes into the `uint64` array `out`,
+//! rather than allocating its own, so that it doesn't need NumPy
+//! itself. Invalid shapes raise a `ValueError`.
+
+use core::cell::Cell;
+use core::slice;
+
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use TopK;
+
+fn error<T>(msg: String) -> PyResult<T> {
+    Err(PyValueError::new_err(msg))
+}
+
+/// The contents of `buf`, which must be C-contiguous.
+fn bytes<'a>(py: Python<'a>, buf: &'a PyBuffer<u8>, name: &str) -> PyResult<&'a [u8]> {
+    match buf.as_slice(py) {
+        // `ReadOnlyCell<u8>` is a transparent wrapper around `u8`, and
+        // nothing can write to the buffer while we hold the GIL, other
+        // than `out` in `cdist`, which is checked not to overlap.
+        Some(cells) => Ok(unsafe { slice::from_raw_parts(cells.as_ptr() as *const u8, cells.len()) }),
+        None => error(format!("`{}` must be C-contiguous", name)),
+    }
+}
+
+/// The number of rows and columns of `buf`, which must be
+/// two-dimensional.
+fn shape(buf: &PyBuffer<u8>, name: &str) -> PyResult<(usize, usize)> {
+    match *buf.shape() {
+        [rows, cols] => Ok((rows, cols)),
+        ref s => error(format!("`{}` must have 2 dimensions, not {}", name, s.len())),
+    }
+}
+
+fn overlaps(x: &[u8], out: &[Cell<u64>]) -> bool {
+    let (x, out) = (x.as_ptr_range(), out.as_ptr_range());
+    (x.start as usize) < out.end as usize && (out.start as usize) < x.end as usize
+}
+
+/// Computes the Hamming weight of `x`, as `hamming::weight`.
+#[pyfunction]
+fn weight(py: Python<'_>, x: PyBuffer<u8>) -> PyResult<u64> {
+    Ok(::weight(bytes(py, &x, "x")?))
+}
+
+/// Computes the bitwise Hamming distance between `x` and `y`, as
+/// `hamming::distance`, raising a `ValueError` if they have different
+/// lengths.
+#[pyfunction]
+fn distance(py: Python<'_>, x: PyBuffer<u8>, y: PyBuffer<u8>) -> PyResult<u64> {
+    let (x, y) = (bytes(py, &x, "x")?, bytes(py, &y, "y")?);
+    if x.len() != y.len() {
+        return error(format!("arrays have different lengths ({} and {})", x.len(), y.len()))
+    }
+    Ok(::distance(x, y))
+}
+
+/// Computes the distance between each row of `a` and each row of `b`,
+/// storing the distance between rows `i` and `j` in `out[i, j]`.
+///
+/// `a` and `b` must be two-dimensional with the same number of
+/// columns, and `out` must be a writable `uint64` array of shape
+/// `(len(a), len(b))`.
+#[pyfunction]
+fn cdist(py: Python<'_>, a: PyBuffer<u8>, b: PyBuffer<u8>, out: PyBuffer<u64>) -> PyResult<()> {
+    let ((n, code_len), (m, b_len)) = (shape(&a, "a")?, shape(&b, "b")?);
+    if code_len != b_len {
+        return error(format!("`a` and `b` have different numbers of columns ({} and {})",
+                             code_len, b_len))
+    }
+    if out.shape() != [n, m] {
+        return error(format!("`out` has shape {:?}, but should be ({}, {})", out.shape(), n, m))
+    }
+    let (a, b) = (bytes(py, &a, "a")?, bytes(py, &b, "b")?);
+    let out = match out.as_mut_slice(py) {
+        Some(out) => out,
+        None => return error("`out` must be writable and C-contiguous".to_string()),
+    };
+    if overlaps(a, out) || overlaps(b, out) {
+        return error("`out` must not overlap `a` or `b`".to_string())
+    }
+    if code_len == 0 {
+        out.iter().for_each(|o| o.set(0));
+        return Ok(())
+    }
+    for (row, x) in out.chunks(m.max(1)).zip(a.chunks_exact(code_len)) {
+        for (o, y) in row.iter().zip(b.chunks_exact(code_len)) {
+            o.set(::distance(x, y));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the `k` rows of `codes` closest to `query`, returning
+/// `(index, distance)` pairs sorted by distance, with ties broken by
+/// the lower index.
+///
+/// `codes` must be two-dimensional with one column per byte of
+/// `query`.
+#[pyfunction]
+fn top_k(py: Python<'_>, query: PyBuffer<u8>, codes: PyBuffer<u8>, k: usize)
+         -> PyResult<Vec<(usize, u64)>> {
+    let (count, code_len) = shape(&codes, "codes")?;
+    let (query, codes) = (bytes(py, &query, "query")?, bytes(py, &codes, "codes")?);
+    if query.len() != code_len {
+        return error(format!("`query` has {} bytes, but the codes have {}", query.len(), code_len))
+    }
+    let mut top = TopK::new(k);
+    if k > 0 {
+        for i in 0..count {
+            let code = &codes[i * code_len..(i + 1) * code_len];
+            top.offer(i, ::distance(query, code));
+        }
+    }
+    Ok(top.into_sorted_vec())
+}
+
+/// The `hamming` Python module.
+#[pymodule]
+fn hamming(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(self::weight, m)?)?;
+    m.add_function(wrap_pyfunction!(self::distance, m)?)?;
+    m.add_function(wrap_pyfunction!(self::cdist, m)?)?;
+    m.add_function(wrap_pyfunction!(self::top_k, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn bindings() {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "hamming").unwrap();
+            super::hamming(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("hamming", module).unwrap();
+            let run = |code: &str| {
+                py.run(&::std::ffi::CString::new(code).unwrap(), None, Some(&locals)).unwrap()
+            };
+            run(r#"
+a = memoryview(bytes([1, 0xFF, 0, 0])).cast('B', (2, 2))
+b = memoryview(bytes([3, 0xFF, 1, 0, 0, 1])).cast('B', (3, 2))
+assert hamming.weight(bytes([1, 0xFF])) == 9
+assert hamming.weight(b"") == 0
+assert hamming.distance(bytes([1, 0xFF]), bytes([3, 0xFF])) == 1
+
+out = memoryview(bytearray(8 * 6)).cast('Q', (2, 3))
+hamming.cdist(a, b, out)
+assert out.tolist() == [[1, 8, 8], [10, 1, 1]]
+
+assert hamming.top_k(bytes(2), b, 2) == [(1, 1), (2, 1)]
+assert hamming.top_k(bytes(2), b, 10) == [(1, 1), (2, 1), (0, 10)]
+assert hamming.top_k(bytes(2), b, 0) == []
+
+def raises(f, *args):
+    try:
+        f(*args)
+    except ValueError:
+        return True
+    return False
+
+assert raises(hamming.distance, b"ab", b"a")
+assert raises(hamming.cdist, a, memoryview(bytes(3)).cast('B', (1, 3)), out)
+assert raises(hamming.cdist, a, b, memoryview(bytearray(8 * 6)).cast('Q', (3, 2)))
+assert raises(hamming.cdist, a, b, memoryview(bytes(8 * 6)).cast('Q', (2, 3)))
+assert raises(hamming.weight, memoryview(b"abcd")[::2])
+assert raises(hamming.top_k, b"ab", b"abc", 1)
+assert raises(hamming.top_k, b"abc", b, 1)
+
+buf = bytearray(8 * 32)
+out = memoryview(buf).cast('Q', (16, 2))
+assert raises(hamming.cdist, memoryview(buf)[:32].cast('B', (16, 2)), a, out)
+"#);
+        });
+    }
+}
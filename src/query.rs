@@ -0,0 +1,134 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::slice;
+
+/// A code that can be used as the query of a search.
+///
+/// Every type that is `AsRef<[u8]>` is a query, as is a `Query`, which
+/// has its metadata computed in advance. The searches of `Database`,
+/// `Scanner` and the indexes accept any `AsQuery`.
+pub trait AsQuery {
+    /// The bytes of the query.
+    fn query_bytes(&self) -> &[u8];
+
+    /// The Hamming weight of the query.
+    fn query_weight(&self) -> u64 {
+        ::weight(self.query_bytes())
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> AsQuery for T {
+    fn query_bytes(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
+
+/// A query code, with the metadata that searches use computed once, up
+/// front.
+///
+/// Searches compute the weight of their query to skip the codes whose
+/// cached weights show they are too far away, and compare it with the
+/// codes fastest when it has the same 8-byte alignment as them, as
+/// every code of a `Database::padded` does. A `Query` computes the
+/// weight when it is created, and stores the code in an aligned
+/// buffer, so when the same query is used for several searches, such
+/// as a `within` followed by a `top_k`, or searches of several
+/// databases or shards, none of this is repeated. It needs the `alloc`
+/// feature.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(2, &[0x00, 0x00, 0xFF, 0x00, 0x0F, 0x01]);
+/// let query = hamming::Query::new(&[0x0F, 0x00]);
+/// assert_eq!(query.weight(), 4);
+/// assert_eq!(db.top_k(&query, 2), vec![(2, 1), (0, 4)]);
+/// assert_eq!(db.within(&query, 4), vec![(0, 4), (1, 4), (2, 1)]);
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Query {
+    words: Vec<u64>,
+    len: usize,
+    weight: u64,
+}
+
+#[cfg(feature = "alloc")]
+impl Query {
+    /// Create a query for `code`.
+    pub fn new<C: AsRef<[u8]>>(code: C) -> Query {
+        let code = code.as_ref();
+        let mut query = Query { words: vec![0; code.len().div_ceil(8)], len: code.len(), weight: 0 };
+        let bytes = unsafe {
+            slice::from_raw_parts_mut(query.words.as_mut_ptr() as *mut u8, code.len())
+        };
+        bytes.copy_from_slice(code);
+        query.weight = ::weight(&query.words);
+        query
+    }
+
+    /// The bytes of the code, starting on an 8-byte boundary.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.words.as_ptr() as *const u8, self.len) }
+    }
+
+    /// The Hamming weight of the code.
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl AsQuery for Query {
+    fn query_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn query_weight(&self) -> u64 {
+        self.weight
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl AsQuery for &Query {
+    fn query_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn query_weight(&self) -> u64 {
+        self.weight
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::Query;
+    use {Database, Scanner};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn query_qc() {
+        fn prop(v: Vec<u8>, code: Vec<u8>, radius: u8, k: u8) -> bool {
+            let (radius, k) = (radius as u64 % 20, k as usize % 8);
+            let code_len = code.len().max(1);
+            let code = &[&code[..], &[0]].concat()[..code_len];
+            let mut db = Database::padded(code_len);
+            db.extend(v.chunks_exact(code_len));
+            let query = Query::new(code);
+            let mut out = (vec![0; db.len()], vec![0; db.len()]);
+            db.scan(&query, &mut out.0);
+            db.scan(code, &mut out.1);
+            query.as_bytes() == code && query.weight() == ::weight(code) &&
+                (query.as_bytes().as_ptr() as usize).is_multiple_of(8) &&
+                out.0 == out.1 &&
+                db.within(&query, radius) == db.within(code, radius) &&
+                db.top_k(&query, k) == db.top_k(code, k) &&
+                Scanner::new().top_k(&db, query.clone(), k) == &db.top_k(code, k)[..]
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, u8, u8) -> bool);
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::vec::Vec;
+use core::slice;
+
+use {AsQuery, Database, TopK};
+
+/// Reusable scratch space for searching `Database`s, for callers
+/// making many queries.
+///
+/// `Database::scan`, `within` and `top_k` allocate their results (and
+/// `top_k` its `TopK` collector) on every call, and compare a query
+/// wherever it happens to be in memory, so an unaligned query misses
+/// the fast path of `distance` against the aligned codes of a
+/// `Database::padded`. A `Scanner` owns all of these buffers,
+/// including an 8-byte aligned copy of the query, and reuses them from
+/// one query to the next, so a service answering thousands of queries
+/// a second does no allocation once the buffers have grown to size.
+/// The results are borrowed from the scanner, and are the same as
+/// those of the corresponding `Database` methods.
+///
+/// A scanner isn't tied to a database, and can be used with any
+/// number of them. For concurrent queries, use a scanner per thread.
+///
+/// # Examples
+///
+/// ```rust
+/// let db = hamming::Database::from_packed(2, &[0x00, 0x00, 0xFF, 0x00, 0x0F, 0x01]);
+/// let mut scanner = hamming::Scanner::new();
+///
+/// assert_eq!(scanner.top_k(&db, &[0x0F, 0x00], 2), &[(2, 1), (0, 4)]);
+/// assert_eq!(scanner.within(&db, &[0x00, 0x00], 5), &[(0, 0), (2, 5)]);
+/// assert_eq!(scanner.scan(&db, &[0xFF, 0xFF]), &[16, 8, 11]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Scanner {
+    query: Vec<u64>,
+    distances: Vec<u64>,
+    top: TopK,
+    results: Vec<(usize, u64)>,
+}
+
+/// Copy `query` into the start of `buffer`, returning the copy.
+fn align<'a>(buffer: &'a mut Vec<u64>, query: &[u8]) -> &'a [u8] {
+    buffer.clear();
+    buffer.resize(query.len().div_ceil(8), 0);
+    let bytes = unsafe {
+        slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, query.len())
+    };
+    bytes.copy_from_slice(query);
+    bytes
+}
+
+/// An aligned copy of a query, with its weight.
+struct Aligned<'a>(&'a [u8], u64);
+
+impl<'a> AsQuery for Aligned<'a> {
+    fn query_bytes(&self) -> &[u8] {
+        self.0
+    }
+
+    fn query_weight(&self) -> u64 {
+        self.1
+    }
+}
+
+impl Scanner {
+    /// Create a scanner, with empty buffers.
+    pub fn new() -> Scanner {
+        Scanner::default()
+    }
+
+    /// Compute the distance from `query` to every code in `db`, as
+    /// `Database::scan`, returning the distance to code `i` at index
+    /// `i`.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `db.code_len()` bytes long.
+    pub fn scan<Q: AsQuery>(&mut self, db: &Database, query: Q) -> &[u64] {
+        let query = align(&mut self.query, query.query_bytes());
+        self.distances.clear();
+        self.distances.resize(db.len(), 0);
+        db.scan(query, &mut self.distances);
+        &self.distances
+    }
+
+    /// Find every code in `db` within distance `radius` of `query`, as
+    /// `Database::within`.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `db.code_len()` bytes long.
+    pub fn within<Q: AsQuery>(&mut self, db: &Database, query: Q, radius: u64) -> &[(usize, u64)] {
+        let query_weight = query.query_weight();
+        let query = Aligned(align(&mut self.query, query.query_bytes()), query_weight);
+        db.codes().within_into(query, radius, &mut self.results);
+        &self.results
+    }
+
+    /// Find the `k` codes in `db` closest to `query`, as
+    /// `Database::top_k`.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `db.code_len()` bytes long.
+    pub fn top_k<Q: AsQuery>(&mut self, db: &Database, query: Q, k: usize) -> &[(usize, u64)] {
+        let query_weight = query.query_weight();
+        let query = Aligned(align(&mut self.query, query.query_bytes()), query_weight);
+        db.codes().top_k_into(query, k, 0, |_| true, &mut self.top, &mut self.results);
+        &self.results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scanner;
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn matches_database_qc() {
+        fn prop(v: Vec<u8>, queries: Vec<(u8, u8, u8)>, radius: u8, k: u8) -> bool {
+            let (radius, k) = (radius as u64 % 12, k as usize % 8);
+            let mut db = Database::padded(3);
+            db.extend(v.chunks_exact(3));
+            let mut scanner = Scanner::new();
+            queries.iter().all(|&(a, b, c)| {
+                // a misaligned query
+                let buffer = [0, a, b, c];
+                let query = &buffer[1..];
+                let mut out = vec![0; db.len()];
+                db.scan(query, &mut out);
+                scanner.scan(&db, query) == &out[..] &&
+                    scanner.within(&db, query, radius) == &db.within(query, radius)[..] &&
+                    scanner.top_k(&db, query, k) == &db.top_k(query, k)[..]
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, Vec<(u8, u8, u8)>, u8, u8) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_query_length() {
+        Scanner::new().top_k(&Database::new(2), [0], 1);
+    }
+}
@@ -0,0 +1,165 @@
+use alloc::sync::Arc;
+use core::ops::Deref;
+
+/// A cheaply cloneable, read-only handle to a database or index, for
+/// serving queries from many threads.
+///
+/// `Database`, `MmapDatabase` and every index in this crate are `Send`
+/// and `Sync`, and all their searches take `&self` without any
+/// interior mutability, so any number of threads can query one of them
+/// at once, each getting exactly the results it would get alone. Only
+/// the methods taking `&mut self`, like `Database::push` or
+/// `MultiIndex::insert`, modify them, and the borrow checker keeps
+/// those from overlapping with queries. (Per-query scratch space, like
+/// a `Scanner` or a `TopK`, is the caller's, so use one per thread.)
+///
+/// So an index never needs to be cloned to be shared: a `Shared` wraps
+/// it in an `Arc`, so that cloning the handle for each request thread
+/// just bumps a reference count, and it dereferences to the index for
+/// queries. To update a shared index, use `make_mut`, which modifies
+/// it in place if this is the only handle, and otherwise clones it
+/// first, leaving other handles with the old version, so threads
+/// that are mid-query never see a partial update.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::thread;
+///
+/// let db = hamming::Database::from_packed(1, &[0x00, 0x0F, 0xFF]);
+/// let index = hamming::Shared::new(hamming::VpTree::new(db));
+///
+/// let threads = (0..4u8).map(|i| {
+///     let index = index.clone();
+///     thread::spawn(move || index.top_k(&[i], 1))
+/// }).collect::<Vec<_>>();
+/// for t in threads {
+///     assert_eq!(t.join().unwrap()[0].0, 0);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Shared<T> {
+    inner: Arc<T>,
+}
+
+impl<T> Shared<T> {
+    /// Wrap `value` in a new handle.
+    pub fn new(value: T) -> Shared<T> {
+        Shared { inner: Arc::new(value) }
+    }
+
+    /// The number of handles to this value, including this one.
+    pub fn handles(this: &Shared<T>) -> usize {
+        Arc::strong_count(&this.inner)
+    }
+
+    /// Whether `a` and `b` are handles to the same value.
+    pub fn ptr_eq(a: &Shared<T>, b: &Shared<T>) -> bool {
+        Arc::ptr_eq(&a.inner, &b.inner)
+    }
+
+    /// The value, if this is the only handle to it, and otherwise this
+    /// handle back.
+    pub fn try_unwrap(this: Shared<T>) -> Result<T, Shared<T>> {
+        Arc::try_unwrap(this.inner).map_err(|inner| Shared { inner })
+    }
+
+    /// The underlying `Arc`.
+    pub fn into_arc(this: Shared<T>) -> Arc<T> {
+        this.inner
+    }
+}
+
+impl<T: Clone> Shared<T> {
+    /// A mutable reference to the value, cloning it first unless this
+    /// is the only handle to it, so that other handles are unaffected.
+    pub fn make_mut(this: &mut Shared<T>) -> &mut T {
+        Arc::make_mut(&mut this.inner)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Shared<T> {
+        Shared { inner: self.inner.clone() }
+    }
+}
+
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> From<Arc<T>> for Shared<T> {
+    fn from(inner: Arc<T>) -> Shared<T> {
+        Shared { inner }
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Shared<T> {
+        Shared::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shared;
+    #[cfg(feature = "std")]
+    use std::thread;
+    use {Database, Scanner, TopK, VpTree};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn send_sync() {
+        assert_send_sync::<Database>();
+        assert_send_sync::<VpTree>();
+        assert_send_sync::<::PivotIndex>();
+        #[cfg(feature = "std")]
+        assert_send_sync::<::MultiIndex>();
+        #[cfg(feature = "std")]
+        assert_send_sync::<::BitSamplingIndex>();
+        assert_send_sync::<Scanner>();
+        assert_send_sync::<TopK>();
+        assert_send_sync::<Shared<VpTree>>();
+        #[cfg(feature = "memmap")]
+        assert_send_sync::<::MmapDatabase>();
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn concurrent_queries() {
+        let db = Database::from_codes(2, (0..1000u16).map(|i| i.wrapping_mul(7919).to_le_bytes()));
+        let index = Shared::new(::MultiIndex::new(db.clone(), 2));
+        let threads = (0..8u16).map(|t| {
+            let index = index.clone();
+            thread::spawn(move || {
+                (0..50u16).map(|q| index.within((t * 50 + q).to_le_bytes(), 3)).collect::<Vec<_>>()
+            })
+        }).collect::<Vec<_>>();
+        for (t, thread) in threads.into_iter().enumerate() {
+            for (q, found) in thread.join().unwrap().into_iter().enumerate() {
+                assert_eq!(found, db.within((t as u16 * 50 + q as u16).to_le_bytes(), 3));
+            }
+        }
+        assert_eq!(Shared::handles(&index), 1);
+    }
+
+    #[test]
+    fn make_mut() {
+        let mut a = Shared::new(Database::from_packed(1, [1, 2]));
+        let b = a.clone();
+        Shared::make_mut(&mut a).push([3]);
+        assert!(!Shared::ptr_eq(&a, &b));
+        assert_eq!((a.len(), b.len()), (3, 2));
+
+        let before = &*a as *const Database;
+        Shared::make_mut(&mut a).push([4]);
+        assert_eq!(&*a as *const Database, before);
+        assert_eq!(Shared::try_unwrap(a).unwrap().len(), 4);
+        assert!(Shared::try_unwrap(b.clone()).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! SimHash fingerprints, for near-duplicate detection.
+//!
+//! A document is described by a set of weighted features (words,
+//! shingles, ...), each hashed to a 64- or 128-bit value by the caller.
+//! Every feature votes on each bit of the fingerprint with its weight,
+//! positively if its hash has that bit set and negatively otherwise,
+//! and the fingerprint has exactly the bits with a positive total.
+//! Similar documents then have fingerprints at a small Hamming
+//! distance. See Charikar, [Similarity Estimation Techniques from
+//! Rounding Algorithms](https://doi.org/10.1145/509907.509965) and
+//! Manku, Jain and Das Sarma, [Detecting Near-Duplicates for Web
+//! Crawling](https://doi.org/10.1145/1242572.1242592).
+//!
+//! Fingerprints are stored in `Database`s and the indexes as their
+//! little-endian bytes, so bit `i` of a fingerprint is bit `i % 8` of
+//! byte `i / 8`, as everywhere else in this crate.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use hamming::simhash;
+//!
+//! // stand-ins for real hashes of words
+//! let a = simhash::fingerprint64(vec![(0x1234_5678_9ABC_DEF0, 1.0), (0xFFFF_0000_FFFF_0000, 2.0)]);
+//! let b = simhash::fingerprint64(vec![(0x1234_5678_9ABC_DEF0, 1.0), (0xFFFF_0000_FFFF_0001, 2.0)]);
+//! assert_eq!(simhash::distance64(a, b), 1);
+//! assert_eq!(hamming::distance(&a.to_le_bytes(), &b.to_le_bytes()), 1);
+//! ```
+
+macro_rules! fingerprint {
+    ($name: ident, $distance: ident, $t: ty, $bits: expr) => {
+        /// Compute the SimHash fingerprint of `features`, given as
+        #[doc = concat!("`(hash, weight)` pairs with ", stringify!($bits), "-bit hashes.")]
+        ///
+        /// Bits with a total vote of exactly zero, including every bit
+        /// when there are no features, are `0`.
+        pub fn $name<I>(features: I) -> $t
+            where I: IntoIterator<Item = ($t, f64)>
+        {
+            let mut votes = [0f64; $bits];
+            for (hash, weight) in features {
+                for (i, vote) in votes.iter_mut().enumerate() {
+                    if hash >> i & 1 == 1 {
+                        *vote += weight;
+                    } else {
+                        *vote -= weight;
+                    }
+                }
+            }
+            votes.iter().enumerate()
+                .fold(0, |fp, (i, &vote)| fp | ((vote > 0.0) as $t) << i)
+        }
+
+        #[doc = concat!("The Hamming distance between two ", stringify!($bits), "-bit fingerprints.")]
+        ///
+        /// This is the same as `distance` on their little-endian bytes.
+        pub fn $distance(a: $t, b: $t) -> u64 {
+            (a ^ b).count_ones() as u64
+        }
+    }
+}
+
+fingerprint!(fingerprint64, distance64, u64, 64);
+fingerprint!(fingerprint128, distance128, u128, 128);
+
+#[cfg(test)]
+mod tests {
+    use super::{fingerprint64, fingerprint128, distance64, distance128};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn single_feature() {
+        assert_eq!(fingerprint64(None), 0);
+        assert_eq!(fingerprint64(Some((0xDEAD_BEEF, 3.0))), 0xDEAD_BEEF);
+        assert_eq!(fingerprint64(Some((0xDEAD_BEEF, -3.0))), !0xDEAD_BEEF);
+        assert_eq!(fingerprint128(Some((1 << 100 | 1, 0.5))), 1 << 100 | 1);
+    }
+
+    #[test]
+    fn weighted_vote() {
+        let fp = fingerprint64(vec![(0b0011, 1.0), (0b0101, 1.0), (0b0110, 1.5)]);
+        // bit 0: 1 + 1 - 1.5 > 0; bit 1: 1 - 1 + 1.5 > 0;
+        // bit 2: -1 + 1 + 1.5 > 0; bit 3 and up: all against.
+        assert_eq!(fp, 0b0111);
+        // a tie is a zero bit.
+        assert_eq!(fingerprint64(vec![(0b01, 1.0), (0b10, 1.0)]), 0);
+    }
+
+    #[test]
+    fn distance_qc() {
+        fn prop(a: u64, b: u64, c: u64, d: u64) -> bool {
+            let (x, y) = ((a as u128) << 64 | b as u128, (c as u128) << 64 | d as u128);
+            distance64(a, c) == ::distance(&a.to_le_bytes(), &c.to_le_bytes()) &&
+                distance128(x, y) == ::distance(&x.to_le_bytes(), &y.to_le_bytes())
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(u64, u64, u64, u64) -> bool)
+    }
+}
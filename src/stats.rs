@@ -0,0 +1,755 @@
+//! Statistical tests of bit sequences, from NIST [SP
+//! 800-22](https://csrc.nist.gov/publications/detail/sp/800-22/rev-1a/final),
+//! "A Statistical Test Suite for Random and Pseudorandom Number
+//! Generators for Cryptographic Applications".
+//!
+//! A sequence of `bit_len` bits is stored in a byte slice with bit `i`
+//! as bit `i % 8` of byte `i / 8`, so the first bit of the sequence is
+//! the least significant bit of the first byte.
+//!
+//! This requires the `libm` feature for the floating point functions,
+//! and works without `std`.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use libm::{erfc, log2, sqrt};
+
+use util::VerticalCounter;
+
+/// Count the ones in the first `bit_len` bits of `x`.
+fn weight_prefix(x: &[u8], bit_len: usize) -> u64 {
+    assert!(bit_len <= 8 * x.len(),
+            "{} bits is longer than {} bytes", bit_len, x.len());
+    let whole = ::weight(&x[..bit_len / 8]);
+    let partial = if bit_len.is_multiple_of(8) {
+        0
+    } else {
+        (x[bit_len / 8] & ((1 << (bit_len % 8)) - 1)).count_ones() as u64
+    };
+    whole + partial
+}
+
+/// The test statistic `s_obs = |S_n| / sqrt(n)` of the frequency
+/// (monobit) test, for the first `bit_len` bits of `x`, where `S_n` is
+/// the number of ones minus the number of zeros.
+///
+/// For a random sequence this is approximately half-normal.
+///
+/// # Panics
+///
+/// `bit_len` must be non-zero, and at most `8 * x.len()`.
+///
+/// # Examples
+///
+/// ```rust
+/// // the example from SP 800-22, section 2.1.8: 1011010101
+/// let s = hamming::stats::monobit_statistic(&[0b1010_1101, 0b10], 10);
+/// assert!((s - 0.632455532).abs() < 1e-9);
+/// ```
+pub fn monobit_statistic(x: &[u8], bit_len: usize) -> f64 {
+    assert!(bit_len > 0, "the monobit test needs at least one bit");
+    let ones = weight_prefix(x, bit_len);
+    let sum = 2 * ones as i64 - bit_len as i64;
+    sum.unsigned_abs() as f64 / sqrt(bit_len as f64)
+}
+
+/// The P-value of the frequency (monobit) test, for the first
+/// `bit_len` bits of `x`: `erfc(s_obs / sqrt(2))`.
+///
+/// # Panics
+///
+/// As for `monobit_statistic`.
+pub fn monobit_p_value(x: &[u8], bit_len: usize) -> f64 {
+    erfc(monobit_statistic(x, bit_len) / ::core::f64::consts::SQRT_2)
+}
+
+/// Whether the first `bit_len` bits of `x` pass the frequency
+/// (monobit) test at the given `significance` level, i.e. whether the
+/// P-value is at least `significance`.
+///
+/// SP 800-22 uses a significance of 0.01, and recommends at least 100
+/// bits.
+///
+/// # Panics
+///
+/// As for `monobit_statistic`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::monobit_test;
+///
+/// assert!(monobit_test(&[0x5A; 16], 128, 0.01));
+/// assert!(!monobit_test(&[0xFF, 0xFF, 0xFF, 0x7F], 32, 0.01));
+/// ```
+pub fn monobit_test(x: &[u8], bit_len: usize, significance: f64) -> bool {
+    monobit_p_value(x, bit_len) >= significance
+}
+
+/// Count the positions `k < bit_len - 1` where bits `k` and `k + 1`
+/// of `x` differ.
+fn transitions(x: &[u8], bit_len: usize) -> u64 {
+    let pairs = bit_len.saturating_sub(1);
+    let mut count = 0;
+    for (i, chunk) in x[..bit_len.div_ceil(8)].chunks(8).enumerate() {
+        let start = 64 * i;
+        if start >= pairs {
+            break
+        }
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let word = u64::from_le_bytes(word);
+        // the bit after this word, if any
+        let next = x.get(8 * i + 8).map_or(0, |&b| b as u64 & 1);
+        let mut differ = word ^ (word >> 1 | next << 63);
+        if pairs - start < 64 {
+            differ &= (1 << (pairs - start)) - 1;
+        }
+        count += differ.count_ones() as u64;
+    }
+    count
+}
+
+/// The number of runs (maximal blocks of identical bits) in the first
+/// `bit_len` bits of `x`, which is the statistic `V_n(obs)` of the
+/// runs test.
+///
+/// # Panics
+///
+/// `bit_len` must be at most `8 * x.len()`.
+///
+/// # Examples
+///
+/// ```rust
+/// // 1001101011, from SP 800-22 section 2.3.4
+/// assert_eq!(hamming::stats::runs(&[0b0101_1001, 0b11], 10), 7);
+/// ```
+pub fn runs(x: &[u8], bit_len: usize) -> u64 {
+    assert!(bit_len <= 8 * x.len(),
+            "{} bits is longer than {} bytes", bit_len, x.len());
+    if bit_len == 0 {
+        0
+    } else {
+        1 + transitions(x, bit_len)
+    }
+}
+
+/// The P-value of the runs test, for the first `bit_len` bits of `x`.
+///
+/// This tests whether the sequence oscillates between zeros and ones
+/// too quickly or too slowly. As specified, the P-value is `0` if the
+/// sequence fails the frequency prerequisite `|ones / bit_len - 1/2| <
+/// 2 / sqrt(bit_len)`.
+///
+/// # Panics
+///
+/// `bit_len` must be non-zero, and at most `8 * x.len()`.
+pub fn runs_p_value(x: &[u8], bit_len: usize) -> f64 {
+    assert!(bit_len > 0, "the runs test needs at least one bit");
+    let n = bit_len as f64;
+    let pi = weight_prefix(x, bit_len) as f64 / n;
+    if (pi - 0.5).abs() >= 2.0 / sqrt(n) {
+        return 0.0
+    }
+    let v = runs(x, bit_len) as f64;
+    let spread = 2.0 * n * pi * (1.0 - pi);
+    erfc((v - spread).abs() / (2.0 * sqrt(2.0 * n) * pi * (1.0 - pi)))
+}
+
+/// Whether the first `bit_len` bits of `x` pass the runs test at the
+/// given `significance` level, i.e. whether the P-value is at least
+/// `significance`.
+///
+/// # Panics
+///
+/// As for `runs_p_value`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::runs_test;
+///
+/// // balanced, but alternating far too regularly
+/// assert!(!runs_test(&[0x55; 16], 128, 0.01));
+/// assert!(runs_test(&[0x4E, 0x2B, 0x93, 0xD1, 0x6A, 0x1C, 0xB5, 0x87], 64, 0.01));
+/// ```
+pub fn runs_test(x: &[u8], bit_len: usize, significance: f64) -> bool {
+    runs_p_value(x, bit_len) >= significance
+}
+
+/// `-p log2(p)`, extended continuously to `0` at `p = 0`.
+fn plogp(p: f64) -> f64 {
+    if p == 0.0 { 0.0 } else { -p * log2(p) }
+}
+
+/// The binary entropy, in bits, of the fraction of ones in the first
+/// `bit_len` bits of `x`.
+///
+/// This is `1` for a balanced sequence and `0` for a constant one
+/// (including an empty one). It only measures the balance of ones and
+/// zeros, so a highly structured sequence like `0101...` still has
+/// entropy `1`; see `byte_entropy` for a finer measure.
+///
+/// # Panics
+///
+/// `bit_len` must be at most `8 * x.len()`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::bit_entropy;
+///
+/// assert_eq!(bit_entropy(&[0x0F, 0x33], 16), 1.0);
+/// assert_eq!(bit_entropy(&[0xFF, 0xFF], 16), 0.0);
+/// assert!((bit_entropy(&[0x01], 4) - 0.811278).abs() < 1e-6);
+/// ```
+pub fn bit_entropy(x: &[u8], bit_len: usize) -> f64 {
+    let ones = weight_prefix(x, bit_len);
+    if bit_len == 0 {
+        return 0.0
+    }
+    let p = ones as f64 / bit_len as f64;
+    plogp(p) + plogp(1.0 - p)
+}
+
+/// The Shannon entropy, in bits per byte, of the distribution of the
+/// byte values in `x`.
+///
+/// This ranges from `0` for a constant (or empty) slice to `8` when
+/// every byte value is equally common. Compressed or encrypted data
+/// is typically very close to `8`, text and machine code much lower.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::byte_entropy;
+///
+/// assert_eq!(byte_entropy(&[7; 100]), 0.0);
+/// assert_eq!(byte_entropy(b"abab"), 1.0);
+/// let all = (0..=255).collect::<Vec<u8>>();
+/// assert_eq!(byte_entropy(&all), 8.0);
+/// ```
+pub fn byte_entropy(x: &[u8]) -> f64 {
+    if x.is_empty() {
+        return 0.0
+    }
+    let mut counts = [0u64; 256];
+    for &b in x {
+        counts[b as usize] += 1;
+    }
+    let n = x.len() as f64;
+    counts.iter().map(|&c| plogp(c as f64 / n)).sum()
+}
+
+/// The chi-square statistic for the balance of ones and zeros in the
+/// first `bit_len` bits of `x`, with one degree of freedom.
+///
+/// This is `(ones - zeros)^2 / bit_len`, the square of the monobit
+/// statistic, and is `0` for an empty sequence.
+///
+/// # Panics
+///
+/// `bit_len` must be at most `8 * x.len()`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::bit_balance_chi2;
+///
+/// assert_eq!(bit_balance_chi2(&[0x0F], 8), 0.0);
+/// assert_eq!(bit_balance_chi2(&[0x07], 8), 0.5);
+/// ```
+pub fn bit_balance_chi2(x: &[u8], bit_len: usize) -> f64 {
+    let ones = weight_prefix(x, bit_len);
+    if bit_len == 0 {
+        return 0.0
+    }
+    let difference = 2 * ones as i64 - bit_len as i64;
+    (difference * difference) as f64 / bit_len as f64
+}
+
+/// The chi-square statistic for the balance of ones and zeros at
+/// each bit position across the `code_len`-byte codes stored one
+/// after another in `codes`, with `8 * code_len` degrees of freedom.
+///
+/// This is the sum over positions of `(ones - zeros)^2 / n` for `n`
+/// codes. Good hash functions set each bit of their output for about
+/// half of their inputs, keeping this near its expected value of `8 *
+/// code_len`. It is `0` when there are no codes.
+///
+/// # Panics
+///
+/// `code_len` must be non-zero and `codes` must be a whole number of
+/// codes.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::bit_position_chi2;
+///
+/// // every position is balanced...
+/// assert_eq!(bit_position_chi2(&[0x00, 0xFF, 0x0F, 0xF0], 1), 0.0);
+/// // ...but here bit 0 is always set: (4 - 0)^2 / 4 = 4.
+/// assert_eq!(bit_position_chi2(&[0x01, 0xFF, 0x0F, 0xF1], 1), 4.0);
+/// ```
+pub fn bit_position_chi2(codes: &[u8], code_len: usize) -> f64 {
+    assert!(code_len > 0 && codes.len().is_multiple_of(code_len),
+            "{} bytes are not a whole number of {}-byte codes", codes.len(), code_len);
+    let n = (codes.len() / code_len) as i64;
+    if n == 0 {
+        return 0.0
+    }
+    // count 64 positions at a time
+    let mut total = 0;
+    for start in (0..code_len).step_by(8) {
+        let width = (code_len - start).min(8);
+        let mut counter = VerticalCounter::new();
+        for code in codes.chunks(code_len) {
+            let mut word = [0; 8];
+            word[..width].copy_from_slice(&code[start..start + width]);
+            counter.add(u64::from_le_bytes(word));
+        }
+        for i in 0..8 * width {
+            let difference = 2 * counter.get(i) as i64 - n;
+            total += difference * difference;
+        }
+    }
+    total as f64 / n as f64
+}
+
+/// The balance of each bit position across a set of codes, as
+/// computed by `bit_bias`.
+///
+/// For a good binary embedding or hash, each bit is set for about half
+/// of the codes: a bit that is nearly always (or never) set carries
+/// little information, and wastes a position in every distance. This
+/// reports the fraction of codes with each bit set, its bias (the
+/// distance of that fraction from one half) and its binary entropy,
+/// along with summaries of these over all positions.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitBias {
+    codes: u64,
+    ones: Vec<u64>,
+}
+
+#[cfg(feature = "alloc")]
+impl BitBias {
+    /// The number of codes counted.
+    pub fn codes(&self) -> u64 {
+        self.codes
+    }
+
+    /// The number of codes with each bit set, where element `i`
+    /// counts bit `i`.
+    pub fn ones(&self) -> &[u64] {
+        &self.ones
+    }
+
+    /// The fraction of codes with bit `i` set.
+    pub fn fraction(&self, i: usize) -> f64 {
+        self.ones[i] as f64 / self.codes as f64
+    }
+
+    /// The bias of bit `i`, `|fraction - 1/2|`, from `0` for a
+    /// balanced bit to `0.5` for a constant one.
+    pub fn bias(&self, i: usize) -> f64 {
+        (self.fraction(i) - 0.5).abs()
+    }
+
+    /// The binary entropy of bit `i`, in bits, from `1` for a
+    /// balanced bit to `0` for a constant one.
+    pub fn entropy(&self, i: usize) -> f64 {
+        let p = self.fraction(i);
+        plogp(p) + plogp(1.0 - p)
+    }
+
+    /// The most biased bit and its bias, choosing the lowest bit on
+    /// ties.
+    pub fn max_bias(&self) -> (usize, f64) {
+        (0..self.ones.len()).map(|i| (i, self.bias(i)))
+            .fold((0, -1.0), |best, (i, b)| if b > best.1 { (i, b) } else { best })
+    }
+
+    /// The mean bias over all bits.
+    pub fn mean_bias(&self) -> f64 {
+        (0..self.ones.len()).map(|i| self.bias(i)).sum::<f64>() / self.ones.len() as f64
+    }
+
+    /// The mean entropy over all bits, in bits: `1` when every bit is
+    /// balanced.
+    pub fn mean_entropy(&self) -> f64 {
+        self.total_entropy() / self.ones.len() as f64
+    }
+
+    /// The sum of the entropies of all bits, which is an upper bound
+    /// on the information in each code, in bits.
+    pub fn total_entropy(&self) -> f64 {
+        (0..self.ones.len()).map(|i| self.entropy(i)).sum()
+    }
+
+    /// The bits that are set for every code, or for none.
+    pub fn constant_bits(&self) -> Vec<usize> {
+        (0..self.ones.len()).filter(|&i| self.ones[i] == 0 || self.ones[i] == self.codes).collect()
+    }
+}
+
+/// Measure the balance of each bit position across the `code_len`-byte
+/// codes stored one after another in `codes`.
+///
+/// The bits are counted with `column_weights`, 64 positions at a time.
+///
+/// # Panics
+///
+/// `code_len` must be non-zero and `codes` must be a non-zero whole
+/// number of codes.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::bit_bias;
+///
+/// let report = bit_bias(&[0x01, 0x03, 0x05, 0x07], 1);
+/// assert_eq!(report.fraction(0), 1.0);
+/// assert_eq!(report.fraction(1), 0.5);
+/// assert_eq!(report.max_bias(), (0, 0.5));
+/// assert_eq!(report.entropy(1), 1.0);
+/// assert_eq!(report.total_entropy(), 2.0);
+/// assert_eq!(report.constant_bits(), [0, 3, 4, 5, 6, 7]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn bit_bias(codes: &[u8], code_len: usize) -> BitBias {
+    assert!(code_len > 0 && !codes.is_empty() && codes.len().is_multiple_of(code_len),
+            "{} bytes are not a non-zero whole number of {}-byte codes", codes.len(), code_len);
+    let mut ones = vec![0; 8 * code_len];
+    ::column_weights(codes, code_len, &mut ones);
+    BitBias { codes: (codes.len() / code_len) as u64, ones }
+}
+
+/// The co-occurrence counts and correlations of every pair of bit
+/// positions across a set of codes, as computed by `bit_correlations`.
+///
+/// Two bits that are strongly correlated (or anti-correlated) carry
+/// much the same information, so a code with many such pairs has
+/// fewer effective bits than its length suggests.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitCorrelations {
+    codes: u64,
+    bits: usize,
+    // `counts[i * bits + j]` is the number of codes with bits `i` and
+    // `j` both set
+    counts: Vec<u64>,
+}
+
+#[cfg(feature = "alloc")]
+impl BitCorrelations {
+    /// The number of codes counted.
+    pub fn codes(&self) -> u64 {
+        self.codes
+    }
+
+    /// The number of bits in each code.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The number of codes with both bit `i` and bit `j` set (or just
+    /// bit `i`, when `i == j`).
+    pub fn co_occurrence(&self, i: usize, j: usize) -> u64 {
+        assert!(i < self.bits && j < self.bits,
+                "bits {} and {} out of range for {}-bit codes", i, j, self.bits);
+        self.counts[i * self.bits + j]
+    }
+
+    /// The co-occurrence counts of all pairs, as a `bits` by `bits`
+    /// matrix in row-major order.
+    pub fn co_occurrences(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The (Pearson, or phi) correlation between bits `i` and `j`
+    /// across the codes, from `-1` for bits that always differ to `1`
+    /// for bits that are always equal.
+    ///
+    /// This is `0` if either bit is constant, since such a bit varies
+    /// with nothing.
+    pub fn correlation(&self, i: usize, j: usize) -> f64 {
+        let n = self.codes as f64;
+        let both = self.co_occurrence(i, j) as f64;
+        let (a, b) = (self.co_occurrence(i, i) as f64, self.co_occurrence(j, j) as f64);
+        let spread = a * (n - a) * b * (n - b);
+        if spread == 0.0 { 0.0 } else { (n * both - a * b) / sqrt(spread) }
+    }
+
+    /// The correlations of all pairs, as a `bits` by `bits` matrix in
+    /// row-major order.
+    pub fn correlations(&self) -> Vec<f64> {
+        (0..self.bits * self.bits).map(|k| self.correlation(k / self.bits, k % self.bits)).collect()
+    }
+
+    /// The pair of distinct bits `(i, j)`, with `i < j`, whose
+    /// correlation is furthest from zero, with that correlation, or
+    /// `None` for codes of one bit.
+    pub fn max_correlation(&self) -> Option<(usize, usize, f64)> {
+        let mut best: Option<(usize, usize, f64)> = None;
+        for i in 0..self.bits {
+            for j in i + 1..self.bits {
+                let r = self.correlation(i, j);
+                if best.is_none_or(|(_, _, b)| r.abs() > b.abs()) {
+                    best = Some((i, j, r));
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Count how often each pair of bit positions is set together across
+/// the `code_len`-byte codes stored one after another in `codes`, for
+/// measuring their correlations.
+///
+/// The codes are first transposed into one bitmap per bit position,
+/// with a bit for each code, so that each of the `B (B + 1) / 2` pairs
+/// of the `B = 8 * code_len` positions is counted with a single fused
+/// and-and-popcount over two bitmaps, 64 codes at a time. This takes
+/// `O(B^2 N / 64)` word operations for `N` codes, and `O(B N / 8)`
+/// bytes for the bitmaps.
+///
+/// # Panics
+///
+/// `code_len` must be non-zero and `codes` must be a non-zero whole
+/// number of codes.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::stats::bit_correlations;
+///
+/// // bit 1 copies bit 0, and bit 2 is its opposite
+/// let report = bit_correlations(&[0b011, 0b100, 0b011, 0b100, 0b111], 1);
+/// assert_eq!(report.co_occurrence(0, 1), 3);
+/// assert_eq!(report.correlation(0, 1), 1.0);
+/// assert!(report.correlation(0, 2) < -0.6);
+/// assert_eq!(report.max_correlation(), Some((0, 1, 1.0)));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn bit_correlations(codes: &[u8], code_len: usize) -> BitCorrelations {
+    assert!(code_len > 0 && !codes.is_empty() && codes.len().is_multiple_of(code_len),
+            "{} bytes are not a non-zero whole number of {}-byte codes", codes.len(), code_len);
+    let n = codes.len() / code_len;
+    let bits = 8 * code_len;
+    let words = n.div_ceil(64);
+
+    // transpose: bit `r` of `columns[i]` is bit `i` of code `r`
+    let mut columns = vec![0u64; bits * words];
+    for (r, code) in codes.chunks(code_len).enumerate() {
+        for (byte_index, &byte) in code.iter().enumerate() {
+            let mut byte = byte;
+            while byte != 0 {
+                let i = 8 * byte_index + byte.trailing_zeros() as usize;
+                columns[i * words + r / 64] |= 1 << (r % 64);
+                byte &= byte - 1;
+            }
+        }
+    }
+
+    let mut counts = vec![0; bits * bits];
+    for i in 0..bits {
+        let x = &columns[i * words..(i + 1) * words];
+        for j in i..bits {
+            let y = &columns[j * words..(j + 1) * words];
+            let both = x.iter().zip(y).map(|(a, b)| (a & b).count_ones() as u64).sum();
+            counts[i * bits + j] = both;
+            counts[j * bits + i] = both;
+        }
+    }
+    BitCorrelations { codes: n as u64, bits, counts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn bit_bias_qc() {
+        fn prop(v: Vec<u8>, code_len: u8) -> qc::TestResult {
+            let code_len = code_len as usize % 4 + 1;
+            let v = &v[..v.len() - v.len() % code_len];
+            if v.is_empty() {
+                return qc::TestResult::discard()
+            }
+            let report = bit_bias(v, code_len);
+            let n = (v.len() / code_len) as f64;
+            let ok = (0..8 * code_len).all(|i| {
+                let bits = v.chunks(code_len).map(|c| c[i / 8] >> (i % 8) & 1).collect::<Vec<_>>();
+                let expected = bit_entropy(&pack(&bits.iter().map(|b| (b'0' + b) as char).collect::<String>()),
+                                           bits.len());
+                report.fraction(i) == bits.iter().filter(|&&b| b == 1).count() as f64 / n &&
+                    (report.entropy(i) - expected).abs() < 1e-12
+            });
+            let (worst, max) = report.max_bias();
+            qc::TestResult::from_bool(ok && (0..8 * code_len).all(|i| report.bias(i) <= max) &&
+                                      report.bias(worst) == max &&
+                                      report.mean_bias() <= max && report.mean_entropy() <= 1.0)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> qc::TestResult)
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn bit_correlations_qc() {
+        fn prop(v: Vec<u8>, code_len: u8) -> qc::TestResult {
+            let code_len = code_len as usize % 3 + 1;
+            let v = &v[..v.len() - v.len() % code_len];
+            if v.is_empty() {
+                return qc::TestResult::discard()
+            }
+            let report = bit_correlations(v, code_len);
+            let bit = |c: &[u8], i: usize| c[i / 8] >> (i % 8) & 1 == 1;
+            let bits = 8 * code_len;
+            let counts = (0..bits * bits).all(|k| {
+                let (i, j) = (k / bits, k % bits);
+                report.co_occurrence(i, j) ==
+                    v.chunks(code_len).filter(|c| bit(c, i) && bit(c, j)).count() as u64
+            });
+            let correlations = report.correlations();
+            let symmetric = (0..bits * bits).all(|k| {
+                let r = correlations[k];
+                r == correlations[k % bits * bits + k / bits] && (-1.0 - 1e-9..=1.0 + 1e-9).contains(&r)
+            });
+            let max = report.max_correlation().unwrap().2.abs();
+            let bounded = (0..bits).all(|i| (i + 1..bits).all(|j| report.correlation(i, j).abs() <= max));
+            qc::TestResult::from_bool(counts && symmetric && bounded &&
+                                      report.bits() == bits &&
+                                      report.co_occurrences().len() == bits * bits)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 300))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> qc::TestResult)
+    }
+
+    #[test]
+    fn weight_prefix_qc() {
+        fn prop(v: Vec<u8>, bit_len: usize) -> bool {
+            let bit_len = bit_len % (8 * v.len() + 1);
+            let naive = (0..bit_len).filter(|&i| v[i / 8] >> (i % 8) & 1 == 1).count();
+            weight_prefix(&v, bit_len) == naive as u64
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, usize) -> bool)
+    }
+
+    /// Pack a string of `0`s and `1`s, first bit least significant.
+    fn pack(bits: &str) -> Vec<u8> {
+        let mut bytes = vec![0; bits.len().div_ceil(8)];
+        for (i, c) in bits.bytes().enumerate() {
+            bytes[i / 8] |= (c - b'0') << (i % 8);
+        }
+        bytes
+    }
+
+    /// The first 100 binary digits of e, used in several examples of
+    /// SP 800-22.
+    const E: &str = "11001001000011111101101010100010001000010110100011\
+                     00001000110100110001001100011001100010100010111000";
+
+    #[test]
+    fn monobit_reference() {
+        // SP 800-22 section 2.1.8: 1011010101, with the first bit in
+        // the least significant position.
+        let x = [0b1010_1101, 0b10];
+        assert!((monobit_statistic(&x, 10) - 0.632455532).abs() < 1e-9);
+        assert!((monobit_p_value(&x, 10) - 0.527089).abs() < 1e-6);
+
+        // SP 800-22 section 2.1.8
+        let e = pack(E);
+        assert!((monobit_p_value(&e, 100) - 0.109599).abs() < 1e-6);
+        assert!(monobit_test(&e, 100, 0.01));
+    }
+
+    #[test]
+    #[should_panic]
+    fn monobit_too_long() {
+        monobit_statistic(&[0xFF], 9);
+    }
+
+    #[test]
+    fn runs_qc() {
+        fn prop(v: Vec<u8>, bit_len: usize) -> bool {
+            let bit_len = bit_len % (8 * v.len() + 1);
+            let bit = |i: usize| v[i / 8] >> (i % 8) & 1;
+            let naive = (1..bit_len).filter(|&i| bit(i) != bit(i - 1)).count() as u64 +
+                (bit_len > 0) as u64;
+            runs(&v, bit_len) == naive
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, usize) -> bool)
+    }
+
+    #[test]
+    fn runs_reference() {
+        // SP 800-22 section 2.3.4
+        let x = pack("1001101011");
+        assert_eq!(runs(&x, 10), 7);
+        assert!((runs_p_value(&x, 10) - 0.147232).abs() < 1e-6);
+
+        // SP 800-22 section 2.3.8
+        let e = pack(E);
+        assert_eq!(runs(&e, 100), 52);
+        assert!((runs_p_value(&e, 100) - 0.500798).abs() < 1e-6);
+
+        // fails the frequency prerequisite
+        assert_eq!(runs_p_value(&[0xFF; 4], 32), 0.0);
+    }
+
+    #[test]
+    fn entropy() {
+        assert_eq!(bit_entropy(&[], 0), 0.0);
+        assert_eq!(bit_entropy(&[0xAA], 8), 1.0);
+        assert_eq!(bit_entropy(&[0xFF, 0x00], 8), 0.0);
+        assert_eq!(bit_entropy(&[0xFF, 0x00], 16), 1.0);
+        // p = 1/3 and 2/3 have the same entropy
+        let (a, b) = (bit_entropy(&[0b001], 3), bit_entropy(&[0b011], 3));
+        assert!((a - 0.918296).abs() < 1e-6 && a == b);
+
+        assert_eq!(byte_entropy(&[]), 0.0);
+        assert_eq!(byte_entropy(&[1, 2, 3, 4]), 2.0);
+        assert!((byte_entropy(&[1, 1, 1, 2]) - 0.811278).abs() < 1e-6);
+    }
+
+    #[test]
+    fn chi2() {
+        assert_eq!(bit_balance_chi2(&[], 0), 0.0);
+        assert_eq!(bit_balance_chi2(&[0xFF, 0xFF], 16), 16.0);
+        assert_eq!(bit_balance_chi2(&[0xFF, 0xFF], 12), 12.0);
+        let x = [0xA5, 0x01];
+        assert!((bit_balance_chi2(&x, 12) - monobit_statistic(&x, 12).powi(2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn bit_position_chi2_qc() {
+        fn prop(v: Vec<u8>, code_len: u8) -> bool {
+            let code_len = 1 + code_len as usize % 20;
+            let codes = &v[..v.len() / code_len * code_len];
+            let n = (codes.len() / code_len) as f64;
+            let naive = (0..8 * code_len).map(|i| {
+                let ones = codes.chunks(code_len).filter(|c| c[i / 8] >> (i % 8) & 1 == 1).count();
+                let difference = 2.0 * ones as f64 - n;
+                difference * difference / n
+            }).sum::<f64>();
+            let chi2 = bit_position_chi2(codes, code_len);
+            if n == 0.0 { chi2 == 0.0 } else { (chi2 - naive).abs() < 1e-9 * naive.max(1.0) }
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 500))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+}
@@ -0,0 +1,348 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+
+#[cfg(feature = "alloc")]
+use DistanceError;
+use weight_::weight_bytes;
+
+/// An incremental Hamming weight computation, for data that arrives
+/// in pieces.
+///
+/// This is like a `Hasher`: feed it the data in chunks of any size
+/// with `update`, and read the weight of everything so far with
+/// `finish`. The bytes at the end of a chunk that don't make up a
+/// whole 64-bit word are carried over to be completed by the next
+/// one, so the whole words of every chunk are counted with the same
+/// kernel as `weight`, however the data is split. It doesn't allocate
+/// or need `std`, so it suits network and DMA buffers on embedded
+/// targets.
+///
+/// `weight_chunks` does this for an iterator of chunks.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut acc = hamming::WeightAccumulator::new();
+/// acc.update(&[0xFF; 3]);
+/// acc.update(&[0x01; 13]);
+/// assert_eq!(acc.finish(), 3 * 8 + 13);
+/// acc.update(&[0x0F]);
+/// assert_eq!(acc.finish(), 3 * 8 + 13 + 4);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WeightAccumulator {
+    count: u64,
+    // a partial word, of `used` bytes from the ends of earlier chunks
+    carry: u64,
+    used: usize,
+}
+
+impl WeightAccumulator {
+    /// Create an accumulator that hasn't seen any data.
+    pub fn new() -> WeightAccumulator {
+        WeightAccumulator::default()
+    }
+
+    /// Add the bytes of `chunk` to the data.
+    pub fn update(&mut self, mut chunk: &[u8]) {
+        if self.used > 0 {
+            let take = (8 - self.used).min(chunk.len());
+            self.push(&chunk[..take]);
+            chunk = &chunk[take..];
+            if self.used == 8 {
+                self.count += self.carry.count_ones() as u64;
+                self.carry = 0;
+                self.used = 0;
+            }
+        }
+        let split = chunk.len() - chunk.len() % 8;
+        self.count += weight_bytes(&chunk[..split]);
+        self.push(&chunk[split..]);
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.carry |= (b as u64) << (8 * self.used);
+            self.used += 1;
+        }
+    }
+
+    /// The weight of all the data added so far.
+    pub fn finish(&self) -> u64 {
+        self.count + self.carry.count_ones() as u64
+    }
+}
+
+/// An incremental bitwise Hamming distance computation, for comparing
+/// two streams of data that arrive in pieces.
+///
+/// Feed it successive chunks of the two streams with `update`, and
+/// compute the distance between them with `finish`. The chunks of the
+/// two streams don't need to line up: each call can pass different
+/// amounts of each, as long as the totals match by the end. Whatever
+/// one stream has received beyond the other is held until the other
+/// catches up, and everything else is compared as it arrives, with
+/// `distance`.
+///
+/// With the `alloc` feature, this can compare, say, the frames
+/// received from a device with the frames expected, without
+/// buffering either side in full.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut acc = hamming::DistanceAccumulator::new();
+/// acc.update(&[0xFF, 0x00, 0x0F], &[0x0F]);
+/// acc.update(&[], &[0x00]);
+/// acc.update(&[0x01], &[0x00, 0x00]);
+/// assert_eq!(acc.finish(), Ok(4 + 4 + 1));
+///
+/// acc.update(&[1], &[]);
+/// assert_eq!(acc.finish(), Err(hamming::DistanceError::LengthMismatch {
+///     len: 4, x_shorter: false,
+/// }));
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Default)]
+pub struct DistanceAccumulator {
+    count: u64,
+    // the number of bytes of each stream that have been compared
+    compared: usize,
+    // the bytes of the stream that is ahead that haven't been
+    // compared yet, starting at `pending_start`.
+    pending: Vec<u8>,
+    pending_start: usize,
+    x_ahead: bool,
+}
+
+#[cfg(feature = "alloc")]
+impl DistanceAccumulator {
+    /// Create an accumulator that hasn't seen any data.
+    pub fn new() -> DistanceAccumulator {
+        DistanceAccumulator::default()
+    }
+
+    /// Add `x` to the end of the first stream and `y` to the end of the
+    /// second.
+    pub fn update<'a>(&mut self, mut x: &'a [u8], mut y: &'a [u8]) {
+        // catch up the stream that is behind
+        if self.pending_start < self.pending.len() {
+            let behind = if self.x_ahead { &mut y } else { &mut x };
+            let pending = &self.pending[self.pending_start..];
+            let n = pending.len().min(behind.len());
+            self.count += ::distance(&pending[..n], &behind[..n]);
+            self.compared += n;
+            self.pending_start += n;
+            *behind = &behind[n..];
+        }
+
+        let n = x.len().min(y.len());
+        self.count += ::distance(&x[..n], &y[..n]);
+        self.compared += n;
+        let (x, y) = (&x[n..], &y[n..]);
+
+        if self.pending_start == self.pending.len() {
+            self.pending.clear();
+            self.pending_start = 0;
+        } else if self.pending_start > self.pending.len() / 2 {
+            self.pending.drain(..self.pending_start);
+            self.pending_start = 0;
+        }
+        // at most one of these is non-empty, and if the pending bytes
+        // weren't all compared, it is from the same stream
+        if !x.is_empty() {
+            self.x_ahead = true;
+            self.pending.extend_from_slice(x);
+        } else if !y.is_empty() {
+            self.x_ahead = false;
+            self.pending.extend_from_slice(y);
+        }
+    }
+
+    /// The distance between the two streams, if they have the same
+    /// length.
+    ///
+    /// If one is longer, this returns `DistanceError::LengthMismatch`
+    /// with the length of the other.
+    pub fn finish(&self) -> Result<u64, DistanceError> {
+        if self.pending_start < self.pending.len() {
+            Err(DistanceError::LengthMismatch { len: self.compared, x_shorter: !self.x_ahead })
+        } else {
+            Ok(self.count)
+        }
+    }
+}
+
+/// A writer that counts the set bits of everything written through
+/// it, forwarding the bytes on to another writer.
+///
+/// With the `std` feature, this can be inserted into an existing
+/// pipeline (such as a serialiser writing to a file, or `io::copy`)
+/// to compute the weight of the data as it goes past, without a
+/// second pass over it. Many small writes are counted as efficiently
+/// as a few large ones, as with a `WeightAccumulator`. Only the bytes
+/// that the inner writer accepts are counted. To count without
+/// forwarding the data anywhere, wrap `io::sink()`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::{self, Write};
+///
+/// let mut writer = hamming::WeightWriter::new(Vec::new());
+/// writer.write_all(&[0xFF, 0x01])?;
+/// writer.write_all(&[0x0F])?;
+/// assert_eq!(writer.weight(), 8 + 1 + 4);
+/// assert_eq!(writer.into_inner(), [0xFF, 0x01, 0x0F]);
+///
+/// let mut counter = hamming::WeightWriter::new(io::sink());
+/// io::copy(&mut &[0xFFu8; 1000][..], &mut counter)?;
+/// assert_eq!(counter.weight(), 8000);
+/// # Ok::<(), io::Error>(())
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct WeightWriter<W> {
+    inner: W,
+    acc: WeightAccumulator,
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> WeightWriter<W> {
+    /// Create a writer forwarding to `inner`, with a weight of zero.
+    pub fn new(inner: W) -> WeightWriter<W> {
+        WeightWriter { inner, acc: WeightAccumulator::new() }
+    }
+
+    /// The total weight of the bytes written so far.
+    pub fn weight(&self) -> u64 {
+        self.acc.finish()
+    }
+
+    /// A reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// A mutable reference to the inner writer.
+    ///
+    /// Anything written directly to it is not counted.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwrap this, returning the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> io::Write for WeightWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.acc.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck as qc;
+    use rand;
+    #[cfg(feature = "std")]
+    use std::io::Write;
+
+    #[test]
+    fn weight_accumulator_qc() {
+        fn prop(v: Vec<Vec<u8>>) -> bool {
+            let mut acc = WeightAccumulator::new();
+            let mut flat = Vec::new();
+            v.iter().all(|chunk| {
+                acc.update(chunk);
+                flat.extend_from_slice(chunk);
+                acc.finish() == ::weight(&flat)
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<Vec<u8>>) -> bool);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn distance_accumulator_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, splits: Vec<(u8, u8)>) -> bool {
+            let l = v.len().min(w.len());
+            let (mut x, mut y) = (&v[..], &w[..l]);
+            let mut acc = DistanceAccumulator::new();
+            for &(i, j) in &splits {
+                let (i, j) = ((i as usize).min(x.len()), (j as usize).min(y.len()));
+                acc.update(&x[..i], &y[..j]);
+                x = &x[i..];
+                y = &y[j..];
+            }
+            acc.update(x, y);
+            let expected = if v.len() == l {
+                Ok(::distance(&v, &w[..l]))
+            } else {
+                Err(DistanceError::LengthMismatch { len: l, x_shorter: false })
+            };
+            acc.finish() == expected
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 300))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, Vec<(u8, u8)>) -> bool);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn distance_accumulator_lagging() {
+        // one stream far ahead of the other, which catches up a byte
+        // at a time
+        let mut acc = DistanceAccumulator::new();
+        acc.update(&[0xFF; 1000], &[]);
+        for _ in 0..999 {
+            acc.update(&[], &[0x0F]);
+        }
+        assert_eq!(acc.finish(), Err(DistanceError::LengthMismatch { len: 999, x_shorter: false }));
+        acc.update(&[0xFF; 2], &[0x00; 4]);
+        assert_eq!(acc.finish(), Err(DistanceError::LengthMismatch { len: 1002, x_shorter: true }));
+        acc.update(&[0x01], &[]);
+        assert_eq!(acc.finish(), Ok(999 * 4 + 3 * 8 + 1));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn weight_writer_qc() {
+        fn prop(v: Vec<Vec<u8>>) -> bool {
+            let mut writer = WeightWriter::new(Vec::new());
+            for chunk in &v {
+                writer.write_all(chunk).unwrap();
+            }
+            let flat = v.concat();
+            writer.weight() == ::weight(&flat) && writer.into_inner() == flat
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<Vec<u8>>) -> bool);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn partial_writes() {
+        // only the bytes that fit are accepted, and counted
+        let mut buf = [0u8; 3];
+        let mut writer = WeightWriter::new(&mut buf[..]);
+        assert_eq!(writer.write(&[0xFF; 5]).unwrap(), 3);
+        assert_eq!(writer.write(&[0xFF; 5]).unwrap(), 0);
+        assert_eq!(writer.weight(), 24);
+    }
+}
@@ -0,0 +1,188 @@
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+
+/// A collector of the `k` best search results offered to it, where
+/// results are ordered by distance, then by index.
+///
+/// Every `top_k` search in this crate collects its results with one
+/// of these, so they all agree on ties: the result is always the `k`
+/// smallest `(distance, index)` pairs among the candidates, whatever
+/// order those were offered in. Searches that visit codes in a
+/// different order (such as a tree walk, or several threads each
+/// scanning part of a database) therefore give exactly the same
+/// results as a sequential scan, and collectors filled separately can
+/// be combined with `merge`.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut a = hamming::TopK::new(2);
+/// a.offer(3, 5);
+/// a.offer(7, 1);
+/// let mut b = hamming::TopK::new(2);
+/// b.offer(1, 5);
+/// b.offer(0, 9);
+///
+/// a.merge(b);
+/// // the tie at distance 5 goes to the lower index
+/// assert_eq!(a.into_sorted_vec(), [(7, 1), (1, 5)]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TopK {
+    k: usize,
+    // a max-heap of `(distance, index)`, so the worst result is on top
+    heap: BinaryHeap<(u64, usize)>,
+}
+
+impl TopK {
+    /// Create a collector keeping the best `k` results.
+    pub fn new(k: usize) -> TopK {
+        TopK { k, heap: BinaryHeap::new() }
+    }
+
+    /// The number of results kept.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The number of results collected so far, at most `k`.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Whether no results have been collected.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Discard every result, and keep the best `k` from now on.
+    pub fn reset(&mut self, k: usize) {
+        self.k = k;
+        self.heap.clear();
+    }
+
+    /// The distance of the worst result, once there are `k` of them.
+    ///
+    /// A candidate further away than this can't be kept, and one at
+    /// exactly this distance is only kept if its index is lower than
+    /// that of the worst result. Before there are `k` results, every
+    /// candidate is kept, and this is `None`.
+    pub fn bound(&self) -> Option<u64> {
+        if self.heap.len() < self.k {
+            None
+        } else {
+            self.heap.peek().map(|&(d, _)| d)
+        }
+    }
+
+    /// Whether a candidate at `distance` might be kept, that is,
+    /// whether it is no further than the `bound`.
+    pub fn admits(&self, distance: u64) -> bool {
+        self.bound().is_none_or(|bound| distance <= bound)
+    }
+
+    /// Offer the code at `index`, at `distance` from the query,
+    /// returning whether it is among the best `k` so far.
+    pub fn offer(&mut self, index: usize, distance: u64) -> bool {
+        let candidate = (distance, index);
+        if self.heap.len() < self.k {
+            self.heap.push(candidate);
+            true
+        } else if self.heap.peek().is_some_and(|&worst| candidate < worst) {
+            self.heap.pop();
+            self.heap.push(candidate);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Offer every result of `other`, as if they had been offered to
+    /// `self` directly.
+    pub fn merge(&mut self, other: TopK) {
+        for (d, i) in other.heap {
+            self.offer(i, d);
+        }
+    }
+
+    /// Replace the contents of `out` with the `(index, distance)`
+    /// pairs collected, sorted by distance with ties broken by the
+    /// lower index, leaving this empty.
+    pub fn drain_sorted(&mut self, out: &mut Vec<(usize, u64)>) {
+        out.clear();
+        out.extend(self.heap.drain().map(|(d, i)| (i, d)));
+        out.sort_unstable_by_key(|&(i, d)| (d, i));
+    }
+
+    /// The `(index, distance)` pairs collected, sorted by distance
+    /// with ties broken by the lower index.
+    pub fn into_sorted_vec(self) -> Vec<(usize, u64)> {
+        self.heap.into_sorted_vec().into_iter().map(|(d, i)| (i, d)).collect()
+    }
+}
+
+/// Sort the `(index, distance)` results of a radius search into index
+/// order, as every `within` search in this crate returns them.
+pub(crate) fn sort_by_index(results: &mut [(usize, u64)]) {
+    // indices are distinct, so there are no ties to break
+    results.sort_unstable_by_key(|&(i, _)| i);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TopK;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn order_independent_qc() {
+        fn prop(v: Vec<(u8, u8)>, k: u8, split: usize) -> bool {
+            let k = k as usize % 10;
+            let mut expected = v.iter().enumerate()
+                .map(|(i, &(_, d))| (i, d as u64 % 4))
+                .collect::<Vec<_>>();
+
+            // offer the candidates in a scrambled order, split between
+            // two collectors
+            let mut shuffled = expected.clone();
+            shuffled.sort_by_key(|&(i, _)| (v[i].0, !i));
+            let split = if v.is_empty() { 0 } else { split % v.len() };
+            let mut a = TopK::new(k);
+            let mut b = TopK::new(k);
+            for &(i, d) in &shuffled[..split] {
+                a.offer(i, d);
+            }
+            for &(i, d) in &shuffled[split..] {
+                b.offer(i, d);
+            }
+            a.merge(b);
+
+            expected.sort_by_key(|&(i, d)| (d, i));
+            expected.truncate(k);
+            let mut drained = vec![(0, 0)];
+            a.clone().drain_sorted(&mut drained);
+            a.len() == expected.len() && drained == expected && a.into_sorted_vec() == expected
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<(u8, u8)>, u8, usize) -> bool)
+    }
+
+    #[test]
+    fn bound() {
+        let mut top = TopK::new(2);
+        assert_eq!(top.bound(), None);
+        assert!(top.offer(5, 3));
+        assert_eq!(top.bound(), None);
+        assert!(top.offer(6, 3));
+        assert_eq!(top.bound(), Some(3));
+        assert!(top.admits(3) && !top.admits(4));
+        assert!(!top.offer(7, 3));
+        assert!(top.offer(4, 3));
+        assert_eq!(top.into_sorted_vec(), [(4, 3), (5, 3)]);
+
+        let mut none = TopK::new(0);
+        assert!(!none.offer(0, 0));
+        assert!(none.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+#[cfg(feature = "bytes")]
+use bytes::{Bytes, BytesMut};
+
+use Words;
+
+/// Types with a [Hamming
+/// weight](https://en.wikipedia.org/wiki/Hamming_weight), i.e. a
+/// number of set bits.
+///
+/// This is implemented for the unsigned integers, and for arrays and
+/// slices of the types accepted by `weight` (via `Words`), for which
+/// it is the same as calling `weight`.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::HammingWeight;
+///
+/// assert_eq!(0b1011u8.hamming_weight(), 3);
+/// assert_eq!([0xFFu8, 1].hamming_weight(), 9);
+/// assert_eq!(vec![!0u64; 3].hamming_weight(), 3 * 64);
+///
+/// fn total<T: HammingWeight>(xs: &[T]) -> u64 {
+///     xs.iter().map(|x| x.hamming_weight()).sum()
+/// }
+/// assert_eq!(total(&[[1u8, 1], [3, 0]]), 4);
+/// ```
+pub trait HammingWeight {
+    /// The number of set bits in `self`.
+    fn hamming_weight(&self) -> u64;
+}
+
+macro_rules! weight_int {
+    ($($t: ty)*) => {
+        $(
+            impl HammingWeight for $t {
+                #[inline]
+                fn hamming_weight(&self) -> u64 {
+                    self.count_ones() as u64
+                }
+            }
+        )*
+    }
+}
+
+weight_int!(u8 u16 u32 u64 u128 usize);
+
+impl<T: Words> HammingWeight for [T] {
+    #[inline]
+    fn hamming_weight(&self) -> u64 {
+        ::weight(self)
+    }
+}
+
+impl<T: Words, const N: usize> HammingWeight for [T; N] {
+    #[inline]
+    fn hamming_weight(&self) -> u64 {
+        ::weight(self)
+    }
+}
+
+/// Types with a bitwise [Hamming
+/// distance](https://en.wikipedia.org/wiki/Hamming_distance), i.e. a
+/// number of bits at which two values differ.
+///
+/// This is implemented for the unsigned integers, and for arrays and
+/// slices of the types accepted by `distance` (via `Words`), for which
+/// it is the same as calling `distance`.
+///
+/// # Panics
+///
+/// Like `distance`, the slice implementation panics if the two slices
+/// have different lengths. Integers and arrays always have matching
+/// lengths, so their implementations never panic.
+///
+/// # Examples
+///
+/// ```rust
+/// use hamming::HammingDistance;
+///
+/// assert_eq!(0b1011u8.hamming_distance(&0b0110), 3);
+/// assert_eq!([0xFFu8, 1].hamming_distance(&[0x0F, 0]), 5);
+///
+/// fn nearest<T: HammingDistance>(query: &T, codes: &[T]) -> Option<usize> {
+///     (0..codes.len()).min_by_key(|&i| codes[i].hamming_distance(query))
+/// }
+/// assert_eq!(nearest(&0xF0u32, &[0x0F, 0xF1, 0xFF]), Some(1));
+/// ```
+pub trait HammingDistance {
+    /// The number of bits at which `self` and `other` differ.
+    fn hamming_distance(&self, other: &Self) -> u64;
+}
+
+macro_rules! distance_int {
+    ($($t: ty)*) => {
+        $(
+            impl HammingDistance for $t {
+                #[inline]
+                fn hamming_distance(&self, other: &$t) -> u64 {
+                    (self ^ other).count_ones() as u64
+                }
+            }
+        )*
+    }
+}
+
+distance_int!(u8 u16 u32 u64 u128 usize);
+
+impl<T: Words> HammingDistance for [T] {
+    #[inline]
+    fn hamming_distance(&self, other: &[T]) -> u64 {
+        ::distance(self, other)
+    }
+}
+
+impl<T: Words, const N: usize> HammingDistance for [T; N] {
+    #[inline]
+    fn hamming_distance(&self, other: &[T; N]) -> u64 {
+        ::distance(self, other)
+    }
+}
+
+/// With the `bytes` feature, `Bytes` and `BytesMut` implement
+/// `HammingWeight` and `HammingDistance` like `[u8]`, so that they can
+/// be used directly with code that is generic over those traits.
+#[cfg(feature = "bytes")]
+macro_rules! bytes_impls {
+    ($($t: ty)*) => {
+        $(
+            impl HammingWeight for $t {
+                #[inline]
+                fn hamming_weight(&self) -> u64 {
+                    ::weight(self)
+                }
+            }
+
+            impl HammingDistance for $t {
+                #[inline]
+                fn hamming_distance(&self, other: &$t) -> u64 {
+                    ::distance(self, other)
+                }
+            }
+        )*
+    }
+}
+
+#[cfg(feature = "bytes")]
+bytes_impls!(Bytes BytesMut);
+
+#[cfg(test)]
+mod tests {
+    use super::{HammingDistance, HammingWeight};
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn hamming_weight_qc() {
+        fn prop(v: Vec<u8>, x: u64) -> bool {
+            let arr = [x as u16, (x >> 16) as u16, (x >> 32) as u16, (x >> 48) as u16];
+            v.hamming_weight() == ::weight(&v) &&
+                v[..].hamming_weight() == ::weight(&v) &&
+                x.hamming_weight() == x.count_ones() as u64 &&
+                (x as usize).hamming_weight() == (x as usize).count_ones() as u64 &&
+                arr.hamming_weight() == x.count_ones() as u64
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u64) -> bool)
+    }
+
+    #[test]
+    fn hamming_distance_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u8>, x: u64, y: u64) -> bool {
+            let l = v.len().min(w.len());
+            let (v, w) = (&v[..l], &w[..l]);
+            let (a, b) = (x.to_le_bytes(), y.to_le_bytes());
+            v.hamming_distance(w) == ::distance(v, w) &&
+                x.hamming_distance(&y) == (x ^ y).count_ones() as u64 &&
+                (x as u32).hamming_distance(&(y as u32)) == ((x ^ y) as u32).count_ones() as u64 &&
+                a.hamming_distance(&b) == (x ^ y).count_ones() as u64
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>, u64, u64) -> bool)
+    }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn bytes() {
+        use bytes::{Bytes, BytesMut};
+        fn prop(v: Vec<u8>, w: Vec<u8>) -> bool {
+            let l = v.len().min(w.len());
+            let (x, y) = (Bytes::from(v[..l].to_vec()), BytesMut::from(&w[..l]));
+            x.hamming_weight() == ::weight(&v[..l]) &&
+                y.hamming_weight() == ::weight(&w[..l]) &&
+                x.hamming_distance(&Bytes::from(w[..l].to_vec())) == ::distance(&v[..l], &w[..l]) &&
+                y.hamming_distance(&y) == 0
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u8>) -> bool)
+    }
+
+    #[test]
+    #[should_panic]
+    fn hamming_distance_length_mismatch() {
+        [1u8, 2][..].hamming_distance(&[1u8][..]);
+    }
+}
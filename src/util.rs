@@ -1,21 +1,20 @@
 use core::{slice, mem};
 
 /// Reinterpret as much of `x` as a slice of (correctly aligned) `U`s
-/// as possible. (Same as `slice::align_to` but available in earlier
-/// compilers.)
+/// as possible. (Like `slice::align_to`, but kept out of line, and
+/// checked so that it can't panic.)
 #[inline(never)] // critical for autovectorization in `weight`.
 pub unsafe fn align_to<T, U>(x: &[T]) -> (&[T], &[U], &[T]) {
     let orig_size = mem::size_of::<T>();
     let size = mem::size_of::<U>();
 
-    debug_assert!(orig_size < size && size % orig_size == 0);
+    debug_assert!(orig_size < size && size.is_multiple_of(orig_size));
     let size_ratio = size / orig_size;
 
     let alignment = mem::align_of::<U>();
 
     let ptr = x.as_ptr() as usize;
-    // round up to the nearest multiple
-    let aligned = (ptr + alignment - 1) / alignment * alignment;
+    let aligned = ptr.next_multiple_of(alignment);
     let byte_distance = aligned - ptr;
 
     // can't fit a single U in
@@ -23,17 +22,131 @@ pub unsafe fn align_to<T, U>(x: &[T]) -> (&[T], &[U], &[T]) {
         return (x, &[], &[])
     }
 
-    let (head, middle) = x.split_at(byte_distance / orig_size);
+    // (these splits are always in bounds, but checking them rather
+    // than panicking keeps the `try_` kernels free of panics)
+    let (head, middle) = match x.split_at_checked(byte_distance / orig_size) {
+        Some(split) => split,
+        None => return (x, &[], &[]),
+    };
+    let (middle, tail) = match middle.split_at_checked(middle.len() / size_ratio * size_ratio) {
+        Some(split) => split,
+        None => return (x, &[], &[]),
+    };
 
-    assert!(middle.as_ptr() as usize % alignment == 0);
+    debug_assert!((middle.as_ptr() as usize).is_multiple_of(alignment));
     let cast_middle =
         slice::from_raw_parts(middle.as_ptr() as *const U,
                               middle.len() / size_ratio);
-    let tail = &middle[cast_middle.len() * size_ratio..];
 
     (head, cast_middle, tail)
 }
 
+/// A small, fast, seedable pseudo-random number generator
+/// ([SplitMix64](http://xoroshiro.di.unimi.it/splitmix64.c)), for
+/// the randomised parts of the crate that must not depend on an
+/// external RNG.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct SplitMix64(u64);
+
+#[cfg(feature = "alloc")]
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// A value in `0..n`, for `n > 0`.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// 64 independent counters stored as bit planes: bit `i` of
+/// `planes[b]` is bit `b` of counter `i`. Adding a word increments
+/// exactly the counters of its set bits, with a ripple-carry across
+/// the planes, so counting `n` words costs `O(n)` word operations
+/// rather than `O(64 n)` bit operations.
+#[derive(Clone)]
+pub struct VerticalCounter {
+    planes: [u64; 64],
+    used: usize,
+}
+
+impl VerticalCounter {
+    pub fn new() -> VerticalCounter {
+        VerticalCounter { planes: [0; 64], used: 0 }
+    }
+
+    pub fn add(&mut self, mut x: u64) {
+        let mut b = 0;
+        for plane in &mut self.planes {
+            if x == 0 {
+                break
+            }
+            let carry = *plane & x;
+            *plane ^= x;
+            x = carry;
+            b += 1;
+        }
+        self.used = self.used.max(b);
+    }
+
+    /// The counters that are at least `threshold`, as a mask.
+    pub fn at_least(&self, threshold: u64) -> u64 {
+        if self.used < 64 && threshold >> self.used != 0 {
+            // more bits than any counter has
+            return 0
+        }
+        // compare from the most significant bit down
+        let (mut greater, mut equal) = (0, !0);
+        for b in (0..self.used).rev() {
+            if threshold >> b & 1 == 1 {
+                equal &= self.planes[b];
+            } else {
+                greater |= equal & self.planes[b];
+                equal &= !self.planes[b];
+            }
+        }
+        greater | equal
+    }
+
+    /// The value of counter `i`.
+    pub fn get(&self, i: usize) -> u64 {
+        self.planes.iter().take(self.used).enumerate()
+            .fold(0, |count, (b, &p)| count | (p >> i & 1) << b)
+    }
+}
+
+/// Call `f` with every value that differs from `key` in exactly
+/// `dist` of its low `bits` bits.
+#[cfg(feature = "std")]
+pub fn for_each_at_distance<F: FnMut(u64)>(key: u64, bits: usize, dist: usize, mut f: F) {
+    if dist > bits {
+        return
+    }
+    // Gosper's hack: step through the masks with `dist` set bits in
+    // increasing order.
+    let limit = 1u128 << bits;
+    let mut mask = (1u128 << dist) - 1;
+    while mask < limit {
+        f(key ^ mask as u64);
+        if mask == 0 {
+            break
+        }
+        let low = mask & mask.wrapping_neg();
+        let ripple = mask + low;
+        mask = (((ripple ^ mask) >> 2) / low) | ripple;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -48,7 +161,7 @@ mod tests {
         let array_and_tuple = (0u64, [0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
         let array = &array_and_tuple.1;
         // the array should be aligned appropriately
-        assert!((array.as_ptr() as usize) % 4 == 0);
+        assert!((array.as_ptr() as usize).is_multiple_of(4));
 
         let (head, middle, tail) = unsafe { align_to::<_, u32>(&array[from..to]) };
         assert_eq!(head, true_head);
@@ -105,6 +218,19 @@ mod tests {
         align_to_test(3, 9, &[3], &[0x07060504], &[8]);
     }
 
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn split_mix_64() {
+        // reference values from the C implementation
+        let mut rng = SplitMix64::new(1234567);
+        assert_eq!(rng.next_u64(), 6457827717110365317);
+        assert_eq!(rng.next_u64(), 3203168211198807973);
+
+        for n in 1..100 {
+            assert!(rng.below(n) < n);
+        }
+    }
+
     #[test]
     #[should_panic]
     #[cfg_attr(not(debug_assertions), ignore)]
@@ -118,4 +244,44 @@ mod tests {
     fn align_to_nondivisible() {
         let _ = unsafe { align_to::<[u8; 2], [u8; 3]>(&[]) };
     }
+
+    #[test]
+    fn vertical_counter() {
+        let words = [0b1011u64, 0b0011, 0b0110, 0b1000, 0b0011, !0];
+        let mut counter = VerticalCounter::new();
+        for &w in &words {
+            counter.add(w);
+        }
+        let counts = (0..64).map(|i| counter.get(i)).collect::<Vec<_>>();
+        assert_eq!(counts[..5], [4, 5, 2, 3, 1]);
+        assert!(counts[5..].iter().all(|&c| c == 1));
+
+        for t in 0..8 {
+            let expected = (0..64).fold(0, |m, i| m | ((counts[i] >= t) as u64) << i);
+            assert_eq!(counter.at_least(t), expected, "threshold {}", t);
+        }
+        assert_eq!(counter.at_least(1 << 40), 0);
+        assert_eq!(VerticalCounter::new().at_least(0), !0);
+        assert_eq!(VerticalCounter::new().at_least(1), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn for_each_at_distance_smoke() {
+        let mut v = vec![];
+        for_each_at_distance(0b0000, 4, 0, |x| v.push(x));
+        assert_eq!(v, [0b0000]);
+
+        v.clear();
+        for_each_at_distance(0b1111, 4, 2, |x| v.push(x));
+        assert_eq!(v, [0b1100, 0b1010, 0b1001, 0b0110, 0b0101, 0b0011]);
+
+        v.clear();
+        for_each_at_distance(0, 3, 4, |x| v.push(x));
+        assert_eq!(v, [0u64; 0]);
+
+        let mut count = 0;
+        for_each_at_distance(!0, 64, 64, |x| { assert_eq!(x, 0); count += 1 });
+        assert_eq!(count, 1);
+    }
 }
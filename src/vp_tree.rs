@@ -0,0 +1,526 @@
+use alloc::vec::Vec;
+use core::mem;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::thread;
+
+use {AsQuery, Database, TopK};
+use top_k;
+#[cfg(feature = "std")]
+use persist::{self, Kind};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The smallest subtree whose construction is split between threads.
+#[cfg(feature = "std")]
+const PARALLEL_MIN_NODES: usize = 4096;
+
+/// An exact nearest-neighbour index using a vantage-point tree.
+///
+/// Each node of the tree picks one code (the vantage point) and
+/// splits the remaining codes in half by their distance from it, with
+/// codes at exactly the median distance shared between the halves so
+/// that the tree stays balanced. Since Hamming distance is a
+/// metric, the triangle inequality then allows whole subtrees to be
+/// skipped during a search. See Yianilos, [Data Structures and
+/// Algorithms for Nearest Neighbor Search in General Metric
+/// Spaces](http://web.cs.iastate.edu/~honavar/nndatastructures.pdf).
+///
+/// Results are identical to the corresponding `Database` methods.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut db = hamming::Database::new(2);
+/// db.push(&[0x00, 0x00]);
+/// db.push(&[0xFF, 0xFF]);
+/// db.push(&[0x01, 0x10]);
+///
+/// let tree = hamming::VpTree::new(db);
+/// assert_eq!(tree.within(&[0, 0], 2), vec![(0, 0), (2, 2)]);
+/// assert_eq!(tree.top_k(&[0xFF, 0x7F], 1), vec![(1, 1)]);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawVpTree"))]
+pub struct VpTree {
+    db: Database,
+    nodes: Vec<Node>,
+}
+
+/// A node of the tree, stored in preorder: the subtree rooted at
+/// position `p` covers positions `p..end`, with codes within
+/// `threshold` of the vantage point at `p + 1..split` and codes at
+/// least `threshold` from it at `split..end`.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Node {
+    index: usize,
+    threshold: u64,
+    split: usize,
+}
+
+/// The serialized form of a `VpTree`, checked like `load` before it
+/// is searched.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawVpTree {
+    db: Database,
+    nodes: Vec<Node>,
+}
+
+#[cfg(feature = "serde")]
+impl ::core::convert::TryFrom<RawVpTree> for VpTree {
+    type Error = String;
+
+    fn try_from(raw: RawVpTree) -> Result<VpTree, String> {
+        if raw.nodes.len() != raw.db.len() || !nested(&raw.nodes) {
+            return Err("invalid tree structure".to_string())
+        }
+        Ok(VpTree { db: raw.db, nodes: raw.nodes })
+    }
+}
+
+impl VpTree {
+    /// Build a tree over the codes in `db`.
+    pub fn new(db: Database) -> VpTree {
+        let mut nodes = (0..db.len())
+            .map(|index| Node { index, threshold: 0, split: 0 })
+            .collect::<Vec<_>>();
+        build(&db, &mut nodes, 0);
+        VpTree { db, nodes }
+    }
+
+    /// Build a tree over the codes in `db`, like `new`, using
+    /// `threads` threads.
+    ///
+    /// With the `std` feature, once the codes have been split around
+    /// a vantage point, the two halves are built on separate threads,
+    /// recursively, until every thread is busy. The result is exactly
+    /// that of `new`.
+    ///
+    /// # Panics
+    ///
+    /// `threads` must be non-zero.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_codes(2, (0..10_000u16).map(|i| i.wrapping_mul(37).to_le_bytes()));
+    /// let tree = hamming::VpTree::new_parallel(db.clone(), 4);
+    /// assert_eq!(tree.top_k(&[0x12, 0x34], 3), hamming::VpTree::new(db).top_k(&[0x12, 0x34], 3));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn new_parallel(db: Database, threads: usize) -> VpTree {
+        assert!(threads > 0, "cannot build a tree with no threads");
+        let mut nodes = (0..db.len())
+            .map(|index| Node { index, threshold: 0, split: 0 })
+            .collect::<Vec<_>>();
+        build_parallel(&db, &mut nodes, 0, threads);
+        VpTree { db, nodes }
+    }
+
+    /// Write the tree to `writer`, in the file format of
+    /// `Database::save`.
+    ///
+    /// The file stores the database and the whole tree, so `load`
+    /// needn't compute any distances.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_packed(1, &[0x00, 0x01, 0xFF, 0x0F]);
+    /// let tree = hamming::VpTree::new(db);
+    ///
+    /// let mut file = Vec::new();
+    /// tree.save(&mut file)?;
+    /// let loaded = hamming::VpTree::load(&file[..])?;
+    /// assert_eq!(loaded.top_k(&[0x07], 2), tree.top_k(&[0x07], 2));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = persist::Writer::new();
+        self.db.write_payload(&mut w);
+        for node in &self.nodes {
+            w.usize(node.index);
+            w.u64(node.threshold);
+            w.usize(node.split);
+        }
+        w.finish(Kind::VpTree, writer)
+    }
+
+    /// Read a tree written by `save` from `reader`.
+    ///
+    /// Errors are returned as for `Database::load`, including if the
+    /// nodes don't form a tree over the codes.
+    #[cfg(feature = "std")]
+    pub fn load<R: Read>(reader: R) -> io::Result<VpTree> {
+        let payload = persist::read(Kind::VpTree, reader)?;
+        let mut r = persist::Reader::new(&payload);
+        let db = Database::read_payload(&mut r)?;
+        if db.len().saturating_mul(24) > r.remaining() {
+            return Err(persist::invalid(format!("missing nodes for {} codes", db.len())))
+        }
+        let nodes = (0..db.len())
+            .map(|_| Ok(Node { index: r.usize()?, threshold: r.u64()?, split: r.usize()? }))
+            .collect::<io::Result<Vec<_>>>()?;
+        if !nested(&nodes) {
+            return Err(persist::invalid("invalid tree structure".to_string()))
+        }
+        Ok(VpTree { db, nodes })
+    }
+
+    /// The indexed codes.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// The number of nodes on the longest path from the root to a
+    /// leaf, or 0 for an empty tree.
+    ///
+    /// A tree of `n` codes is at least `log2(n + 1)` deep, and median
+    /// splits achieve this even when many codes are at the same
+    /// distance from a vantage point.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let db = hamming::Database::from_codes(2, (0..1000u16).map(|i| (i * 37).to_le_bytes()));
+    /// let tree = hamming::VpTree::new(db);
+    /// assert!(tree.depth() >= 10);
+    /// assert!(tree.mean_depth() <= tree.depth() as f64);
+    /// ```
+    pub fn depth(&self) -> usize {
+        self.depths(&mut 0)
+    }
+
+    /// The mean number of nodes on the path from the root to each
+    /// node, including both ends, or 0 for an empty tree.
+    ///
+    /// The further this is above `log2(n)`, the less balanced the
+    /// tree, and the more distance computations a search needs.
+    pub fn mean_depth(&self) -> f64 {
+        let mut total = 0;
+        self.depths(&mut total);
+        if self.nodes.is_empty() { 0.0 } else { total as f64 / self.nodes.len() as f64 }
+    }
+
+    /// The depth of the tree, adding the level of each of its nodes
+    /// to `total`.
+    ///
+    /// Loaded trees needn't be balanced, so this walks the tree with
+    /// an explicit stack rather than recursing.
+    fn depths(&self, total: &mut usize) -> usize {
+        let mut depth = 0;
+        let mut stack = vec![(0, self.nodes.len(), 1)];
+        while let Some((start, end, level)) = stack.pop() {
+            if start == end {
+                depth = depth.max(level - 1);
+                continue
+            }
+            *total += level;
+            let split = self.nodes[start].split;
+            stack.push((split, end, level + 1));
+            stack.push((start + 1, split, level + 1));
+        }
+        depth
+    }
+
+    /// The approximate heap memory used by the index, including its
+    /// database, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.db.memory_usage() + self.nodes.capacity() * mem::size_of::<Node>()
+    }
+
+    /// Find every code within distance `radius` of `query`, returning
+    /// `(index, distance)` pairs in index order.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn within<Q: AsQuery>(&self, query: Q, radius: u64) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        assert_eq!(query.len(), self.db.code_len());
+        let mut result = Vec::new();
+        self.within_(query, radius, &mut result);
+        top_k::sort_by_index(&mut result);
+        result
+    }
+
+    fn within_(&self, query: &[u8], radius: u64, result: &mut Vec<(usize, u64)>) {
+        let mut stack = vec![(0, self.nodes.len())];
+        while let Some((start, end)) = stack.pop() {
+            if start == end {
+                continue
+            }
+            let node = self.nodes[start];
+            let d = ::distance(query, self.db.get(node.index));
+            if d <= radius {
+                result.push((node.index, d));
+            }
+            if d.saturating_add(radius) >= node.threshold {
+                stack.push((node.split, end));
+            }
+            if d <= node.threshold.saturating_add(radius) {
+                stack.push((start + 1, node.split));
+            }
+        }
+    }
+
+    /// Find the `k` codes closest to `query`, returning `(index,
+    /// distance)` pairs sorted by distance, with ties broken by the
+    /// lower index.
+    ///
+    /// # Panics
+    ///
+    /// `query` must be `self.database().code_len()` bytes long.
+    pub fn top_k<Q: AsQuery>(&self, query: Q, k: usize) -> Vec<(usize, u64)> {
+        let query = query.query_bytes();
+        assert_eq!(query.len(), self.db.code_len());
+        let mut top = TopK::new(k);
+        if k > 0 {
+            self.top_k_(query, &mut top);
+        }
+        top.into_sorted_vec()
+    }
+
+    fn top_k_(&self, query: &[u8], top: &mut TopK) {
+        // each entry holds a lower bound on the distance to anything
+        // in its subtree
+        let mut stack = vec![(0, 0, self.nodes.len())];
+        while let Some((bound, start, end)) = stack.pop() {
+            // ties are broken by index, so subtrees at exactly the
+            // current worst distance may still improve the result.
+            if start == end || !top.admits(bound) {
+                continue
+            }
+            let node = self.nodes[start];
+            let d = ::distance(query, self.db.get(node.index));
+            top.offer(node.index, d);
+
+            let inside = (d.saturating_sub(node.threshold), start + 1, node.split);
+            let outside = (node.threshold.saturating_sub(d), node.split, end);
+            // search the side the query falls on first, so the other
+            // is more likely to be pruned.
+            if d <= node.threshold {
+                stack.push(outside);
+                stack.push(inside);
+            } else {
+                stack.push(inside);
+                stack.push(outside);
+            }
+        }
+    }
+}
+
+/// Whether every code appears once in `nodes`, and each subtree is
+/// nested in its parent's, so that searches stay in bounds.
+#[cfg(feature = "std")]
+fn nested(nodes: &[Node]) -> bool {
+    let mut seen = vec![false; nodes.len()];
+    nodes.iter().enumerate().all(|(p, node)| {
+        node.index < seen.len() && !mem::replace(&mut seen[node.index], true) &&
+            node.split > p && node.split <= subtree_end(nodes, p)
+    })
+}
+
+/// The end of the subtree rooted at position `p`, as recorded by the
+/// nodes on the path to it from the root.
+#[cfg(feature = "std")]
+fn subtree_end(nodes: &[Node], p: usize) -> usize {
+    let (mut start, mut end) = (0, nodes.len());
+    while start < p {
+        let split = nodes[start].split;
+        if p < split {
+            start += 1;
+            end = split;
+        } else {
+            start = split;
+        }
+    }
+    end
+}
+
+/// Arrange `nodes` (all with `split` and `threshold` unset) into a
+/// tree, with `offset` the position of `nodes[0]` in the whole tree.
+fn build(db: &Database, nodes: &mut [Node], offset: usize) {
+    if nodes.is_empty() {
+        return
+    }
+    let inside = partition(db, nodes, offset);
+    let (inner, outer) = nodes[1..].split_at_mut(inside);
+    build(db, inner, offset + 1);
+    build(db, outer, offset + 1 + inside);
+}
+
+/// `build`, with the two subtrees of each node built on separate
+/// threads, using up to `threads` threads in total.
+#[cfg(feature = "std")]
+fn build_parallel(db: &Database, nodes: &mut [Node], offset: usize, threads: usize) {
+    if threads <= 1 || nodes.len() < PARALLEL_MIN_NODES {
+        return build(db, nodes, offset)
+    }
+    let inside = partition(db, nodes, offset);
+    let (inner, outer) = nodes[1..].split_at_mut(inside);
+    thread::scope(|scope| {
+        scope.spawn(|| build_parallel(db, inner, offset + 1, threads / 2));
+        build_parallel(db, outer, offset + 1 + inside, threads - threads / 2);
+    });
+}
+
+/// Make `nodes[0]` a vantage point, arranging the rest of `nodes`
+/// into the closer half, within its threshold, followed by the
+/// further half, at least the threshold away, and return the size of
+/// the closer half.
+///
+/// Codes at exactly the threshold may land in either half: splitting
+/// ties by position rather than by distance keeps the tree balanced,
+/// and so its recursion shallow, even when every code is identical.
+fn partition(db: &Database, nodes: &mut [Node], offset: usize) -> usize {
+    let vantage = db.get(nodes[0].index);
+    let rest = &mut nodes[1..];
+    let mut distances = rest.iter()
+        .map(|n| (::distance(vantage, db.get(n.index)), n.index))
+        .collect::<Vec<_>>();
+    distances.sort();
+
+    let inside = distances.len() / 2;
+    let threshold = distances.get(inside).map_or(0, |&(d, _)| d);
+    for (n, &(_, index)) in rest.iter_mut().zip(&distances) {
+        n.index = index;
+    }
+    nodes[0].threshold = threshold;
+    nodes[0].split = offset + 1 + inside;
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VpTree;
+    use Database;
+    use quickcheck as qc;
+    use rand;
+
+    #[test]
+    fn search_qc() {
+        fn prop(v: Vec<u8>, radius: u8, k: u8) -> bool {
+            let code_len = 3;
+            let query = [0x12, 0x34, 0x56];
+            let mut db = Database::new(code_len);
+            for c in v.chunks(2) {
+                let mut code = query;
+                for &b in c {
+                    code[b as usize % code_len] ^= b;
+                }
+                db.push(code);
+            }
+
+            let tree = VpTree::new(db.clone());
+            let radius = radius as u64 % 16;
+            let k = k as usize % 10;
+            tree.within(query, radius) == db.within(query, radius) &&
+                tree.top_k(query, k) == db.top_k(query, k)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8, u8) -> bool)
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+        let db = Database::from_packed(2, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        let tree = VpTree::new(db);
+        let json = serde_json::to_string(&tree).unwrap();
+        let loaded = serde_json::from_str::<VpTree>(&json).unwrap();
+        assert_eq!(loaded.database(), tree.database());
+        assert_eq!(loaded.top_k([3, 3], 3), tree.top_k([3, 3], 3));
+
+        let corrupt = json.replacen("\"split\":", "\"split\":1000", 1);
+        assert!(serde_json::from_str::<VpTree>(&corrupt).is_err());
+        let missing = json.replacen("{\"index\":", "{\"index\":7", 1);
+        assert!(serde_json::from_str::<VpTree>(&missing).is_err());
+    }
+
+    #[test]
+    fn depth_qc() {
+        fn prop(v: Vec<u8>) -> bool {
+            let tree = VpTree::new(Database::from_packed(1, &v));
+            let n = v.len();
+            // a perfectly balanced tree is `log2(n + 1)` deep
+            let min_depth = (usize::BITS - n.leading_zeros()) as usize;
+            tree.depth() >= min_depth && tree.depth() <= n &&
+                tree.mean_depth() <= tree.depth() as f64 &&
+                (n == 0 || tree.mean_depth() >= 1.0)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>) -> bool);
+
+        assert_eq!(VpTree::new(Database::new(1)).depth(), 0);
+        assert_eq!(VpTree::new(Database::from_packed(1, [7])).mean_depth(), 1.0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn save_load() {
+        let db = Database::from_packed(1, (0..100u8).map(|i| i.wrapping_mul(37)).collect::<Vec<_>>());
+        let tree = VpTree::new(db);
+        let mut file = Vec::new();
+        tree.save(&mut file).unwrap();
+        let loaded = VpTree::load(&file[..]).unwrap();
+        assert_eq!(loaded.nodes.len(), tree.nodes.len());
+        assert_eq!(loaded.within([0x55], 3), tree.within([0x55], 3));
+
+        // a well-formed file holding a broken tree is rejected
+        let mut broken = tree.clone();
+        broken.nodes[0].split = 1000;
+        file.clear();
+        broken.save(&mut file).unwrap();
+        assert!(VpTree::load(&file[..]).is_err());
+        broken = tree.clone();
+        broken.nodes[5].index = broken.nodes[6].index;
+        file.clear();
+        broken.save(&mut file).unwrap();
+        assert!(VpTree::load(&file[..]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn new_parallel() {
+        let db = Database::from_codes(2, (0..20_000u32).map(|i| ((i * 7919) as u16).to_le_bytes()));
+        let fields = |tree: &VpTree| {
+            tree.nodes.iter().map(|n| (n.index, n.threshold, n.split)).collect::<Vec<_>>()
+        };
+        let tree = VpTree::new(db.clone());
+        for &threads in &[1, 2, 3, 8] {
+            assert_eq!(fields(&VpTree::new_parallel(db.clone(), threads)), fields(&tree));
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        let db = Database::from_packed(1, [7; 20]);
+        let tree = VpTree::new(db);
+        assert_eq!(tree.within([7], 0), (0..20).map(|i| (i, 0)).collect::<Vec<_>>());
+        assert_eq!(tree.top_k([6], 3), vec![(0, 1), (1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn many_duplicates() {
+        // ties used to all land on one side, making the tree (and its
+        // recursion) as deep as the number of codes.
+        let n = 100_000;
+        let db = Database::from_codes(8, (0..n).map(|i| if i % 1000 == 0 { [1; 8] } else { [0; 8] }));
+        let tree = VpTree::new(db);
+        assert_eq!(tree.depth(), 17);
+        assert_eq!(tree.within([0; 8], 0).len(), n - 100);
+        assert_eq!(tree.within([1; 8], 0), (0..n).step_by(1000).map(|i| (i, 0)).collect::<Vec<_>>());
+        assert_eq!(tree.top_k([1; 8], 2), vec![(0, 0), (1000, 0)]);
+        assert_eq!(tree.top_k([0; 8], 2), vec![(1, 0), (2, 0)]);
+    }
+}
@@ -0,0 +1,101 @@
+//! JavaScript bindings, via `wasm-bindgen`.
+//!
+//! With the `wasm-bindgen` feature, building for `wasm32` with
+//! `wasm-pack` or `wasm-bindgen` exports `weight`, `distance` and
+//! `scan` to JavaScript, taking `Uint8Array`s:
+//!
+//! ```js
+//! import { weight, distance, scan } from "hamming";
+//!
+//! weight(new Uint8Array([1, 0xFF]));                      // 9
+//! distance(new Uint8Array([1, 2]), new Uint8Array([3, 2])); // 1
+//!
+//! const out = new Uint32Array(2);
+//! scan(new Uint8Array([0xF0]), new Uint8Array([0xF0, 0x0F]), out);
+//! // out is [0, 8]
+//! ```
+//!
+//! `weight` and `distance` return plain numbers rather than `BigInt`s,
+//! which are exact for any input that fits in wasm memory, and `scan`
+//! writes 32-bit distances, which are enough for codes of up to
+//! 512MiB. Invalid lengths throw an `Error` instead of aborting.
+//!
+//! wasm-bindgen copies each array into wasm memory for the duration
+//! of the call, and copies `out` back, but these are single
+//! `memcpy`s: the kernels themselves run directly on those copies,
+//! with no per-element conversion.
+
+use wasm_bindgen::prelude::*;
+
+/// Computes the Hamming weight of `x`, as `hamming::weight`.
+#[wasm_bindgen]
+pub fn weight(x: &[u8]) -> f64 {
+    ::weight(x) as f64
+}
+
+/// Computes the bitwise Hamming distance between `x` and `y`, as
+/// `hamming::distance`, throwing if they have different lengths.
+#[wasm_bindgen]
+pub fn distance(x: &[u8], y: &[u8]) -> Result<f64, JsError> {
+    check_distance(x, y).map_err(|e| JsError::new(&e))
+}
+
+/// Computes the distance from `query` to each of the codes of
+/// `query.length` bytes packed one after another in `codes`, storing
+/// the distance to code `i` in `out[i]`.
+///
+/// This throws if `codes` is not a whole number of codes, or `out`
+/// does not have exactly one element per code.
+#[wasm_bindgen]
+pub fn scan(query: &[u8], codes: &[u8], out: &mut [u32]) -> Result<(), JsError> {
+    check_scan(query, codes, out).map_err(|e| JsError::new(&e))
+}
+
+fn check_distance(x: &[u8], y: &[u8]) -> Result<f64, String> {
+    if x.len() != y.len() {
+        return Err(format!("arrays have different lengths ({} and {})", x.len(), y.len()))
+    }
+    Ok(::distance(x, y) as f64)
+}
+
+fn check_scan(query: &[u8], codes: &[u8], out: &mut [u32]) -> Result<(), String> {
+    let code_len = query.len();
+    let count = match codes.len().checked_div(code_len) {
+        Some(count) if codes.len().is_multiple_of(code_len) => count,
+        _ if codes.is_empty() => 0,
+        _ => {
+            return Err(format!("{} bytes are not a whole number of {}-byte codes",
+                               codes.len(), code_len))
+        }
+    };
+    if out.len() != count {
+        return Err(format!("output has {} elements, but there are {} codes", out.len(), count))
+    }
+    if code_len == 0 {
+        return Ok(())
+    }
+    for (o, code) in out.iter_mut().zip(codes.chunks_exact(code_len)) {
+        *o = ::distance(query, code) as u32;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_distance, check_scan, weight};
+
+    #[test]
+    fn bindings() {
+        assert_eq!(weight(&[1, 0xFF]), 9.0);
+        assert_eq!(check_distance(&[1, 2], &[3, 2]), Ok(1.0));
+        assert!(check_distance(&[1, 2], &[3]).is_err());
+
+        let mut out = [!0; 2];
+        assert_eq!(check_scan(&[0xF0], &[0xF0, 0x0F], &mut out), Ok(()));
+        assert_eq!(out, [0, 8]);
+        assert!(check_scan(&[0xF0, 0], &[0xF0, 0x0F, 0], &mut out).is_err());
+        assert!(check_scan(&[0xF0], &[0xF0], &mut out).is_err());
+        assert_eq!(check_scan(&[], &[], &mut []), Ok(()));
+        assert!(check_scan(&[], &[1], &mut []).is_err());
+    }
+}
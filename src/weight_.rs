@@ -1,3 +1,15 @@
+use core::convert::TryInto;
+use core::iter;
+#[cfg(feature = "std")]
+use core::slice;
+#[cfg(feature = "std")]
+use std::io;
+
+use checked::{check_matrix, output_length, unwrap, ArgumentError};
+use stream::WeightAccumulator;
+use util::VerticalCounter;
+use Words;
+
 fn naive(x: &[u8]) -> u64 {
     x.iter().fold(0, |a, b| a + b.count_ones() as u64)
 }
@@ -31,49 +43,442 @@ fn naive(x: &[u8]) -> u64 {
 /// | 100,000  | 276,000  | 17,900  | 15 |
 /// | 1,000,000  | 2,770,000  | 172,000  | 16 |
 ///
+/// # Element types
+///
+/// `x` can be a slice of any unsigned integer type (see `Words`), with
+/// the result being the same as for its bytes. Wider types guarantee
+/// more alignment, so less of the slice needs to be handled
+/// separately before the fast loop.
+///
 /// # Example
 ///
 /// ```rust
-/// assert_eq!(hamming::weight(&[1, 0xFF, 1, 0xFF]), 1 + 8 + 1 + 8);
+/// assert_eq!(hamming::weight(&[1u8, 0xFF, 1, 0xFF]), 1 + 8 + 1 + 8);
+/// assert_eq!(hamming::weight(&[1u64 << 63, !0]), 1 + 64);
 /// ```
-pub fn weight(x: &[u8]) -> u64 {
+pub fn weight<T: Words>(x: &[T]) -> u64 {
+    T::weight(x)
+}
+
+/// `weight` for bytes.
+pub(crate) fn weight_bytes(x: &[u8]) -> u64 {
+    panic_free!("weight", {
+        type T30 = [u64; 30];
+        let (head, thirty, tail) = unsafe {
+            ::util::align_to::<_, T30>(x)
+        };
+
+        let mut count = naive(head) + naive(tail);
+        for array in thirty {
+            count += merge30(array);
+        }
+        count
+    })
+}
+
+/// The total weight of 30 words, using the tree-merging approach
+/// described on `weight`.
+#[inline(always)]
+pub(crate) fn merge30(array: &[u64; 30]) -> u64 {
     const M1: u64 = 0x5555555555555555;
     const M2: u64 = 0x3333333333333333;
     const M4: u64 = 0x0F0F0F0F0F0F0F0F;
     const M8: u64 = 0x00FF00FF00FF00FF;
 
-    type T30 = [u64; 30];
-    let (head, thirty, tail) = unsafe {
-        ::util::align_to::<_, T30>(x)
-    };
+    let mut acc = 0;
+    for j_ in 0..10 {
+        let j = j_ * 3;
+        let mut count1 = array[j];
+        let mut count2 = array[j + 1];
+        let mut half1 = array[j + 2];
+        let mut half2 = half1;
+        half1 &= M1;
+        half2 = (half2 >> 1) & M1;
+        count1 -= (count1 >> 1) & M1;
+        count2 -= (count2 >> 1) & M1;
+        count1 += half1;
+        count2 += half2;
+        count1 = (count1 & M2) + ((count1 >> 2) & M2);
+        count1 += (count2 & M2) + ((count2 >> 2) & M2);
+        acc += (count1 & M4) + ((count1 >> 4) & M4);
+    }
+    acc = (acc & M8) + ((acc >> 8) & M8);
+    acc =  acc       +  (acc >> 16);
+    acc =  acc       +  (acc >> 32);
+    acc & 0xFFFF
+}
+
+/// The weight of a slice of words, 30 at a time.
+pub(crate) fn weight_words(x: &[u64]) -> u64 {
+    let chunks = x.chunks_exact(30);
+    let rest = chunks.remainder().iter().fold(0, |a, w| a + w.count_ones() as u64);
+    chunks.fold(rest, |a, c| a + merge30(c.try_into().unwrap()))
+}
 
-    let mut count = naive(head) + naive(tail);
-    for array in thirty {
-        let mut acc = 0;
-        for j_ in 0..10 {
-            let j = j_ * 3;
-            let mut count1 = array[j];
-            let mut count2 = array[j + 1];
-            let mut half1 = array[j + 2];
-            let mut half2 = half1;
-            half1 &= M1;
-            half2 = (half2 >> 1) & M1;
-            count1 -= (count1 >> 1) & M1;
-            count2 -= (count2 >> 1) & M1;
-            count1 += half1;
-            count2 += half2;
-            count1 = (count1 & M2) + ((count1 >> 2) & M2);
-            count1 += (count2 & M2) + ((count2 >> 2) & M2);
-            acc += (count1 & M4) + ((count1 >> 4) & M4);
-        }
-        acc = (acc & M8) + ((acc >> 8) & M8);
-        acc =  acc       +  (acc >> 16);
-        acc =  acc       +  (acc >> 32);
-        count += acc & 0xFFFF;
+macro_rules! weight_slice {
+    ($name: ident, $t: ty) => {
+        #[doc = concat!("`weight` for slices of `", stringify!($t), "`s, whose alignment lets the")]
+        /// words be counted directly without going through bytes.
+        pub(crate) fn $name(x: &[$t]) -> u64 {
+            panic_free!(stringify!($name), {
+                let (head, middle, tail) = unsafe { x.align_to::<u64>() };
+                let naive = |x: &[$t]| x.iter().fold(0, |a, b| a + b.count_ones() as u64);
+                naive(head) + weight_words(middle) + naive(tail)
+            })
+        }
+    }
+}
+
+weight_slice!(weight_u16_slice, u16);
+weight_slice!(weight_u32_slice, u32);
+weight_slice!(weight_u64_slice, u64);
+weight_slice!(weight_u128_slice, u128);
+
+/// Computes the Hamming weight of a fixed-size array of bytes.
+///
+/// This is the same as `weight`, but the length is known at compile
+/// time, so the loop over 64-bit words is fully unrolled for small
+/// `N`, with no alignment analysis or length checks. This is best for
+/// short codes, like the 8 to 64 byte hashes and descriptors common in
+/// practice; for long arrays, `weight` is faster.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::weight_array(&[0xFF; 32]), 256);
+/// assert_eq!(hamming::weight_array(&[1, 3, 7]), 6);
+/// ```
+#[inline]
+pub fn weight_array<const N: usize>(x: &[u8; N]) -> u64 {
+    let chunks = x.chunks_exact(8);
+    let tail = chunks.remainder().iter().fold(0, |a, b| a + b.count_ones() as u64);
+    chunks.fold(tail, |a, c| a + u64::from_ne_bytes(c.try_into().unwrap()).count_ones() as u64)
+}
+
+/// Computes the Hamming weight of `x` in a constant context.
+///
+/// This is a `const fn`, so it can be used to derive lookup tables,
+/// masks and other constants at compile time. It counts one byte at a
+/// time, so `weight` should be preferred at run time.
+///
+/// # Examples
+///
+/// ```rust
+/// const MASK: [u8; 3] = [0xFF, 0x0F, 0x01];
+/// const BITS: u64 = hamming::weight_const(&MASK);
+/// assert_eq!(BITS, 13);
+/// ```
+pub const fn weight_const(x: &[u8]) -> u64 {
+    let mut count = 0;
+    let mut i = 0;
+    while i < x.len() {
+        count += x[i].count_ones() as u64;
+        i += 1;
     }
     count
 }
 
+/// Computes the Hamming weight of the bytes produced by `iter`.
+///
+/// This is for data that doesn't live in a contiguous slice, such as
+/// the output of a decoder. The bytes are gathered into 64-bit words,
+/// which are counted 30 at a time like `weight`, so this is much
+/// faster than counting byte by byte, although still slower than
+/// `weight` on a slice.
+///
+/// # Examples
+///
+/// ```rust
+/// // every byte value once, so every bit is set half the time
+/// let bytes = (0..256).map(|i| i as u8);
+/// assert_eq!(hamming::weight_iter(bytes), 256 * 8 / 2);
+/// assert_eq!(hamming::weight_iter(vec![0xFF; 10]), 80);
+/// ```
+pub fn weight_iter<I: IntoIterator<Item = u8>>(iter: I) -> u64 {
+    let mut iter = iter.into_iter();
+    weight_iter_u64(iter::from_fn(|| {
+        let mut word = iter.next()? as u64;
+        for j in 1..8 {
+            match iter.next() {
+                Some(b) => word |= (b as u64) << (8 * j),
+                None => break,
+            }
+        }
+        Some(word)
+    }))
+}
+
+/// Computes the Hamming weight of the 64-bit words produced by
+/// `iter`.
+///
+/// Like `weight_iter`, this counts 30 words at a time.
+///
+/// # Examples
+///
+/// ```rust
+/// let words = (0..100u64).map(|i| i << 32 | i);
+/// assert_eq!(hamming::weight_iter_u64(words),
+///            2 * (0..100u64).map(|i| i.count_ones() as u64).sum::<u64>());
+/// ```
+pub fn weight_iter_u64<I: IntoIterator<Item = u64>>(iter: I) -> u64 {
+    let mut iter = iter.into_iter();
+    let mut count = 0;
+    loop {
+        let mut array = [0; 30];
+        let mut filled = 0;
+        for (a, w) in array.iter_mut().zip(&mut iter) {
+            *a = w;
+            filled += 1;
+        }
+        count += merge30(&array);
+        if filled < 30 {
+            return count
+        }
+    }
+}
+
+/// Computes the Hamming weight of every `stride`-th byte of `x`,
+/// starting at `x[offset]`.
+///
+/// This counts one channel of interleaved data without copying it
+/// out first. The selected bytes are gathered into 64-bit words like
+/// `weight_iter`, and a `stride` of 1 is just `weight` on
+/// `&x[offset..]`. An `offset` past the end of `x` selects nothing.
+///
+/// # Panics
+///
+/// `stride` must be non-zero.
+///
+/// # Examples
+///
+/// ```rust
+/// // RGBA pixels
+/// let pixels = [0xFF, 0x00, 0x01, 0xFF,
+///               0x0F, 0x00, 0x03, 0xFF];
+/// assert_eq!(hamming::weight_strided(&pixels, 0, 4), 8 + 4);
+/// assert_eq!(hamming::weight_strided(&pixels, 2, 4), 1 + 2);
+/// assert_eq!(hamming::weight_strided(&pixels, 1, 2), 0 + 8 + 0 + 8);
+/// ```
+pub fn weight_strided(x: &[u8], offset: usize, stride: usize) -> u64 {
+    unwrap(try_weight_strided(x, offset, stride))
+}
+
+/// As `weight_strided`, but returns an error if `stride` is zero,
+/// rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(hamming::try_weight_strided(&[0xFF, 0x00, 0x01], 0, 2), Ok(8 + 1));
+/// assert!(hamming::try_weight_strided(&[0xFF, 0x00, 0x01], 0, 0).is_err());
+/// ```
+pub fn try_weight_strided(x: &[u8], offset: usize, stride: usize) -> Result<u64, ArgumentError> {
+    panic_free!("try_weight_strided", {
+        if stride == 0 {
+            return Err(ArgumentError::InvalidSize { name: "stride", value: 0 })
+        }
+        let x = x.get(offset..).unwrap_or(&[]);
+        Ok(if stride == 1 {
+            weight_bytes(x)
+        } else {
+            weight_iter(x.iter().step_by(stride).cloned())
+        })
+    })
+}
+
+/// Computes the Hamming weight of the concatenation of `chunks`.
+///
+/// This is for data split across several slices, such as a rope or
+/// the buffers of vectored I/O. The whole words in each chunk are
+/// counted like `weight`, and the bytes left over at the end of a
+/// chunk are carried into the next one, so many short chunks are
+/// still counted a word at a time rather than a byte at a time.
+///
+/// # Examples
+///
+/// ```rust
+/// let chunks: [&[u8]; 3] = [&[0xFF; 3], &[], &[0x01; 13]];
+/// assert_eq!(hamming::weight_chunks(chunks.iter().cloned()), 3 * 8 + 13);
+/// ```
+pub fn weight_chunks<'a, I: IntoIterator<Item = &'a [u8]>>(chunks: I) -> u64 {
+    let mut acc = WeightAccumulator::new();
+    for chunk in chunks {
+        acc.update(chunk);
+    }
+    acc.finish()
+}
+
+/// Codes shorter than this are counted by `weights_many` a word at a
+/// time, rather than with the full `weight` kernel.
+const SHORT_CODE_LEN: usize = 256;
+
+/// The weight of a short `x`, one unaligned word at a time.
+#[inline]
+fn weight_short(x: &[u8]) -> u64 {
+    let words = x.chunks_exact(8);
+    let tail = naive(words.remainder());
+    words.fold(tail, |a, w| a + u64::from_ne_bytes(w.try_into().unwrap()).count_ones() as u64)
+}
+
+/// Computes the Hamming weight of each of `codes`, storing the weight
+/// of `codes[i]` in `out[i]`.
+///
+/// This is for counting many small codes, such as when building the
+/// weight tables of an index over millions of fingerprints. `weight`
+/// spends a while splitting each slice at alignment boundaries before
+/// its fast loop, which is slower than counting byte by byte for
+/// slices of less than a few hundred bytes (see its performance
+/// table). Here, short codes are instead counted with a single
+/// straight loop of unaligned word loads and popcounts, with no
+/// setup, and only long ones go through `weight`. The codes can have
+/// different lengths.
+///
+/// # Panics
+///
+/// `out` must have an element for each code.
+///
+/// # Examples
+///
+/// ```rust
+/// let codes = [vec![0xFFu8, 0x01], vec![], vec![0x0F; 100]];
+/// let mut out = [0; 3];
+/// hamming::weights_many(&codes, &mut out);
+/// assert_eq!(out, [9, 0, 400]);
+/// ```
+pub fn weights_many<C: AsRef<[u8]>>(codes: &[C], out: &mut [u64]) {
+    unwrap(try_weights_many(codes, out))
+}
+
+/// As `weights_many`, but returns an error if `out` has the wrong
+/// length, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let codes = [[0xFFu8, 0x01], [0x00, 0x10]];
+/// let mut out = [0; 2];
+/// assert_eq!(hamming::try_weights_many(&codes, &mut out), Ok(()));
+/// assert_eq!(out, [9, 1]);
+/// assert!(hamming::try_weights_many(&codes, &mut out[..1]).is_err());
+/// ```
+pub fn try_weights_many<C: AsRef<[u8]>>(codes: &[C], out: &mut [u64]) -> Result<(), ArgumentError> {
+    output_length(out.len(), codes.len())?;
+    for (o, code) in out.iter_mut().zip(codes) {
+        let code = code.as_ref();
+        *o = if code.len() < SHORT_CODE_LEN { weight_short(code) } else { weight_bytes(code) };
+    }
+    Ok(())
+}
+
+/// Count the ones in each bit position of the matrix `x`, storing in
+/// `out[i]` the number of rows whose bit `i` is set.
+///
+/// The matrix consists of rows of `code_len` bytes stored one after
+/// another, and bit `i` of a row is bit `i % 8` of its byte `i / 8`.
+/// Like `column_distances`, this counts 64 columns at a time with
+/// vertical counters, rather than a branch per bit.
+///
+/// # Panics
+///
+/// `code_len` must be non-zero, `x` must be a whole number of rows,
+/// and `out` must have `8 * code_len` elements.
+///
+/// # Examples
+///
+/// ```rust
+/// let x = [0b0000_0011, 0b0000_0001, 0b1000_0000];
+/// let mut out = [0; 8];
+/// hamming::column_weights(&x, 1, &mut out);
+/// assert_eq!(out, [2, 1, 0, 0, 0, 0, 0, 1]);
+/// ```
+pub fn column_weights(x: &[u8], code_len: usize, out: &mut [u64]) {
+    unwrap(try_column_weights(x, code_len, out))
+}
+
+/// As `column_weights`, but returns an error if `x` or `out` have the
+/// wrong length, rather than panicking.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut out = [0; 8];
+/// assert_eq!(hamming::try_column_weights(&[0b01, 0b11], 1, &mut out), Ok(()));
+/// assert_eq!(out[..2], [2, 1]);
+/// assert!(hamming::try_column_weights(&[0b01, 0b11, 0b00], 2, &mut out).is_err());
+/// ```
+pub fn try_column_weights(x: &[u8], code_len: usize, out: &mut [u64]) -> Result<(), ArgumentError> {
+    panic_free!("try_column_weights", {
+        check_matrix(x.len(), code_len, out.len())?;
+        for (start, out) in (0..code_len).step_by(8).zip(out.chunks_mut(64)) {
+            let mut counter = VerticalCounter::new();
+            for row in x.chunks(code_len) {
+                let mut word = [0; 8];
+                for (w, &b) in word.iter_mut().zip(row.iter().skip(start)) {
+                    *w = b;
+                }
+                counter.add(u64::from_le_bytes(word));
+            }
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = counter.get(i);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The size of the buffer used by `weight_reader`, in 64-bit words.
+#[cfg(feature = "std")]
+const READ_BUFFER_WORDS: usize = 8 * 1024;
+
+/// Computes the Hamming weight of everything read from `reader`, until
+/// it reaches the end of its input.
+///
+/// With the `std` feature, this reads through a 64KiB buffer aligned
+/// for `u64`s, only counting it once it is full (or at the end), so
+/// even readers that return a few bytes at a time are counted a word
+/// at a time. This means arbitrarily large files and streams can be
+/// counted without mapping or loading them all into memory, and the
+/// reader doesn't need to be buffered itself.
+///
+/// Reads that fail with `ErrorKind::Interrupted` are retried, and any
+/// other error is returned.
+///
+/// # Examples
+///
+/// ```rust
+/// let data = [0xFFu8; 100_000];
+/// assert_eq!(hamming::weight_reader(&data[..]).unwrap(), 800_000);
+/// ```
+///
+/// ```rust,no_run
+/// let file = std::fs::File::open("data.bin")?;
+/// println!("{} bits set", hamming::weight_reader(file)?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(feature = "std")]
+pub fn weight_reader<R: io::Read>(mut reader: R) -> io::Result<u64> {
+    let mut words = vec![0u64; READ_BUFFER_WORDS];
+    let buffer = unsafe {
+        slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, words.len() * 8)
+    };
+    let mut count = 0;
+    let mut filled = 0;
+    loop {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => return Ok(count + weight_bytes(&buffer[..filled])),
+            Ok(n) => {
+                filled += n;
+                if filled == buffer.len() {
+                    count += weight_bytes(buffer);
+                    filled = 0;
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use quickcheck as qc;
@@ -104,9 +509,188 @@ mod tests {
             .quickcheck(prop as fn(Vec<u8>,u8) -> qc::TestResult)
     }
     #[test]
+    fn weights_many_qc() {
+        fn prop(v: Vec<Vec<u8>>, long: Vec<u8>) -> bool {
+            // include a code long enough to use `weight`
+            let mut codes = v;
+            codes.push(long.iter().cycle().take(3 * long.len() * 100).cloned().collect());
+            let mut out = vec![!0; codes.len()];
+            super::weights_many(&codes, &mut out);
+            codes.iter().zip(&out).all(|(c, &w)| w == super::naive(c))
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<Vec<u8>>, Vec<u8>) -> bool)
+    }
+    #[test]
+    fn column_weights_qc() {
+        fn prop(v: Vec<u8>, code_len: u8) -> bool {
+            let code_len = code_len as usize % 20 + 1;
+            let v = &v[..v.len() - v.len() % code_len];
+            let mut out = vec![!0; 8 * code_len];
+            super::column_weights(v, code_len, &mut out);
+            out.iter().enumerate().all(|(i, &count)| {
+                count == v.chunks(code_len).filter(|row| row[i / 8] >> (i % 8) & 1 == 1).count() as u64
+            })
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+    #[test]
     fn weight_huge() {
-        let v = vec![0b1001_1101; 10234567];
+        let v = vec![0b1001_1101u8; 10234567];
         assert_eq!(super::weight(&v),
                    v[0].count_ones() as u64 * v.len() as u64);
     }
+    #[test]
+    fn weight_slices_qc() {
+        fn prop(v: Vec<u8>, misalign: u8) -> bool {
+            let v = &v[(misalign as usize % 16).min(v.len())..];
+            let expected = super::naive(v);
+            let u16s = v.chunks(2).map(|c| c.iter().rev().fold(0, |a, &b| a << 8 | b as u16)).collect::<Vec<_>>();
+            let u32s = v.chunks(4).map(|c| c.iter().rev().fold(0, |a, &b| a << 8 | b as u32)).collect::<Vec<_>>();
+            let u64s = v.chunks(8).map(|c| c.iter().rev().fold(0, |a, &b| a << 8 | b as u64)).collect::<Vec<_>>();
+            let u128s = v.chunks(16).map(|c| c.iter().rev().fold(0, |a, &b| a << 8 | b as u128)).collect::<Vec<_>>();
+            let misalign = misalign as usize % 4;
+            super::weight_u16_slice(&u16s) == expected &&
+                super::weight_u32_slice(&u32s) == expected &&
+                super::weight_u64_slice(&u64s) == expected &&
+                super::weight_u128_slice(&u128s) == expected &&
+                super::weight_u16_slice(&u16s[misalign.min(u16s.len())..]) ==
+                    u16s[misalign.min(u16s.len())..].iter().map(|x| x.count_ones() as u64).sum::<u64>()
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, u8) -> bool)
+    }
+
+    #[test]
+    fn weight_array_qc() {
+        fn prop(v: Vec<u8>) -> bool {
+            let mut a = [0u8; 45];
+            for (a, b) in a.iter_mut().zip(&v) {
+                *a = *b;
+            }
+            let b: [u8; 8] = [a[0], a[1], a[2], a[3], a[4], a[5], a[6], a[7]];
+            let c: [u8; 3] = [a[0], a[1], a[2]];
+            super::weight_array(&a) == super::naive(&a) &&
+                super::weight_array(&b) == super::naive(&b) &&
+                super::weight_array(&c) == super::naive(&c)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>) -> bool);
+        assert_eq!(super::weight_array(&[]), 0);
+    }
+
+    #[test]
+    fn weight_const_qc() {
+        fn prop(v: Vec<u8>) -> bool {
+            super::weight_const(&v) == super::naive(&v)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>) -> bool);
+        const W: u64 = super::weight_const(&[0xFF, 3]);
+        assert_eq!(W, 10);
+    }
+
+    #[test]
+    fn weight_iter_qc() {
+        fn prop(v: Vec<u8>, w: Vec<u64>) -> bool {
+            super::weight_iter(v.iter().cloned()) == super::naive(&v) &&
+                super::weight_iter_u64(w.iter().cloned()) == super::weight(&w)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, Vec<u64>) -> bool);
+
+        // several full batches of words
+        assert_eq!(super::weight_iter(vec![0xFF; 8 * 30 * 3 + 5]), 8 * (8 * 30 * 3 + 5));
+        assert_eq!(super::weight_iter_u64(vec![!0; 30 * 2]), 64 * 30 * 2);
+    }
+
+    #[test]
+    fn weight_chunks_qc() {
+        fn prop(v: Vec<Vec<u8>>) -> bool {
+            let flat = v.concat();
+            super::weight_chunks(v.iter().map(|c| &c[..])) == super::naive(&flat)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<Vec<u8>>) -> bool);
+
+        // byte-sized chunks, and one word straddling two chunks
+        assert_eq!(super::weight_chunks(vec![&[0xFFu8][..]; 1000]), 8000);
+        assert_eq!(super::weight_chunks(vec![&[0xFFu8; 5][..], &[0x0F; 5]]), 60);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn weight_reader_qc() {
+        use std::io::{self, Read};
+
+        // returns at most `max` bytes at a time, interrupting every
+        // other read.
+        struct Trickle<'a> { data: &'a [u8], max: usize, interrupt: bool }
+        impl<'a> Read for Trickle<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.interrupt = !self.interrupt;
+                if self.interrupt {
+                    return Err(io::ErrorKind::Interrupted.into())
+                }
+                let n = self.max.min(buf.len()).min(self.data.len());
+                buf[..n].copy_from_slice(&self.data[..n]);
+                self.data = &self.data[n..];
+                Ok(n)
+            }
+        }
+
+        fn prop(v: Vec<u8>, repeat: u8, max: usize) -> bool {
+            let v = v.repeat(repeat as usize * 100);
+            let trickle = Trickle { data: &v, max: max % 10_000 + 1, interrupt: false };
+            super::weight_reader(trickle).unwrap() == super::naive(&v) &&
+                super::weight_reader(&v[..]).unwrap() == super::naive(&v)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 100))
+            .quickcheck(prop as fn(Vec<u8>, u8, usize) -> bool);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn weight_reader_error() {
+        use std::io::{self, Read};
+
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        let err = super::weight_reader((&[1u8; 10][..]).chain(Broken)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn weight_strided_qc() {
+        fn prop(v: Vec<u8>, offset: usize, stride: usize) -> bool {
+            let (offset, stride) = (offset % 20, stride % 10 + 1);
+            let selected = v.iter().enumerate()
+                .filter(|&(i, _)| i >= offset && (i - offset) % stride == 0)
+                .map(|(_, &b)| b)
+                .collect::<Vec<_>>();
+            super::weight_strided(&v, offset, stride) == super::naive(&selected)
+        }
+        qc::QuickCheck::new()
+            .gen(qc::StdGen::new(rand::thread_rng(), 1_000))
+            .quickcheck(prop as fn(Vec<u8>, usize, usize) -> bool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn weight_strided_zero_stride() {
+        super::weight_strided(&[1, 2, 3], 0, 0);
+    }
 }
@@ -0,0 +1,62 @@
+use checked::ArgumentError;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The unsigned integer types that `weight` and `distance` accept
+/// slices of: `u8`, `u16`, `u32`, `u64` and `u128`.
+///
+/// This trait is sealed, and cannot be implemented outside this
+/// crate. Each type has its own fast path: bytes are split at 8-byte
+/// alignment boundaries, while wider types are reinterpreted as
+/// 64-bit words directly, relying on the alignment their type already
+/// guarantees.
+///
+/// # Examples
+///
+/// ```rust
+/// fn total_weight<T: hamming::Words>(rows: &[Vec<T>]) -> u64 {
+///     rows.iter().map(|r| hamming::weight(r)).sum()
+/// }
+///
+/// assert_eq!(total_weight(&[vec![1u16, 3], vec![0xFFFF]]), 1 + 2 + 16);
+/// ```
+pub trait Words: private::Sealed + Copy {
+    #[doc(hidden)]
+    fn weight(x: &[Self]) -> u64;
+    #[doc(hidden)]
+    fn distance(x: &[Self], y: &[Self]) -> u64;
+    #[doc(hidden)]
+    fn try_distance(x: &[Self], y: &[Self]) -> Result<u64, ArgumentError>;
+}
+
+macro_rules! words {
+    ($($t: ty => $weight: path, $distance: path, $try_distance: path;)*) => {
+        $(
+            impl private::Sealed for $t {}
+            impl Words for $t {
+                #[inline]
+                fn weight(x: &[$t]) -> u64 {
+                    $weight(x)
+                }
+                #[inline]
+                fn distance(x: &[$t], y: &[$t]) -> u64 {
+                    $distance(x, y)
+                }
+                #[inline]
+                fn try_distance(x: &[$t], y: &[$t]) -> Result<u64, ArgumentError> {
+                    $try_distance(x, y)
+                }
+            }
+        )*
+    }
+}
+
+words! {
+    u8 => ::weight_::weight_bytes, ::distance_::distance_bytes, ::distance_::try_distance_bytes;
+    u16 => ::weight_::weight_u16_slice, ::distance_::distance_u16_slice, ::distance_::try_distance_u16_slice;
+    u32 => ::weight_::weight_u32_slice, ::distance_::distance_u32_slice, ::distance_::try_distance_u32_slice;
+    u64 => ::weight_::weight_u64_slice, ::distance_::distance_u64_slice, ::distance_::try_distance_u64_slice;
+    u128 => ::weight_::weight_u128_slice, ::distance_::distance_u128_slice, ::distance_::try_distance_u128_slice;
+}